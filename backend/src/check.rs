@@ -18,14 +18,27 @@
 
 use std::env;
 
-use check_if_email_exists::{check_email as ciee_check_email, CheckEmailInput, CheckEmailOutput};
+use check_if_email_exists::{
+	check_email as ciee_check_email, CheckEmailContext, CheckEmailInput, CheckEmailOutput,
+};
+use once_cell::sync::Lazy;
 use warp::Filter;
 
 use super::sentry_util;
 
+/// Context shared by all verifications of this backend, so that e.g. the
+/// catch-all cache benefits both the single and the bulk endpoints.
+static CONTEXT: Lazy<CheckEmailContext> = Lazy::new(CheckEmailContext::new);
+
+/// The context shared by all verifications of this backend.
+pub fn shared_context() -> CheckEmailContext {
+	CONTEXT.clone()
+}
+
 /// Same as `check-if-email-exists`'s check email, but adds some additional
 /// inputs and error handling.
-pub async fn check_email(input: CheckEmailInput) -> CheckEmailOutput {
+pub async fn check_email(mut input: CheckEmailInput) -> CheckEmailOutput {
+	input.set_context(shared_context());
 	let res = ciee_check_email(&input).await;
 
 	sentry_util::log_unknown_errors(&res);
@@ -19,7 +19,10 @@
 
 use check_if_email_exists::LOG_TARGET;
 use dotenv::dotenv;
-use reacher_backend::routes::{bulk::email_verification_task, create_routes};
+use reacher_backend::routes::{
+	bulk::{dns_preflight_task, email_verification_task},
+	create_routes,
+};
 use reacher_backend::sentry_util::{setup_sentry, CARGO_PKG_VERSION};
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use sqlxmq::{JobRegistry, OwnedHandle};
@@ -90,7 +93,7 @@ async fn create_job_registry(pool: &Pool<Postgres>) -> Result<OwnedHandle, sqlx:
 	});
 
 	// registry needs to be given list of jobs it can accept
-	let registry = JobRegistry::new(&[email_verification_task]);
+	let registry = JobRegistry::new(&[email_verification_task, dns_preflight_task]);
 
 	// create runner for the message queue associated
 	// with this job registry
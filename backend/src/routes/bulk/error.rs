@@ -16,6 +16,7 @@
 
 use warp::reject;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum CsvError {
 	CsvLib(csv::Error),
@@ -24,6 +25,7 @@ pub enum CsvError {
 }
 
 /// Catch all error struct for the bulk endpoints
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum BulkError {
 	EmptyInput,
@@ -18,7 +18,9 @@ mod db;
 mod error;
 pub mod get;
 pub mod post;
+mod preflight;
 pub mod results;
 mod task;
 
+pub use preflight::dns_preflight_task;
 pub use task::email_verification_task;
@@ -25,6 +25,7 @@ use warp::Filter;
 use super::{
 	db::with_db,
 	error::BulkError,
+	preflight::submit_preflight,
 	task::{submit_job, TaskInput},
 };
 use crate::check::check_header;
@@ -38,6 +39,11 @@ struct CreateBulkRequestBody {
 	hello_name: Option<String>,
 	from_email: Option<String>,
 	smtp_ports: Option<Vec<u16>>,
+	/// Before queuing the SMTP verifications, resolve all domains' MX
+	/// records, and directly mark as invalid the emails whose domain has no
+	/// MX records. This is done by a task of the job queue, which queues the
+	/// SMTP verifications once done. Defaults to false.
+	dns_preflight: Option<bool>,
 }
 
 struct CreateBulkRequestBodyIterator {
@@ -115,6 +121,21 @@ async fn create_bulk_request(
 		BulkError::from(e)
 	})?;
 
+	if body.dns_preflight.unwrap_or(false) {
+		let task_uuid = submit_preflight(&conn_pool, rec.id, body.into_iter().collect()).await?;
+
+		log::debug!(
+			target: LOG_TARGET,
+			"Submitted pre-flight task to sqlxmq for [job={}] with [uuid={}]",
+			rec.id,
+			task_uuid
+		);
+
+		return Ok(warp::reply::json(&CreateBulkResponseBody {
+			job_id: rec.id,
+		}));
+	}
+
 	for task_input in body.into_iter() {
 		let task_uuid = submit_job(&conn_pool, rec.id, task_input).await?;
 
@@ -0,0 +1,281 @@
+// Reacher - Email Verification
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DNS-only pre-flight of a bulk job: emails whose domain can't receive
+//! mail are resolved right away, before any SMTP task is queued.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use check_if_email_exists::mx::{check_mx, MxDetails, MxError};
+use check_if_email_exists::syntax::{check_syntax, get_similar_mail_provider, SyntaxDetails};
+use check_if_email_exists::{CheckEmailInput, CheckEmailOutput, Reachable, LOG_TARGET};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use sqlxmq::{job, CurrentJob};
+use uuid::Uuid;
+
+use super::error::BulkError;
+use super::task::{insert_result, submit_job, TaskInput};
+
+/// Number of domains whose MX records are resolved concurrently.
+const PREFLIGHT_CONCURRENCY: usize = 50;
+
+/// Split `emails` into the ones which need an SMTP verification, and the
+/// verification results of the ones which don't, because their syntax is
+/// invalid or their domain can't receive mail. The MX lookups use the
+/// options and context of `input`, the job's input.
+pub async fn dns_preflight(
+	emails: Vec<String>,
+	input: &CheckEmailInput,
+) -> (Vec<String>, Vec<CheckEmailOutput>) {
+	let syntaxes = emails
+		.iter()
+		.map(|email| check_syntax(email))
+		.collect::<Vec<_>>();
+
+	// Resolve each domain only once, using one of its emails. Only the MX
+	// results proving that the domain can't receive mail are kept.
+	let domains = emails
+		.iter()
+		.zip(&syntaxes)
+		.filter(|(_, syntax)| syntax.is_valid_syntax)
+		.map(|(email, syntax)| (syntax.domain.to_lowercase(), email.clone()))
+		.collect::<HashMap<_, _>>();
+	let domain_inputs = domains
+		.into_iter()
+		.map(|(domain, email)| {
+			let mut input = input.clone();
+			input.to_email = email;
+			(domain, input)
+		})
+		.collect::<Vec<_>>();
+	let rejected_domains = stream::iter(domain_inputs)
+		.map(|(domain, input)| async move {
+			let syntax = check_syntax(&input.to_email);
+			let mx = match check_mx(&syntax, &input).await {
+				Ok(mx) if mx.lookup.is_err() => Some(Ok(mx)),
+				Err(err) if err.is_invalid_domain() => Some(Err(err)),
+				// Let the SMTP phase handle the other DNS errors.
+				_ => None,
+			};
+			(domain, mx)
+		})
+		.buffer_unordered(PREFLIGHT_CONCURRENCY)
+		.filter_map(|(domain, mx)| async move { mx.map(|mx| (domain, mx)) })
+		.collect::<HashMap<_, _>>()
+		.await;
+
+	let (to_verify, to_reject): (Vec<_>, Vec<_>) =
+		emails.into_iter().zip(syntaxes).partition(|(_, syntax)| {
+			syntax.is_valid_syntax && !rejected_domains.contains_key(&syntax.domain.to_lowercase())
+		});
+
+	let rejected = to_reject
+		.into_iter()
+		.map(|(email, syntax)| {
+			let mx = rejected_domains.get(&syntax.domain.to_lowercase());
+			rejected_output(email, syntax, mx)
+		})
+		.collect::<Vec<_>>();
+
+	log::debug!(
+		target: LOG_TARGET,
+		"DNS pre-flight rejected {} emails, {} left to verify",
+		rejected.len(),
+		to_verify.len()
+	);
+
+	(
+		to_verify.into_iter().map(|(email, _)| email).collect(),
+		rejected,
+	)
+}
+
+/// Struct that's serialized into the sqlxmq own `payload_json` table, for
+/// the pre-flight task: the tasks of all the emails of the job.
+#[derive(Debug, Deserialize, Serialize)]
+struct PreflightPayload {
+	id: i32,
+	input: Vec<TaskInput>,
+}
+
+/// Queue the DNS pre-flight of the job `job_id`, which queues the tasks of
+/// the emails it doesn't reject.
+pub async fn submit_preflight(
+	conn_pool: &Pool<Postgres>,
+	job_id: i32,
+	task_inputs: Vec<TaskInput>,
+) -> Result<Uuid, BulkError> {
+	let payload = PreflightPayload {
+		id: job_id,
+		input: task_inputs,
+	};
+
+	let uuid = dns_preflight_task
+		.builder()
+		.set_json(&payload)
+		.map_err(|e| {
+			log::error!(
+				target: LOG_TARGET,
+				"Failed to submit pre-flight task for [bulk_req={}] with [error={}]",
+				job_id,
+				e
+			);
+
+			BulkError::Json(e)
+		})?
+		.spawn(conn_pool)
+		.await
+		.map_err(|e| {
+			log::error!(
+				target: LOG_TARGET,
+				"Failed to submit pre-flight task for [bulk_req={}] with [error={}]",
+				job_id,
+				e
+			);
+
+			e
+		})?;
+
+	Ok(uuid)
+}
+
+/// The first task of the jobs with a DNS pre-flight: write the results of
+/// the emails rejected by `dns_preflight`, then queue the verification
+/// tasks of the other ones. Resolving the domains of a large job takes a
+/// while, so it's done in the job queue rather than by `POST /bulk`.
+#[job]
+pub async fn dns_preflight_task(
+	mut current_job: CurrentJob,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+	let payload: PreflightPayload = current_job.json()?.ok_or("Got empty task.")?;
+	let job_id = payload.id;
+	let job_input = payload
+		.input
+		.first()
+		.map(TaskInput::job_input)
+		.ok_or("Got pre-flight task without emails.")?;
+
+	let emails = payload
+		.input
+		.iter()
+		.map(|task_input| task_input.to_email.clone())
+		.collect();
+	let (to_verify, rejected) = dns_preflight(emails, &job_input).await;
+
+	for response in rejected {
+		insert_result(current_job.pool(), job_id, &response)
+			.await
+			.map_err(|e| {
+				log::error!(
+					target: LOG_TARGET,
+					"Failed to write pre-flight [email={}] result to db for [job={}] and [uuid={}] with [error={}]",
+					response.input,
+					job_id,
+					current_job.id(),
+					e
+				);
+
+				e
+			})?;
+	}
+
+	let to_verify = to_verify.into_iter().collect::<HashSet<_>>();
+	for task_input in payload
+		.input
+		.into_iter()
+		.filter(|task_input| to_verify.contains(&task_input.to_email))
+	{
+		let task_uuid = submit_job(current_job.pool(), job_id, task_input)
+			.await
+			.map_err(|e| format!("{:?}", e))?;
+
+		log::debug!(
+			target: LOG_TARGET,
+			"Submitted task to sqlxmq for [job={}] with [uuid={}]",
+			job_id,
+			task_uuid
+		);
+	}
+
+	current_job.complete().await?;
+	Ok(())
+}
+
+/// Build the output of a rejected email, as the full verification would:
+/// `mx` is the MX result of its domain, or None if its syntax is invalid.
+fn rejected_output(
+	email: String,
+	mut syntax: SyntaxDetails,
+	mx: Option<&Result<MxDetails, MxError>>,
+) -> CheckEmailOutput {
+	let mut output = CheckEmailOutput {
+		input: email,
+		is_reachable: Reachable::Invalid,
+		..Default::default()
+	};
+	if let Some(mx) = mx {
+		get_similar_mail_provider(&mut syntax);
+		output.mx = match mx {
+			Ok(mx) => Ok(mx.clone()),
+			// Only the errors of `MxError::is_invalid_domain` are rejected.
+			Err(MxError::NullMx) => Err(MxError::NullMx),
+			Err(_) => Err(MxError::NxDomain),
+		};
+	}
+	output.syntax = syntax;
+
+	output
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn should_reject_invalid_syntax_without_dns() {
+		let emails = vec!["foo".to_string(), "foo@[192.0.2.1]".to_string()];
+		let (to_verify, rejected) = dns_preflight(emails, &CheckEmailInput::default()).await;
+
+		assert_eq!(to_verify, vec!["foo@[192.0.2.1]".to_string()]);
+		assert_eq!(rejected.len(), 1);
+		assert_eq!(rejected[0].input, "foo");
+		assert_eq!(rejected[0].is_reachable, Reachable::Invalid);
+		assert!(!rejected[0].syntax.is_valid_syntax);
+	}
+
+	#[test]
+	fn should_build_rejected_output_from_mx_result() {
+		let output = rejected_output(
+			"foo@example.invalid".into(),
+			check_syntax("foo@example.invalid"),
+			Some(&Err(MxError::NxDomain)),
+		);
+		assert_eq!(output.input, "foo@example.invalid");
+		assert_eq!(output.is_reachable, Reachable::Invalid);
+		assert!(output.syntax.is_valid_syntax);
+		assert!(matches!(output.mx, Err(MxError::NxDomain)));
+
+		let output = rejected_output(
+			"foo@example.org".into(),
+			check_syntax("foo@example.org"),
+			Some(&Ok(MxDetails::default())),
+		);
+		assert!(output.mx.unwrap().lookup.is_err());
+	}
+}
@@ -139,10 +139,8 @@ impl TryFrom<CsvWrapper> for JobResultCsvResponse {
 								misc_is_role_account =
 									val.as_bool().ok_or("is_role_account should be a boolean")?
 							}
-							"gravatar_url" => {
-								if Option::is_some(&val.as_str()) {
-									misc_gravatar_url = Some(val.to_string())
-								}
+							"gravatar_url" if Option::is_some(&val.as_str()) => {
+								misc_gravatar_url = Some(val.to_string())
 							}
 							_ => {}
 						}
@@ -17,7 +17,7 @@
 //! This file implements the `POST /bulk` endpoint.
 
 use super::error::BulkError;
-use crate::check::check_email;
+use crate::check::{check_email, shared_context};
 use check_if_email_exists::LOG_TARGET;
 use check_if_email_exists::{CheckEmailInput, CheckEmailInputProxy, CheckEmailOutput, Reachable};
 use serde::{Deserialize, Serialize};
@@ -36,6 +36,26 @@ pub struct TaskInput {
 	pub from_email: Option<String>,
 }
 
+impl TaskInput {
+	/// The input shared by all the emails of the job, without the email and
+	/// the SMTP port, with the backend's context.
+	pub fn job_input(&self) -> CheckEmailInput {
+		let mut input = CheckEmailInput::default();
+		if let Some(name) = &self.hello_name {
+			input.set_hello_name(name.clone());
+		}
+		if let Some(email) = &self.from_email {
+			input.set_from_email(email.clone());
+		}
+		if let Some(proxy) = &self.proxy {
+			input.set_proxy(proxy.clone());
+		}
+		input.set_context(shared_context());
+
+		input
+	}
+}
+
 pub struct TaskInputIterator {
 	body: TaskInput,
 	index: usize,
@@ -129,6 +149,32 @@ pub async fn submit_job(
 	Ok(uuid)
 }
 
+/// Write the verification result of an email of the job into the email
+/// results table.
+pub async fn insert_result(
+	conn_pool: &Pool<Postgres>,
+	job_id: i32,
+	response: &CheckEmailOutput,
+) -> Result<(), sqlx::Error> {
+	sqlx::query!(
+		r#"
+			INSERT INTO email_results (job_id, result)
+			VALUES ($1, $2)
+			"#,
+		job_id,
+		serde_json::json!(response)
+	)
+	// TODO: This is a simplified solution and will work when
+	// the job queue and email results tables are in the same
+	// database. Keeping them in separate database will require
+	// some custom logic on the job registry side
+	// https://github.com/Diggsey/sqlxmq/issues/4
+	.fetch_optional(conn_pool)
+	.await?;
+
+	Ok(())
+}
+
 /// Arguments to the `#[job]` attribute allow setting default task options.
 /// This task tries to verify the given email and inserts the results
 /// into the email verification db table
@@ -187,34 +233,20 @@ pub async fn email_verification_task(
 	// never occur currently
 	if let Some(response) = final_response {
 		// write results and terminate iteration
-		#[allow(unused_variables)]
-		let rec = sqlx::query!(
-			r#"
-			INSERT INTO email_results (job_id, result)
-			VALUES ($1, $2)
-			"#,
-			job_id,
-			serde_json::json!(response)
-		)
-		// TODO: This is a simplified solution and will work when
-		// the job queue and email results tables are in the same
-		// database. Keeping them in separate database will require
-		// some custom logic on the job registry side
-		// https://github.com/Diggsey/sqlxmq/issues/4
-		.fetch_optional(current_job.pool())
-		.await
-		.map_err(|e| {
-			log::error!(
-				target:LOG_TARGET,
-				"Failed to write [email={}] result to db for [job={}] and [uuid={}] with [error={}]",
-				response.input,
-				job_id,
-				current_job.id(),
-				e
-			);
+		insert_result(current_job.pool(), job_id, &response)
+			.await
+			.map_err(|e| {
+				log::error!(
+					target:LOG_TARGET,
+					"Failed to write [email={}] result to db for [job={}] and [uuid={}] with [error={}]",
+					response.input,
+					job_id,
+					current_job.id(),
+					e
+				);
 
-			e
-		})?;
+				e
+			})?;
 
 		log::debug!(
 			target: LOG_TARGET,
@@ -59,6 +59,7 @@ fn get_backend_name<'a>() -> Option<Cow<'a, str>> {
 	None
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 enum SentryError<'a> {
 	// TODO: Probably a good idea would be to `impl std:error:Error` for the
@@ -136,8 +137,12 @@ pub fn log_unknown_errors(result: &CheckEmailOutput) {
 			// We log all misc errors.
 			sentry_util::error(SentryError::Misc(err), result);
 		}
+		(_, Err(err), _) if err.is_invalid_domain() => {
+			// The domain doesn't exist or doesn't accept email, this is a
+			// valid result.
+		}
 		(_, Err(err), _) => {
-			// We log all mx errors.
+			// We log all other mx errors.
 			sentry_util::error(SentryError::Mx(err), result);
 		}
 		(_, _, Err(err)) if err.get_description().is_some() => {
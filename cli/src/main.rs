@@ -14,6 +14,8 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::time::Duration;
+
 use check_if_email_exists::{check_email, CheckEmailInput, CheckEmailInputProxy};
 use clap::Parser;
 use once_cell::sync::Lazy;
@@ -53,6 +55,11 @@ pub struct Cli {
 	#[clap(long, env, default_value = "25")]
 	pub smtp_port: u16,
 
+	/// Ports to try, in order, when the SMTP port can't be reached, e.g.
+	/// `587,465,2525`.
+	#[clap(long, env, use_value_delimiter = true)]
+	pub smtp_fallback_ports: Vec<u16>,
+
 	/// For Yahoo email addresses, use Yahoo's API instead of connecting
 	/// directly to their SMTP servers.
 	#[clap(long, env, default_value = "true", parse(try_from_str))]
@@ -73,14 +80,62 @@ pub struct Cli {
 	#[clap(long, env, default_value = "false", parse(try_from_str))]
 	pub microsoft365_use_api: bool,
 
+	/// For Microsoft 365 email addresses, use a headless browser to connect
+	/// to the Microsoft login page.
+	#[clap(long, env)]
+	pub microsoft365_use_headless: Option<String>,
+
+	/// For all Microsoft addresses, use a headless browser on the page
+	/// matching whether they're Hotmail/Outlook or Microsoft 365 addresses.
+	#[clap(long, env)]
+	pub microsoft_use_headless: Option<String>,
+
+	/// For Proton Mail email addresses, use Proton's address availability
+	/// API instead of connecting directly to their SMTP servers.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub proton_use_api: bool,
+
+	/// For iCloud addresses, use Apple's Apple ID validation API instead of
+	/// skipping them, as iCloud's SMTP servers accept all mailboxes.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub icloud_use_api: bool,
+
+	/// For Mail.ru addresses, use Mail.ru's user API instead of connecting
+	/// directly to their SMTP servers.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub mailru_use_api: bool,
+
+	/// For Yandex addresses, use Yandex's login validation API instead of
+	/// connecting directly to their SMTP servers.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub yandex_use_api: bool,
+
+	/// For GMX and WEB.DE addresses, use the availability API of their
+	/// signup pages instead of skipping them.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub gmx_use_api: bool,
+
+	/// For Yahoo addresses, fall back to a headless browser on the Yahoo
+	/// password recovery page when Yahoo's API breaks.
+	#[clap(long, env)]
+	pub yahoo_use_headless: Option<String>,
+
 	/// Whether to check if a gravatar image is existing for the given email.
 	#[clap(long, env, default_value = "false", parse(try_from_str))]
 	pub check_gravatar: bool,
 
+	/// Timeout of the Gravatar request, in seconds.
+	#[clap(long, env, parse(try_from_str))]
+	pub gravatar_timeout: Option<u64>,
+
 	/// HaveIBeenPnwed API key, ignore if not provided.
 	#[clap(long, env, parse(try_from_str))]
 	pub haveibeenpwned_api_key: Option<String>,
 
+	/// Only report the breaches HaveIBeenPwned verified.
+	#[clap(long, env, default_value = "false", parse(try_from_str))]
+	pub haveibeenpwned_verified_only: bool,
+
 	/// The email to check.
 	pub to_email: String,
 }
@@ -99,12 +154,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 		.set_from_email(CONF.from_email.clone())
 		.set_hello_name(CONF.hello_name.clone())
 		.set_smtp_port(CONF.smtp_port)
+		.set_smtp_fallback_ports(CONF.smtp_fallback_ports.clone())
 		.set_yahoo_use_api(CONF.yahoo_use_api)
 		.set_gmail_use_api(CONF.gmail_use_api)
 		.set_microsoft365_use_api(CONF.microsoft365_use_api)
+		.set_proton_use_api(CONF.proton_use_api)
+		.set_icloud_use_api(CONF.icloud_use_api)
+		.set_mailru_use_api(CONF.mailru_use_api)
+		.set_yandex_use_api(CONF.yandex_use_api)
+		.set_gmx_use_api(CONF.gmx_use_api)
 		.set_check_gravatar(CONF.check_gravatar)
+		.set_gravatar_timeout(CONF.gravatar_timeout.map(Duration::from_secs))
 		.set_hotmail_use_headless(CONF.hotmail_use_headless.clone())
-		.set_haveibeenpwned_api_key(CONF.haveibeenpwned_api_key.clone());
+		.set_microsoft365_use_headless(CONF.microsoft365_use_headless.clone())
+		.set_microsoft_use_headless(CONF.microsoft_use_headless.clone())
+		.set_yahoo_use_headless(CONF.yahoo_use_headless.clone())
+		.set_haveibeenpwned_api_key(CONF.haveibeenpwned_api_key.clone())
+		.set_haveibeenpwned_verified_only(CONF.haveibeenpwned_verified_only);
 
 	if let Some(proxy_host) = &CONF.proxy_host {
 		input.set_proxy(CheckEmailInputProxy {
@@ -112,6 +178,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 			port: CONF.proxy_port,
 			username: CONF.proxy_username.clone(),
 			password: CONF.proxy_password.clone(),
+			..Default::default()
 		});
 	}
 
@@ -0,0 +1,167 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::smtp::{check_smtp_batch, with_smtp_deadline};
+use crate::syntax::{parse_email_like, requires_smtputf8};
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+use crate::{
+	enrich_output, smtp_attempts, smtp_output, verify_email, verify_email_until_smtp,
+	CheckEmailOutput, PreSmtp, SmtpError,
+};
+
+/// The input of the verification of `to_email`, with the same options as
+/// `input`.
+fn email_input(input: &CheckEmailInput, to_email: &str) -> CheckEmailInput {
+	let mut email_input = input.clone();
+	email_input.to_email = if input.extract_email {
+		parse_email_like(to_email).unwrap_or_else(|| to_email.to_string())
+	} else {
+		to_email.to_string()
+	};
+
+	email_input
+}
+
+/// Verify many emails with the options of `input`, whose own `to_email` is
+/// ignored. Unlike calling `check_email` on each email, the emails of the
+/// same domain are verified over a single SMTP session, with one `RCPT TO`
+/// command per email, which cuts the number of connections for lists
+/// dominated by a few domains.
+///
+/// Returns the output of each email, in the same order as `to_emails`.
+pub async fn check_email_batch<S: AsRef<str>>(
+	input: &CheckEmailInput,
+	to_emails: &[S],
+) -> Vec<CheckEmailOutput> {
+	let inputs = to_emails
+		.iter()
+		.map(|to_email| email_input(input, to_email.as_ref()))
+		.collect::<Vec<_>>();
+	let mut outputs = inputs.iter().map(|_| None).collect::<Vec<_>>();
+
+	// The emails which can be verified over SMTP, by domain.
+	let mut domains = HashMap::<String, Vec<(usize, PreSmtp)>>::new();
+	for (index, email_input) in inputs.iter().enumerate() {
+		match verify_email_until_smtp(email_input).await {
+			Ok(pre_smtp) if !requires_smtputf8(&email_input.to_email) => domains
+				.entry(pre_smtp.syntax.domain.to_lowercase())
+				.or_default()
+				.push((index, pre_smtp)),
+			// The `MAIL FROM` command depends on whether the email needs
+			// SMTPUTF8, so these emails get their own session.
+			Ok(_) => outputs[index] = Some(verify_email(email_input).await),
+			Err(output) => outputs[index] = Some(output),
+		}
+	}
+
+	for (domain, emails) in domains {
+		// The options are the same for all the emails, apart from `to_email`.
+		let domain_input = &*inputs[emails[0].0].for_domain(&domain);
+		let to_emails = emails
+			.iter()
+			.map(|(_, pre_smtp)| {
+				pre_smtp
+					.syntax
+					.address
+					.clone()
+					.expect("We already checked that the email has valid format. qed.")
+			})
+			.collect::<Vec<_>>();
+
+		// Fall through to the next port, then to the next MX host, if one
+		// can't be reached.
+		let mut attempts = smtp_attempts(&emails[0].1.mx, domain_input)
+			.into_iter()
+			.peekable();
+		let start = Instant::now();
+		let (host, results) = loop {
+			let (host, port) = attempts
+				.next()
+				.expect("There's at least one MX host to try. qed.");
+			let results = with_smtp_deadline(
+				domain_input,
+				start,
+				check_smtp_batch(
+					&to_emails,
+					host.exchange(),
+					port,
+					&domain,
+					&emails[0].1.mx,
+					domain_input,
+				),
+			)
+			.await;
+
+			match &results {
+				Err(err) if err.is_host_error() && attempts.peek().is_some() => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[domain={}] Cannot verify on [host={}:{}], trying the next port or MX host: {:?}",
+						domain,
+						host.exchange(),
+						port,
+						err
+					);
+				}
+				_ => break (host, results),
+			}
+		};
+
+		let results = results.unwrap_or_else(|err| {
+			let message = format!("{err:?}");
+			let mut results = vec![Err(err)];
+			results.extend((1..emails.len()).map(|_| Err(SmtpError::BatchError(message.clone()))));
+			results
+		});
+		for ((index, pre_smtp), my_smtp) in emails.into_iter().zip(results) {
+			outputs[index] = Some(smtp_output(&inputs[index], pre_smtp, &host, my_smtp));
+		}
+	}
+
+	let mut outputs = outputs
+		.into_iter()
+		.map(|output| output.expect("Each email has an output. qed."))
+		.collect::<Vec<_>>();
+	for ((output, email_input), to_email) in outputs.iter_mut().zip(&inputs).zip(to_emails) {
+		enrich_output(output, email_input).await;
+		output.input = to_email.as_ref().to_string();
+	}
+
+	outputs
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Reachable;
+
+	#[tokio::test]
+	async fn should_keep_emails_order() {
+		let mut input = CheckEmailInput::default();
+		input.set_blocked_domains(vec!["example.org".into()]);
+
+		let outputs = check_email_batch(&input, &["foo", "bar@example.org"]).await;
+
+		assert_eq!(outputs.len(), 2);
+		assert_eq!(outputs[0].input, "foo");
+		assert_eq!(outputs[0].is_reachable, Reachable::Invalid);
+		assert_eq!(outputs[1].input, "bar@example.org");
+		assert_eq!(outputs[1].is_reachable, Reachable::Blocked);
+	}
+}
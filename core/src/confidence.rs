@@ -0,0 +1,265 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compute a numeric confidence score (0-100) on how reachable an email is,
+//! together with the breakdown of how it was computed.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::mx::MxProvider;
+use crate::util::input_output::CheckEmailOutput;
+
+/// A signal taken into account in the confidence score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceSignal {
+	/// The email address is syntactically valid.
+	ValidSyntax,
+	/// The email's domain has MX records.
+	MxPresent,
+	/// The SMTP server accepted the email address.
+	SmtpAccepted,
+	/// The domain accepts all email addresses.
+	CatchAllPenalty,
+	/// The email address is disposable.
+	DisposablePenalty,
+	/// The email address is a role account, e.g. `info@`.
+	RoleAccountPenalty,
+	/// The MX is of poor quality: it's the implicit MX, one of its hosts is
+	/// listed on a DNSBL, or the domain is parked. Only detected with the
+	/// corresponding checks of the input.
+	WeakMxPenalty,
+	/// The MX hosts are an email security gateway, whose answers say
+	/// little about the mailbox.
+	SecurityGatewayPenalty,
+	/// The MX hosts belong to a known mailbox provider, e.g. Google.
+	KnownProvider,
+	/// The email address has a Gravatar. Only detected if `check_gravatar`
+	/// is set in the input.
+	GravatarPresent,
+	/// The email address was found in breaches by HaveIBeenPwned, so it
+	/// was in use. Only detected if `haveibeenpwned_api_key` is set in the
+	/// input.
+	BreachHistory,
+}
+
+impl ConfidenceSignal {
+	/// The number of points this signal adds to (or removes from) the score
+	/// when it's present.
+	pub fn weight(self) -> i32 {
+		match self {
+			Self::ValidSyntax => 10,
+			Self::MxPresent => 20,
+			Self::SmtpAccepted => 70,
+			Self::CatchAllPenalty => -30,
+			Self::DisposablePenalty => -40,
+			Self::RoleAccountPenalty => -15,
+			Self::WeakMxPenalty => -15,
+			Self::SecurityGatewayPenalty => -10,
+			Self::KnownProvider => 5,
+			Self::GravatarPresent => 10,
+			Self::BreachHistory => 10,
+		}
+	}
+}
+
+/// One line of the confidence score breakdown.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConfidenceFactor {
+	/// The signal this factor is about.
+	pub signal: ConfidenceSignal,
+	/// The points this signal is worth when present, i.e. its default
+	/// weight, unless the input's `confidence_weights` override it.
+	pub weight: i32,
+	/// The points this signal actually contributed to the score: `weight` if
+	/// the signal is present, 0 otherwise.
+	pub contribution: i32,
+}
+
+/// A numeric estimate of how reachable an email is.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Confidence {
+	/// The score, between 0 and 100, computed as the sum of all factors'
+	/// contributions.
+	pub score: u8,
+	/// How the score was computed.
+	pub confidence_factors: Vec<ConfidenceFactor>,
+}
+
+/// Compute the confidence score of a verification's output.
+pub fn calculate_confidence(output: &CheckEmailOutput) -> Confidence {
+	calculate_confidence_with_weights(output, &HashMap::new())
+}
+
+/// Compute the confidence score of a verification's output, with the
+/// signals' weights overridden by `weights`.
+pub fn calculate_confidence_with_weights(
+	output: &CheckEmailOutput,
+	weights: &HashMap<ConfidenceSignal, i32>,
+) -> Confidence {
+	let smtp = output.smtp.as_ref().ok();
+	let mx = output.mx.as_ref().ok();
+	let misc = output.misc.as_ref().ok();
+	let provider = mx.and_then(|mx| mx.provider);
+	let is_weak_mx = mx.is_some_and(|mx| {
+		mx.implicit_mx
+			|| mx
+				.dnsbl_listings
+				.as_ref()
+				.is_some_and(|listings| !listings.is_empty())
+			|| mx
+				.parked_domain
+				.as_ref()
+				.is_some_and(|parked| parked.is_parked)
+	});
+	let signals = [
+		(ConfidenceSignal::ValidSyntax, output.syntax.is_valid_syntax),
+		(
+			ConfidenceSignal::MxPresent,
+			output
+				.mx
+				.as_ref()
+				.map(|mx| mx.lookup.is_ok())
+				.unwrap_or(false),
+		),
+		(
+			ConfidenceSignal::SmtpAccepted,
+			smtp.map(|s| s.is_deliverable).unwrap_or(false),
+		),
+		(
+			ConfidenceSignal::CatchAllPenalty,
+			smtp.map(|s| s.is_catch_all).unwrap_or(false),
+		),
+		(
+			ConfidenceSignal::DisposablePenalty,
+			output
+				.misc
+				.as_ref()
+				.map(|misc| misc.is_disposable)
+				.unwrap_or(false),
+		),
+		(
+			ConfidenceSignal::RoleAccountPenalty,
+			misc.is_some_and(|misc| misc.is_role_account),
+		),
+		(ConfidenceSignal::WeakMxPenalty, is_weak_mx),
+		(
+			ConfidenceSignal::SecurityGatewayPenalty,
+			provider.is_some_and(|provider| provider.is_security_gateway()),
+		),
+		(
+			ConfidenceSignal::KnownProvider,
+			provider.is_some_and(|provider| {
+				!provider.is_security_gateway()
+					&& !matches!(provider, MxProvider::Selfhosted | MxProvider::Other)
+			}),
+		),
+		(
+			ConfidenceSignal::GravatarPresent,
+			misc.is_some_and(|misc| misc.gravatar_url.is_some()),
+		),
+		(
+			ConfidenceSignal::BreachHistory,
+			misc.is_some_and(|misc| misc.haveibeenpwned == Some(true)),
+		),
+	];
+
+	let confidence_factors = signals
+		.iter()
+		.map(|(signal, is_present)| {
+			let weight = weights.get(signal).copied().unwrap_or(signal.weight());
+			ConfidenceFactor {
+				signal: *signal,
+				weight,
+				contribution: if *is_present { weight } else { 0 },
+			}
+		})
+		.collect::<Vec<_>>();
+	let score = confidence_factors
+		.iter()
+		.map(|factor| factor.contribution)
+		.sum::<i32>()
+		.clamp(0, 100);
+
+	Confidence {
+		score: score as u8,
+		confidence_factors,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{misc::MiscDetails, smtp::SmtpDetails};
+
+	#[test]
+	fn should_score_invalid_syntax_zero() {
+		let confidence = calculate_confidence(&CheckEmailOutput::default());
+
+		assert_eq!(confidence.score, 0);
+		assert_eq!(confidence.confidence_factors.len(), 11);
+	}
+
+	#[test]
+	fn should_penalize_catch_all_and_disposable() {
+		let mut output = CheckEmailOutput::default();
+		output.syntax.is_valid_syntax = true;
+		output.smtp = Ok(SmtpDetails {
+			can_connect_smtp: true,
+			is_catch_all: true,
+			is_deliverable: true,
+			..Default::default()
+		});
+		output.misc = Ok(MiscDetails {
+			is_disposable: true,
+			..Default::default()
+		});
+
+		// 10 (syntax) + 70 (smtp) - 30 (catch-all) - 40 (disposable)
+		assert_eq!(calculate_confidence(&output).score, 10);
+	}
+
+	#[test]
+	fn should_override_weights() {
+		let mut output = CheckEmailOutput::default();
+		output.syntax.is_valid_syntax = true;
+		output.smtp = Ok(SmtpDetails {
+			can_connect_smtp: true,
+			is_catch_all: true,
+			is_deliverable: true,
+			..Default::default()
+		});
+		output.misc = Ok(MiscDetails {
+			gravatar_url: Some("https://www.gravatar.com/avatar/foo".into()),
+			..Default::default()
+		});
+
+		// 10 (syntax) + 70 (smtp) - 30 (catch-all) + 10 (gravatar)
+		assert_eq!(calculate_confidence(&output).score, 60);
+
+		let weights = HashMap::from([(ConfidenceSignal::CatchAllPenalty, 0)]);
+		let confidence = calculate_confidence_with_weights(&output, &weights);
+		assert_eq!(confidence.score, 90);
+		let catch_all = confidence
+			.confidence_factors
+			.iter()
+			.find(|factor| factor.signal == ConfidenceSignal::CatchAllPenalty)
+			.unwrap();
+		assert_eq!((catch_all.weight, catch_all.contribution), (0, 0));
+	}
+}
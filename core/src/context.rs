@@ -0,0 +1,525 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+
+use crate::misc::{
+	DisposableList, DisposableListConfig, DisposableListError, DisposableListStore, GravatarCache,
+	ListProvider, TrapFeed,
+};
+use crate::smtp::{
+	ChallengeSolver, ProviderLimitConfig, ProviderLimitStats, ProviderLimiter, ProviderVerifier,
+	ProxyPool, ProxyPoolConfig, ProxyStats, RateLimitConfig, RateLimitStats, RateLimiter,
+	RetryPolicy, SmtpPool, SmtpPoolConfig, SmtpPoolStats, SmtpResponseHook, SourceIpPool,
+	SourceIpPoolConfig, SourceIpStats,
+};
+#[cfg(feature = "headless")]
+use crate::smtp::{
+	HeadlessPool, HeadlessPoolConfig, HeadlessPoolStats, ManagedWebDriver, ManagedWebDriverConfig,
+};
+use crate::util::dns_cache::{DnsCache, DnsCacheKey};
+use crate::util::domain_policy::HostPattern;
+use crate::util::ip_info::IpInfoSource;
+use crate::util::resolver::Resolver;
+use crate::util::ttl_cache::{CacheStats, TtlCache};
+use async_std_resolver::lookup::Lookup;
+
+/// Key of the catch-all cache: the email domain, and the MX host which
+/// answered for it. Both are lowercase.
+pub(crate) type CatchAllKey = (String, String);
+
+/// State shared across multiple email verifications, such as caches.
+///
+/// Cloning a context is cheap, and all clones share the same state. To let
+/// all verifications of a service benefit from it, create one context at
+/// startup, and pass a clone of it to each `CheckEmailInput` with
+/// `set_context`.
+#[derive(Clone, Default)]
+pub struct CheckEmailContext {
+	/// Catch-all determinations, keyed by domain and MX host. Entries are
+	/// valid as long as the MX record used to determine them. Like the
+	/// other in-memory caches, it holds at most 10,000 entries.
+	catch_all_cache: Arc<TtlCache<CatchAllKey, bool>>,
+	/// Whether the email addresses have a Gravatar, keyed by their hash.
+	/// Disabled by default.
+	gravatar_cache: Option<Arc<GravatarCache>>,
+	/// HTTP client of the Gravatar checks, to reuse its connections.
+	/// Created on first use.
+	gravatar_client: Arc<OnceCell<reqwest::Client>>,
+	/// Idle SMTP connections, reused across verifications. Disabled by
+	/// default.
+	smtp_pool: Option<Arc<SmtpPool>>,
+	/// Local IPs the SMTP connections rotate across. Disabled by default.
+	source_ip_pool: Option<Arc<SourceIpPool>>,
+	/// Proxies the SMTP connections rotate across. Disabled by default.
+	proxy_pool: Option<Arc<ProxyPool>>,
+	/// Limits the `RCPT TO` probes per MX host or provider. Disabled by
+	/// default.
+	rate_limiter: Option<Arc<RateLimiter>>,
+	/// Request budgets and cooldowns of the providers' HTTP APIs and
+	/// headless pages. Disabled by default.
+	provider_limiter: Option<Arc<ProviderLimiter>>,
+	/// Successful DNS lookups, valid as long as their records' TTL.
+	/// Disabled by default.
+	dns_cache: Option<Arc<dyn DnsCache>>,
+	/// ASN and geolocation data of the MX hosts' IP addresses. Disabled by
+	/// default.
+	ip_info_source: Option<Arc<dyn IpInfoSource>>,
+	/// Custom DNS resolver, replacing the built-in ones. Disabled by
+	/// default.
+	resolver: Option<Arc<dyn Resolver>>,
+	/// Decides whether to retry failed SMTP verifications, replacing the
+	/// input's `retries` and `greylisting_retry_delay`. Disabled by default.
+	retry_policy: Option<Arc<dyn RetryPolicy>>,
+	/// Published list of spamtraps, for the input's `check_spamtraps`.
+	/// Disabled by default.
+	trap_feed: Option<Arc<dyn TrapFeed>>,
+	/// Disposable domain list loaded at runtime, replacing the bundled one
+	/// once loaded. Disabled by default.
+	disposable_list: Option<Arc<DisposableListStore>>,
+	/// Custom data source of the disposable domains and role accounts.
+	/// Disabled by default.
+	list_provider: Option<Arc<dyn ListProvider>>,
+	/// Called with each raw response of the SMTP servers. Disabled by
+	/// default.
+	smtp_response_hook: Option<Arc<dyn SmtpResponseHook>>,
+	/// Custom verification methods, with the MX host or domain pattern they
+	/// apply to. Empty by default.
+	provider_verifiers: Vec<(HostPattern, Arc<dyn ProviderVerifier>)>,
+	/// Solves the challenges of the headless verifications. Disabled by
+	/// default.
+	challenge_solver: Option<Arc<dyn ChallengeSolver>>,
+	/// WebDriver sessions of the headless verifications, reused across
+	/// verifications. Disabled by default.
+	#[cfg(feature = "headless")]
+	headless_pool: Option<Arc<HeadlessPool>>,
+	/// WebDriver server spawned by the crate. Disabled by default.
+	#[cfg(feature = "headless")]
+	managed_webdriver: Option<Arc<ManagedWebDriver>>,
+	/// Counter of the round-robin rotation of the EHLO names and `MAIL
+	/// FROM` addresses.
+	rotation: Arc<AtomicUsize>,
+}
+
+impl fmt::Debug for CheckEmailContext {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut debug = f.debug_struct("CheckEmailContext");
+		debug
+			.field("catch_all_cache", &self.catch_all_cache.stats())
+			.field("gravatar_cache", &self.gravatar_cache_stats())
+			.field("smtp_pool", &self.smtp_pool)
+			.field("source_ip_stats", &self.source_ip_stats())
+			.field("proxy_stats", &self.proxy_stats())
+			.field("rate_limiter", &self.rate_limiter)
+			.field("provider_limiter", &self.provider_limiter)
+			.field("dns_cache", &self.dns_cache_stats())
+			.field("ip_info_source", &self.ip_info_source)
+			.field("resolver", &self.resolver)
+			.field("retry_policy", &self.retry_policy)
+			.field("trap_feed", &self.trap_feed)
+			.field("disposable_list_version", &self.disposable_list_version())
+			.field("list_provider", &self.list_provider)
+			.field("smtp_response_hook", &self.smtp_response_hook)
+			.field("provider_verifiers", &self.provider_verifiers)
+			.field("challenge_solver", &self.challenge_solver);
+		#[cfg(feature = "headless")]
+		debug
+			.field("headless_pool", &self.headless_pool)
+			.field("managed_webdriver", &self.managed_webdriver);
+		debug.finish()
+	}
+}
+
+impl CheckEmailContext {
+	/// Create a new context, with empty caches.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub(crate) fn catch_all_cache(&self) -> &TtlCache<CatchAllKey, bool> {
+		&self.catch_all_cache
+	}
+
+	/// Hit/miss stats of the catch-all cache.
+	pub fn catch_all_cache_stats(&self) -> CacheStats {
+		self.catch_all_cache.stats()
+	}
+
+	/// Forget all cached catch-all determinations.
+	pub fn clear_catch_all_cache(&self) {
+		self.catch_all_cache.clear()
+	}
+
+	/// Enable the Gravatar cache: whether an email address has a Gravatar
+	/// is remembered for `ttl`, so that next verifications of the same
+	/// address skip the request.
+	pub fn with_gravatar_cache(mut self, ttl: Duration) -> Self {
+		self.gravatar_cache = Some(Arc::new(GravatarCache::new(ttl)));
+		self
+	}
+
+	pub(crate) fn gravatar_cache(&self) -> Option<Arc<GravatarCache>> {
+		self.gravatar_cache.clone()
+	}
+
+	pub(crate) fn gravatar_client(&self) -> reqwest::Client {
+		self.gravatar_client
+			.get_or_init(reqwest::Client::new)
+			.clone()
+	}
+
+	/// Hit/miss stats of the Gravatar cache, or None if it's not enabled.
+	pub fn gravatar_cache_stats(&self) -> Option<CacheStats> {
+		self.gravatar_cache.as_ref().map(|cache| cache.stats())
+	}
+
+	/// Forget all cached Gravatar answers, if the cache is enabled.
+	pub fn clear_gravatar_cache(&self) {
+		if let Some(cache) = &self.gravatar_cache {
+			cache.clear()
+		}
+	}
+
+	/// Enable the SMTP connection pool: after a verification, the SMTP
+	/// connection is reset with RSET and kept open, so that the next
+	/// verification on the same MX host, port and TLS security can skip the
+	/// connection and EHLO steps.
+	pub fn with_smtp_pool(mut self, config: SmtpPoolConfig) -> Self {
+		self.smtp_pool = Some(Arc::new(SmtpPool::new(config)));
+		self
+	}
+
+	pub(crate) fn smtp_pool(&self) -> Option<&SmtpPool> {
+		self.smtp_pool.as_deref()
+	}
+
+	/// Stats of the SMTP connection pool, or None if it's not enabled.
+	pub fn smtp_pool_stats(&self) -> Option<SmtpPoolStats> {
+		self.smtp_pool.as_ref().map(|pool| pool.stats())
+	}
+
+	/// Close all idle SMTP connections of the pool, if enabled.
+	pub fn clear_smtp_pool(&self) {
+		if let Some(pool) = &self.smtp_pool {
+			pool.clear()
+		}
+	}
+
+	/// Enable the source IP pool: the SMTP connections of the verifications
+	/// without a `source_ip` in their input rotate across the configured
+	/// IPs, and IPs which get blacklisted too often are rested for a while.
+	pub fn with_source_ip_pool(mut self, config: SourceIpPoolConfig) -> Self {
+		self.source_ip_pool = Some(Arc::new(SourceIpPool::new(config)));
+		self
+	}
+
+	pub(crate) fn source_ip_pool(&self) -> Option<&SourceIpPool> {
+		self.source_ip_pool.as_deref()
+	}
+
+	/// Counters of each IP of the source IP pool, or None if it's not
+	/// enabled.
+	pub fn source_ip_stats(&self) -> Option<Vec<SourceIpStats>> {
+		self.source_ip_pool.as_ref().map(|pool| pool.stats())
+	}
+
+	/// Enable the proxy pool: the SMTP connections of the verifications
+	/// without a `proxy` in their input rotate across the configured
+	/// proxies. Proxies which keep failing to connect are skipped until they
+	/// pass a health check, which runs periodically before verifications.
+	pub fn with_proxy_pool(mut self, config: ProxyPoolConfig) -> Self {
+		self.proxy_pool = Some(Arc::new(ProxyPool::new(config)));
+		self
+	}
+
+	pub(crate) fn proxy_pool(&self) -> Option<Arc<ProxyPool>> {
+		self.proxy_pool.clone()
+	}
+
+	/// Counters of each proxy of the proxy pool, or None if it's not
+	/// enabled.
+	pub fn proxy_stats(&self) -> Option<Vec<ProxyStats>> {
+		self.proxy_pool.as_ref().map(|pool| pool.stats())
+	}
+
+	/// Run the health check of the proxy pool now, if enabled, e.g. at
+	/// startup.
+	pub async fn check_proxy_health(&self) {
+		if let Some(pool) = &self.proxy_pool {
+			pool.health_check().await
+		}
+	}
+
+	/// Enable the rate limiter: the `RCPT TO` probes of all verifications
+	/// sharing the context wait, if needed, so that each MX host or provider
+	/// gets at most `max_probes` per `period`. The waits count towards the
+	/// input's `smtp_timeout`.
+	pub fn with_rate_limit(mut self, config: RateLimitConfig) -> Self {
+		self.rate_limiter = Some(Arc::new(RateLimiter::new(config)));
+		self
+	}
+
+	pub(crate) fn rate_limiter(&self) -> Option<Arc<RateLimiter>> {
+		self.rate_limiter.clone()
+	}
+
+	/// Stats of the rate limiter, or None if it's not enabled.
+	pub fn rate_limit_stats(&self) -> Option<RateLimitStats> {
+		self.rate_limiter.as_ref().map(|limiter| limiter.stats())
+	}
+
+	/// Enable the provider limits: the requests of all verifications
+	/// sharing the context to the providers' HTTP APIs and headless pages
+	/// wait, if needed, so that each provider gets at most its budget of
+	/// requests per `period`, and none during a cooldown once it throttles
+	/// us.
+	pub fn with_provider_limits(mut self, config: ProviderLimitConfig) -> Self {
+		self.provider_limiter = Some(Arc::new(ProviderLimiter::new(config)));
+		self
+	}
+
+	pub(crate) fn provider_limiter(&self) -> Option<Arc<ProviderLimiter>> {
+		self.provider_limiter.clone()
+	}
+
+	/// Stats of the provider limits, or None if they're not enabled.
+	pub fn provider_limit_stats(&self) -> Option<ProviderLimitStats> {
+		self.provider_limiter
+			.as_ref()
+			.map(|limiter| limiter.stats())
+	}
+
+	/// Enable the in-memory DNS cache: MX and other DNS lookups are shared
+	/// across verifications, as long as their records' TTL.
+	pub fn with_dns_cache(self) -> Self {
+		self.with_dns_cache_backend(Arc::new(TtlCache::<DnsCacheKey, Lookup>::default()))
+	}
+
+	/// Enable the DNS cache, with a custom storage.
+	pub fn with_dns_cache_backend(mut self, dns_cache: Arc<dyn DnsCache>) -> Self {
+		self.dns_cache = Some(dns_cache);
+		self
+	}
+
+	pub(crate) fn dns_cache(&self) -> Option<Arc<dyn DnsCache>> {
+		self.dns_cache.clone()
+	}
+
+	/// Hit/miss stats of the DNS cache, or None if it's not enabled.
+	pub fn dns_cache_stats(&self) -> Option<CacheStats> {
+		self.dns_cache.as_ref().map(|cache| cache.stats())
+	}
+
+	/// Forget all cached DNS lookups, if the cache is enabled.
+	pub fn clear_dns_cache(&self) {
+		if let Some(cache) = &self.dns_cache {
+			cache.clear()
+		}
+	}
+
+	/// Enrich the MX details with the ASN, network owner and country of the
+	/// MX hosts' IP addresses, using `source`.
+	pub fn with_ip_info_source(mut self, source: Arc<dyn IpInfoSource>) -> Self {
+		self.ip_info_source = Some(source);
+		self
+	}
+
+	pub(crate) fn ip_info_source(&self) -> Option<&dyn IpInfoSource> {
+		self.ip_info_source.as_deref()
+	}
+
+	/// Use a custom DNS resolver for all DNS lookups, instead of the input's
+	/// `dns_nameservers` or `encrypted_dns`. The DNS cache, if enabled, is
+	/// still used in front of it.
+	pub fn with_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+		self.resolver = Some(resolver);
+		self
+	}
+
+	pub(crate) fn resolver(&self) -> Option<Arc<dyn Resolver>> {
+		self.resolver.clone()
+	}
+
+	/// Decide whether to retry failed SMTP verifications with `policy`,
+	/// e.g. `ExponentialBackoff`, instead of the input's `retries` and
+	/// `greylisting_retry_delay`.
+	pub fn with_retry_policy(mut self, policy: Arc<dyn RetryPolicy>) -> Self {
+		self.retry_policy = Some(policy);
+		self
+	}
+
+	pub(crate) fn retry_policy(&self) -> Option<&dyn RetryPolicy> {
+		self.retry_policy.as_deref()
+	}
+
+	/// Flag the emails listed by `feed` as possible spamtraps, if the
+	/// input's `check_spamtraps` is set.
+	pub fn with_trap_feed(mut self, feed: Arc<dyn TrapFeed>) -> Self {
+		self.trap_feed = Some(feed);
+		self
+	}
+
+	pub(crate) fn trap_feed(&self) -> Option<Arc<dyn TrapFeed>> {
+		self.trap_feed.clone()
+	}
+
+	/// Detect the disposable domains with the list loaded from the config's
+	/// source, instead of the list bundled with the crate. The first
+	/// verification, then the first one after each `refresh_interval`,
+	/// start loading the list in a background task of the Tokio runtime,
+	/// without waiting for it. Until it's loaded, or if it can't be, the
+	/// bundled list is used.
+	pub fn with_disposable_list(mut self, config: DisposableListConfig) -> Self {
+		self.disposable_list = Some(Arc::new(DisposableListStore::new(config)));
+		self
+	}
+
+	pub(crate) fn disposable_list(&self) -> Option<Arc<DisposableList>> {
+		self.disposable_list.as_ref().and_then(|store| store.list())
+	}
+
+	pub(crate) fn refresh_stale_disposable_list(&self) {
+		if let Some(store) = &self.disposable_list {
+			store.refresh_if_stale();
+		}
+	}
+
+	/// Load the disposable domain list from its source now, e.g. at
+	/// startup. Returns the version of the new list, or None if
+	/// `with_disposable_list` wasn't called.
+	pub async fn refresh_disposable_list(&self) -> Option<Result<String, DisposableListError>> {
+		match &self.disposable_list {
+			Some(store) => Some(store.load().await),
+			None => None,
+		}
+	}
+
+	/// Ask `provider` whether the domains are disposable, and the usernames
+	/// role accounts, before the built-in lists.
+	pub fn with_list_provider(mut self, provider: Arc<dyn ListProvider>) -> Self {
+		self.list_provider = Some(provider);
+		self
+	}
+
+	pub(crate) fn list_provider(&self) -> Option<Arc<dyn ListProvider>> {
+		self.list_provider.clone()
+	}
+
+	/// The version of the loaded disposable domain list, i.e. the MD5
+	/// digest of its content, or None if the bundled list is in use.
+	pub fn disposable_list_version(&self) -> Option<String> {
+		self.disposable_list()
+			.map(|list| list.version().to_string())
+	}
+
+	/// Call `hook` with each raw response of the SMTP servers, e.g. to
+	/// extract provider-specific data into the `annotations` of the
+	/// `SmtpDetails`.
+	pub fn with_smtp_response_hook(mut self, hook: Arc<dyn SmtpResponseHook>) -> Self {
+		self.smtp_response_hook = Some(hook);
+		self
+	}
+
+	pub(crate) fn smtp_response_hook(&self) -> Option<Arc<dyn SmtpResponseHook>> {
+		self.smtp_response_hook.clone()
+	}
+
+	/// Verify the emails whose MX host or domain matches `pattern` with
+	/// `verifier`, before the built-in methods. The patterns have the same
+	/// syntax as the input's `skipped_domains`, and the first matching one
+	/// wins. The input's `provider_routes` can also refer to `verifier`,
+	/// with the "custom:<pattern>" method.
+	pub fn with_provider_verifier(
+		mut self,
+		pattern: HostPattern,
+		verifier: Arc<dyn ProviderVerifier>,
+	) -> Self {
+		self.provider_verifiers.push((pattern, verifier));
+		self
+	}
+
+	pub(crate) fn provider_verifiers(&self) -> &[(HostPattern, Arc<dyn ProviderVerifier>)] {
+		&self.provider_verifiers
+	}
+
+	/// Solve the CAPTCHAs and bot challenges the headless verifications
+	/// run into with `solver`, instead of failing with
+	/// `SmtpError::ChallengeRequired`.
+	pub fn with_challenge_solver(mut self, solver: Arc<dyn ChallengeSolver>) -> Self {
+		self.challenge_solver = Some(solver);
+		self
+	}
+
+	#[cfg(feature = "headless")]
+	pub(crate) fn challenge_solver(&self) -> Option<Arc<dyn ChallengeSolver>> {
+		self.challenge_solver.clone()
+	}
+
+	/// Enable the headless session pool: the WebDriver sessions of the
+	/// headless verifications are kept open and reused, at most
+	/// `max_sessions` of them in parallel per WebDriver endpoint, and the
+	/// verifications beyond that wait for a free session.
+	#[cfg(feature = "headless")]
+	pub fn with_headless_pool(mut self, config: HeadlessPoolConfig) -> Self {
+		self.headless_pool = Some(Arc::new(HeadlessPool::new(config)));
+		self
+	}
+
+	#[cfg(feature = "headless")]
+	pub(crate) fn headless_pool(&self) -> Option<Arc<HeadlessPool>> {
+		self.headless_pool.clone()
+	}
+
+	/// Stats of the headless session pool, or None if it's not enabled.
+	#[cfg(feature = "headless")]
+	pub fn headless_pool_stats(&self) -> Option<HeadlessPoolStats> {
+		self.headless_pool.as_ref().map(|pool| pool.stats())
+	}
+
+	/// Close all idle sessions of the headless pool, if enabled.
+	#[cfg(feature = "headless")]
+	pub async fn clear_headless_pool(&self) {
+		if let Some(pool) = &self.headless_pool {
+			pool.clear().await
+		}
+	}
+
+	/// Spawn a WebDriver server, e.g. chromedriver, when a headless
+	/// verification first needs it, and respawn it if it crashes. The
+	/// headless verifications use it when their WebDriver endpoint is
+	/// `MANAGED_WEBDRIVER`, e.g. `hotmail_use_headless: Some("managed")`.
+	/// The server is killed when the last clone of the context is dropped.
+	#[cfg(feature = "headless")]
+	pub fn with_managed_webdriver(mut self, config: ManagedWebDriverConfig) -> Self {
+		self.managed_webdriver = Some(Arc::new(ManagedWebDriver::new(config)));
+		self
+	}
+
+	#[cfg(feature = "headless")]
+	pub(crate) fn managed_webdriver(&self) -> Option<Arc<ManagedWebDriver>> {
+		self.managed_webdriver.clone()
+	}
+
+	/// Next value of the round-robin rotation counter.
+	pub(crate) fn next_rotation(&self) -> usize {
+		self.rotation.fetch_add(1, Ordering::Relaxed)
+	}
+}
@@ -0,0 +1,224 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DMARC (RFC 7489) policy lookup and parsing.
+
+use serde::{Deserialize, Serialize};
+
+/// What receivers should do with mail which fails the DMARC checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DmarcPolicy {
+	/// Monitoring only, no specific action.
+	None,
+	/// Treat the mail as suspicious, e.g. put it in the spam folder.
+	Quarantine,
+	/// Reject the mail.
+	Reject,
+}
+
+/// How strictly the domains of the DKIM signature or of the SPF check must
+/// match the `From` domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlignmentMode {
+	/// The organizational domains must match, e.g. `mail.example.org` and
+	/// `example.org`.
+	Relaxed,
+	/// The domains must be identical.
+	Strict,
+}
+
+/// Details about the domain's DMARC record, published at `_dmarc.<domain>`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DmarcDetails {
+	/// Does the domain publish a DMARC record?
+	pub has_dmarc: bool,
+	/// The raw DMARC record.
+	pub record: Option<String>,
+	/// The `p` tag: the policy for the domain.
+	pub policy: Option<DmarcPolicy>,
+	/// The `sp` tag: the policy for its subdomains, if different.
+	pub subdomain_policy: Option<DmarcPolicy>,
+	/// The `adkim` tag: the DKIM alignment mode. Relaxed if unset.
+	pub dkim_alignment: Option<AlignmentMode>,
+	/// The `aspf` tag: the SPF alignment mode. Relaxed if unset.
+	pub spf_alignment: Option<AlignmentMode>,
+	/// The `rua` tag: where to send aggregate reports.
+	pub rua: Vec<String>,
+	/// The `ruf` tag: where to send failure reports.
+	pub ruf: Vec<String>,
+	/// Syntax errors of the record, or the DNS error if the lookup failed.
+	pub errors: Vec<String>,
+}
+
+/// Is `txt` a DMARC record, i.e. does it start with the `v=DMARC1` tag?
+fn is_dmarc_record(txt: &str) -> bool {
+	txt.split(';')
+		.next()
+		.and_then(|tag| tag.split_once('='))
+		.is_some_and(|(name, value)| name.trim() == "v" && value.trim() == "DMARC1")
+}
+
+fn parse_policy(value: &str) -> Option<DmarcPolicy> {
+	match value.to_lowercase().as_str() {
+		"none" => Some(DmarcPolicy::None),
+		"quarantine" => Some(DmarcPolicy::Quarantine),
+		"reject" => Some(DmarcPolicy::Reject),
+		_ => None,
+	}
+}
+
+fn parse_alignment(value: &str) -> Option<AlignmentMode> {
+	match value.to_lowercase().as_str() {
+		"r" => Some(AlignmentMode::Relaxed),
+		"s" => Some(AlignmentMode::Strict),
+		_ => None,
+	}
+}
+
+/// Parse a comma-separated list of report URIs, e.g.
+/// `mailto:dmarc@example.org,mailto:reports@example.net`.
+fn parse_uris(value: &str) -> Vec<String> {
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|uri| !uri.is_empty())
+		.map(String::from)
+		.collect()
+}
+
+/// Parse a DMARC record, collecting its tags and its syntax errors.
+pub fn parse_dmarc(record: &str) -> DmarcDetails {
+	let mut details = DmarcDetails {
+		has_dmarc: true,
+		record: Some(record.to_string()),
+		..Default::default()
+	};
+
+	let mut has_policy_tag = false;
+	for tag in record.split(';').skip(1) {
+		let tag = tag.trim();
+		if tag.is_empty() {
+			continue;
+		}
+		let (name, value) = match tag.split_once('=') {
+			Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+			None => {
+				details.errors.push(format!("Invalid tag \"{tag}\""));
+				continue;
+			}
+		};
+
+		let valid = match name.as_str() {
+			"p" => {
+				has_policy_tag = true;
+				details.policy = parse_policy(value);
+				details.policy.is_some()
+			}
+			"sp" => {
+				details.subdomain_policy = parse_policy(value);
+				details.subdomain_policy.is_some()
+			}
+			"adkim" => {
+				details.dkim_alignment = parse_alignment(value);
+				details.dkim_alignment.is_some()
+			}
+			"aspf" => {
+				details.spf_alignment = parse_alignment(value);
+				details.spf_alignment.is_some()
+			}
+			"rua" => {
+				details.rua = parse_uris(value);
+				true
+			}
+			"ruf" => {
+				details.ruf = parse_uris(value);
+				true
+			}
+			// Other tags, e.g. "pct" or "fo", are not reported, and unknown
+			// tags must be ignored.
+			_ => true,
+		};
+		if !valid {
+			details
+				.errors
+				.push(format!("Invalid value \"{value}\" for tag \"{name}\""));
+		}
+	}
+
+	if !has_policy_tag {
+		details.errors.push("Missing required tag \"p\"".into());
+	}
+
+	details
+}
+
+/// Get the DMARC details from the TXT records of `_dmarc.<domain>`.
+pub(crate) fn dmarc_from_txt_records(txt_records: &[String]) -> DmarcDetails {
+	let records = txt_records
+		.iter()
+		.filter(|txt| is_dmarc_record(txt))
+		.collect::<Vec<_>>();
+
+	match records.as_slice() {
+		[] => DmarcDetails::default(),
+		[record] => parse_dmarc(record),
+		[record, ..] => {
+			let mut details = parse_dmarc(record);
+			details
+				.errors
+				.push("The domain publishes multiple DMARC records".into());
+			details
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_dmarc_records() {
+		let details = dmarc_from_txt_records(&[
+			"v=DMARC1; p=quarantine; sp=reject; adkim=s; rua=mailto:a@example.org, mailto:b@example.org".into(),
+		]);
+		assert_eq!(details.policy, Some(DmarcPolicy::Quarantine));
+		assert_eq!(details.subdomain_policy, Some(DmarcPolicy::Reject));
+		assert_eq!(details.dkim_alignment, Some(AlignmentMode::Strict));
+		assert_eq!(details.spf_alignment, None);
+		assert_eq!(
+			details.rua,
+			vec!["mailto:a@example.org", "mailto:b@example.org"]
+		);
+		assert!(details.errors.is_empty());
+
+		let details = parse_dmarc("v=DMARC1; p=block; aspf=x");
+		assert_eq!(
+			details.errors,
+			vec![
+				"Invalid value \"block\" for tag \"p\"",
+				"Invalid value \"x\" for tag \"aspf\"",
+			]
+		);
+		assert_eq!(
+			parse_dmarc("v=DMARC1; rua=mailto:a@example.org").errors,
+			vec!["Missing required tag \"p\""]
+		);
+
+		assert!(!dmarc_from_txt_records(&["v=spf1 -all".into()]).has_dmarc);
+	}
+}
@@ -0,0 +1,158 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Optional checks on the DNS records of the email's domain, other than its
+//! MX records.
+
+mod dmarc;
+mod mta_sts;
+mod spf;
+
+use serde::{Deserialize, Serialize};
+use trust_dns_resolver::error::ResolveErrorKind;
+
+use crate::util::{
+	constants::LOG_TARGET,
+	input_output::CheckEmailInput,
+	resolver::{create_resolver, DnsResolver},
+};
+pub use dmarc::{parse_dmarc, AlignmentMode, DmarcDetails, DmarcPolicy};
+pub use mta_sts::{parse_mta_sts_policy, MtaStsDetails, MtaStsMode, MtaStsPolicy, TlsRptDetails};
+pub use spf::{parse_spf, SpfDetails, SpfPolicy};
+
+/// Details about the DNS records of the email's domain. Each field is only
+/// present if the corresponding check is enabled in the input.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DnsDetails {
+	/// The domain's SPF record, only fetched if `check_spf` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub spf: Option<SpfDetails>,
+	/// The domain's DMARC policy, only fetched if `check_dmarc` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dmarc: Option<DmarcDetails>,
+	/// The domain's MTA-STS policy, only fetched if `check_mta_sts` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mta_sts: Option<MtaStsDetails>,
+	/// The domain's SMTP TLS reporting record, only fetched if
+	/// `check_mta_sts` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tls_rpt: Option<TlsRptDetails>,
+}
+
+impl DnsDetails {
+	/// Are all the DNS checks disabled in `input`?
+	pub(crate) fn is_disabled(input: &CheckEmailInput) -> bool {
+		!input.check_spf && !input.check_dmarc && !input.check_mta_sts
+	}
+}
+
+/// Get the TXT records of `name`. A domain without TXT records is not an
+/// error.
+async fn txt_records(resolver: &DnsResolver, name: &str) -> Result<Vec<String>, String> {
+	match resolver.txt_lookup(name).await {
+		Ok(records) => Ok(records),
+		Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(vec![]),
+		Err(err) => Err(err.to_string()),
+	}
+}
+
+async fn check_spf(resolver: &DnsResolver, domain: &str) -> SpfDetails {
+	match txt_records(resolver, domain).await {
+		Ok(records) => spf::spf_from_txt_records(&records),
+		Err(err) => SpfDetails {
+			errors: vec![err],
+			..Default::default()
+		},
+	}
+}
+
+/// Look up the DMARC record of `domain`. The organizational domain is not
+/// queried as a fallback.
+async fn check_dmarc(resolver: &DnsResolver, domain: &str) -> DmarcDetails {
+	match txt_records(resolver, &format!("_dmarc.{domain}")).await {
+		Ok(records) => dmarc::dmarc_from_txt_records(&records),
+		Err(err) => DmarcDetails {
+			errors: vec![err],
+			..Default::default()
+		},
+	}
+}
+
+/// Look up the MTA-STS record of `domain`, and fetch its policy file if the
+/// record exists.
+async fn check_mta_sts(resolver: &DnsResolver, domain: &str) -> MtaStsDetails {
+	let mut details = match txt_records(resolver, &format!("_mta-sts.{domain}")).await {
+		Ok(records) => mta_sts::mta_sts_from_txt_records(&records),
+		Err(err) => {
+			return MtaStsDetails {
+				errors: vec![err],
+				..Default::default()
+			}
+		}
+	};
+
+	if details.has_mta_sts {
+		match mta_sts::fetch_mta_sts_policy(domain).await {
+			Ok(policy) => {
+				details.enforces_tls = policy.mode == MtaStsMode::Enforce;
+				details.policy = Some(policy);
+			}
+			Err(err) => details.errors.push(err),
+		}
+	}
+
+	details
+}
+
+async fn check_tls_rpt(resolver: &DnsResolver, domain: &str) -> TlsRptDetails {
+	match txt_records(resolver, &format!("_smtp._tls.{domain}")).await {
+		Ok(records) => mta_sts::tls_rpt_from_txt_records(&records),
+		Err(err) => TlsRptDetails {
+			errors: vec![err],
+			..Default::default()
+		},
+	}
+}
+
+/// Run the DNS checks enabled in `input` on `domain`.
+pub async fn check_dns(domain: &str, input: &CheckEmailInput) -> DnsDetails {
+	let resolver = match create_resolver(input).await {
+		Ok(resolver) => resolver,
+		Err(err) => {
+			log::debug!(
+				target: LOG_TARGET,
+				"[email={}] Cannot create DNS resolver: {}",
+				input.to_email,
+				err
+			);
+			return DnsDetails::default();
+		}
+	};
+
+	let mut details = DnsDetails::default();
+	if input.check_spf {
+		details.spf = Some(check_spf(&resolver, domain).await);
+	}
+	if input.check_dmarc {
+		details.dmarc = Some(check_dmarc(&resolver, domain).await);
+	}
+	if input.check_mta_sts {
+		details.mta_sts = Some(check_mta_sts(&resolver, domain).await);
+		details.tls_rpt = Some(check_tls_rpt(&resolver, domain).await);
+	}
+
+	details
+}
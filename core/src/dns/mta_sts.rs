@@ -0,0 +1,251 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! MTA-STS (RFC 8461) and SMTP TLS reporting (RFC 8460) discovery.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Timeout to fetch the MTA-STS policy file.
+const POLICY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How sending servers should apply the MTA-STS policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MtaStsMode {
+	/// Mail must not be delivered to MX hosts which fail the TLS checks.
+	Enforce,
+	/// Failures are only reported, mail is delivered anyway.
+	Testing,
+	/// The domain doesn't have an active policy anymore.
+	None,
+}
+
+/// The MTA-STS policy file, served at
+/// `https://mta-sts.<domain>/.well-known/mta-sts.txt`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MtaStsPolicy {
+	pub mode: MtaStsMode,
+	/// The MX host patterns allowed to receive mail, e.g. `*.example.org`.
+	pub mx: Vec<String>,
+	/// How long senders may cache the policy, in seconds.
+	pub max_age: Option<u64>,
+}
+
+/// Details about the domain's MTA-STS policy.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MtaStsDetails {
+	/// Does the domain publish a `_mta-sts` TXT record?
+	pub has_mta_sts: bool,
+	/// The `id` of the TXT record, which changes with the policy.
+	pub id: Option<String>,
+	/// The policy file, only fetched if the TXT record exists.
+	pub policy: Option<MtaStsPolicy>,
+	/// Does the domain require TLS to receive mail, i.e. is the policy in
+	/// enforce mode?
+	pub enforces_tls: bool,
+	/// Errors while fetching or parsing the TXT record or the policy.
+	pub errors: Vec<String>,
+}
+
+/// Details about the domain's SMTP TLS reporting record, published at
+/// `_smtp._tls.<domain>`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TlsRptDetails {
+	/// Does the domain publish a TLS-RPT record?
+	pub has_tls_rpt: bool,
+	/// The raw TLS-RPT record.
+	pub record: Option<String>,
+	/// Where to send the TLS reports.
+	pub rua: Vec<String>,
+	/// Syntax errors of the record, or the DNS error if the lookup failed.
+	pub errors: Vec<String>,
+}
+
+/// Parse a `tag=value; ...` record into its tags, if its first tag is `v`
+/// with the `version` value.
+fn tags(record: &str, version: &str) -> Option<Vec<(String, String)>> {
+	let tags = record
+		.split(';')
+		.filter_map(|tag| tag.split_once('='))
+		.map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+		.collect::<Vec<_>>();
+
+	match tags.first() {
+		Some((name, value)) if name == "v" && value == version => Some(tags),
+		_ => None,
+	}
+}
+
+/// Get the MTA-STS details from the TXT records of `_mta-sts.<domain>`. The
+/// policy file is fetched separately.
+pub(crate) fn mta_sts_from_txt_records(txt_records: &[String]) -> MtaStsDetails {
+	let records = txt_records
+		.iter()
+		.filter_map(|txt| tags(txt, "STSv1"))
+		.collect::<Vec<_>>();
+
+	let mut details = MtaStsDetails::default();
+	match records.as_slice() {
+		[] => {}
+		[tags] => {
+			details.has_mta_sts = true;
+			details.id = tags
+				.iter()
+				.find(|(name, _)| name == "id")
+				.map(|(_, id)| id.clone());
+			if details.id.is_none() {
+				details.errors.push("Missing required tag \"id\"".into());
+			}
+		}
+		_ => {
+			details.has_mta_sts = true;
+			details
+				.errors
+				.push("The domain publishes multiple MTA-STS records".into());
+		}
+	}
+
+	details
+}
+
+/// Parse a MTA-STS policy file, made of `key: value` lines.
+pub fn parse_mta_sts_policy(policy: &str) -> Result<MtaStsPolicy, String> {
+	let mut version = None;
+	let mut mode = None;
+	let mut mx = Vec::new();
+	let mut max_age = None;
+
+	for line in policy.lines() {
+		let (key, value) = match line.split_once(':') {
+			Some((key, value)) => (key.trim(), value.trim()),
+			None => continue,
+		};
+		match key {
+			"version" => version = Some(value),
+			"mode" => {
+				mode = match value {
+					"enforce" => Some(MtaStsMode::Enforce),
+					"testing" => Some(MtaStsMode::Testing),
+					"none" => Some(MtaStsMode::None),
+					_ => return Err(format!("Invalid mode \"{value}\"")),
+				}
+			}
+			"mx" => mx.push(value.to_string()),
+			"max_age" => {
+				max_age = Some(
+					value
+						.parse()
+						.map_err(|_| format!("Invalid max_age \"{value}\""))?,
+				)
+			}
+			_ => {}
+		}
+	}
+
+	if version != Some("STSv1") {
+		return Err("Missing or invalid version, expected \"STSv1\"".into());
+	}
+
+	Ok(MtaStsPolicy {
+		mode: mode.ok_or("Missing required field \"mode\"")?,
+		mx,
+		max_age,
+	})
+}
+
+/// Fetch the MTA-STS policy file of `domain`.
+pub(crate) async fn fetch_mta_sts_policy(domain: &str) -> Result<MtaStsPolicy, String> {
+	let url = format!("https://mta-sts.{domain}/.well-known/mta-sts.txt");
+
+	let policy = reqwest::Client::new()
+		.get(&url)
+		.timeout(POLICY_TIMEOUT)
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|err| format!("Cannot fetch MTA-STS policy: {err}"))?
+		.text()
+		.await
+		.map_err(|err| format!("Cannot fetch MTA-STS policy: {err}"))?;
+
+	parse_mta_sts_policy(&policy)
+}
+
+/// Get the TLS-RPT details from the TXT records of `_smtp._tls.<domain>`.
+pub(crate) fn tls_rpt_from_txt_records(txt_records: &[String]) -> TlsRptDetails {
+	let records = txt_records
+		.iter()
+		.filter_map(|txt| tags(txt, "TLSRPTv1").map(|tags| (txt, tags)))
+		.collect::<Vec<_>>();
+
+	let mut details = TlsRptDetails::default();
+	if let Some((record, tags)) = records.first() {
+		details.has_tls_rpt = true;
+		details.record = Some(record.to_string());
+		details.rua = tags
+			.iter()
+			.find(|(name, _)| name == "rua")
+			.map(|(_, rua)| rua.split(',').map(|uri| uri.trim().to_string()).collect())
+			.unwrap_or_default();
+		if details.rua.is_empty() {
+			details.errors.push("Missing required tag \"rua\"".into());
+		}
+	}
+	if records.len() > 1 {
+		details
+			.errors
+			.push("The domain publishes multiple TLS-RPT records".into());
+	}
+
+	details
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_mta_sts_and_tls_rpt() {
+		let details = mta_sts_from_txt_records(&["v=STSv1; id=20190429T010101;".into()]);
+		assert!(details.has_mta_sts);
+		assert_eq!(details.id.as_deref(), Some("20190429T010101"));
+
+		let policy = parse_mta_sts_policy(
+			"version: STSv1\r\nmode: enforce\r\nmx: mail.example.org\r\nmx: *.example.net\r\nmax_age: 86400\r\n",
+		)
+		.unwrap();
+		assert_eq!(
+			policy,
+			MtaStsPolicy {
+				mode: MtaStsMode::Enforce,
+				mx: vec!["mail.example.org".into(), "*.example.net".into()],
+				max_age: Some(86400),
+			}
+		);
+		assert!(parse_mta_sts_policy("version: STSv1\nmode: strict\n").is_err());
+
+		let details = tls_rpt_from_txt_records(&[
+			"v=TLSRPTv1; rua=mailto:tlsrpt@example.org,https://example.org/tlsrpt".into(),
+		]);
+		assert!(details.has_tls_rpt);
+		assert_eq!(
+			details.rua,
+			vec!["mailto:tlsrpt@example.org", "https://example.org/tlsrpt"]
+		);
+	}
+}
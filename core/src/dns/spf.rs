@@ -0,0 +1,195 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SPF (RFC 7208) record lookup and parsing.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+
+/// The result of the `all` mechanism of a SPF record, i.e. what to do with
+/// mail from hosts which don't match any other mechanism.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpfPolicy {
+	/// `+all`: any host is allowed to send mail.
+	Pass,
+	/// `-all`: only the listed hosts are allowed to send mail.
+	Fail,
+	/// `~all`: mail from other hosts is probably not legitimate.
+	SoftFail,
+	/// `?all`: no assertion is made about other hosts.
+	Neutral,
+}
+
+/// Details about the domain's SPF record.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SpfDetails {
+	/// Does the domain publish a SPF record?
+	pub has_spf: bool,
+	/// The raw SPF record.
+	pub record: Option<String>,
+	/// The policy of the `all` mechanism, if the record has one.
+	pub policy: Option<SpfPolicy>,
+	/// Syntax errors of the record, or the DNS error if the lookup failed.
+	pub errors: Vec<String>,
+}
+
+const MECHANISMS: &[&str] = &["all", "include", "a", "mx", "ptr", "ip4", "ip6", "exists"];
+
+/// Is `txt` a SPF record, i.e. does it start with the `v=spf1` version?
+fn is_spf_record(txt: &str) -> bool {
+	let txt = txt.trim_start();
+
+	txt.get(..6)
+		.is_some_and(|version| version.eq_ignore_ascii_case("v=spf1"))
+		&& txt[6..].chars().next().is_none_or(|c| c == ' ')
+}
+
+/// Check the syntax of a single mechanism, without its qualifier. Returns
+/// the error message if it's invalid.
+fn check_mechanism(name: &str, argument: Option<&str>) -> Option<String> {
+	let missing = || Some(format!("Mechanism \"{name}\" requires an argument"));
+	let (address, prefix) = match argument {
+		Some(argument) => match argument.split_once('/') {
+			Some((address, prefix)) => (address, Some(prefix)),
+			None => (argument, None),
+		},
+		None => ("", None),
+	};
+
+	match name {
+		"all" if argument.is_some() => Some("Mechanism \"all\" takes no argument".into()),
+		"include" | "exists" if argument.is_none_or(str::is_empty) => missing(),
+		"ip4" | "ip6" if argument.is_none() => missing(),
+		"ip4"
+			if address.parse::<Ipv4Addr>().is_err()
+				|| prefix.is_some_and(|p| p.parse::<u8>().map_or(true, |p| p > 32)) =>
+		{
+			Some(format!(
+				"Invalid IPv4 network \"{}\"",
+				argument.unwrap_or_default()
+			))
+		}
+		"ip6"
+			if address.parse::<Ipv6Addr>().is_err()
+				|| prefix.is_some_and(|p| p.parse::<u8>().map_or(true, |p| p > 128)) =>
+		{
+			Some(format!(
+				"Invalid IPv6 network \"{}\"",
+				argument.unwrap_or_default()
+			))
+		}
+		_ if !MECHANISMS.contains(&name) => Some(format!("Unknown mechanism \"{name}\"")),
+		_ => None,
+	}
+}
+
+/// Parse a SPF record, collecting its `all` policy and its syntax errors.
+pub fn parse_spf(record: &str) -> SpfDetails {
+	let mut details = SpfDetails {
+		has_spf: true,
+		record: Some(record.to_string()),
+		..Default::default()
+	};
+
+	for term in record.split_whitespace().skip(1) {
+		// Modifiers, e.g. "redirect=example.org". Unknown modifiers must be
+		// ignored.
+		if let Some((name, _)) = term.split_once('=') {
+			if !name.is_empty() && !name.contains(':') {
+				continue;
+			}
+		}
+
+		let (qualifier, mechanism) = match term.chars().next() {
+			Some('+') => (SpfPolicy::Pass, &term[1..]),
+			Some('-') => (SpfPolicy::Fail, &term[1..]),
+			Some('~') => (SpfPolicy::SoftFail, &term[1..]),
+			Some('?') => (SpfPolicy::Neutral, &term[1..]),
+			_ => (SpfPolicy::Pass, term),
+		};
+		let (name, argument) = match mechanism.find([':', '/']) {
+			Some(index) if mechanism[index..].starts_with(':') => {
+				(&mechanism[..index], Some(&mechanism[index + 1..]))
+			}
+			Some(index) => (&mechanism[..index], Some(&mechanism[index..])),
+			None => (mechanism, None),
+		};
+		let name = name.to_lowercase();
+
+		// "a" and "mx" accept a domain and/or a CIDR length, which we don't
+		// validate further.
+		if let Some(error) =
+			check_mechanism(&name, argument.filter(|_| name != "a" && name != "mx"))
+		{
+			details.errors.push(error);
+		} else if name == "all" {
+			details.policy = Some(qualifier);
+		}
+	}
+
+	details
+}
+
+/// Get the SPF details from the TXT records of a domain.
+pub(crate) fn spf_from_txt_records(txt_records: &[String]) -> SpfDetails {
+	let records = txt_records
+		.iter()
+		.filter(|txt| is_spf_record(txt))
+		.collect::<Vec<_>>();
+
+	match records.as_slice() {
+		[] => SpfDetails::default(),
+		[record] => parse_spf(record),
+		[record, ..] => {
+			let mut details = parse_spf(record);
+			details
+				.errors
+				.push("The domain publishes multiple SPF records".into());
+			details
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_spf_records() {
+		let details = spf_from_txt_records(&[
+			"google-site-verification=abc".into(),
+			"v=spf1 ip4:192.0.2.0/24 include:_spf.example.org mx ~all".into(),
+		]);
+		assert!(details.has_spf);
+		assert_eq!(details.policy, Some(SpfPolicy::SoftFail));
+		assert!(details.errors.is_empty());
+
+		let details = parse_spf("v=spf1 ip4:192.0.2.300 include foo:bar redirect=example.org -all");
+		assert_eq!(details.policy, Some(SpfPolicy::Fail));
+		assert_eq!(
+			details.errors,
+			vec![
+				"Invalid IPv4 network \"192.0.2.300\"",
+				"Mechanism \"include\" requires an argument",
+				"Unknown mechanism \"foo\"",
+			]
+		);
+
+		assert!(!spf_from_txt_records(&["v=spf10".into()]).has_spf);
+	}
+}
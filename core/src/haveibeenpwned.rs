@@ -16,12 +16,58 @@
 
 use crate::util::constants::LOG_TARGET;
 use pwned::api::PwnedBuilder;
+use serde::{Deserialize, Serialize};
 
-/// Check if the email has been found in any breach or paste using the
-/// HaveIBeenPwned API.
-/// This function will return the number of times the email has been found in
-/// any breach.
-pub async fn check_haveibeenpwned(to_email: &str, api_key: Option<String>) -> Option<bool> {
+/// The breaches an email address was found in by HaveIBeenPwned.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HaveIBeenPwnedDetails {
+	/// The number of breaches the email address was found in.
+	pub breach_count: usize,
+	pub breaches: Vec<BreachDetails>,
+}
+
+/// A breach an email address was found in.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BreachDetails {
+	/// The breach's identifier on HaveIBeenPwned, e.g. "Adobe".
+	pub name: String,
+	pub title: String,
+	/// The domain of the breached website.
+	pub domain: String,
+	/// The date of the breach, as YYYY-MM-DD.
+	pub breach_date: String,
+	/// When the breach was added to HaveIBeenPwned, in ISO 8601.
+	pub added_date: String,
+	/// The number of accounts in the breach.
+	pub pwn_count: u64,
+	/// The kinds of data exposed, e.g. "Email addresses" or "Passwords".
+	pub data_classes: Vec<String>,
+	/// Was the breach confirmed to be legitimate?
+	pub is_verified: bool,
+}
+
+/// The details of breaches, keeping only the verified ones if
+/// `verified_only` is set.
+fn breach_details(breaches: Vec<BreachDetails>, verified_only: bool) -> HaveIBeenPwnedDetails {
+	let breaches = breaches
+		.into_iter()
+		.filter(|breach| breach.is_verified || !verified_only)
+		.collect::<Vec<_>>();
+
+	HaveIBeenPwnedDetails {
+		breach_count: breaches.len(),
+		breaches,
+	}
+}
+
+/// Check if the email has been found in any breach using the HaveIBeenPwned
+/// API, keeping only the verified breaches if `verified_only` is set.
+/// Returns None if the API can't be reached.
+pub async fn check_haveibeenpwned(
+	to_email: &str,
+	api_key: Option<String>,
+	verified_only: bool,
+) -> Option<HaveIBeenPwnedDetails> {
 	let pwned = PwnedBuilder::default()
 		.user_agent("reacher")
 		.api_key(api_key)
@@ -35,7 +81,21 @@ pub async fn check_haveibeenpwned(to_email: &str, api_key: Option<String>) -> Op
 				"Email found in {} breaches",
 				answer.len()
 			);
-			Some(!answer.is_empty())
+			// The pwned crate doesn't export its breach type.
+			let breaches = answer
+				.into_iter()
+				.map(|breach| BreachDetails {
+					name: breach.name,
+					title: breach.title,
+					domain: breach.domain,
+					breach_date: breach.breach_date,
+					added_date: breach.added_date,
+					pwn_count: breach.pwn_count,
+					data_classes: breach.data_classes,
+					is_verified: breach.is_verified,
+				})
+				.collect();
+			Some(breach_details(breaches, verified_only))
 		}
 		Err(e) => {
 			log::error!(
@@ -45,7 +105,7 @@ pub async fn check_haveibeenpwned(to_email: &str, api_key: Option<String>) -> Op
 			);
 			match e {
 				pwned::errors::Error::IoError(e) => match e.kind() {
-					std::io::ErrorKind::NotFound => Some(false),
+					std::io::ErrorKind::NotFound => Some(HaveIBeenPwnedDetails::default()),
 					_ => None,
 				},
 				_ => None,
@@ -53,3 +113,34 @@ pub async fn check_haveibeenpwned(to_email: &str, api_key: Option<String>) -> Op
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn breach(name: &str, is_verified: bool) -> BreachDetails {
+		BreachDetails {
+			name: name.into(),
+			title: name.into(),
+			domain: format!("{}.com", name.to_lowercase()),
+			breach_date: "2013-10-04".into(),
+			added_date: "2013-12-04T00:00:00Z".into(),
+			pwn_count: 152445165,
+			data_classes: vec!["Email addresses".into(), "Passwords".into()],
+			is_verified,
+		}
+	}
+
+	#[test]
+	fn should_keep_verified_breaches() {
+		let breaches = vec![breach("Adobe", true), breach("Unverified", false)];
+
+		let details = breach_details(breaches.clone(), false);
+		assert_eq!(details.breach_count, 2);
+		assert_eq!(details.breaches[0].domain, "adobe.com");
+
+		let details = breach_details(breaches, true);
+		assert_eq!(details.breach_count, 1);
+		assert_eq!(details.breaches[0].name, "Adobe");
+	}
+}
@@ -23,18 +23,18 @@
 //! - Email deliverability: Is an email sent to this address deliverable?
 //! - Syntax validation. Is the address syntactically valid?
 //! - DNS records validation. Does the domain of the email address have valid
-//! MX DNS records?
+//!   MX DNS records?
 //! - Disposable email address (DEA) validation. Is the address provided by a
-//! known disposable email address provider?
+//!   known disposable email address provider?
 //! - SMTP server validation. Can the mail exchanger of the email address
-//! domain be contacted successfully?
+//!   domain be contacted successfully?
 //! - Mailbox disabled. Has this email address been disabled by the email
-//! provider?
+//!   provider?
 //! - Full inbox. Is the inbox of this mailbox full?
 //! - Catch-all address. Is this email address a catch-all address?
 //!
 //! ```rust
-//! use check_if_email_exists::{check_email, CheckEmailInput, CheckEmailInputProxy};
+//! use check_if_email_exists::{check_email, CheckEmailInput, CheckEmailInputProxy, ProxyKind};
 //!
 //! async fn check() {
 //!     // Let's say we want to test the deliverability of someone@gmail.com.
@@ -50,7 +50,8 @@
 //!             host: "my-proxy.io".into(),
 //!             port: 1080,
 //!             username: None,                      // You can also set it non-empty
-//!             password: None
+//!             password: None,
+//!             kind: ProxyKind::Socks5              // Or Socks4, HttpConnect
 //!     });
 //!
 //!     // Verify this input, using async/await syntax.
@@ -62,33 +63,80 @@
 //! }
 //! ```
 
+mod batch;
+pub mod confidence;
+mod context;
+pub mod dns;
 mod haveibeenpwned;
 pub mod misc;
 pub mod mx;
+pub mod provider;
+pub mod rdap;
+pub mod rules;
 pub mod smtp;
 pub mod syntax;
 mod util;
 
-use misc::{check_misc, MiscDetails};
-use mx::{check_mx, is_antispam_mx};
+use async_std::future::{self, TimeoutError};
+pub use batch::check_email_batch;
+use confidence::calculate_confidence_with_weights;
+pub use context::CheckEmailContext;
+use dns::{check_dns, DnsDetails};
+use misc::{check_misc_with_disposable, trap_signals, MiscDetails, RoleSeverity};
+use mx::{check_mx, host_to_string, is_antispam_mx, MxDetails, MxProvider};
+use provider::calculate_provider_category;
 use rand::Rng;
-use smtp::{check_smtp, SmtpDetails, SmtpError};
-use syntax::{check_syntax, get_similar_mail_provider};
+use rdap::check_rdap;
+use rules::apply_reachability_rules;
+use smtp::{
+	catch_all_key, check_smtp, with_smtp_deadline, SmtpDetails, SmtpError, VerificationMethod,
+};
+use std::iter;
+use std::time::Instant;
+pub use syntax::{check_syntax, check_syntax_batch, parse_email_like};
+use syntax::{
+	check_syntax_with_input, check_syntax_with_list_provider, get_similar_mail_provider_with_input,
+	SyntaxDetails,
+};
 use trust_dns_proto::rr::rdata::MX;
 pub use util::constants::LOG_TARGET;
+pub use util::dns_cache::{DnsCache, DnsCacheKey};
+use util::domain_policy::is_domain_blocked;
+pub use util::domain_policy::HostPattern;
+pub use util::encrypted_dns::EncryptedDns;
 pub use util::input_output::*;
+pub use util::ip_info::{Ip2AsnTable, IpInfo, IpInfoSource};
+pub use util::resolver::Resolver;
+pub use util::ttl_cache::CacheStats;
 
 /// Given an email's misc and smtp details, calculate an estimate of our
 /// confidence on how reachable the email is.
 ///
 /// Maybe we can switch to a points-based system?
 /// ref: https://github.com/reacherhq/check-if-email-exists/issues/935
-fn calculate_reachable(misc: &MiscDetails, smtp: &Result<SmtpDetails, SmtpError>) -> Reachable {
+fn calculate_reachable(
+	input: &CheckEmailInput,
+	misc: &MiscDetails,
+	smtp: &Result<SmtpDetails, SmtpError>,
+) -> Reachable {
+	if misc.role_account_severity == Some(RoleSeverity::NeverSend) {
+		return Reachable::Invalid;
+	}
+
+	if matches!(smtp, Err(SmtpError::SkippedDomain(_))) && input.skipped_domains_are_risky {
+		return Reachable::Risky;
+	}
+
 	if let Ok(smtp) = smtp {
 		if misc.is_disposable || misc.is_role_account || smtp.is_catch_all || smtp.has_full_inbox {
 			return Reachable::Risky;
 		}
 
+		// The mailbox wasn't probed.
+		if input.smtp_verification_mode != SmtpVerificationMode::Mailbox {
+			return Reachable::Unknown;
+		}
+
 		if !smtp.is_deliverable || !smtp.can_connect_smtp || smtp.is_disabled {
 			return Reachable::Invalid;
 		}
@@ -110,7 +158,111 @@ fn calculate_reachable(misc: &MiscDetails, smtp: &Result<SmtpDetails, SmtpError>
 /// Returns a `CheckEmailOutput` output, whose `is_reachable` field is one of
 /// `Safe`, `Invalid`, `Risky` or `Unknown`.
 pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
+	match input.total_timeout {
+		Some(total_timeout) => future::timeout(total_timeout, Box::pin(check_email_untimed(input)))
+			.await
+			.unwrap_or_else(|err| timed_out_output(input, err)),
+		None => Box::pin(check_email_untimed(input)).await,
+	}
+}
+
+/// The output of a verification which exceeded the input's `total_timeout`.
+fn timed_out_output(input: &CheckEmailInput, err: TimeoutError) -> CheckEmailOutput {
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Verification exceeded the total timeout of {:?}",
+		input.to_email,
+		input.total_timeout
+	);
+
+	CheckEmailOutput {
+		input: input.to_email.clone(),
+		is_reachable: Reachable::Unknown,
+		smtp: Err(SmtpError::TimeoutError(err)),
+		syntax: check_syntax_with_input(&input.to_email, input),
+		..Default::default()
+	}
+}
+
+/// `check_email`, without the input's `total_timeout`.
+async fn check_email_untimed(input: &CheckEmailInput) -> CheckEmailOutput {
+	let extracted_email = if input.extract_email {
+		parse_email_like(&input.to_email)
+	} else {
+		None
+	};
+	let mut output = match extracted_email {
+		Some(email) if email != input.to_email => {
+			let mut extracted_input = input.clone();
+			extracted_input.to_email = email;
+
+			let mut output = verify_email(&extracted_input).await;
+			output.input = input.to_email.clone();
+			output
+		}
+		_ => verify_email(input).await,
+	};
+	enrich_output(&mut output, input).await;
+
+	output
+}
+
+/// Add the optional details enabled in `input` to the verification's output.
+pub(crate) async fn enrich_output(output: &mut CheckEmailOutput, input: &CheckEmailInput) {
+	let is_verified_domain = output.syntax.is_valid_syntax
+		&& output.syntax.ip_literal.is_none()
+		&& output.is_reachable != Reachable::Blocked;
+	if is_verified_domain && !DnsDetails::is_disabled(input) {
+		output.dns = Some(check_dns(&output.syntax.domain, input).await);
+	}
+	if is_verified_domain && input.check_rdap {
+		output.rdap = Some(check_rdap(&output.syntax.domain).await);
+	}
+	if is_verified_domain && input.check_spamtraps {
+		let signals = trap_signals(output, input).await;
+		if let Ok(misc) = &mut output.misc {
+			misc.possible_trap = !signals.is_empty();
+			misc.trap_signals = signals;
+		}
+	}
+	if input.compute_confidence {
+		output.confidence = Some(calculate_confidence_with_weights(
+			output,
+			&input.confidence_weights,
+		));
+	}
+	if input.check_provider_category {
+		output.provider_category = Some(calculate_provider_category(
+			output,
+			input.free_provider_domains.as_deref(),
+		));
+	}
+	apply_reachability_rules(output, &input.reachability_rules);
+}
+
+/// Run all the verification steps of `check_email`.
+async fn verify_email(input: &CheckEmailInput) -> CheckEmailOutput {
+	match verify_email_until_smtp(input).await {
+		Ok(pre_smtp) => Box::pin(verify_smtp(input, pre_smtp)).await,
+		Err(output) => output,
+	}
+}
+
+/// The details gathered before the SMTP verification.
+pub(crate) struct PreSmtp {
+	pub(crate) syntax: SyntaxDetails,
+	pub(crate) mx: MxDetails,
+	pub(crate) misc: MiscDetails,
+}
+
+/// Run the verification steps of `check_email` which come before the SMTP
+/// verification. Returns the final output instead if the email can't be
+/// verified over SMTP, e.g. because its syntax is invalid.
+pub(crate) async fn verify_email_until_smtp(
+	input: &CheckEmailInput,
+) -> Result<PreSmtp, CheckEmailOutput> {
 	let to_email = &input.to_email;
+	input.context.refresh_stale_disposable_list();
 
 	log::debug!(
 		target: LOG_TARGET,
@@ -118,14 +270,36 @@ pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
 		to_email,
 		to_email
 	);
-	let mut my_syntax = check_syntax(to_email.as_ref());
+	// The list provider is only asked once whether the domain is disposable.
+	let (mut my_syntax, provider_disposable) = check_syntax_with_list_provider(
+		to_email.as_ref(),
+		input,
+		input.context.list_provider().as_deref(),
+	)
+	.await;
 	if !my_syntax.is_valid_syntax {
-		return CheckEmailOutput {
+		return Err(CheckEmailOutput {
 			input: to_email.to_string(),
 			is_reachable: Reachable::Invalid,
 			syntax: my_syntax,
 			..Default::default()
-		};
+		});
+	}
+
+	if is_domain_blocked(&my_syntax.domain, input) {
+		log::debug!(
+			target: LOG_TARGET,
+			"[email={}] Domain {} is blocked, skipping verification",
+			to_email,
+			my_syntax.domain
+		);
+
+		return Err(CheckEmailOutput {
+			input: to_email.to_string(),
+			is_reachable: Reachable::Blocked,
+			syntax: my_syntax,
+			..Default::default()
+		});
 	}
 
 	log::debug!(
@@ -135,34 +309,46 @@ pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
 		my_syntax
 	);
 
-	let my_mx = match check_mx(&my_syntax).await {
+	let my_mx = match check_mx(&my_syntax, input).await {
 		Ok(m) => m,
+		Err(err) if err.is_invalid_domain() => {
+			get_similar_mail_provider_with_input(&mut my_syntax, input);
+
+			return Err(CheckEmailOutput {
+				input: to_email.to_string(),
+				is_reachable: Reachable::Invalid,
+				mx: Err(err),
+				syntax: my_syntax,
+				..Default::default()
+			});
+		}
 		e => {
-			get_similar_mail_provider(&mut my_syntax);
+			get_similar_mail_provider_with_input(&mut my_syntax, input);
 
-			// This happens when there's an internal error while checking MX
-			// records. Should happen fairly rarely.
-			return CheckEmailOutput {
+			// This happens when the DNS servers fail to answer, or when
+			// there's an internal error while checking MX records. The MX
+			// error tells whether it's worth retrying later.
+			return Err(CheckEmailOutput {
 				input: to_email.to_string(),
 				is_reachable: Reachable::Unknown,
 				mx: e,
 				syntax: my_syntax,
 				..Default::default()
-			};
+			});
 		}
 	};
 
 	// Return if we didn't find any MX records.
 	if my_mx.lookup.is_err() {
-		get_similar_mail_provider(&mut my_syntax);
+		get_similar_mail_provider_with_input(&mut my_syntax, input);
 
-		return CheckEmailOutput {
+		return Err(CheckEmailOutput {
 			input: to_email.to_string(),
 			is_reachable: Reachable::Invalid,
 			mx: Ok(my_mx),
 			syntax: my_syntax,
 			..Default::default()
-		};
+		});
 	}
 
 	log::debug!(
@@ -178,11 +364,12 @@ pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
 			.collect::<Vec<String>>()
 	);
 
-	let my_misc = check_misc(
+	// Boxed, as the misc checks' future is large.
+	let my_misc = Box::pin(check_misc_with_disposable(
 		&my_syntax,
-		input.check_gravatar,
-		input.haveibeenpwned_api_key.clone(),
-	)
+		input,
+		provider_disposable,
+	))
 	.await;
 	log::debug!(
 		target: LOG_TARGET,
@@ -191,14 +378,25 @@ pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
 		my_misc
 	);
 
-	// From the list of MX records, we only choose one: we don't choose the
-	// first or last ones, because some domains put dummy MX records at the
-	// beginning or end of the list (sorted by priority). Instead, we choose a
-	// random one in the middle of the list.
-	//
-	// If anyone has a better algorithm, let me know by creating an issue on
-	// Github.
-	// ref: https://github.com/reacherhq/check-if-email-exists/issues/1049
+	Ok(PreSmtp {
+		syntax: my_syntax,
+		mx: my_mx,
+		misc: my_misc,
+	})
+}
+
+/// The MX hosts to try the SMTP verification on, in order, each with the
+/// ports to try.
+///
+/// Unless `try_all_mx_hosts` is set, we only choose one MX host from the
+/// list: we don't choose the first or last ones, because some domains put
+/// dummy MX records at the beginning or end of the list (sorted by
+/// priority). Instead, we choose a random one in the middle of the list.
+///
+/// If anyone has a better algorithm, let me know by creating an issue on
+/// Github.
+/// ref: https://github.com/reacherhq/check-if-email-exists/issues/1049
+pub(crate) fn smtp_attempts(my_mx: &MxDetails, input: &CheckEmailInput) -> Vec<(MX, u16)> {
 	let mut mx_records = my_mx
 		.lookup
 		.as_ref()
@@ -207,36 +405,313 @@ pub async fn check_email(input: &CheckEmailInput) -> CheckEmailOutput {
 		.filter(|host| !is_antispam_mx(host.exchange()))
 		.collect::<Vec<&MX>>();
 	mx_records.sort_by_key(|a| a.preference());
-	let host = if mx_records.len() >= 3 {
+	let mx_records = if input.try_all_mx_hosts {
+		mx_records
+	} else if mx_records.len() >= 3 {
 		let mut rng = rand::thread_rng();
 		let index = rng.gen_range(1..mx_records.len() - 1);
-		mx_records[index]
+		vec![mx_records[index]]
 	} else {
-		mx_records[mx_records.len() - 1]
+		vec![mx_records[mx_records.len() - 1]]
 	};
 
-	let my_smtp = check_smtp(
-		my_syntax
-			.address
+	let ports = iter::once(input.smtp_port)
+		.chain(input.smtp_fallback_ports.iter().copied())
+		.collect::<Vec<_>>();
+	mx_records
+		.into_iter()
+		.flat_map(|host| ports.iter().map(move |port| (host.clone(), *port)))
+		.collect()
+}
+
+/// Run the SMTP verification, falling through to the next port, then to the
+/// next MX host, if one can't be reached.
+async fn verify_smtp(input: &CheckEmailInput, pre_smtp: PreSmtp) -> CheckEmailOutput {
+	let input = &*input.for_domain(pre_smtp.syntax.domain.as_ref());
+	let mut attempts = smtp_attempts(&pre_smtp.mx, input).into_iter().peekable();
+	let start = Instant::now();
+	let (host, my_smtp) = loop {
+		let (host, port) = attempts
+			.next()
+			.expect("There's at least one MX host to try. qed.");
+		let my_smtp = with_smtp_deadline(
+			input,
+			start,
+			check_smtp(
+				pre_smtp
+					.syntax
+					.address
+					.as_ref()
+					.expect("We already checked that the email has valid format. qed."),
+				host.exchange(),
+				port,
+				pre_smtp.syntax.domain.as_ref(),
+				&pre_smtp.mx,
+				input,
+			),
+		)
+		.await;
+
+		match &my_smtp {
+			Err(err) if err.is_host_error() && attempts.peek().is_some() => {
+				log::debug!(
+					target: LOG_TARGET,
+					"[email={}] Cannot verify on [host={}:{}], trying the next port or MX host: {:?}",
+					input.to_email,
+					host.exchange(),
+					port,
+					err
+				);
+			}
+			_ => break (host, my_smtp),
+		}
+	};
+
+	smtp_output(input, pre_smtp, &host, my_smtp)
+}
+
+/// Build the output of the verification, once `host` answered the SMTP
+/// verification.
+pub(crate) fn smtp_output(
+	input: &CheckEmailInput,
+	pre_smtp: PreSmtp,
+	host: &MX,
+	mut my_smtp: Result<SmtpDetails, SmtpError>,
+) -> CheckEmailOutput {
+	let PreSmtp {
+		syntax: mut my_syntax,
+		mx: my_mx,
+		misc: my_misc,
+	} = pre_smtp;
+
+	// Remember the catch-all determination for as long as the MX record is
+	// valid, so that next verifications on this domain can skip it. Only the
+	// SMTP sessions whose probes all got an answer determine it: not the
+	// connectivity-only modes, the providers' HTTP APIs and headless
+	// browsers, nor the sessions which reused a cached determination.
+	if let Ok(smtp_details) = &mut my_smtp {
+		smtp_details
+			.mx_host
+			.get_or_insert_with(|| host_to_string(host.exchange()));
+		let has_probed = smtp_details
+			.catch_all_probes
 			.as_ref()
-			.expect("We already checked that the email has valid format. qed."),
-		host.exchange(),
-		input.smtp_port,
-		my_syntax.domain.as_ref(),
-		input,
-	)
-	.await;
+			.is_some_and(|probes| !probes.errored);
+		if has_probed {
+			input.context.catch_all_cache().insert(
+				catch_all_key(my_syntax.domain.as_ref(), host.exchange()),
+				smtp_details.is_catch_all,
+				my_mx
+					.lookup
+					.as_ref()
+					.expect("If lookup is error, we already returned. qed.")
+					.valid_until(),
+			);
+		}
+	}
+
+	if let (Ok(smtp_details), Some(provider)) = (&mut my_smtp, my_mx.provider) {
+		flag_security_gateway(input, provider, smtp_details);
+	}
 
 	if my_smtp.is_err() {
-		get_similar_mail_provider(&mut my_syntax);
+		get_similar_mail_provider_with_input(&mut my_syntax, input);
 	}
 
 	CheckEmailOutput {
-		input: to_email.to_string(),
-		is_reachable: calculate_reachable(&my_misc, &my_smtp),
+		input: input.to_email.to_string(),
+		is_reachable: calculate_reachable(input, &my_misc, &my_smtp),
 		misc: Ok(my_misc),
 		mx: Ok(my_mx),
 		smtp: my_smtp,
 		syntax: my_syntax,
+		..Default::default()
+	}
+}
+
+/// Report the email security gateway hosting the MX servers, if provider
+/// is one, and downgrade the deliverable verdicts of a regular SMTP session
+/// to catch-all if the input's `security_gateways_are_risky` is set. The
+/// verdicts of the providers' HTTP APIs and headless browsers don't go
+/// through the gateway.
+fn flag_security_gateway(
+	input: &CheckEmailInput,
+	provider: MxProvider,
+	smtp_details: &mut SmtpDetails,
+) {
+	if !provider.is_security_gateway() {
+		return;
+	}
+	smtp_details.gateway = Some(provider);
+
+	let is_smtp_verdict = matches!(
+		smtp_details.verification_method,
+		None | Some(VerificationMethod::Smtp)
+	);
+	if input.security_gateways_are_risky && is_smtp_verdict && smtp_details.is_deliverable {
+		smtp_details.is_catch_all = true;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use smtp::test_server;
+	use smtp::{CatchAllConfidence, CatchAllProbes};
+	use std::str::FromStr;
+	use std::sync::Arc;
+	use std::time::Duration;
+	use trust_dns_proto::op::Query;
+	use trust_dns_proto::rr::{Name, RData, Record, RecordType};
+	use trust_dns_resolver::lookup::{Lookup, MxLookup};
+
+	/// The MX details of example.org, with hosts in order of preference.
+	fn mx_details(hosts: &[&str]) -> MxDetails {
+		let domain = Name::from_ascii("example.org.").unwrap();
+		let records = hosts
+			.iter()
+			.enumerate()
+			.map(|(preference, host)| {
+				let mx = MX::new(preference as u16, Name::from_ascii(host).unwrap());
+				Record::from_rdata(domain.clone(), 300, RData::MX(mx))
+			})
+			.collect::<Vec<_>>();
+		let query = Query::query(domain, RecordType::MX);
+
+		MxDetails::from(MxLookup::from(Lookup::new_with_max_ttl(
+			query,
+			Arc::from(records),
+		)))
+	}
+
+	#[test]
+	fn should_only_cache_probed_catch_all() {
+		let input = CheckEmailInput::new("foo@example.org".into());
+		let host = MX::new(0, Name::from_str("mx.example.org.").unwrap());
+		let key = catch_all_key("example.org", host.exchange());
+		let pre_smtp = || PreSmtp {
+			syntax: check_syntax("foo@example.org"),
+			mx: mx_details(&["mx.example.org."]),
+			misc: MiscDetails::default(),
+		};
+		let probes = |errored| CatchAllProbes {
+			total: 1,
+			accepted: 0,
+			confidence: CatchAllConfidence::High,
+			errored,
+		};
+
+		let api_verdict = SmtpDetails {
+			can_connect_smtp: true,
+			is_deliverable: true,
+			verification_method: Some(VerificationMethod::GmailApi),
+			..Default::default()
+		};
+		smtp_output(&input, pre_smtp(), &host, Ok(api_verdict));
+		assert_eq!(input.context.catch_all_cache().get(&key), None);
+
+		let errored_probe = SmtpDetails {
+			catch_all_probes: Some(probes(true)),
+			..Default::default()
+		};
+		smtp_output(&input, pre_smtp(), &host, Ok(errored_probe));
+		assert_eq!(input.context.catch_all_cache().get(&key), None);
+
+		let probed = SmtpDetails {
+			catch_all_probes: Some(probes(false)),
+			..Default::default()
+		};
+		smtp_output(&input, pre_smtp(), &host, Ok(probed));
+		assert_eq!(input.context.catch_all_cache().get(&key), Some(false));
+	}
+
+	#[test]
+	fn should_downgrade_security_gateway_verdicts() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		let deliverable = SmtpDetails {
+			can_connect_smtp: true,
+			is_deliverable: true,
+			..Default::default()
+		};
+
+		let mut smtp_details = deliverable.clone();
+		flag_security_gateway(&input, MxProvider::Proofpoint, &mut smtp_details);
+		assert_eq!(smtp_details.gateway, Some(MxProvider::Proofpoint));
+		assert!(!smtp_details.is_catch_all);
+
+		input.set_security_gateways_are_risky(true);
+		let mut smtp_details = deliverable.clone();
+		flag_security_gateway(&input, MxProvider::Mimecast, &mut smtp_details);
+		assert!(smtp_details.is_catch_all);
+		let misc = MiscDetails::default();
+		assert_eq!(
+			calculate_reachable(&input, &misc, &Ok(smtp_details)),
+			Reachable::Risky
+		);
+
+		let mut smtp_details = deliverable;
+		flag_security_gateway(&input, MxProvider::Google, &mut smtp_details);
+		assert_eq!(smtp_details.gateway, None);
+		assert!(!smtp_details.is_catch_all);
+	}
+
+	#[tokio::test]
+	async fn should_fall_through_to_next_mx_host() {
+		let port = test_server::serve(test_server::PLAIN_EHLO).await;
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input
+			.set_smtp_port(port)
+			.set_try_all_mx_hosts(true)
+			.set_retries(1);
+		// Only 127.0.0.1 listens on the port, so 127.0.0.2 refuses the
+		// connection.
+		let pre_smtp = PreSmtp {
+			syntax: check_syntax("foo@example.org"),
+			mx: mx_details(&["127.0.0.2.", "127.0.0.1."]),
+			misc: MiscDetails::default(),
+		};
+
+		let output = verify_smtp(&input, pre_smtp).await;
+		let smtp_details = output.smtp.unwrap();
+		assert!(smtp_details.can_connect_smtp);
+		assert_eq!(smtp_details.mx_host.as_deref(), Some("127.0.0.1"));
+	}
+
+	#[tokio::test]
+	async fn should_fall_through_to_fallback_port() {
+		// Nothing listens on the main port once its listener is dropped.
+		let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap()
+			.port();
+		let port = test_server::serve(test_server::PLAIN_EHLO).await;
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input
+			.set_smtp_port(closed_port)
+			.set_smtp_fallback_ports(vec![port])
+			.set_retries(1);
+		let pre_smtp = PreSmtp {
+			syntax: check_syntax("foo@example.org"),
+			mx: mx_details(&["127.0.0.1."]),
+			misc: MiscDetails::default(),
+		};
+
+		let output = verify_smtp(&input, pre_smtp).await;
+		let smtp_details = output.smtp.unwrap();
+		assert!(smtp_details.can_connect_smtp);
+		assert_eq!(smtp_details.mx_port, Some(port));
+	}
+
+	#[tokio::test]
+	async fn should_stop_at_total_timeout() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_total_timeout(Some(Duration::ZERO));
+
+		let output = check_email(&input).await;
+		assert_eq!(output.is_reachable, Reachable::Unknown);
+		assert!(output.syntax.is_valid_syntax);
+		assert!(matches!(output.smtp, Err(SmtpError::TimeoutError(_))));
 	}
 }
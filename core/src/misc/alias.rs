@@ -0,0 +1,74 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detect addresses of email aliasing and forwarding services.
+
+/// Domains of the aliasing services, whose addresses forward to the user's
+/// real mailbox. Their subdomains match too, as some services give each
+/// user their own, e.g. `alias@user.anonaddy.com`.
+const ALIAS_SERVICE_DOMAINS: &[&str] = &[
+	// SimpleLogin
+	"simplelogin.com",
+	"simplelogin.fr",
+	"simplelogin.co",
+	"aleeas.com",
+	"slmail.me",
+	"silomails.com",
+	"slmails.com",
+	"8alias.com",
+	"8shield.net",
+	"dralias.com",
+	// DuckDuckGo Email Protection
+	"duck.com",
+	// Firefox Relay
+	"mozmail.com",
+	"relay.firefox.com",
+	// addy.io (formerly AnonAddy)
+	"anonaddy.com",
+	"anonaddy.me",
+	"addy.io",
+	// Apple Hide My Email
+	"privaterelay.appleid.com",
+	// 33mail
+	"33mail.com",
+];
+
+/// Is `domain` the domain of an email aliasing or forwarding service, e.g.
+/// SimpleLogin or Firefox Relay, or one of its subdomains?
+pub fn is_alias_service(domain: &str) -> bool {
+	let domain = domain.trim_end_matches('.').to_lowercase();
+
+	ALIAS_SERVICE_DOMAINS.iter().any(|alias_domain| {
+		domain == *alias_domain
+			|| domain
+				.strip_suffix(alias_domain)
+				.is_some_and(|subdomain| subdomain.ends_with('.'))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_detect_alias_service() {
+		assert!(is_alias_service("duck.com"));
+		assert!(is_alias_service("Mozmail.com."));
+		assert!(is_alias_service("john.anonaddy.com"));
+		assert!(!is_alias_service("notduck.com"));
+		assert!(!is_alias_service("gmail.com"));
+	}
+}
@@ -0,0 +1,333 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Disposable email domains, from the bundled list or from a list loaded
+//! at runtime.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use reqwest::Error as ReqwestError;
+
+use crate::LOG_TARGET;
+
+/// A list of disposable email domains.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisposableList {
+	domains: HashSet<String>,
+	/// The MD5 digest of the list's content, in hex.
+	version: String,
+}
+
+impl DisposableList {
+	/// Parse a list with one domain per line, in the format of the
+	/// disposable-email-domains project. Lines starting with "#" are
+	/// comments.
+	pub fn parse(list: &str) -> Self {
+		DisposableList {
+			domains: list
+				.lines()
+				.map(str::trim)
+				.filter(|line| !line.is_empty() && !line.starts_with('#'))
+				.map(|domain| domain.trim_end_matches('.').to_lowercase())
+				.collect(),
+			version: format!("{:x}", md5::compute(list)),
+		}
+	}
+
+	/// The version of the list: the MD5 digest of its content, in hex, so
+	/// that the same list always has the same version.
+	pub fn version(&self) -> &str {
+		&self.version
+	}
+
+	/// The number of domains in the list.
+	pub fn len(&self) -> usize {
+		self.domains.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.domains.is_empty()
+	}
+
+	/// Is `domain`, or one of its parent domains, in this list? The
+	/// comparison is case-insensitive.
+	pub fn contains(&self, domain: &str) -> bool {
+		let domain = domain.trim_end_matches('.').to_lowercase();
+		let mut parent = domain.as_str();
+		loop {
+			if self.domains.contains(parent) {
+				return true;
+			}
+			match parent.split_once('.') {
+				Some((_, rest)) => parent = rest,
+				None => return false,
+			}
+		}
+	}
+}
+
+/// Where to load a disposable domain list from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisposableListSource {
+	File(PathBuf),
+	Url(String),
+}
+
+impl DisposableListSource {
+	/// Read or download the list, giving up on the download after
+	/// `timeout`.
+	pub async fn load(&self, timeout: Duration) -> Result<DisposableList, DisposableListError> {
+		let list = match self {
+			DisposableListSource::File(path) => async_std::fs::read_to_string(path).await?,
+			DisposableListSource::Url(url) => {
+				reqwest::Client::builder()
+					.timeout(timeout)
+					.build()?
+					.get(url)
+					.send()
+					.await?
+					.error_for_status()?
+					.text()
+					.await?
+			}
+		};
+
+		Ok(DisposableList::parse(&list))
+	}
+}
+
+/// Error when loading a disposable domain list.
+#[derive(Debug)]
+pub enum DisposableListError {
+	Io(io::Error),
+	Reqwest(ReqwestError),
+}
+
+impl fmt::Display for DisposableListError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			DisposableListError::Io(err) => write!(f, "{err}"),
+			DisposableListError::Reqwest(err) => write!(f, "{err}"),
+		}
+	}
+}
+
+impl From<io::Error> for DisposableListError {
+	fn from(err: io::Error) -> Self {
+		DisposableListError::Io(err)
+	}
+}
+
+impl From<ReqwestError> for DisposableListError {
+	fn from(err: ReqwestError) -> Self {
+		DisposableListError::Reqwest(err)
+	}
+}
+
+/// Configuration of the disposable domain list loaded at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisposableListConfig {
+	pub source: DisposableListSource,
+	/// How often to load the list again. None to load it only once.
+	pub refresh_interval: Option<Duration>,
+	/// Timeout of the download of the list.
+	pub timeout: Duration,
+}
+
+impl DisposableListConfig {
+	/// Load the list from source, every `refresh_interval` if set, with a
+	/// download timeout of 30s.
+	pub fn new(source: DisposableListSource, refresh_interval: Option<Duration>) -> Self {
+		DisposableListConfig {
+			source,
+			refresh_interval,
+			timeout: Duration::from_secs(30),
+		}
+	}
+}
+
+/// The disposable domain list loaded at runtime, shared by the
+/// verifications of a context. Until it's loaded, the bundled list is used.
+#[derive(Debug)]
+pub(crate) struct DisposableListStore {
+	config: DisposableListConfig,
+	list: RwLock<Option<Arc<DisposableList>>>,
+	/// When the list was last loaded, successfully or not.
+	last_load: Mutex<Option<Instant>>,
+	/// Whether a background task is loading the list, so that others
+	/// don't.
+	loading: AtomicBool,
+}
+
+/// Resets the store's `loading` flag when dropped, even if the load was
+/// cancelled.
+struct LoadingGuard(Arc<DisposableListStore>);
+
+impl Drop for LoadingGuard {
+	fn drop(&mut self) {
+		self.0.loading.store(false, Ordering::SeqCst);
+	}
+}
+
+impl DisposableListStore {
+	pub(crate) fn new(config: DisposableListConfig) -> Self {
+		DisposableListStore {
+			config,
+			list: RwLock::new(None),
+			last_load: Mutex::new(None),
+			loading: AtomicBool::new(false),
+		}
+	}
+
+	/// The loaded list, or None if it wasn't loaded yet.
+	pub(crate) fn list(&self) -> Option<Arc<DisposableList>> {
+		self.list
+			.read()
+			.expect("Lock is not poisoned. qed.")
+			.clone()
+	}
+
+	/// Load the list from its source, replacing the current one. Returns
+	/// the new list's version. The current list is kept on error.
+	pub(crate) async fn load(&self) -> Result<String, DisposableListError> {
+		*self.last_load.lock().expect("Lock is not poisoned. qed.") = Some(Instant::now());
+		let list = self.config.source.load(self.config.timeout).await?;
+		let version = list.version().to_string();
+		log::debug!(
+			target: LOG_TARGET,
+			"Loaded {} disposable domains from {:?}, version {}",
+			list.len(),
+			self.config.source,
+			version
+		);
+		*self.list.write().expect("Lock is not poisoned. qed.") = Some(Arc::new(list));
+
+		Ok(version)
+	}
+
+	/// Was the list never loaded, or last loaded more than the refresh
+	/// interval ago?
+	fn is_stale(&self, now: Instant) -> bool {
+		match *self.last_load.lock().expect("Lock is not poisoned. qed.") {
+			None => true,
+			Some(last_load) => self
+				.config
+				.refresh_interval
+				.is_some_and(|interval| now.duration_since(last_load) >= interval),
+		}
+	}
+
+	/// Load the list in a background task if it's stale, unless one is
+	/// already loading it. Verifications don't wait for it, and use the
+	/// current list meanwhile. Errors are logged, and the current list is
+	/// kept.
+	pub(crate) fn refresh_if_stale(self: &Arc<Self>) {
+		let runtime = match tokio::runtime::Handle::try_current() {
+			Ok(runtime) => runtime,
+			Err(_) => return,
+		};
+		if !self.is_stale(Instant::now()) || self.loading.swap(true, Ordering::SeqCst) {
+			return;
+		}
+
+		let guard = LoadingGuard(self.clone());
+		runtime.spawn(async move {
+			if let Err(err) = guard.0.load().await {
+				log::debug!(
+					target: LOG_TARGET,
+					"Cannot load the disposable domains from {:?}: {}",
+					guard.0.config.source,
+					err
+				);
+			}
+		});
+	}
+}
+
+/// Is `domain` a disposable email domain? If `list` is None, the list
+/// bundled with the mailchecker crate is used.
+pub fn is_disposable(domain: &str, list: Option<&DisposableList>) -> bool {
+	match list {
+		Some(list) => list.contains(domain),
+		// mailchecker::is_valid also checks the syntax, so we pass a
+		// placeholder username to only check if the domain is disposable.
+		None => !mailchecker::is_valid(&format!("user@{domain}")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_match_disposable_domains() {
+		let list = DisposableList::parse("# Updated daily\nmailinator.com\nTempMail.org\n\n");
+
+		assert_eq!(list.len(), 2);
+		assert_eq!(list.version().len(), 32);
+		assert!(is_disposable("mailinator.com", Some(&list)));
+		assert!(is_disposable("eu.tempmail.org.", Some(&list)));
+		assert!(!is_disposable("gmail.com", Some(&list)));
+		assert!(is_disposable("mailinator.com", None));
+	}
+
+	#[tokio::test]
+	async fn should_refresh_stale_list_in_background() {
+		let path = std::env::temp_dir().join(format!("disposable-{}.txt", std::process::id()));
+		std::fs::write(&path, "mailinator.com\n").unwrap();
+		let store = Arc::new(DisposableListStore::new(DisposableListConfig::new(
+			DisposableListSource::File(path.clone()),
+			Some(Duration::ZERO),
+		)));
+		assert!(store.list().is_none());
+
+		store.refresh_if_stale();
+		// A second refresh is skipped while the first one is loading.
+		store.refresh_if_stale();
+		let first = loop {
+			match store.list() {
+				Some(list) if !store.loading.load(Ordering::SeqCst) => break list,
+				_ => tokio::task::yield_now().await,
+			}
+		};
+		assert!(first.contains("mailinator.com"));
+
+		std::fs::write(&path, "tempmail.org\n").unwrap();
+		store.load().await.unwrap();
+		let second = store.list().unwrap();
+		assert!(!second.contains("mailinator.com"));
+		assert_ne!(first.version(), second.version());
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn should_reset_loading_when_cancelled() {
+		let store = Arc::new(DisposableListStore::new(DisposableListConfig::new(
+			DisposableListSource::Url("http://localhost:1/list.txt".into()),
+			None,
+		)));
+		store.loading.store(true, Ordering::SeqCst);
+		drop(LoadingGuard(store.clone()));
+
+		assert!(!store.loading.load(Ordering::SeqCst));
+	}
+}
@@ -14,18 +14,106 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::util::constants::LOG_TARGET;
-use md5;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use md5::Digest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::util::constants::LOG_TARGET;
+use crate::util::ttl_cache::{CacheStats, TtlCache};
 
 const API_BASE_URL: &str = "https://www.gravatar.com/avatar/";
 
-pub async fn check_gravatar(to_email: &str) -> Option<String> {
-	let client = reqwest::Client::new();
+/// The hashes Gravatar identifies an email address by, to build the URLs
+/// of its avatar and profile, e.g. with a size or a default image.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GravatarHashes {
+	/// The MD5 hash of the trimmed, lowercase email address, in hex.
+	pub md5: String,
+	/// The SHA-256 hash of the trimmed, lowercase email address, in hex.
+	pub sha256: String,
+}
+
+impl GravatarHashes {
+	pub fn new(to_email: &str) -> Self {
+		let email = to_email.trim().to_lowercase();
+		let md5: Digest = md5::compute(&email);
+		let sha256 = Sha256::digest(&email)
+			.iter()
+			.map(|byte| format!("{byte:02x}"))
+			.collect();
+
+		GravatarHashes {
+			md5: format!("{md5:x}"),
+			sha256,
+		}
+	}
+}
+
+/// Whether the email addresses have a Gravatar, keyed by their SHA-256
+/// hash. Shared by the verifications of a context.
+#[derive(Debug)]
+pub(crate) struct GravatarCache {
+	entries: TtlCache<String, bool>,
+	ttl: Duration,
+}
 
-	let mail_hash: Digest = md5::compute(to_email);
+impl GravatarCache {
+	pub(crate) fn new(ttl: Duration) -> Self {
+		GravatarCache {
+			entries: TtlCache::default(),
+			ttl,
+		}
+	}
+
+	pub(crate) fn stats(&self) -> CacheStats {
+		self.entries.stats()
+	}
 
-	let url = format!("{API_BASE_URL}{mail_hash:x}");
+	pub(crate) fn clear(&self) {
+		self.entries.clear()
+	}
+}
+
+/// How to check the Gravatar of an email address, see `check_misc`.
+#[derive(Debug, Clone, Default)]
+pub struct GravatarCheck {
+	/// Timeout of the Gravatar request. None for no timeout.
+	pub timeout: Option<Duration>,
+	/// HTTP client of the requests, shared by the verifications of a
+	/// context to reuse its connections.
+	pub(crate) client: reqwest::Client,
+	pub(crate) cache: Option<Arc<GravatarCache>>,
+}
+
+impl GravatarCheck {
+	pub fn new(timeout: Option<Duration>) -> Self {
+		GravatarCheck {
+			timeout,
+			..Default::default()
+		}
+	}
+}
+
+/// Get the URL of the email's Gravatar, if it has one, and the hashes
+/// Gravatar identifies it by. Answers are cached in the check's cache, if
+/// any, but not errors.
+pub async fn check_gravatar(
+	to_email: &str,
+	check: &GravatarCheck,
+) -> (Option<String>, GravatarHashes) {
+	let hashes = GravatarHashes::new(to_email);
+	let url = format!("{API_BASE_URL}{}", hashes.md5);
+
+	if let Some(has_gravatar) = check
+		.cache
+		.as_ref()
+		.and_then(|cache| cache.entries.get(&hashes.sha256))
+	{
+		return (has_gravatar.then_some(url), hashes);
+	}
 
 	log::debug!(
 		target: LOG_TARGET,
@@ -34,13 +122,16 @@ pub async fn check_gravatar(to_email: &str) -> Option<String> {
 		url
 	);
 
-	let response = client
+	let mut request = check
+		.client
 		.get(&url)
 		// This option is necessary to return a NotFound exception instead of the default gravatar
 		// image if none for the given email is found.
-		.query(&[("d", "404")])
-		.send()
-		.await;
+		.query(&[("d", "404")]);
+	if let Some(timeout) = check.timeout {
+		request = request.timeout(timeout);
+	}
+	let response = request.send().await;
 
 	log::debug!(
 		target: LOG_TARGET,
@@ -51,11 +142,54 @@ pub async fn check_gravatar(to_email: &str) -> Option<String> {
 
 	let response = match response {
 		Ok(response) => response,
-		Err(_) => return None,
+		Err(_) => return (None, hashes),
+	};
+
+	let has_gravatar = match response.status() {
+		reqwest::StatusCode::OK => true,
+		reqwest::StatusCode::NOT_FOUND => false,
+		_ => return (None, hashes),
 	};
+	if let Some(cache) = &check.cache {
+		cache.entries.insert(
+			hashes.sha256.clone(),
+			has_gravatar,
+			Instant::now() + cache.ttl,
+		);
+	}
+
+	(has_gravatar.then_some(url), hashes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_hash_normalized_email() {
+		let hashes = GravatarHashes::new(" MyEmailAddress@example.com ");
+
+		assert_eq!(hashes.md5, "0bc83cb571cd1c50ba6f3e8a78ef1346");
+		assert_eq!(
+			hashes.sha256,
+			"84059b07d4be67b806386c0aad8070a23f18836bbaae342275dc0a83414c32ee"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_use_cached_answer() {
+		let check = GravatarCheck {
+			cache: Some(Arc::new(GravatarCache::new(Duration::from_secs(60)))),
+			..Default::default()
+		};
+		let hashes = GravatarHashes::new("foo@example.org");
+		let cache = check.cache.as_ref().unwrap();
+		cache
+			.entries
+			.insert(hashes.sha256.clone(), true, Instant::now() + cache.ttl);
 
-	match response.status() {
-		reqwest::StatusCode::OK => Some(url),
-		_ => None,
+		let (url, _) = check_gravatar("foo@example.org", &check).await;
+		assert_eq!(url, Some(format!("{API_BASE_URL}{}", hashes.md5)));
+		assert_eq!(cache.stats().hits, 1);
 	}
 }
@@ -0,0 +1,122 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Custom data sources of the disposable domains and role accounts.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use super::RoleSeverity;
+
+/// A custom data source, e.g. a database, an API or a bloom filter, of the
+/// disposable domains and role accounts. Set it on the context with
+/// `CheckEmailContext::with_list_provider`. Each method falls back to the
+/// built-in lists by default.
+#[async_trait]
+pub trait ListProvider: Debug + Send + Sync {
+	/// Is `domain` a disposable email domain? None to fall back to the
+	/// context's disposable domain list, or the bundled one.
+	async fn is_disposable(&self, _domain: &str) -> Option<bool> {
+		None
+	}
+
+	/// The severity of `username` at `domain` if it's a role account, or
+	/// Some(None) if it's not one. None to fall back to the input's
+	/// `role_accounts`, or the built-in list.
+	async fn role_account_severity(
+		&self,
+		_username: &str,
+		_domain: &str,
+	) -> Option<Option<RoleSeverity>> {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::misc::{check_misc, check_misc_with_disposable};
+	use crate::syntax::{check_syntax, check_syntax_with_list_provider};
+	use crate::{CheckEmailContext, CheckEmailInput};
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	/// Flags example.org as disposable, and "ceo" as a role account.
+	#[derive(Debug)]
+	struct CustomLists;
+
+	#[async_trait]
+	impl ListProvider for CustomLists {
+		async fn is_disposable(&self, domain: &str) -> Option<bool> {
+			(domain == "example.org").then_some(true)
+		}
+
+		async fn role_account_severity(
+			&self,
+			username: &str,
+			_domain: &str,
+		) -> Option<Option<RoleSeverity>> {
+			(username == "ceo").then_some(Some(RoleSeverity::Risky))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_use_list_provider() {
+		let input = CheckEmailInput::default();
+		let (syntax, _) =
+			check_syntax_with_list_provider("foo@example.org", &input, Some(&CustomLists)).await;
+		assert!(!syntax.is_valid_syntax);
+		let (syntax, _) =
+			check_syntax_with_list_provider("foo@mailinator.com", &input, Some(&CustomLists)).await;
+		assert!(!syntax.is_valid_syntax);
+
+		let mut input = CheckEmailInput::default();
+		input.set_context(CheckEmailContext::new().with_list_provider(Arc::new(CustomLists)));
+		let syntax = check_syntax("ceo@reacher.email");
+		let misc = check_misc(&syntax, &input).await;
+		assert!(misc.is_role_account && !misc.is_disposable);
+		let syntax = check_syntax("info@reacher.email");
+		let misc = check_misc(&syntax, &input).await;
+		assert_eq!(misc.role_account_severity, Some(RoleSeverity::Risky));
+	}
+
+	/// Counts the questions about disposable domains.
+	#[derive(Debug, Default)]
+	struct CountingLists(AtomicUsize);
+
+	#[async_trait]
+	impl ListProvider for CountingLists {
+		async fn is_disposable(&self, _domain: &str) -> Option<bool> {
+			self.0.fetch_add(1, Ordering::Relaxed);
+			Some(false)
+		}
+	}
+
+	#[tokio::test]
+	async fn should_ask_list_provider_once() {
+		let provider = Arc::new(CountingLists::default());
+		let mut input = CheckEmailInput::default();
+		input.set_context(CheckEmailContext::new().with_list_provider(provider.clone()));
+
+		let (syntax, provider_disposable) =
+			check_syntax_with_list_provider("foo@reacher.email", &input, Some(provider.as_ref()))
+				.await;
+		let misc = check_misc_with_disposable(&syntax, &input, provider_disposable).await;
+		assert!(syntax.is_valid_syntax && !misc.is_disposable);
+		assert_eq!(provider.0.load(Ordering::Relaxed), 1);
+	}
+}
@@ -14,16 +14,40 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod alias;
+mod disposable;
 mod gravatar;
+mod list_provider;
+mod role;
+mod trap;
 use crate::haveibeenpwned::check_haveibeenpwned;
+pub use crate::haveibeenpwned::{BreachDetails, HaveIBeenPwnedDetails};
 
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::default::Default;
 
 use crate::syntax::SyntaxDetails;
+use crate::util::input_output::CheckEmailInput;
+pub use alias::is_alias_service;
+pub(crate) use disposable::DisposableListStore;
+pub use disposable::{
+	is_disposable, DisposableList, DisposableListConfig, DisposableListError, DisposableListSource,
+};
 use gravatar::check_gravatar;
+pub(crate) use gravatar::GravatarCache;
+pub use gravatar::{GravatarCheck, GravatarHashes};
+pub use list_provider::ListProvider;
+pub use role::{role_account_severity, RoleAccount, RoleSeverity};
+pub(crate) use trap::trap_signals;
+pub use trap::{TrapFeed, TrapSignal};
 
-const ROLE_ACCOUNTS: &str = include_str!("./roles.json");
+/// Domains of free email providers, one per line.
+const FREE_PROVIDERS: &str = include_str!("./free_providers.txt");
+
+static DEFAULT_FREE_PROVIDERS: Lazy<HashSet<&'static str>> =
+	Lazy::new(|| FREE_PROVIDERS.lines().collect());
 
 /// Miscelleanous details about the email address.
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -32,10 +56,38 @@ pub struct MiscDetails {
 	pub is_disposable: bool,
 	/// Is this email a role-based account?
 	pub is_role_account: bool,
+	/// How bad it is to send an email to this role account. Only present for
+	/// role accounts.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub role_account_severity: Option<RoleSeverity>,
+	/// Is this email hosted by a free email provider, such as Gmail or
+	/// Yahoo?
+	pub is_free_provider: bool,
+	/// Is this email on an aliasing or forwarding service, such as
+	/// SimpleLogin, DuckDuckGo Email Protection, Firefox Relay or addy.io?
+	/// These addresses forward to a real mailbox, so they're usually
+	/// deliverable.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub is_alias_service: bool,
 	pub gravatar_url: Option<String>,
+	/// The hashes Gravatar identifies this email by, to build the URLs of
+	/// its avatar variants. Only present if Gravatar was checked.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub gravatar_hashes: Option<GravatarHashes>,
 	/// Is this email address listed in the haveibeenpwned database for
 	/// previous breaches?
 	pub haveibeenpwned: Option<bool>,
+	/// The breaches this email address was found in, only present if the
+	/// haveibeenpwned API was checked.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub haveibeenpwned_details: Option<HaveIBeenPwnedDetails>,
+	/// Is this email likely a spamtrap? Only checked if `check_spamtraps`
+	/// is set in the input.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub possible_trap: bool,
+	/// The heuristics which flagged the email as a possible spamtrap.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub trap_signals: Vec<TrapSignal>,
 }
 
 /// Error occured connecting to this email server via SMTP. Right now this
@@ -45,14 +97,46 @@ pub struct MiscDetails {
 #[serde(tag = "type", content = "message")]
 pub enum MiscError {}
 
-/// Fetch misc details about the email address, such as whether it's disposable.
-pub async fn check_misc(
+/// Is `domain` a free email provider? If `free_provider_domains` is None,
+/// the bundled list is used.
+pub fn is_free_provider(domain: &str, free_provider_domains: Option<&[String]>) -> bool {
+	let domain = domain.trim_end_matches('.').to_lowercase();
+
+	match free_provider_domains {
+		Some(domains) => domains.iter().any(|d| d.eq_ignore_ascii_case(&domain)),
+		None => DEFAULT_FREE_PROVIDERS.contains(domain.as_str()),
+	}
+}
+
+/// Fetch misc details about the email address, such as whether it's
+/// disposable, with the options of `input` and the lists of its context.
+pub async fn check_misc(syntax: &SyntaxDetails, input: &CheckEmailInput) -> MiscDetails {
+	let provider_disposable = match input.context.list_provider() {
+		Some(provider) => provider.is_disposable(&syntax.domain).await,
+		None => None,
+	};
+
+	check_misc_with_disposable(syntax, input, provider_disposable).await
+}
+
+/// Same as `check_misc`, with `provider_disposable`, the answer of the
+/// context's list provider about the domain, which was already asked while
+/// checking the syntax.
+pub(crate) async fn check_misc_with_disposable(
 	syntax: &SyntaxDetails,
-	cfg_check_gravatar: bool,
-	haveibeenpwned_api_key: Option<String>,
+	input: &CheckEmailInput,
+	provider_disposable: Option<bool>,
 ) -> MiscDetails {
-	let role_accounts: Vec<&str> =
-		serde_json::from_str(ROLE_ACCOUNTS).expect("roles.json is a valid json. qed.");
+	let provider_role = match input.context.list_provider() {
+		Some(provider) => {
+			provider
+				.role_account_severity(&syntax.username, &syntax.domain)
+				.await
+		}
+		None => None,
+	};
+	let role_account_severity = provider_role
+		.unwrap_or_else(|| role_account_severity(&syntax.username, input.role_accounts.as_deref()));
 
 	let address = syntax
 		.address
@@ -61,24 +145,60 @@ pub async fn check_misc(
 		.to_string();
 
 	let mut gravatar_url: Option<String> = None;
-
-	if cfg_check_gravatar {
-		gravatar_url = check_gravatar(address.as_ref()).await;
+	let mut gravatar_hashes: Option<GravatarHashes> = None;
+
+	if input.check_gravatar {
+		let gravatar_check = GravatarCheck {
+			timeout: input.gravatar_timeout,
+			client: input.context.gravatar_client(),
+			cache: input.context.gravatar_cache(),
+		};
+		let (url, hashes) = check_gravatar(address.as_ref(), &gravatar_check).await;
+		gravatar_url = url;
+		gravatar_hashes = Some(hashes);
 	}
 
-	let mut haveibeenpwned: Option<bool> = None;
+	let mut haveibeenpwned_details: Option<HaveIBeenPwnedDetails> = None;
 
-	if haveibeenpwned_api_key.is_some() {
-		haveibeenpwned = check_haveibeenpwned(address.as_ref(), haveibeenpwned_api_key).await;
+	if input.haveibeenpwned_api_key.is_some() {
+		haveibeenpwned_details = check_haveibeenpwned(
+			address.as_ref(),
+			input.haveibeenpwned_api_key.clone(),
+			input.haveibeenpwned_verified_only,
+		)
+		.await;
 	}
 
 	MiscDetails {
-		// mailchecker::is_valid checks also if the syntax is valid. But if
-		// we're here, it means we're sure the syntax is valid, so is_valid
-		// actually will only check if it's disposable.
-		is_disposable: !mailchecker::is_valid(address.as_ref()),
-		is_role_account: role_accounts.contains(&syntax.username.to_lowercase().as_ref()),
+		is_disposable: provider_disposable.unwrap_or_else(|| {
+			is_disposable(&syntax.domain, input.context.disposable_list().as_deref())
+		}),
+		is_role_account: role_account_severity.is_some(),
+		role_account_severity,
+		is_free_provider: is_free_provider(&syntax.domain, input.free_provider_domains.as_deref()),
+		is_alias_service: is_alias_service(&syntax.domain),
 		gravatar_url,
-		haveibeenpwned,
+		gravatar_hashes,
+		haveibeenpwned: haveibeenpwned_details
+			.as_ref()
+			.map(|details| details.breach_count > 0),
+		haveibeenpwned_details,
+		..Default::default()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_detect_free_provider() {
+		assert!(is_free_provider("gmail.com", None));
+		assert!(is_free_provider("Yahoo.co.uk", None));
+		assert!(!is_free_provider("reacher.email", None));
+
+		let custom = vec!["reacher.email".to_string()];
+		assert!(is_free_provider("reacher.email", Some(&custom)));
+		assert!(!is_free_provider("gmail.com", Some(&custom)));
 	}
 }
@@ -0,0 +1,122 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+const ROLE_ACCOUNTS: &str = include_str!("./roles.json");
+
+/// Role accounts of the built-in list which are only meant for operational
+/// or automated messages. All other roles of the list are `Risky`.
+const NEVER_SEND_ROLES: &[&str] = &[
+	"abuse",
+	"devnull",
+	"hostmaster",
+	"mailer-daemon",
+	"no-reply",
+	"nobody",
+	"noc",
+	"noreply",
+	"postmaster",
+	"root",
+	"security",
+	"spam",
+	"unsubscribe",
+];
+
+static DEFAULT_ROLE_ACCOUNTS: Lazy<HashMap<String, RoleSeverity>> = Lazy::new(|| {
+	let role_accounts: Vec<&str> =
+		serde_json::from_str(ROLE_ACCOUNTS).expect("roles.json is a valid json. qed.");
+
+	role_accounts
+		.into_iter()
+		.map(|username| {
+			let severity = if NEVER_SEND_ROLES.contains(&username) {
+				RoleSeverity::NeverSend
+			} else {
+				RoleSeverity::Risky
+			};
+
+			(username.to_string(), severity)
+		})
+		.collect()
+});
+
+/// How bad it is to send an email to a role account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoleSeverity {
+	/// The address is not meant to receive regular emails, e.g. `abuse@` or
+	/// `postmaster@`. The email is reported as `Invalid`.
+	NeverSend,
+	/// The address is shared by a team, e.g. `info@` or `sales@`, so it's
+	/// likely to have low engagement. The email is reported as `Risky`.
+	Risky,
+}
+
+/// A role account username, with its severity.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RoleAccount {
+	pub username: String,
+	pub severity: RoleSeverity,
+}
+
+/// Get the severity of `username` if it's a role account. If
+/// `role_accounts` is None, the built-in list is used.
+pub fn role_account_severity(
+	username: &str,
+	role_accounts: Option<&[RoleAccount]>,
+) -> Option<RoleSeverity> {
+	let username = username.to_lowercase();
+
+	match role_accounts {
+		Some(role_accounts) => role_accounts
+			.iter()
+			.find(|role| role.username.eq_ignore_ascii_case(&username))
+			.map(|role| role.severity),
+		None => DEFAULT_ROLE_ACCOUNTS.get(&username).copied(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_get_role_account_severity() {
+		assert_eq!(
+			role_account_severity("Postmaster", None),
+			Some(RoleSeverity::NeverSend)
+		);
+		assert_eq!(
+			role_account_severity("info", None),
+			Some(RoleSeverity::Risky)
+		);
+		assert_eq!(role_account_severity("john.doe", None), None);
+
+		let custom = vec![RoleAccount {
+			username: "hr".into(),
+			severity: RoleSeverity::NeverSend,
+		}];
+		assert_eq!(
+			role_account_severity("hr", Some(&custom)),
+			Some(RoleSeverity::NeverSend)
+		);
+		assert_eq!(role_account_severity("info", Some(&custom)), None);
+	}
+}
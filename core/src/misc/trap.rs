@@ -0,0 +1,125 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Heuristics flagging likely spamtraps, i.e. addresses which only exist to
+//! catch senders mailing without consent. None of them is conclusive on its
+//! own.
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::util::input_output::{CheckEmailInput, CheckEmailOutput};
+
+/// Usernames of known trap seeds, matched anywhere in the username.
+const TRAP_USERNAMES: &[&str] = &[
+	"spamtrap",
+	"spam-trap",
+	"spam_trap",
+	"spam.trap",
+	"honeypot",
+	"honey-pot",
+	"blackhole",
+];
+
+/// Domains re-registered more recently than this, in days, are considered
+/// dormant domains which were re-activated.
+const REACTIVATED_DOMAIN_DAYS: u64 = 365;
+
+/// Domains younger than this, in days, which accept all recipients are
+/// suspicious.
+const YOUNG_DOMAIN_DAYS: u64 = 90;
+
+/// Why an email is flagged as a possible spamtrap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrapSignal {
+	/// The username is a known trap seed pattern, e.g. "spamtrap".
+	TrapUsername,
+	/// The email or its domain is listed by the context's trap feed.
+	TrapFeed,
+	/// The domain was re-registered recently, e.g. after expiring. Only
+	/// detected if `check_rdap` is set in the input.
+	ReactivatedDomain,
+	/// A freshly registered domain accepts all recipients. Only detected
+	/// if `check_rdap` is set in the input.
+	SuspiciousAcceptAll,
+}
+
+/// A published list of spamtrap addresses or domains. Set it on the context
+/// with `CheckEmailContext::with_trap_feed`.
+#[async_trait]
+pub trait TrapFeed: Debug + Send + Sync {
+	/// Is `email`, or its `domain`, listed as a spamtrap?
+	async fn is_listed(&self, email: &str, domain: &str) -> bool;
+}
+
+/// Does `username` look like a trap seed?
+fn is_trap_username(username: &str) -> bool {
+	let username = username.to_lowercase();
+
+	TRAP_USERNAMES
+		.iter()
+		.any(|pattern| username.contains(pattern))
+}
+
+/// The spamtrap signals of a verification's output.
+pub(crate) async fn trap_signals(
+	output: &CheckEmailOutput,
+	input: &CheckEmailInput,
+) -> Vec<TrapSignal> {
+	let mut signals = vec![];
+	if is_trap_username(&output.syntax.username) {
+		signals.push(TrapSignal::TrapUsername);
+	}
+
+	if let (Some(feed), Some(address)) = (input.context.trap_feed(), &output.syntax.address) {
+		if feed
+			.is_listed(address.as_ref(), &output.syntax.domain)
+			.await
+		{
+			signals.push(TrapSignal::TrapFeed);
+		}
+	}
+
+	if let Some(rdap) = &output.rdap {
+		if rdap
+			.reregistered_days
+			.is_some_and(|days| days < REACTIVATED_DOMAIN_DAYS)
+		{
+			signals.push(TrapSignal::ReactivatedDomain);
+		}
+		let is_catch_all = output.smtp.as_ref().is_ok_and(|smtp| smtp.is_catch_all);
+		if is_catch_all && rdap.age_days.is_some_and(|days| days < YOUNG_DOMAIN_DAYS) {
+			signals.push(TrapSignal::SuspiciousAcceptAll);
+		}
+	}
+
+	signals
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_detect_trap_usernames() {
+		assert!(is_trap_username("SpamTrap42"));
+		assert!(is_trap_username("the.honeypot"));
+		assert!(!is_trap_username("john.doe"));
+	}
+}
@@ -0,0 +1,163 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DNS-based blocklist (DNSBL) lookups of the MX hosts' IP addresses.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::{RData, RecordType};
+
+use super::MxRecord;
+use crate::util::{constants::LOG_TARGET, resolver::DnsResolver};
+
+/// Spamhaus ZEN, which combines all the Spamhaus IP blocklists. Note that
+/// Spamhaus refuses queries sent through public resolvers.
+pub const SPAMHAUS_ZEN: &str = "zen.spamhaus.org";
+
+/// A MX host's IP address which is listed on a DNSBL.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DnsblListing {
+	/// The MX host.
+	pub host: String,
+	/// The IP address of the MX host which is listed.
+	pub ip: IpAddr,
+	/// The DNSBL zone, e.g. `zen.spamhaus.org`.
+	pub zone: String,
+	/// The addresses returned by the DNSBL, e.g. `127.0.0.2`. Their meaning
+	/// depends on the DNSBL. Error codes, e.g. Spamhaus refusing the query,
+	/// aren't included.
+	pub return_codes: Vec<IpAddr>,
+}
+
+/// Get the name to query to check whether `ip` is listed on `zone`: the
+/// reversed IPv4 octets, or the reversed IPv6 nibbles, prepended to the zone.
+fn dnsbl_query(ip: IpAddr, zone: &str) -> String {
+	let labels = match ip {
+		IpAddr::V4(ip) => ip.octets().iter().rev().map(u8::to_string).collect(),
+		IpAddr::V6(ip) => ip
+			.octets()
+			.iter()
+			.rev()
+			.flat_map(|byte| [byte & 0xf, byte >> 4])
+			.map(|nibble| format!("{nibble:x}"))
+			.collect::<Vec<_>>(),
+	};
+
+	format!("{}.{}", labels.join("."), zone.trim_end_matches('.'))
+}
+
+/// Whether `ip`, returned by a DNSBL, means the queried address is listed:
+/// listings are in 127.0.0.0/8, except 127.255.255.0/24, which DNSBLs use
+/// for errors, e.g. Spamhaus' 127.255.255.254 for queries sent through a
+/// public resolver.
+fn is_listing_code(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => {
+			let [a, b, c, _] = ip.octets();
+			a == 127 && (b, c) != (255, 255)
+		}
+		IpAddr::V6(_) => false,
+	}
+}
+
+/// Check the IP addresses of all the MX hosts against each DNSBL zone, and
+/// return the listings. Lookup errors and DNSBL error codes are treated as
+/// not listed.
+pub(crate) async fn check_dnsbl(
+	resolver: &DnsResolver,
+	records: &[MxRecord],
+	zones: &[String],
+) -> Vec<DnsblListing> {
+	let mut listings = Vec::new();
+
+	for record in records {
+		let host = &record.hostname;
+		for ip in record.ips.iter().flatten().copied() {
+			for zone in zones {
+				let query = dnsbl_query(ip, zone);
+				let codes = match resolver.lookup(&query, RecordType::A).await {
+					Ok(lookup) => lookup
+						.iter()
+						.filter_map(|rdata| match rdata {
+							RData::A(ip) => Some(IpAddr::V4(*ip)),
+							_ => None,
+						})
+						.collect::<Vec<_>>(),
+					Err(_) => continue,
+				};
+				let (return_codes, errors): (Vec<_>, Vec<_>) =
+					codes.into_iter().partition(|code| is_listing_code(*code));
+				if !errors.is_empty() {
+					log::debug!(
+						target: LOG_TARGET,
+						"[host={}] {} got error codes from {}: {:?}",
+						host,
+						ip,
+						zone,
+						errors
+					);
+				}
+				if return_codes.is_empty() {
+					continue;
+				}
+
+				log::debug!(
+					target: LOG_TARGET,
+					"[host={}] {} is listed on {}: {:?}",
+					host,
+					ip,
+					zone,
+					return_codes
+				);
+				listings.push(DnsblListing {
+					host: host.clone(),
+					ip,
+					zone: zone.clone(),
+					return_codes,
+				});
+			}
+		}
+	}
+
+	listings
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_build_dnsbl_queries() {
+		assert_eq!(
+			dnsbl_query("192.0.2.1".parse().unwrap(), SPAMHAUS_ZEN),
+			"1.2.0.192.zen.spamhaus.org"
+		);
+		assert_eq!(
+			dnsbl_query("2001:db8::1".parse().unwrap(), "dnsbl.example.org."),
+			"1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.dnsbl.example.org"
+		);
+	}
+
+	#[test]
+	fn should_only_count_listing_codes() {
+		assert!(is_listing_code("127.0.0.2".parse().unwrap()));
+		assert!(is_listing_code("127.0.1.4".parse().unwrap()));
+		assert!(!is_listing_code("127.255.255.254".parse().unwrap()));
+		assert!(!is_listing_code("127.255.255.252".parse().unwrap()));
+		assert!(!is_listing_code("192.0.2.1".parse().unwrap()));
+	}
+}
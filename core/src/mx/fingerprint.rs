@@ -0,0 +1,171 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Identify the provider hosting a domain's email from its MX hosts.
+
+use serde::{Deserialize, Serialize};
+
+/// The provider hosting the MX servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MxProvider {
+	/// Gmail or Google Workspace.
+	Google,
+	/// Outlook.com or Microsoft 365.
+	Outlook,
+	Yahoo,
+	Icloud,
+	Zoho,
+	Fastmail,
+	Protonmail,
+	Yandex,
+	/// Proofpoint email security gateway.
+	Proofpoint,
+	/// Mimecast email security gateway.
+	Mimecast,
+	/// Barracuda email security gateway.
+	Barracuda,
+	/// Broadcom (Symantec) MessageLabs email security gateway.
+	Messagelabs,
+	/// Cisco Secure Email (IronPort) email security gateway.
+	Ironport,
+	/// GoDaddy hosted email.
+	Godaddy,
+	/// OVHcloud hosted email.
+	Ovh,
+	/// IONOS hosted email.
+	Ionos,
+	/// The MX hosts are on the email's domain itself.
+	Selfhosted,
+	/// None of the known providers.
+	Other,
+}
+
+/// Suffixes of the providers' MX hosts, fully qualified.
+const MX_PROVIDER_SUFFIXES: &[(&str, MxProvider)] = &[
+	(".google.com.", MxProvider::Google),
+	(".googlemail.com.", MxProvider::Google),
+	(".protection.outlook.com.", MxProvider::Outlook),
+	(".yahoodns.net.", MxProvider::Yahoo),
+	(".mail.icloud.com.", MxProvider::Icloud),
+	(".zoho.com.", MxProvider::Zoho),
+	(".zoho.eu.", MxProvider::Zoho),
+	(".messagingengine.com.", MxProvider::Fastmail),
+	(".fastmail.com.", MxProvider::Fastmail),
+	(".protonmail.ch.", MxProvider::Protonmail),
+	(".yandex.net.", MxProvider::Yandex),
+	(".yandex.ru.", MxProvider::Yandex),
+	(".pphosted.com.", MxProvider::Proofpoint),
+	(".ppe-hosted.com.", MxProvider::Proofpoint),
+	(".mimecast.com.", MxProvider::Mimecast),
+	(".mimecast.co.za.", MxProvider::Mimecast),
+	(".barracudanetworks.com.", MxProvider::Barracuda),
+	(".messagelabs.com.", MxProvider::Messagelabs),
+	(".iphmx.com.", MxProvider::Ironport),
+	(".secureserver.net.", MxProvider::Godaddy),
+	(".ovh.net.", MxProvider::Ovh),
+	(".ionos.com.", MxProvider::Ionos),
+	(".ionos.de.", MxProvider::Ionos),
+	(".kundenserver.de.", MxProvider::Ionos),
+];
+
+impl MxProvider {
+	/// Is it an email security gateway, which filters the email before
+	/// relaying it to the actual mailbox provider? Gateways usually accept
+	/// any recipient at `RCPT TO`, so their answer says little about the
+	/// mailbox.
+	pub fn is_security_gateway(&self) -> bool {
+		matches!(
+			self,
+			MxProvider::Proofpoint
+				| MxProvider::Mimecast
+				| MxProvider::Barracuda
+				| MxProvider::Messagelabs
+				| MxProvider::Ironport
+		)
+	}
+}
+
+/// Identify the provider from the MX hosts of `domain`. The first host
+/// matching a known provider wins, so pass the hosts sorted by preference.
+pub fn fingerprint_mx(domain: &str, mx_hosts: &[String]) -> MxProvider {
+	let domain = domain.trim_end_matches('.').to_lowercase();
+	let mx_hosts = mx_hosts
+		.iter()
+		.map(|host| format!(".{}.", host.trim_end_matches('.').to_lowercase()))
+		.collect::<Vec<_>>();
+
+	let known = mx_hosts.iter().find_map(|host| {
+		MX_PROVIDER_SUFFIXES
+			.iter()
+			.find(|(suffix, _)| host.ends_with(suffix))
+			.map(|(_, provider)| *provider)
+	});
+	if let Some(provider) = known {
+		return provider;
+	}
+
+	if !domain.is_empty()
+		&& mx_hosts
+			.iter()
+			.any(|host| host.ends_with(&format!(".{domain}.")))
+	{
+		MxProvider::Selfhosted
+	} else {
+		MxProvider::Other
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_fingerprint_mx_hosts() {
+		let fingerprint = |domain: &str, host: &str| fingerprint_mx(domain, &[host.into()]);
+
+		assert_eq!(
+			fingerprint("reacher.email", "aspmx.l.google.com."),
+			MxProvider::Google
+		);
+		assert_eq!(
+			fingerprint("example.org", "example-org.mail.protection.outlook.com"),
+			MxProvider::Outlook
+		);
+		assert_eq!(
+			fingerprint("example.org", "mx0a-001.pphosted.com."),
+			MxProvider::Proofpoint
+		);
+		assert_eq!(
+			fingerprint("example.org", "mx1.hc1234-56.iphmx.com."),
+			MxProvider::Ironport
+		);
+		assert!(MxProvider::Ironport.is_security_gateway());
+		assert!(!MxProvider::Google.is_security_gateway());
+		assert_eq!(
+			fingerprint("Example.org", "mx1.example.org."),
+			MxProvider::Selfhosted
+		);
+		assert_eq!(
+			fingerprint("example.org", "example.org."),
+			MxProvider::Selfhosted
+		);
+		assert_eq!(
+			fingerprint("example.org", "mx.some-host.net."),
+			MxProvider::Other
+		);
+	}
+}
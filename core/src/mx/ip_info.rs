@@ -0,0 +1,53 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! ASN and geolocation enrichment of the MX hosts' IP addresses.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use super::MxRecord;
+use crate::util::ip_info::{IpInfo, IpInfoSource};
+
+/// The network of one of a MX host's IP addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MxHostIpInfo {
+	/// The MX host.
+	pub host: String,
+	/// One of the IP addresses of the MX host.
+	pub ip: IpAddr,
+	/// The network of `ip`, with empty fields if the source doesn't know it.
+	#[serde(flatten)]
+	pub info: IpInfo,
+}
+
+/// Look up the network of the IP addresses of all the MX hosts in `source`.
+pub(crate) fn check_ip_info(records: &[MxRecord], source: &dyn IpInfoSource) -> Vec<MxHostIpInfo> {
+	let mut ip_info = Vec::new();
+
+	for record in records {
+		for ip in record.ips.iter().flatten() {
+			ip_info.push(MxHostIpInfo {
+				host: record.hostname.clone(),
+				ip: *ip,
+				info: source.lookup(*ip).unwrap_or_default(),
+			});
+		}
+	}
+
+	ip_info
+}
@@ -14,31 +14,107 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod dnsbl;
+mod fingerprint;
+mod ip_info;
+mod parked;
+mod rdns;
+mod record;
+
 use crate::syntax::SyntaxDetails;
-use crate::util::ser_with_display::ser_with_display;
-use async_std_resolver::{lookup::MxLookup, resolver_from_system_conf, ResolveError};
+use crate::util::{
+	constants::LOG_TARGET,
+	input_output::CheckEmailInput,
+	resolver::{create_resolver, DnsResolver},
+	ser_with_display::ser_with_display,
+};
+use async_std_resolver::{
+	lookup::{Lookup, MxLookup},
+	ResolveError,
+};
+use dnsbl::check_dnsbl;
+pub use dnsbl::{DnsblListing, SPAMHAUS_ZEN};
+pub use fingerprint::{fingerprint_mx, MxProvider};
+use ip_info::check_ip_info;
+pub use ip_info::MxHostIpInfo;
+use parked::check_parked_domain;
+pub use parked::{ParkedDomainDetails, ParkingProvider};
+use rdns::check_rdns;
+pub use rdns::MxHostRdns;
+use record::mx_records;
+pub use record::MxRecord;
 use serde::{ser::SerializeMap, Serialize, Serializer};
 use std::io::Error;
-use trust_dns_proto::rr::Name;
+use std::net::IpAddr;
+use std::sync::Arc;
+use trust_dns_proto::op::{Query, ResponseCode};
+use trust_dns_proto::rr::{rdata::MX, Name, RData, Record, RecordType};
+use trust_dns_resolver::error::ResolveErrorKind;
 
 /// Details about the MX lookup.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MxDetails {
 	/// MX lookup of this DNS.
 	pub lookup: Result<MxLookup, ResolveError>,
+	/// The records of `lookup`, sorted by priority, with their TTL and the
+	/// IP addresses of their host. Empty if no MX records were found.
+	pub mx_records: Vec<MxRecord>,
+	/// The domain whose MX records are in `lookup`. This is the email's
+	/// domain itself, unless `mx_parent_domain_fallback` is set and one of
+	/// its parent domains provided the MX records instead. It will be `None`
+	/// if no MX records were found.
+	pub mx_domain: Option<String>,
+	/// Whether `lookup` is the implicit MX (RFC 5321, section 5.1): the
+	/// domain has no MX records, but an A or AAAA record, so the domain
+	/// itself is used as the only MX host. Only set if
+	/// `implicit_mx_fallback` is set in the input.
+	pub implicit_mx: bool,
+	/// Whether the MX records of `mx_domain` are signed, with a valid DNSSEC
+	/// chain of trust. If false, the MX answer could have been spoofed. Only
+	/// set if `dnssec_validation` is set in the input, and if DNS queries are
+	/// not encrypted.
+	pub dnssec_validated: Option<bool>,
+	/// The MX hosts' IP addresses which are listed on a DNSBL. Only set if
+	/// `dnsbl_zones` is set in the input.
+	pub dnsbl_listings: Option<Vec<DnsblListing>>,
+	/// The provider hosting the MX servers, e.g. Google or Proofpoint. Only
+	/// set if MX records were found.
+	pub provider: Option<MxProvider>,
+	/// The reverse DNS of each of the MX hosts' IP addresses. Only set if
+	/// `check_mx_rdns` is set in the input.
+	pub rdns: Option<Vec<MxHostRdns>>,
+	/// The ASN, network owner and country of each of the MX hosts' IP
+	/// addresses. Only set if the context has an IP info source.
+	pub ip_info: Option<Vec<MxHostIpInfo>>,
+	/// Whether the domain is parked, and whether it resolves all its
+	/// subdomains with wildcard DNS records. Only set if `check_parked_domain` is set in the
+	/// input.
+	pub parked_domain: Option<ParkedDomainDetails>,
 }
 
 impl Default for MxDetails {
 	fn default() -> Self {
 		MxDetails {
 			lookup: Err(ResolveError::from("Skipped")),
+			mx_records: Vec::new(),
+			mx_domain: None,
+			implicit_mx: false,
+			dnssec_validated: None,
+			dnsbl_listings: None,
+			provider: None,
+			rdns: None,
+			ip_info: None,
+			parked_domain: None,
 		}
 	}
 }
 
 impl From<MxLookup> for MxDetails {
 	fn from(lookup: MxLookup) -> Self {
-		MxDetails { lookup: Ok(lookup) }
+		MxDetails {
+			lookup: Ok(lookup),
+			..Default::default()
+		}
 	}
 }
 
@@ -53,14 +129,41 @@ impl Serialize for MxDetails {
 			.map(|lookup| {
 				lookup
 					.iter()
-					.map(|host| host.exchange().to_string())
+					.map(|host| host_to_string(host.exchange()))
 					.collect::<Vec<_>>()
 			})
 			.unwrap_or_else(|_| Vec::new()); // In case of a resolve error, we don't serialize the error.
 
-		let mut map = serializer.serialize_map(Some(2))?;
+		let mut map = serializer.serialize_map(None)?;
 		map.serialize_entry("accepts_mail", &!records.is_empty())?;
 		map.serialize_entry("records", &records)?;
+		if !self.mx_records.is_empty() {
+			map.serialize_entry("mx_records", &self.mx_records)?;
+		}
+		if let Some(provider) = &self.provider {
+			map.serialize_entry("provider", provider)?;
+		}
+		if let Some(mx_domain) = &self.mx_domain {
+			map.serialize_entry("mx_domain", mx_domain)?;
+		}
+		if self.implicit_mx {
+			map.serialize_entry("implicit_mx", &true)?;
+		}
+		if let Some(dnssec_validated) = self.dnssec_validated {
+			map.serialize_entry("dnssec_validated", &dnssec_validated)?;
+		}
+		if let Some(dnsbl_listings) = &self.dnsbl_listings {
+			map.serialize_entry("dnsbl_listings", dnsbl_listings)?;
+		}
+		if let Some(rdns) = &self.rdns {
+			map.serialize_entry("rdns", rdns)?;
+		}
+		if let Some(ip_info) = &self.ip_info {
+			map.serialize_entry("ip_info", ip_info)?;
+		}
+		if let Some(parked_domain) = &self.parked_domain {
+			map.serialize_entry("parked_domain", parked_domain)?;
+		}
 		map.end()
 	}
 }
@@ -75,6 +178,32 @@ pub enum MxError {
 	/// Error while resolving MX lookups.
 	#[serde(serialize_with = "ser_with_display")]
 	ResolveError(Box<ResolveError>),
+	/// The domain publishes a null MX record (RFC 7505), i.e. `MX 0 .`: it
+	/// explicitly doesn't accept any email.
+	NullMx,
+	/// The domain doesn't exist (NXDOMAIN).
+	NxDomain,
+	/// The DNS server failed to answer (SERVFAIL), e.g. because the domain's
+	/// nameservers are unreachable. This is usually transient.
+	ServFail,
+	/// The DNS query timed out. This is usually transient.
+	Timeout,
+}
+
+impl MxError {
+	/// Is the error transient, so that verifying the email again later may
+	/// succeed?
+	pub fn is_retryable(&self) -> bool {
+		matches!(
+			self,
+			MxError::IoError(_) | MxError::ResolveError(_) | MxError::ServFail | MxError::Timeout
+		)
+	}
+
+	/// Does the error prove that the domain doesn't accept email?
+	pub fn is_invalid_domain(&self) -> bool {
+		matches!(self, MxError::NullMx | MxError::NxDomain)
+	}
 }
 
 impl From<ResolveError> for MxError {
@@ -83,17 +212,320 @@ impl From<ResolveError> for MxError {
 	}
 }
 
+/// Classify a failed MX lookup. Returns None if the domain exists but has no
+/// MX records, which is not an error: `MxDetails` then has no records.
+fn classify_resolve_error(error: &ResolveError) -> Option<MxError> {
+	match error.kind() {
+		ResolveErrorKind::NoRecordsFound {
+			response_code: ResponseCode::NXDomain,
+			..
+		} => Some(MxError::NxDomain),
+		ResolveErrorKind::NoRecordsFound {
+			response_code: ResponseCode::ServFail,
+			..
+		} => Some(MxError::ServFail),
+		ResolveErrorKind::NoRecordsFound { .. } => None,
+		ResolveErrorKind::Timeout => Some(MxError::Timeout),
+		_ => Some(error.clone().into()),
+	}
+}
+
+/// Encode an IP address as a MX host. Since it's not a valid domain, the
+/// whole `[ip]` literal is stored as a single label.
+fn ip_literal_to_name(ip: IpAddr) -> Name {
+	Name::from_labels(vec![format!("[{ip}]").as_bytes()])
+		.expect("An IP literal is a valid single label. qed.")
+}
+
+/// Encode the host of an LMTP server, a hostname or an IP address, as a MX
+/// host.
+fn lmtp_server_to_name(host: &str) -> Result<Name, MxError> {
+	match host.parse::<IpAddr>() {
+		Ok(ip) => Ok(ip_literal_to_name(ip)),
+		Err(_) => Name::from_ascii(host).map_err(|err| MxError::ResolveError(Box::new(err.into()))),
+	}
+}
+
+/// Get the string to connect to a MX host: the hostname without the final
+/// dot, or the IP address for IP literals (in brackets for IPv6).
+pub fn host_to_string(host: &Name) -> String {
+	let ip = match host.iter().collect::<Vec<_>>().as_slice() {
+		[label] => std::str::from_utf8(label)
+			.ok()
+			.and_then(|label| label.strip_prefix('['))
+			.and_then(|label| label.strip_suffix(']'))
+			.and_then(|ip| ip.parse::<IpAddr>().ok()),
+		_ => None,
+	};
+
+	match ip {
+		Some(ip) => ip_to_string(ip),
+		None => host.to_string().trim_end_matches('.').to_string(),
+	}
+}
+
+/// Get the string to connect to an IP address, in brackets for IPv6.
+pub(crate) fn ip_to_string(ip: IpAddr) -> String {
+	match ip {
+		IpAddr::V4(ip) => ip.to_string(),
+		IpAddr::V6(ip) => format!("[{ip}]"),
+	}
+}
+
+/// Get the IP addresses of a MX host.
+pub(crate) async fn host_ips(resolver: &DnsResolver, host: &str) -> Vec<IpAddr> {
+	if let Ok(ip) = host
+		.trim_start_matches('[')
+		.trim_end_matches(']')
+		.parse::<IpAddr>()
+	{
+		return vec![ip];
+	}
+
+	let mut ips = Vec::new();
+	for record_type in [RecordType::A, RecordType::AAAA] {
+		if let Ok(lookup) = resolver.lookup(host, record_type).await {
+			ips.extend(lookup.iter().filter_map(|rdata| match rdata {
+				RData::A(ip) => Some(IpAddr::V4(*ip)),
+				RData::AAAA(ip) => Some(IpAddr::V6(*ip)),
+				_ => None,
+			}));
+		}
+	}
+
+	ips
+}
+
+/// Is `lookup` a null MX (RFC 7505), i.e. a single record pointing to the
+/// root domain?
+fn is_null_mx(lookup: &MxLookup) -> bool {
+	let mut records = lookup.iter();
+
+	matches!(
+		(records.next(), records.next()),
+		(Some(mx), None) if mx.exchange().is_root()
+	)
+}
+
+/// Build a lookup with a single MX record, pointing to `host`.
+fn single_mx_lookup(host: Name, ttl: u32) -> MxLookup {
+	let record = Record::from_rdata(host.clone(), ttl, RData::MX(MX::new(0, host.clone())));
+
+	Lookup::new_with_max_ttl(Query::query(host, RecordType::MX), Arc::from(vec![record])).into()
+}
+
+/// Get the implicit MX of `domain`, i.e. the domain itself, if it has an A
+/// or AAAA record.
+async fn implicit_mx_lookup(resolver: &DnsResolver, domain: &str) -> Option<MxLookup> {
+	for record_type in [RecordType::A, RecordType::AAAA] {
+		if let Ok(lookup) = resolver.lookup(domain, record_type).await {
+			if let Some(record) = lookup.record_iter().next() {
+				return Some(single_mx_lookup(record.name().clone(), record.ttl()));
+			}
+		}
+	}
+
+	None
+}
+
+/// Get the parent domain of `domain`, i.e. `domain` without its first label.
+/// Returns `None` if `domain` is already a registrable domain, e.g.
+/// `example.com` or `example.co.uk`, as its parent would be a public suffix
+/// from the Public Suffix List.
+fn parent_domain(domain: &str) -> Option<&str> {
+	let domain = domain.trim_end_matches('.');
+	let (_, parent) = domain.split_once('.')?;
+
+	match psl::domain_str(domain) {
+		Some(registrable) if registrable.len() < domain.len() => Some(parent),
+		_ => None,
+	}
+}
+
 /// Make a MX lookup.
-pub async fn check_mx(syntax: &SyntaxDetails) -> Result<MxDetails, MxError> {
-	// Construct a new Resolver with default configuration options
-	let resolver = resolver_from_system_conf().await?;
+///
+/// The email's full domain is always looked up as-is, subdomains included.
+/// If it has no MX records and `input.mx_parent_domain_fallback` is set, we
+/// try its parent domains one level at a time, up to its registrable domain,
+/// and report which one provided the MX records in `mx_domain`. A failed
+/// lookup (SERVFAIL, timeout...) doesn't mean there are no MX records, so we
+/// don't fall back to the parent domains then.
+///
+/// If the email's domain has no MX records and `input.implicit_mx_fallback`
+/// is set, its A/AAAA records are tried before its parent domains: the
+/// domain itself is then the MX host, and `implicit_mx` is set.
+///
+/// IP literal domains, e.g. `user@[192.168.1.1]`, are not looked up: the IP
+/// address is returned as the only MX host. Neither are the domains when
+/// `input.lmtp_server` is set, as internal domains may have no public MX
+/// records: the LMTP server is returned as the only MX host.
+///
+/// If `input.dnssec_validation` is set, the MX records are also validated
+/// with DNSSEC, see `dnssec_validated`. If `input.dnsbl_zones` is set, the
+/// MX hosts' IP addresses are checked against these DNSBLs, see
+/// `dnsbl_listings`. If `input.check_mx_rdns` is set, their reverse DNS is
+/// resolved, see `rdns`. If the context has an IP info source, their
+/// network is looked up, see `ip_info`. If `input.check_parked_domain` is
+/// set, the domain is checked for parking, see `parked_domain`.
+///
+/// The records are also returned in structured form, see `mx_records`. If
+/// `input.resolve_mx_ips` is set, the IP addresses of their hosts are
+/// resolved.
+///
+/// The provider hosting the MX servers is identified from their hostnames,
+/// see `provider`.
+///
+/// Returns `MxError::NullMx` if the domain explicitly doesn't accept email,
+/// and `MxError::NxDomain` if it doesn't exist. If the domain exists, but
+/// has no MX records, `lookup` is an error instead.
+pub async fn check_mx(
+	syntax: &SyntaxDetails,
+	input: &CheckEmailInput,
+) -> Result<MxDetails, MxError> {
+	let resolver = create_resolver(input).await?;
 
-	// Lookup the MX records associated with a name.
-	// The final dot forces this to be an FQDN, otherwise the search rules as specified
-	// in `ResolverOpts` will take effect. FQDN's are generally cheaper queries.
-	match resolver.mx_lookup(syntax.domain.as_str()).await {
-		Ok(lookup) => Ok(MxDetails::from(lookup)),
-		Err(err) => Ok(MxDetails { lookup: Err(err) }),
+	let mut details = match (syntax.ip_literal, &input.lmtp_server) {
+		(_, Some(lmtp_server)) => MxDetails {
+			lookup: Ok(single_mx_lookup(lmtp_server_to_name(&lmtp_server.host)?, 0)),
+			mx_domain: Some(syntax.domain.clone()),
+			..Default::default()
+		},
+		(Some(ip), None) => MxDetails {
+			lookup: Ok(single_mx_lookup(ip_literal_to_name(ip), 0)),
+			mx_domain: Some(syntax.domain.clone()),
+			..Default::default()
+		},
+		(None, None) => lookup_mx(syntax, input, &resolver).await?,
+	};
+
+	if let Ok(lookup) = &details.lookup {
+		details.mx_records = mx_records(lookup);
+	}
+	let ip_info_source = input.context.ip_info_source();
+	if input.resolve_mx_ips
+		|| !input.dnsbl_zones.is_empty()
+		|| input.check_mx_rdns
+		|| ip_info_source.is_some()
+	{
+		for record in details.mx_records.iter_mut() {
+			record.ips = Some(host_ips(&resolver, &record.hostname).await);
+		}
+	}
+
+	let mx_hosts = details
+		.mx_records
+		.iter()
+		.map(|record| record.hostname.clone())
+		.collect::<Vec<_>>();
+	if details.lookup.is_ok() {
+		let domain = details.mx_domain.as_deref().unwrap_or(&syntax.domain);
+		details.provider = Some(fingerprint_mx(domain, &mx_hosts));
+	}
+
+	if details.lookup.is_ok() && !input.dnsbl_zones.is_empty() {
+		details.dnsbl_listings =
+			Some(check_dnsbl(&resolver, &details.mx_records, &input.dnsbl_zones).await);
+	}
+
+	if details.lookup.is_ok() && input.check_mx_rdns {
+		details.rdns = Some(check_rdns(&resolver, &details.mx_records).await);
+	}
+
+	if let (Ok(_), Some(source)) = (&details.lookup, ip_info_source) {
+		details.ip_info = Some(check_ip_info(&details.mx_records, source));
+	}
+
+	if input.check_parked_domain && syntax.ip_literal.is_none() {
+		details.parked_domain =
+			Some(check_parked_domain(&resolver, &syntax.domain, &mx_hosts).await);
+	}
+
+	Ok(details)
+}
+
+/// Make the MX lookup of a domain, with the fallbacks described in
+/// `check_mx`.
+async fn lookup_mx(
+	syntax: &SyntaxDetails,
+	input: &CheckEmailInput,
+	resolver: &DnsResolver,
+) -> Result<MxDetails, MxError> {
+	let mut domain = syntax.domain.as_str();
+	let mut first_err = None;
+	loop {
+		// Lookup the MX records associated with a name.
+		// The final dot forces this to be an FQDN, otherwise the search rules as specified
+		// in `ResolverOpts` will take effect. FQDN's are generally cheaper queries.
+		let err = match resolver.mx_lookup(domain).await {
+			Ok(lookup) if is_null_mx(&lookup) => return Err(MxError::NullMx),
+			Ok(lookup) => {
+				return Ok(MxDetails {
+					lookup: Ok(lookup),
+					mx_domain: Some(domain.to_string()),
+					dnssec_validated: if input.dnssec_validation {
+						resolver.validate_mx(domain).await
+					} else {
+						None
+					},
+					..Default::default()
+				})
+			}
+			Err(err) => err,
+		};
+
+		// The implicit MX only applies to the email's own domain, not to its
+		// parents. Both it and the parent domains are only tried if the domain
+		// exists without MX records: a failed lookup (SERVFAIL, timeout...)
+		// doesn't mean there are none.
+		let no_mx_records = matches!(
+			err.kind(),
+			ResolveErrorKind::NoRecordsFound {
+				response_code: ResponseCode::NoError,
+				..
+			}
+		);
+		if first_err.is_none() && input.implicit_mx_fallback && no_mx_records {
+			if let Some(lookup) = implicit_mx_lookup(resolver, domain).await {
+				log::debug!(
+					target: LOG_TARGET,
+					"[email={}] No MX records on {}, using its implicit MX",
+					input.to_email,
+					domain
+				);
+				return Ok(MxDetails {
+					lookup: Ok(lookup),
+					mx_domain: Some(domain.to_string()),
+					implicit_mx: true,
+					..Default::default()
+				});
+			}
+		}
+
+		// We only report the error of the email's own domain.
+		let first_err = first_err.get_or_insert(err);
+
+		match parent_domain(domain) {
+			Some(parent) if input.mx_parent_domain_fallback && no_mx_records => {
+				log::debug!(
+					target: LOG_TARGET,
+					"[email={}] No MX records on {}, trying parent domain {}",
+					input.to_email,
+					domain,
+					parent
+				);
+				domain = parent;
+			}
+			_ => {
+				if let Some(err) = classify_resolve_error(first_err) {
+					return Err(err);
+				}
+
+				return Ok(MxDetails {
+					lookup: Err(first_err.clone()),
+					..Default::default()
+				});
+			}
+		}
 	}
 }
 
@@ -105,3 +537,257 @@ pub fn is_antispam_mx(host: &Name) -> bool {
 	// mx.spamexperts.com (see https://documentation.n-able.com/spamexperts/userguide/Content/B_Admin%20Level/domains/mx-records.htm)
 	|| host.contains("spamexperts.com")
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_convert_ip_literal_hosts_to_string() {
+		assert_eq!(
+			host_to_string(&ip_literal_to_name("192.168.1.1".parse().unwrap())),
+			"192.168.1.1"
+		);
+		assert_eq!(
+			host_to_string(&ip_literal_to_name("2001:db8::1".parse().unwrap())),
+			"[2001:db8::1]"
+		);
+		assert_eq!(
+			host_to_string(&Name::from_ascii("mx.example.org.").unwrap()),
+			"mx.example.org"
+		);
+	}
+
+	#[test]
+	fn should_detect_null_mx() {
+		let lookup = |exchanges: &[&str]| {
+			let name = Name::from_ascii("example.org.").unwrap();
+			let records = exchanges
+				.iter()
+				.map(|exchange| {
+					let exchange = Name::from_ascii(exchange).unwrap();
+					Record::from_rdata(name.clone(), 0, RData::MX(MX::new(0, exchange)))
+				})
+				.collect::<Vec<_>>();
+
+			MxLookup::from(Lookup::new_with_max_ttl(
+				Query::query(name, RecordType::MX),
+				Arc::from(records),
+			))
+		};
+
+		assert!(is_null_mx(&lookup(&["."])));
+		assert!(!is_null_mx(&lookup(&["mx.example.org."])));
+		assert!(!is_null_mx(&lookup(&[".", "mx.example.org."])));
+	}
+
+	#[test]
+	fn should_serialize_implicit_mx() {
+		let host = Name::from_ascii("example.org.").unwrap();
+		let details = MxDetails {
+			lookup: Ok(single_mx_lookup(host, 300)),
+			mx_domain: Some("example.org".into()),
+			implicit_mx: true,
+			dnssec_validated: Some(false),
+			dnsbl_listings: None,
+			provider: None,
+			rdns: None,
+			ip_info: None,
+			parked_domain: None,
+			mx_records: Vec::new(),
+		};
+
+		assert_eq!(
+			serde_json::to_string(&details).unwrap(),
+			r#"{"accepts_mail":true,"records":["example.org"],"mx_domain":"example.org","implicit_mx":true,"dnssec_validated":false}"#
+		);
+	}
+
+	#[test]
+	fn should_classify_resolve_errors() {
+		let no_records = |response_code| -> ResolveError {
+			ResolveErrorKind::NoRecordsFound {
+				query: Box::new(Query::query(
+					Name::from_ascii("example.org.").unwrap(),
+					RecordType::MX,
+				)),
+				soa: None,
+				negative_ttl: None,
+				response_code,
+				trusted: true,
+			}
+			.into()
+		};
+
+		assert!(matches!(
+			classify_resolve_error(&no_records(ResponseCode::NXDomain)),
+			Some(MxError::NxDomain)
+		));
+		assert!(matches!(
+			classify_resolve_error(&no_records(ResponseCode::ServFail)),
+			Some(MxError::ServFail)
+		));
+		assert!(classify_resolve_error(&no_records(ResponseCode::NoError)).is_none());
+		assert!(matches!(
+			classify_resolve_error(&ResolveErrorKind::Timeout.into()),
+			Some(MxError::Timeout)
+		));
+		assert!(MxError::Timeout.is_retryable());
+		assert!(!MxError::NxDomain.is_retryable());
+	}
+
+	/// A resolver with an A record for every name, whose MX lookups fail
+	/// with the given error.
+	#[derive(Debug)]
+	struct FailingMxResolver(ResolveErrorKind);
+
+	#[async_trait::async_trait]
+	impl crate::util::resolver::Resolver for FailingMxResolver {
+		async fn lookup(
+			&self,
+			name: &str,
+			record_type: RecordType,
+		) -> Result<Lookup, ResolveError> {
+			let query = Query::query(Name::from_ascii(name)?, record_type);
+			if record_type == RecordType::MX {
+				return Err(self.0.clone().into());
+			}
+
+			let a = RData::A("192.0.2.1".parse().unwrap());
+			let record = Record::from_rdata(query.name().clone(), 300, a);
+			Ok(Lookup::new_with_max_ttl(query, Arc::from(vec![record])))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_only_use_implicit_mx_without_mx_records() {
+		let check = |kind: ResolveErrorKind| async move {
+			let mut input = CheckEmailInput::new("foo@example.org".into());
+			input.set_implicit_mx_fallback(true).set_context(
+				crate::CheckEmailContext::new().with_resolver(Arc::new(FailingMxResolver(kind))),
+			);
+			let syntax = crate::syntax::check_syntax("foo@example.org");
+			check_mx(&syntax, &input).await
+		};
+		let no_records = |response_code| ResolveErrorKind::NoRecordsFound {
+			query: Box::new(Query::query(
+				Name::from_ascii("example.org.").unwrap(),
+				RecordType::MX,
+			)),
+			soa: None,
+			negative_ttl: None,
+			response_code,
+			trusted: true,
+		};
+
+		assert!(
+			check(no_records(ResponseCode::NoError))
+				.await
+				.unwrap()
+				.implicit_mx
+		);
+		assert!(matches!(
+			check(no_records(ResponseCode::ServFail)).await,
+			Err(MxError::ServFail)
+		));
+		assert!(matches!(
+			check(ResolveErrorKind::Timeout).await,
+			Err(MxError::Timeout)
+		));
+	}
+
+	#[tokio::test]
+	async fn should_not_look_up_mx_with_lmtp_server() {
+		let mut input = CheckEmailInput::new("foo@corp.internal".into());
+		input
+			.set_lmtp_server(Some(crate::util::input_output::LmtpServer {
+				host: "10.0.0.25".into(),
+				port: 24,
+			}))
+			.set_context(
+				crate::CheckEmailContext::new()
+					.with_resolver(Arc::new(FailingMxResolver(ResolveErrorKind::Timeout))),
+			);
+		let syntax = crate::syntax::check_syntax("foo@corp.internal");
+		let details = check_mx(&syntax, &input).await.unwrap();
+
+		let hosts = details
+			.lookup
+			.unwrap()
+			.iter()
+			.map(|mx| host_to_string(mx.exchange()))
+			.collect::<Vec<_>>();
+		assert_eq!(hosts, vec!["10.0.0.25".to_string()]);
+		assert_eq!(details.mx_domain.as_deref(), Some("corp.internal"));
+	}
+
+	#[test]
+	fn should_get_parent_domain() {
+		assert_eq!(parent_domain("team.example.com"), Some("example.com"));
+		assert_eq!(
+			parent_domain("a.team.example.com."),
+			Some("team.example.com")
+		);
+		assert_eq!(parent_domain("example.com"), None);
+		assert_eq!(parent_domain("com"), None);
+		assert_eq!(parent_domain("team.example.co.uk"), Some("example.co.uk"));
+		assert_eq!(parent_domain("example.co.uk"), None);
+		assert_eq!(parent_domain("co.uk"), None);
+	}
+
+	/// A resolver with MX records for `example.org` only, whose other MX
+	/// lookups fail with the given error.
+	#[derive(Debug)]
+	struct ParentMxResolver(ResolveErrorKind);
+
+	#[async_trait::async_trait]
+	impl crate::util::resolver::Resolver for ParentMxResolver {
+		async fn lookup(
+			&self,
+			name: &str,
+			record_type: RecordType,
+		) -> Result<Lookup, ResolveError> {
+			let query = Query::query(Name::from_ascii(name)?, record_type);
+			if record_type != RecordType::MX || name.trim_end_matches('.') != "example.org" {
+				return Err(self.0.clone().into());
+			}
+
+			let mx = RData::MX(MX::new(10, Name::from_ascii("mx.example.org.")?));
+			let record = Record::from_rdata(query.name().clone(), 300, mx);
+			Ok(Lookup::new_with_max_ttl(query, Arc::from(vec![record])))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_only_fall_back_to_parent_domain_without_mx_records() {
+		let check = |kind: ResolveErrorKind| async move {
+			let mut input = CheckEmailInput::new("foo@team.example.org".into());
+			input.set_mx_parent_domain_fallback(true).set_context(
+				crate::CheckEmailContext::new().with_resolver(Arc::new(ParentMxResolver(kind))),
+			);
+			let syntax = crate::syntax::check_syntax("foo@team.example.org");
+			check_mx(&syntax, &input).await
+		};
+		let no_records = |response_code| ResolveErrorKind::NoRecordsFound {
+			query: Box::new(Query::query(
+				Name::from_ascii("team.example.org.").unwrap(),
+				RecordType::MX,
+			)),
+			soa: None,
+			negative_ttl: None,
+			response_code,
+			trusted: true,
+		};
+
+		let details = check(no_records(ResponseCode::NoError)).await.unwrap();
+		assert_eq!(details.mx_domain.as_deref(), Some("example.org"));
+		assert!(matches!(
+			check(no_records(ResponseCode::ServFail)).await,
+			Err(MxError::ServFail)
+		));
+		assert!(matches!(
+			check(ResolveErrorKind::Timeout).await,
+			Err(MxError::Timeout)
+		));
+	}
+}
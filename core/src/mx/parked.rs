@@ -0,0 +1,139 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of parked domains, and of domains with wildcard DNS records.
+
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::{RData, RecordType};
+
+use crate::util::resolver::DnsResolver;
+
+/// Domain parking providers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParkingProvider {
+	Sedo,
+	Bodis,
+	Godaddy,
+	Parkingcrew,
+	Above,
+	Dan,
+}
+
+/// Suffixes of the nameservers and MX hosts of parking providers, fully
+/// qualified.
+const PARKING_HOST_SUFFIXES: &[(&str, ParkingProvider)] = &[
+	(".sedoparking.com.", ParkingProvider::Sedo),
+	(".bodis.com.", ParkingProvider::Bodis),
+	(".parkingcrew.net.", ParkingProvider::Parkingcrew),
+	(".above.com.", ParkingProvider::Above),
+	(".dan.com.", ParkingProvider::Dan),
+	(".park.do.", ParkingProvider::Dan),
+	// GoDaddy's parking nameservers. Its regular nameservers,
+	// domaincontrol.com, are used by active domains too.
+	(".parkeddomain.com.", ParkingProvider::Godaddy),
+	(".afternic.com.", ParkingProvider::Godaddy),
+];
+
+/// Details about whether a domain is parked, i.e. registered but not used,
+/// and typically showing ads. Parked domains often accept all emails.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ParkedDomainDetails {
+	/// Is the domain parked by a known parking provider?
+	pub is_parked: bool,
+	/// The parking provider, if the domain's nameservers or MX hosts belong
+	/// to a known one.
+	pub parking_provider: Option<ParkingProvider>,
+	/// Does the domain resolve any subdomain, even random ones? Many active
+	/// domains do too, so it doesn't make the domain parked on its own.
+	pub has_wildcard_dns: bool,
+}
+
+/// Find the parking provider of the given nameservers or MX hosts.
+fn parking_provider(hosts: &[String]) -> Option<ParkingProvider> {
+	hosts.iter().find_map(|host| {
+		let host = format!(".{}.", host.trim_end_matches('.').to_lowercase());
+		PARKING_HOST_SUFFIXES
+			.iter()
+			.find(|(suffix, _)| host.ends_with(suffix))
+			.map(|(_, provider)| *provider)
+	})
+}
+
+/// Get the nameservers of `domain`. Lookup errors are treated as no
+/// nameservers.
+async fn nameservers(resolver: &DnsResolver, domain: &str) -> Vec<String> {
+	match resolver.lookup(domain, RecordType::NS).await {
+		Ok(lookup) => lookup
+			.iter()
+			.filter_map(|rdata| match rdata {
+				RData::NS(name) => Some(name.to_string()),
+				_ => None,
+			})
+			.collect(),
+		Err(_) => Vec::new(),
+	}
+}
+
+/// Check whether `domain` is parked, from its nameservers and its MX hosts,
+/// and whether it has wildcard DNS records, from the A records of a random
+/// subdomain.
+pub(crate) async fn check_parked_domain(
+	resolver: &DnsResolver,
+	domain: &str,
+	mx_hosts: &[String],
+) -> ParkedDomainDetails {
+	let mut hosts = nameservers(resolver, domain).await;
+	hosts.extend_from_slice(mx_hosts);
+	let parking_provider = parking_provider(&hosts);
+
+	let label = rand::thread_rng()
+		.sample_iter(&Alphanumeric)
+		.take(20)
+		.map(char::from)
+		.collect::<String>()
+		.to_lowercase();
+	let has_wildcard_dns = resolver
+		.lookup(&format!("{label}.{domain}"), RecordType::A)
+		.await
+		.is_ok();
+
+	ParkedDomainDetails {
+		is_parked: parking_provider.is_some(),
+		parking_provider,
+		has_wildcard_dns,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_detect_parking_providers() {
+		assert_eq!(
+			parking_provider(&["ns1.sedoparking.com.".into()]),
+			Some(ParkingProvider::Sedo)
+		);
+		assert_eq!(
+			parking_provider(&["ns1.example.org.".into(), "NS2.BODIS.COM".into()]),
+			Some(ParkingProvider::Bodis)
+		);
+		assert_eq!(parking_provider(&["ns01.domaincontrol.com.".into()]), None);
+	}
+}
@@ -0,0 +1,99 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reverse DNS (PTR) lookups of the MX hosts' IP addresses.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::{Name, RData, RecordType};
+
+use super::MxRecord;
+use crate::util::resolver::DnsResolver;
+
+/// The reverse DNS of one of a MX host's IP addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MxHostRdns {
+	/// The MX host.
+	pub host: String,
+	/// One of the IP addresses of the MX host.
+	pub ip: IpAddr,
+	/// The PTR hostnames of `ip`, without their final dot. Empty if `ip`
+	/// has no reverse DNS.
+	pub ptr: Vec<String>,
+	/// Does one of the PTR hostnames match the MX host?
+	pub matches_host: bool,
+}
+
+/// Get the PTR hostnames of `ip`. Lookup errors are treated as no reverse
+/// DNS.
+async fn ptr_lookup(resolver: &DnsResolver, ip: IpAddr) -> Vec<String> {
+	match resolver
+		.lookup(&Name::from(ip).to_string(), RecordType::PTR)
+		.await
+	{
+		Ok(lookup) => lookup
+			.iter()
+			.filter_map(|rdata| match rdata {
+				RData::PTR(name) => Some(name.to_string().trim_end_matches('.').to_lowercase()),
+				_ => None,
+			})
+			.collect(),
+		Err(_) => Vec::new(),
+	}
+}
+
+/// Build the reverse DNS details of an IP address of `host`.
+fn mx_host_rdns(host: &str, ip: IpAddr, ptr: Vec<String>) -> MxHostRdns {
+	let matches_host = ptr.iter().any(|name| name.eq_ignore_ascii_case(host));
+
+	MxHostRdns {
+		host: host.to_string(),
+		ip,
+		ptr,
+		matches_host,
+	}
+}
+
+/// Resolve the PTR hostnames of the IP addresses of all the MX hosts.
+pub(crate) async fn check_rdns(resolver: &DnsResolver, records: &[MxRecord]) -> Vec<MxHostRdns> {
+	let mut rdns = Vec::new();
+
+	for record in records {
+		for ip in record.ips.iter().flatten() {
+			let ptr = ptr_lookup(resolver, *ip).await;
+			rdns.push(mx_host_rdns(&record.hostname, *ip, ptr));
+		}
+	}
+
+	rdns
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_match_ptr_with_host() {
+		let ip = "192.0.2.1".parse().unwrap();
+
+		assert!(mx_host_rdns("mx.example.org", ip, vec!["MX.example.org".into()]).matches_host);
+		assert!(
+			!mx_host_rdns("mx.example.org", ip, vec!["host-192-0-2-1.isp.net".into()]).matches_host
+		);
+		assert!(!mx_host_rdns("mx.example.org", ip, vec![]).matches_host);
+	}
+}
@@ -0,0 +1,104 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Structured MX records.
+
+use std::net::IpAddr;
+
+use async_std_resolver::lookup::MxLookup;
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::RData;
+
+use super::host_to_string;
+
+/// A MX record, with the IP addresses of its host.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MxRecord {
+	/// The preference of the record: lower values are tried first.
+	pub priority: u16,
+	/// The MX host, without its final dot. IP literal hosts are the IP
+	/// address itself.
+	pub hostname: String,
+	/// The remaining time to live of the record, in seconds.
+	pub ttl: u32,
+	/// The IP addresses of the host. Only resolved if `resolve_mx_ips`, or
+	/// another check needing them, is set in the input.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ips: Option<Vec<IpAddr>>,
+}
+
+/// Get the records of `lookup`, sorted by priority.
+pub(crate) fn mx_records(lookup: &MxLookup) -> Vec<MxRecord> {
+	let mut records = lookup
+		.as_lookup()
+		.record_iter()
+		.filter_map(|record| match record.data() {
+			Some(RData::MX(mx)) => Some(MxRecord {
+				priority: mx.preference(),
+				hostname: host_to_string(mx.exchange()),
+				ttl: record.ttl(),
+				ips: None,
+			}),
+			_ => None,
+		})
+		.collect::<Vec<_>>();
+	records.sort_by_key(|record| record.priority);
+
+	records
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_std_resolver::lookup::Lookup;
+	use std::sync::Arc;
+	use trust_dns_proto::op::Query;
+	use trust_dns_proto::rr::{rdata::MX, Name, Record, RecordType};
+
+	#[test]
+	fn should_sort_records_by_priority() {
+		let name = Name::from_ascii("example.org.").unwrap();
+		let record = |priority, host, ttl| {
+			let mx = MX::new(priority, Name::from_ascii(host).unwrap());
+			Record::from_rdata(name.clone(), ttl, RData::MX(mx))
+		};
+		let lookup = MxLookup::from(Lookup::new_with_max_ttl(
+			Query::query(name.clone(), RecordType::MX),
+			Arc::from(vec![
+				record(20, "mx2.example.org.", 600),
+				record(10, "mx1.example.org.", 300),
+			]),
+		));
+
+		assert_eq!(
+			mx_records(&lookup),
+			vec![
+				MxRecord {
+					priority: 10,
+					hostname: "mx1.example.org".into(),
+					ttl: 300,
+					ips: None,
+				},
+				MxRecord {
+					priority: 20,
+					hostname: "mx2.example.org".into(),
+					ttl: 600,
+					ips: None,
+				},
+			]
+		);
+	}
+}
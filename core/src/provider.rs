@@ -0,0 +1,238 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Classify an email's provider into a coarse category, independently of
+//! the specific provider.
+
+use serde::{Deserialize, Serialize};
+
+use crate::misc::is_free_provider;
+use crate::util::input_output::CheckEmailOutput;
+
+/// Suffixes of MX hosts of free webmail providers. This catches country
+/// variants of consumer domains, e.g. hotmail.fr or yahoo.co.uk.
+const CONSUMER_MX_SUFFIXES: &[&str] = &["olc.protection.outlook.com.", "yahoodns.net."];
+
+/// Suffixes of MX hosts of providers hosting email for businesses on their
+/// own domain.
+const BUSINESS_HOSTED_MX_SUFFIXES: &[&str] = &[
+	".google.com.",
+	".googlemail.com.",
+	".mail.protection.outlook.com.",
+	".zoho.com.",
+	".zoho.eu.",
+	".mimecast.com.",
+	".pphosted.com.",
+	".messagelabs.com.",
+	".secureserver.net.",
+	".ovh.net.",
+	".ionos.com.",
+	".protonmail.ch.",
+	".yandex.net.",
+	".mailgun.org.",
+	".fastmail.com.",
+	".messagingengine.com.",
+];
+
+/// A coarse category of email provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProviderCategory {
+	/// Free webmail provider used by individuals, e.g. Gmail or Yahoo.
+	Consumer,
+	/// Company domain, with email hosted by a third-party provider, e.g.
+	/// Google Workspace or Microsoft 365.
+	BusinessHosted,
+	/// Domain hosting its own mail servers.
+	SelfHosted,
+	/// Disposable email address provider.
+	Disposable,
+	/// None of the above could be determined.
+	Unknown,
+}
+
+/// Detect the provider category from the domain, the MX hosts and whether
+/// the address is disposable. If `free_provider_domains` is None, the
+/// bundled list of free email providers is used.
+pub fn detect_provider_category(
+	domain: &str,
+	mx_hosts: &[String],
+	is_disposable: bool,
+	free_provider_domains: Option<&[String]>,
+) -> ProviderCategory {
+	if is_disposable {
+		return ProviderCategory::Disposable;
+	}
+
+	let domain = domain.to_lowercase();
+	let mx_hosts = mx_hosts
+		.iter()
+		.map(|host| {
+			// Make sure all hosts are fully qualified, to match the suffixes.
+			let host = host.to_lowercase();
+			if host.ends_with('.') {
+				host
+			} else {
+				format!("{host}.")
+			}
+		})
+		.collect::<Vec<_>>();
+	let has_mx_suffix = |suffixes: &[&str]| {
+		mx_hosts
+			.iter()
+			.any(|host| suffixes.iter().any(|suffix| host.ends_with(suffix)))
+	};
+
+	if is_free_provider(&domain, free_provider_domains) || has_mx_suffix(CONSUMER_MX_SUFFIXES) {
+		ProviderCategory::Consumer
+	} else if has_mx_suffix(BUSINESS_HOSTED_MX_SUFFIXES) {
+		ProviderCategory::BusinessHosted
+	} else if mx_hosts
+		.iter()
+		.any(|host| host == &format!("{domain}.") || host.ends_with(&format!(".{domain}.")))
+	{
+		ProviderCategory::SelfHosted
+	} else {
+		ProviderCategory::Unknown
+	}
+}
+
+/// Detect the provider category of a verification's output, with the
+/// input's `free_provider_domains`.
+pub fn calculate_provider_category(
+	output: &CheckEmailOutput,
+	free_provider_domains: Option<&[String]>,
+) -> ProviderCategory {
+	let mx_hosts = output
+		.mx
+		.as_ref()
+		.ok()
+		.and_then(|mx| mx.lookup.as_ref().ok())
+		.map(|lookup| {
+			lookup
+				.iter()
+				.map(|host| host.exchange().to_string())
+				.collect::<Vec<_>>()
+		})
+		.unwrap_or_default();
+	let (is_disposable, is_free_provider) = output
+		.misc
+		.as_ref()
+		.map(|misc| (misc.is_disposable, misc.is_free_provider))
+		.unwrap_or((false, false));
+
+	match detect_provider_category(
+		&output.syntax.domain,
+		&mx_hosts,
+		is_disposable,
+		free_provider_domains,
+	) {
+		ProviderCategory::Disposable => ProviderCategory::Disposable,
+		_ if is_free_provider => ProviderCategory::Consumer,
+		category => category,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn category(domain: &str, mx_host: &str) -> ProviderCategory {
+		detect_provider_category(domain, &[mx_host.to_string()], false, None)
+	}
+
+	#[test]
+	fn should_detect_consumer() {
+		assert_eq!(
+			category("gmail.com", "gmail-smtp-in.l.google.com."),
+			ProviderCategory::Consumer
+		);
+		assert_eq!(
+			category("hotmail.fr", "eur.olc.protection.outlook.com."),
+			ProviderCategory::Consumer
+		);
+		assert_eq!(
+			category("yahoo.co.uk", "mx-eu.mail.am0.yahoodns.net."),
+			ProviderCategory::Consumer
+		);
+	}
+
+	#[test]
+	fn should_detect_business_hosted() {
+		assert_eq!(
+			category("reacher.email", "aspmx.l.google.com."),
+			ProviderCategory::BusinessHosted
+		);
+		assert_eq!(
+			category("example.org", "example-org.mail.protection.outlook.com."),
+			ProviderCategory::BusinessHosted
+		);
+		assert_eq!(
+			category("example.org", "mx0a-001.pphosted.com"),
+			ProviderCategory::BusinessHosted
+		);
+	}
+
+	#[test]
+	fn should_detect_self_hosted() {
+		assert_eq!(
+			category("example.org", "mx1.example.org."),
+			ProviderCategory::SelfHosted
+		);
+		assert_eq!(
+			category("Example.org", "example.org."),
+			ProviderCategory::SelfHosted
+		);
+	}
+
+	#[test]
+	fn should_detect_disposable_and_unknown() {
+		assert_eq!(
+			detect_provider_category("gmail.com", &[], true, None),
+			ProviderCategory::Disposable
+		);
+		assert_eq!(
+			category("example.org", "mx.some-host.net."),
+			ProviderCategory::Unknown
+		);
+		assert_eq!(
+			detect_provider_category("example.org", &[], false, None),
+			ProviderCategory::Unknown
+		);
+	}
+
+	#[test]
+	fn should_use_free_provider_domains() {
+		let free_provider_domains = ["acme-mail.com".to_string()];
+		assert_eq!(
+			detect_provider_category(
+				"acme-mail.com",
+				&["mx.acme-mail.com.".into()],
+				false,
+				Some(&free_provider_domains)
+			),
+			ProviderCategory::Consumer
+		);
+		assert_eq!(
+			detect_provider_category(
+				"gmail.com",
+				&["mx.gmail.com.".into()],
+				false,
+				Some(&free_provider_domains)
+			),
+			ProviderCategory::SelfHosted
+		);
+	}
+}
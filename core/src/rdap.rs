@@ -0,0 +1,219 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Registration details of the email's domain, fetched over RDAP (RFC 9083),
+//! the structured successor of WHOIS.
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// RDAP bootstrap service, which redirects each domain query to the
+/// registry of its TLD.
+const RDAP_URL: &str = "https://rdap.org/domain/";
+
+/// Timeout of each RDAP query.
+const RDAP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Registration details of the email's domain.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RdapDetails {
+	/// The registered domain the details are about. It's a parent of the
+	/// email's domain if the latter is a subdomain.
+	pub domain: Option<String>,
+	/// Registration date of the domain, as returned by the registry, e.g.
+	/// `2001-03-16T04:00:00Z`.
+	pub created_at: Option<String>,
+	/// Expiration date of the domain, in the same format.
+	pub expires_at: Option<String>,
+	/// Number of full days since the domain was registered. Freshly
+	/// registered domains are often used for spam.
+	pub age_days: Option<u64>,
+	/// Number of full days since the domain was last re-registered, e.g.
+	/// after it expired.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reregistered_days: Option<u64>,
+	/// Name of the registrar of the domain.
+	pub registrar: Option<String>,
+	/// The error if the RDAP query failed.
+	pub errors: Vec<String>,
+}
+
+/// Get the registration details from the RDAP response of `domain`.
+/// `now` is the number of days since the Unix epoch, to compute its age.
+pub fn parse_rdap_response(domain: &str, response: &Value, now: u64) -> RdapDetails {
+	let created_at = event_date(response, "registration");
+
+	RdapDetails {
+		domain: Some(domain.to_string()),
+		age_days: created_at
+			.as_deref()
+			.and_then(days_since_epoch)
+			.map(|created| now.saturating_sub(created)),
+		created_at,
+		expires_at: event_date(response, "expiration"),
+		reregistered_days: event_date(response, "reregistration")
+			.as_deref()
+			.and_then(days_since_epoch)
+			.map(|reregistered| now.saturating_sub(reregistered)),
+		registrar: registrar(response),
+		errors: vec![],
+	}
+}
+
+/// The date of the first event with the `action` event action.
+fn event_date(response: &Value, action: &str) -> Option<String> {
+	response["events"]
+		.as_array()?
+		.iter()
+		.find(|event| event["eventAction"] == action)
+		.and_then(|event| event["eventDate"].as_str())
+		.map(String::from)
+}
+
+/// The formatted name, in the jCard (RFC 7095), of the entity with the
+/// registrar role.
+fn registrar(response: &Value) -> Option<String> {
+	let entity = response["entities"].as_array()?.iter().find(|entity| {
+		entity["roles"]
+			.as_array()
+			.is_some_and(|roles| roles.iter().any(|role| role == "registrar"))
+	})?;
+
+	entity["vcardArray"][1]
+		.as_array()?
+		.iter()
+		.find(|property| property[0] == "fn")
+		.and_then(|property| property[3].as_str())
+		.map(String::from)
+}
+
+/// Number of days between the Unix epoch and the date at the start of
+/// `date`, which is `YYYY-MM-DD...`.
+fn days_since_epoch(date: &str) -> Option<u64> {
+	let year = date.get(0..4)?.parse::<i64>().ok()?;
+	let month = date.get(5..7)?.parse::<i64>().ok()?;
+	let day = date.get(8..10)?.parse::<i64>().ok()?;
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+		return None;
+	}
+
+	// Days from civil, shifting the year to start in March so that the leap
+	// day is the last day of the year.
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	let days = era * 146_097 + day_of_era - 719_468;
+
+	u64::try_from(days).ok()
+}
+
+/// Fetch the RDAP response of `domain`. Returns None if the registry doesn't
+/// know the domain.
+async fn fetch_rdap(domain: &str) -> Result<Option<Value>, String> {
+	let response = reqwest::Client::new()
+		.get(format!("{RDAP_URL}{domain}"))
+		.header("accept", "application/rdap+json")
+		.timeout(RDAP_TIMEOUT)
+		.send()
+		.await
+		.map_err(|err| format!("Cannot fetch RDAP response: {err}"))?;
+	if response.status() == reqwest::StatusCode::NOT_FOUND {
+		return Ok(None);
+	}
+
+	response
+		.error_for_status()
+		.map_err(|err| format!("Cannot fetch RDAP response: {err}"))?
+		.json()
+		.await
+		.map(Some)
+		.map_err(|err| format!("Invalid RDAP response: {err}"))
+}
+
+/// Look up the registration details of `domain`. Registries only know
+/// registered domains, so for subdomains the parent domains are queried one
+/// level at a time, until one is found.
+pub async fn check_rdap(domain: &str) -> RdapDetails {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs() / 86_400)
+		.unwrap_or_default();
+
+	let mut domain = domain.trim_end_matches('.');
+	loop {
+		match fetch_rdap(domain).await {
+			Ok(Some(response)) => return parse_rdap_response(domain, &response, now),
+			Ok(None) => match domain.split_once('.') {
+				Some((_, parent)) if parent.contains('.') => domain = parent,
+				_ => {
+					return RdapDetails {
+						errors: vec!["Domain not found in RDAP".into()],
+						..Default::default()
+					}
+				}
+			},
+			Err(err) => {
+				return RdapDetails {
+					errors: vec![err],
+					..Default::default()
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_rdap_response() {
+		let response = serde_json::json!({
+			"objectClassName": "domain",
+			"ldhName": "EXAMPLE.ORG",
+			"events": [
+				{ "eventAction": "registration", "eventDate": "2020-02-29T04:00:00Z" },
+				{ "eventAction": "expiration", "eventDate": "2030-02-28T04:00:00Z" }
+			],
+			"entities": [
+				{ "roles": ["registrant"], "vcardArray": ["vcard", [["fn", {}, "text", "Jane Doe"]]] },
+				{
+					"roles": ["registrar"],
+					"vcardArray": ["vcard", [
+						["version", {}, "text", "4.0"],
+						["fn", {}, "text", "Example Registrar, Inc."]
+					]]
+				}
+			]
+		});
+
+		// 2020-03-10.
+		let details = parse_rdap_response("example.org", &response, 18_331);
+
+		assert_eq!(details.created_at.as_deref(), Some("2020-02-29T04:00:00Z"));
+		assert_eq!(details.expires_at.as_deref(), Some("2030-02-28T04:00:00Z"));
+		assert_eq!(details.age_days, Some(10));
+		assert_eq!(
+			details.registrar.as_deref(),
+			Some("Example Registrar, Inc.")
+		);
+	}
+}
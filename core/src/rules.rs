@@ -0,0 +1,155 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Declarative rules overriding how `is_reachable` is computed, evaluated
+//! once all the checks ran.
+
+use serde::{Deserialize, Serialize};
+
+use crate::mx::MxProvider;
+use crate::util::domain_policy::{matches_domain, HostPattern};
+use crate::util::input_output::{CheckEmailOutput, Reachable};
+
+/// A condition on the output of a verification.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleCondition {
+	/// The verdict computed by the built-in rules is one of these.
+	Verdict(Vec<Reachable>),
+	/// The email's domain matches the pattern, with the same rules as
+	/// `allowed_domains`, e.g. ".example.com".
+	Domain(String),
+	/// One of the MX hosts matches the pattern, with the same rules as
+	/// `skipped_domains`, e.g. "*.zoho.com".
+	MxHost(HostPattern),
+	/// The MX hosts belong to this provider, e.g. Google or Proofpoint.
+	MxProvider(MxProvider),
+	IsDeliverable,
+	IsCatchAll,
+	HasFullInbox,
+	IsDisposable,
+	IsRoleAccount,
+	IsFreeProvider,
+	IsAliasService,
+	/// The confidence score is below this value. Only holds if
+	/// `compute_confidence` is set in the input.
+	ConfidenceBelow(u8),
+	/// The inner condition doesn't hold.
+	Not(Box<RuleCondition>),
+}
+
+impl RuleCondition {
+	/// Does the condition hold for output?
+	pub fn holds(&self, output: &CheckEmailOutput) -> bool {
+		let smtp = output.smtp.as_ref().ok();
+		let misc = output.misc.as_ref().ok();
+		let mx = output.mx.as_ref().ok();
+
+		match self {
+			RuleCondition::Verdict(verdicts) => verdicts.contains(&output.is_reachable),
+			RuleCondition::Domain(pattern) => {
+				output.syntax.is_valid_syntax && matches_domain(&output.syntax.domain, pattern)
+			}
+			RuleCondition::MxHost(pattern) => mx.is_some_and(|mx| {
+				mx.mx_records
+					.iter()
+					.any(|record| pattern.matches(&record.hostname))
+			}),
+			RuleCondition::MxProvider(provider) => mx.and_then(|mx| mx.provider) == Some(*provider),
+			RuleCondition::IsDeliverable => smtp.is_some_and(|smtp| smtp.is_deliverable),
+			RuleCondition::IsCatchAll => smtp.is_some_and(|smtp| smtp.is_catch_all),
+			RuleCondition::HasFullInbox => smtp.is_some_and(|smtp| smtp.has_full_inbox),
+			RuleCondition::IsDisposable => misc.is_some_and(|misc| misc.is_disposable),
+			RuleCondition::IsRoleAccount => misc.is_some_and(|misc| misc.is_role_account),
+			RuleCondition::IsFreeProvider => misc.is_some_and(|misc| misc.is_free_provider),
+			RuleCondition::IsAliasService => misc.is_some_and(|misc| misc.is_alias_service),
+			RuleCondition::ConfidenceBelow(score) => output
+				.confidence
+				.as_ref()
+				.is_some_and(|confidence| confidence.score < *score),
+			RuleCondition::Not(condition) => !condition.holds(output),
+		}
+	}
+}
+
+/// A rule setting `is_reachable` to `reachable` if all its conditions
+/// hold, e.g. treat the catch-all domains of Google as `Safe`, or the
+/// disposable emails as `Invalid`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReachabilityRule {
+	pub conditions: Vec<RuleCondition>,
+	pub reachable: Reachable,
+}
+
+/// Set the output's `is_reachable` with the first of rules whose conditions
+/// all hold. It's kept if none does.
+pub(crate) fn apply_reachability_rules(output: &mut CheckEmailOutput, rules: &[ReachabilityRule]) {
+	let matching = rules.iter().find(|rule| {
+		rule.conditions
+			.iter()
+			.all(|condition| condition.holds(output))
+	});
+	if let Some(rule) = matching {
+		output.is_reachable = rule.reachable;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::misc::MiscDetails;
+	use crate::smtp::SmtpDetails;
+
+	#[test]
+	fn should_apply_first_matching_rule() {
+		let rules: Vec<ReachabilityRule> = serde_json::from_str(
+			r#"[
+				{"conditions": ["is_catch_all", {"domain": ".example.org"}], "reachable": "safe"},
+				{"conditions": ["is_disposable"], "reachable": "invalid"},
+				{"conditions": [{"not": "is_deliverable"}, {"verdict": ["risky"]}], "reachable": "unknown"}
+			]"#,
+		)
+		.unwrap();
+
+		let mut output = CheckEmailOutput {
+			is_reachable: Reachable::Risky,
+			smtp: Ok(SmtpDetails {
+				is_catch_all: true,
+				is_deliverable: true,
+				..Default::default()
+			}),
+			misc: Ok(MiscDetails {
+				is_disposable: true,
+				..Default::default()
+			}),
+			..Default::default()
+		};
+		output.syntax.is_valid_syntax = true;
+		output.syntax.domain = "mail.example.org".into();
+
+		apply_reachability_rules(&mut output, &rules);
+		assert_eq!(output.is_reachable, Reachable::Safe);
+
+		output.syntax.domain = "example.com".into();
+		apply_reachability_rules(&mut output, &rules);
+		assert_eq!(output.is_reachable, Reachable::Invalid);
+
+		output.misc = Ok(MiscDetails::default());
+		output.is_reachable = Reachable::Risky;
+		apply_reachability_rules(&mut output, &rules);
+		assert_eq!(output.is_reachable, Reachable::Risky);
+	}
+}
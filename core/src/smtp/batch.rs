@@ -0,0 +1,303 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Verification of several email addresses of the same domain over a single
+//! SMTP session, with one `RCPT TO` command per address.
+
+use std::future::Future;
+
+use async_smtp::{smtp::commands::RsetCommand, EmailAddress};
+use async_std::{future, task};
+use trust_dns_proto::rr::Name;
+
+use super::catch_all::{smtp_is_catch_all, CatchAllProbes};
+use super::connect::{
+	checkout_or_connect, connect_to_host, email_deliverable, mail_from, release, Deliverability,
+};
+use super::retry::{retry_delay, retry_host};
+use super::tarpit::detect_tarpit;
+use super::transcript::SmtpConnection;
+use super::vrfy::with_vrfy_fallback;
+use super::{parser, SmtpDetails, SmtpError};
+use crate::mx::{host_to_string, MxDetails};
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// Maximum number of recipients of a mail transaction. RFC 5321 requires
+/// servers to accept at least 100.
+const MAX_RECIPIENTS: usize = 100;
+
+/// Results of the emails of a batch, in the same order as the emails.
+pub type SmtpBatchResults = Vec<Result<SmtpDetails, SmtpError>>;
+
+/// Run `fut` with the input's `smtp_timeout`, if set.
+async fn with_timeout<T>(
+	input: &CheckEmailInput,
+	fut: impl Future<Output = Result<T, SmtpError>>,
+) -> Result<T, SmtpError> {
+	let result = match input.smtp_timeout {
+		Some(smtp_timeout) => future::timeout(smtp_timeout, fut).await?,
+		None => fut.await,
+	};
+
+	result.map_err(detect_tarpit)
+}
+
+fn smtp_details(
+	is_catch_all: bool,
+	catch_all_probes: &Option<CatchAllProbes>,
+	deliverability: Deliverability,
+	verified_with_vrfy: bool,
+) -> SmtpDetails {
+	SmtpDetails {
+		can_connect_smtp: true,
+		has_full_inbox: deliverability.has_full_inbox,
+		is_catch_all,
+		is_deliverable: deliverability.is_deliverable,
+		is_disabled: deliverability.is_disabled,
+		catch_all_probes: catch_all_probes.clone(),
+		verified_with_vrfy,
+		..Default::default()
+	}
+}
+
+/// Add the transcript of the session, if it was recorded, where the session
+/// was connected, and the server's TLS certificate, its DANE validation and
+/// the server's capabilities, if they were inspected, to the details of the
+/// emails verified so far.
+fn attach_session_details(results: &mut SmtpBatchResults, smtp_transport: &mut SmtpConnection) {
+	let tls_details = smtp_transport.tls_details();
+	let dane = smtp_transport.dane();
+	let server_info = smtp_transport.server_info();
+	let endpoint = smtp_transport.endpoint();
+	for smtp_details in results.iter_mut().flatten() {
+		if smtp_details.mx_port.is_none() {
+			smtp_details.mx_ip = endpoint.ip;
+			smtp_details.mx_port = Some(endpoint.port);
+			smtp_details.used_tls = endpoint.used_tls;
+		}
+		if smtp_details.tls.is_none() {
+			smtp_details.tls = tls_details.clone();
+		}
+		if smtp_details.dane.is_none() {
+			smtp_details.dane = dane;
+		}
+		if smtp_details.server_info.is_none() {
+			smtp_details.server_info = server_info.clone();
+		}
+	}
+
+	if let Some(transcript) = smtp_transport.take_transcript() {
+		for smtp_details in results.iter_mut().flatten() {
+			smtp_details.transcript = Some(transcript.clone());
+		}
+	}
+}
+
+/// Fill the results of the `count` remaining emails, which can't be
+/// verified because the session failed with `err`. The first one gets
+/// `err` itself.
+fn fail_remaining(results: &mut SmtpBatchResults, count: usize, err: SmtpError) {
+	let message = format!("{err:?}");
+	results.push(Err(err));
+	results.extend((1..count).map(|_| Err(SmtpError::BatchError(message.clone()))));
+}
+
+/// Verify `to_emails` over a single SMTP session with host. Failing to open
+/// the session is an error, otherwise each email has its own result.
+async fn check_smtp_batch_without_retry(
+	to_emails: &[EmailAddress],
+	host: &Name,
+	port: u16,
+	domain: &str,
+	input: &CheckEmailInput,
+	known_catch_all: Option<bool>,
+) -> Result<SmtpBatchResults, SmtpError> {
+	let (mut smtp_transport, is_catch_all, catch_all_probes) = with_timeout(input, async {
+		let mut smtp_transport = checkout_or_connect(host, port, input).await?;
+		// Skip the random email probe if we already know the answer.
+		let (is_catch_all, catch_all_probes) = match known_catch_all {
+			Some(is_catch_all) => (is_catch_all, None),
+			None => {
+				let probes = smtp_is_catch_all(&mut smtp_transport, domain, input).await;
+				(probes.is_catch_all(), Some(probes))
+			}
+		};
+
+		Ok((smtp_transport, is_catch_all, catch_all_probes))
+	})
+	.await?;
+
+	let mut results = SmtpBatchResults::with_capacity(to_emails.len());
+	for (index, to_email) in to_emails.iter().enumerate() {
+		if is_catch_all {
+			results.push(Ok(smtp_details(
+				true,
+				&catch_all_probes,
+				Deliverability {
+					has_full_inbox: false,
+					is_deliverable: true,
+					is_disabled: false,
+				},
+				false,
+			)));
+			continue;
+		}
+
+		// Start a new mail transaction once it has too many recipients.
+		if index > 0 && index % MAX_RECIPIENTS == 0 {
+			let restarted = with_timeout(input, async {
+				let _ = smtp_transport.command(RsetCommand).await;
+				mail_from(smtp_transport, &host_to_string(host), port, input).await
+			})
+			.await;
+			smtp_transport = match restarted {
+				Ok(smtp_transport) => smtp_transport,
+				Err(err) => {
+					fail_remaining(&mut results, to_emails.len() - index, err);
+					return Ok(results);
+				}
+			};
+		}
+
+		let mut result =
+			with_timeout(input, email_deliverable(&mut smtp_transport, to_email)).await;
+
+		// Some SMTP servers close the connection after a few invalid
+		// recipients, so reconnect to verify the next ones.
+		if let Err(err) = &result {
+			if parser::is_err_io_errors(err) {
+				log::debug!(
+					target: LOG_TARGET,
+					"[email={}] Got `io: incomplete` error, reconnecting.",
+					to_email
+				);
+
+				let _ = smtp_transport.close().await;
+				smtp_transport = match with_timeout(input, connect_to_host(host, port, input)).await
+				{
+					Ok(smtp_transport) => smtp_transport,
+					Err(err) => {
+						attach_session_details(&mut results, &mut smtp_transport);
+						fail_remaining(&mut results, to_emails.len() - index, err);
+						return Ok(results);
+					}
+				};
+				result =
+					with_timeout(input, email_deliverable(&mut smtp_transport, to_email)).await;
+			}
+		}
+
+		// The tarpit's next answers would be as slow.
+		if let Err(err @ SmtpError::Tarpitted(_)) = result {
+			let _ = smtp_transport.close().await;
+			attach_session_details(&mut results, &mut smtp_transport);
+			fail_remaining(&mut results, to_emails.len() - index, err);
+			return Ok(results);
+		}
+
+		let (result, verified_with_vrfy) =
+			with_vrfy_fallback(&mut smtp_transport, to_email, input, result).await;
+		results.push(result.map(|deliverability| SmtpDetails {
+			timings: smtp_transport.timings(),
+			annotations: smtp_transport.take_annotations(),
+			..smtp_details(false, &catch_all_probes, deliverability, verified_with_vrfy)
+		}));
+	}
+
+	attach_session_details(&mut results, &mut smtp_transport);
+	// All the emails are verified, so ignore errors when closing.
+	let _ = release(smtp_transport, host, port, input).await;
+
+	Ok(results)
+}
+
+/// Verify `to_emails` over a single SMTP session with host. Retry opening
+/// the session on error, in particular to avoid greylisting.
+///
+/// If `known_catch_all` is set, the catch-all verification is skipped and
+/// its value is used instead. The other hosts of `mx`, the domain's MX
+/// lookup, can be retried on, see `RetryStrategy::OtherHost`.
+pub(super) async fn check_smtp_batch_with_retry(
+	to_emails: &[EmailAddress],
+	host: &Name,
+	port: u16,
+	domain: &str,
+	mx: &MxDetails,
+	input: &CheckEmailInput,
+	known_catch_all: Option<bool>,
+) -> Result<SmtpBatchResults, SmtpError> {
+	let mut attempt = 1;
+	let mut host = host.clone();
+	loop {
+		let mut result =
+			check_smtp_batch_without_retry(to_emails, &host, port, domain, input, known_catch_all)
+				.await;
+		if let Ok(results) = &mut result {
+			for smtp_details in results.iter_mut().flatten() {
+				smtp_details.mx_host = Some(host_to_string(&host));
+			}
+		}
+
+		let (class, delay) = match &result {
+			Err(err) => match retry_delay(input, attempt, err) {
+				Some((class, delay)) => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[host={}:{}] Cannot open SMTP session for {} emails, retrying in {:?}: {:?}",
+						host,
+						port,
+						to_emails.len(),
+						delay,
+						err
+					);
+					(class, delay)
+				}
+				None => return result,
+			},
+			Ok(_) => return result,
+		};
+		if !delay.is_zero() {
+			task::sleep(delay).await;
+		}
+		host = retry_host(&host, mx, class, input);
+		attempt += 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_smtp::smtp::error::Error as AsyncSmtpError;
+
+	#[test]
+	fn should_fail_remaining_emails() {
+		let mut results = SmtpBatchResults::new();
+		fail_remaining(
+			&mut results,
+			3,
+			SmtpError::SmtpError(AsyncSmtpError::Resolution),
+		);
+
+		assert_eq!(results.len(), 3);
+		assert!(matches!(
+			results[0],
+			Err(SmtpError::SmtpError(AsyncSmtpError::Resolution))
+		));
+		assert!(
+			matches!(&results[2], Err(SmtpError::BatchError(message)) if message == "SmtpError(Resolution)")
+		);
+	}
+}
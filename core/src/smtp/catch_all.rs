@@ -0,0 +1,153 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Catch-all detection: probe the SMTP server with `RCPT TO` commands on
+//! random addresses of the domain, which shouldn't exist.
+
+use std::iter;
+
+use async_smtp::EmailAddress;
+use async_std::task;
+use rand::rngs::SmallRng;
+use rand::{distributions::Alphanumeric, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::connect::email_deliverable;
+use super::transcript::SmtpConnection;
+use crate::util::input_output::CheckEmailInput;
+
+/// How confident we are in the catch-all determination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchAllConfidence {
+	/// Several probes were all accepted, or a probe was rejected.
+	High,
+	/// A single probe was made, and it was accepted.
+	Medium,
+	/// Some probes were accepted, and a later one was rejected: the server
+	/// might only accept a limited number of unknown recipients.
+	Low,
+}
+
+/// Results of the catch-all probes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CatchAllProbes {
+	/// Number of probes made. The probes stop at the first rejected one.
+	pub total: usize,
+	/// Number of probes accepted by the server.
+	pub accepted: usize,
+	pub confidence: CatchAllConfidence,
+	/// Did the last probe fail with an error, e.g. a timeout, instead of
+	/// being rejected? The determination isn't cached then.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub errored: bool,
+}
+
+impl CatchAllProbes {
+	fn new(total: usize, accepted: usize) -> Self {
+		let confidence = match accepted {
+			0 => CatchAllConfidence::High,
+			_ if accepted < total => CatchAllConfidence::Low,
+			1 => CatchAllConfidence::Medium,
+			_ => CatchAllConfidence::High,
+		};
+
+		CatchAllProbes {
+			total,
+			accepted,
+			confidence,
+			errored: false,
+		}
+	}
+
+	/// Is the domain a catch-all, i.e. were all the probes accepted?
+	pub fn is_catch_all(&self) -> bool {
+		self.total > 0 && self.accepted == self.total
+	}
+}
+
+/// A random 15-char alphanumerical address on `domain`.
+fn random_email(domain: &str) -> EmailAddress {
+	let mut rng = SmallRng::from_entropy();
+	let random_email: String = iter::repeat(())
+		.map(|()| rng.sample(Alphanumeric))
+		.map(char::from)
+		.take(15)
+		.collect();
+
+	EmailAddress::new(format!("{random_email}@{domain}"))
+		.expect("Email is correctly constructed. qed.")
+}
+
+/// Verify the existence of a catch-all on the domain, with the input's
+/// `catch_all_probes` random addresses, waiting `catch_all_probe_delay`
+/// between them. An error on a probe counts as a rejection.
+pub(super) async fn smtp_is_catch_all(
+	smtp_transport: &mut SmtpConnection,
+	domain: &str,
+	input: &CheckEmailInput,
+) -> CatchAllProbes {
+	let mut total = 0;
+	let mut accepted = 0;
+	let mut errored = false;
+	while total < input.catch_all_probes.max(1) {
+		if total > 0 {
+			if let Some(delay) = input.catch_all_probe_delay {
+				task::sleep(delay).await;
+			}
+		}
+
+		total += 1;
+		match email_deliverable(smtp_transport, &random_email(domain)).await {
+			Ok(deliverability) if deliverability.is_deliverable => accepted += 1,
+			Ok(_) => break,
+			Err(_) => {
+				errored = true;
+				break;
+			}
+		}
+	}
+
+	CatchAllProbes {
+		errored,
+		..CatchAllProbes::new(total, accepted)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_compute_catch_all_confidence() {
+		let probes = CatchAllProbes::new(3, 3);
+		assert!(probes.is_catch_all());
+		assert_eq!(probes.confidence, CatchAllConfidence::High);
+
+		let probes = CatchAllProbes::new(2, 1);
+		assert!(!probes.is_catch_all());
+		assert_eq!(probes.confidence, CatchAllConfidence::Low);
+
+		assert_eq!(
+			CatchAllProbes::new(1, 1).confidence,
+			CatchAllConfidence::Medium
+		);
+		assert_eq!(
+			CatchAllProbes::new(1, 0).confidence,
+			CatchAllConfidence::High
+		);
+	}
+}
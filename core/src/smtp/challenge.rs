@@ -0,0 +1,211 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of the CAPTCHAs and bot challenges which providers show
+//! instead of telling whether an address exists.
+
+use std::fmt::Debug;
+#[cfg(feature = "headless")]
+use std::future::Future;
+#[cfg(feature = "headless")]
+use std::time::Duration;
+
+#[cfg(feature = "headless")]
+use async_std::prelude::FutureExt;
+use async_trait::async_trait;
+#[cfg(feature = "headless")]
+use fantoccini::{error::CmdError, Client, Locator};
+#[cfg(feature = "headless")]
+use futures::TryFutureExt;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "headless")]
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// The iframes of the challenge widgets. Invisible reCAPTCHAs are on many
+/// pages, and only become a challenge if they pop up another iframe.
+#[cfg(feature = "headless")]
+const CHALLENGE_FRAMES: &str = "iframe[src*='recaptcha']:not([src*='size=invisible']), iframe[src*='hcaptcha'], iframe[src*='arkoselabs'], iframe[src*='funcaptcha']";
+
+/// Challenges show up right after submitting the form, so don't wait for
+/// them as long as for the page's answer.
+#[cfg(feature = "headless")]
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Put the solver's token, `arguments[0]`, in the response fields of the
+/// widgets, and submit their form.
+#[cfg(feature = "headless")]
+const SOLUTION_SCRIPT: &str = r#"
+const fields = document.querySelectorAll("[name='g-recaptcha-response'], [name='h-captcha-response'], [name='fc-token'], [name='verification-token']");
+fields.forEach((field) => { field.value = arguments[0]; });
+if (fields.length > 0 && fields[0].form) { fields[0].form.submit(); }
+"#;
+
+/// The kind of challenge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChallengeKind {
+	Recaptcha,
+	Hcaptcha,
+	/// Arkose Labs' FunCaptcha, which Microsoft uses.
+	Arkose,
+	/// A bot challenge we don't know the widget of.
+	Other,
+}
+
+/// A CAPTCHA or bot challenge a provider answered with. It means the
+/// provider blocks us for now, not that the address is unknown, so the
+/// verification is worth retrying later, e.g. from another IP.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Challenge {
+	/// The provider's verification, e.g. "yahoo" or "hotmail".
+	pub provider: String,
+	pub kind: ChallengeKind,
+	/// The URL of the page showing the challenge.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	/// The site key of the widget, which solver services need.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub site_key: Option<String>,
+}
+
+impl Challenge {
+	/// The challenge of the widget whose iframe is loaded from `src`.
+	#[cfg_attr(not(feature = "headless"), allow(dead_code))]
+	fn from_frame(provider: &str, src: &str) -> Self {
+		let src_lowercase = src.to_lowercase();
+		let kind = if src_lowercase.contains("recaptcha") {
+			ChallengeKind::Recaptcha
+		} else if src_lowercase.contains("hcaptcha") {
+			ChallengeKind::Hcaptcha
+		} else if src_lowercase.contains("arkoselabs") || src_lowercase.contains("funcaptcha") {
+			ChallengeKind::Arkose
+		} else {
+			ChallengeKind::Other
+		};
+		// reCAPTCHA has the key in "k", hCaptcha in "sitekey" and Arkose in
+		// "pk", in the query or in the fragment.
+		let site_key = src
+			.split(['?', '#', '&'])
+			.skip(1)
+			.filter_map(|param| param.split_once('='))
+			.find(|(name, _)| ["k", "sitekey", "pk"].contains(name))
+			.map(|(_, value)| value.to_string());
+
+		Challenge {
+			provider: provider.into(),
+			kind,
+			url: None,
+			site_key,
+		}
+	}
+}
+
+/// Solves the challenges of the headless verifications, e.g. with a
+/// CAPTCHA solving service. Set it on the context with
+/// `CheckEmailContext::with_challenge_solver`.
+#[async_trait]
+pub trait ChallengeSolver: Debug + Send + Sync {
+	/// Solve `challenge`, returning the token its widget gives once solved,
+	/// which is put in the widget's response field before submitting the
+	/// page's form. None if it can't be solved.
+	async fn solve(&self, challenge: &Challenge) -> Option<String>;
+}
+
+/// Wait for the frame of a challenge widget to show up on the `provider`
+/// page.
+#[cfg(feature = "headless")]
+async fn wait_for_challenge<T>(
+	client: &Client,
+	provider: &str,
+) -> Result<Result<T, Challenge>, CmdError> {
+	let frame = client
+		.wait()
+		.at_most(CHALLENGE_TIMEOUT)
+		.for_element(Locator::Css(CHALLENGE_FRAMES))
+		.await?;
+	let src = frame.attr("src").await?.unwrap_or_default();
+
+	let mut challenge = Challenge::from_frame(provider, &src);
+	challenge.url = client.current_url().await.ok().map(|url| url.to_string());
+	Ok(Err(challenge))
+}
+
+/// Wait for `outcome`, the answer of the `provider` page, unless the page
+/// shows a challenge instead. The challenge is returned, unless the
+/// context's challenge solver solves it, in which case `outcome` is waited
+/// for again.
+#[cfg(feature = "headless")]
+pub(crate) async fn solve_challenges<T, F, Fut>(
+	client: &Client,
+	input: &CheckEmailInput,
+	provider: &str,
+	outcome: F,
+) -> Result<Result<T, Challenge>, CmdError>
+where
+	F: Fn() -> Fut,
+	Fut: Future<Output = Result<T, CmdError>>,
+{
+	let challenge = wait_for_challenge(client, provider);
+	let challenge = match outcome().map_ok(Ok).try_race(challenge).await? {
+		Ok(result) => return Ok(Ok(result)),
+		Err(challenge) => challenge,
+	};
+	log::debug!(
+		target: LOG_TARGET,
+		"[provider={}] Got a challenge: {:?}",
+		provider,
+		challenge
+	);
+
+	let token = match input.context.challenge_solver() {
+		Some(solver) => solver.solve(&challenge).await,
+		None => None,
+	};
+	match token {
+		Some(token) => {
+			client.execute(SOLUTION_SCRIPT, vec![token.into()]).await?;
+			outcome().await.map(Ok)
+		}
+		None => Ok(Err(challenge)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_read_challenge_frame() {
+		let challenge = Challenge::from_frame(
+			"yahoo",
+			"https://www.google.com/recaptcha/api2/anchor?ar=1&k=6LdAbc&co=aHR0cHM6",
+		);
+		assert_eq!(challenge.kind, ChallengeKind::Recaptcha);
+		assert_eq!(challenge.site_key.as_deref(), Some("6LdAbc"));
+
+		let challenge = Challenge::from_frame(
+			"hotmail",
+			"https://client-api.arkoselabs.com/fc/gc/?token=1a2b#pk=B7D8911C",
+		);
+		assert_eq!(challenge.kind, ChallengeKind::Arkose);
+		assert_eq!(challenge.site_key.as_deref(), Some("B7D8911C"));
+
+		let challenge = Challenge::from_frame("yahoo", "https://example.org/challenge");
+		assert_eq!(challenge.kind, ChallengeKind::Other);
+		assert_eq!(challenge.site_key, None);
+	}
+}
@@ -0,0 +1,812 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! SMTP connections whose TCP connection async-smtp's transport can't open:
+//! from a specific source IP or network interface, for multi-homed servers,
+//! or through a SOCKS4 or HTTP CONNECT proxy, or whose TLS certificate we
+//! inspect. These connections use async-smtp's lower-level client, and
+//! handle the greeting, EHLO and STARTTLS themselves.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_smtp::smtp::authentication::{Credentials, Mechanism};
+use async_smtp::smtp::client::net::{ClientTlsParameters, Connector, NetworkStream};
+use async_smtp::smtp::client::InnerClient;
+use async_smtp::smtp::commands::StarttlsCommand;
+use async_smtp::smtp::error::{Error as AsyncSmtpError, SmtpResult};
+use async_smtp::smtp::extension::{Extension, ServerInfo};
+use async_smtp::smtp::response::Response;
+use async_smtp::smtp::{ServerAddress, Socks5Config};
+use async_smtp::{ClientSecurity, SmtpTransport};
+use async_std::future;
+use async_trait::async_trait;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::X509;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+use tokio_openssl::SslStream;
+
+use super::lmtp::hello_command;
+use super::proxy::{proxy_handshake, ProxyHandshakeError};
+use super::tarpit::DripDetector;
+use super::timings::{elapsed_millis, SmtpTimings};
+use crate::util::input_output::{CheckEmailInput, CheckEmailInputProxy};
+
+/// Where an SMTP channel is connected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SmtpEndpoint {
+	/// The server's IP address, unless we connected to its hostname through
+	/// a proxy, or through async-smtp's transport, which doesn't expose it.
+	pub(crate) ip: Option<IpAddr>,
+	pub(crate) port: u16,
+	/// Whether the connection is encrypted. Unknown with opportunistic TLS
+	/// through async-smtp's transport.
+	pub(crate) used_tls: Option<bool>,
+}
+
+impl SmtpEndpoint {
+	/// The endpoint of a connection to `address`, which is either a host or
+	/// one of its IP addresses, as far as security tells.
+	pub(super) fn new(address: &str, port: u16, security: &ClientSecurity) -> Self {
+		SmtpEndpoint {
+			ip: address.parse().ok(),
+			port,
+			used_tls: match security {
+				ClientSecurity::None => Some(false),
+				ClientSecurity::Opportunistic(_) => None,
+				ClientSecurity::Required(_) | ClientSecurity::Wrapper(_) => Some(true),
+			},
+		}
+	}
+}
+
+/// The client of an SMTP connection.
+pub(crate) enum SmtpChannel {
+	/// async-smtp's transport, which connects by itself.
+	Transport(SmtpTransport, SmtpEndpoint),
+	/// A client whose TCP connection we open ourselves.
+	Custom(CustomClient),
+}
+
+/// Run `fut`, a stage of the SMTP connection, within timeout if set.
+pub(super) async fn with_stage_timeout<T>(
+	timeout: Option<Duration>,
+	fut: impl Future<Output = Result<T, AsyncSmtpError>>,
+) -> Result<T, AsyncSmtpError> {
+	match timeout {
+		Some(timeout) => future::timeout(timeout, fut)
+			.await
+			.map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+		None => fut.await,
+	}
+}
+
+impl SmtpChannel {
+	pub(super) async fn connect(&mut self) -> Result<(), AsyncSmtpError> {
+		match self {
+			SmtpChannel::Transport(transport, _) => transport.connect().await,
+			SmtpChannel::Custom(custom) => custom.connect().await,
+		}
+	}
+
+	pub(super) async fn command<C: Display>(&mut self, command: C) -> SmtpResult {
+		match self {
+			SmtpChannel::Transport(transport, _) => transport.command(command).await,
+			SmtpChannel::Custom(custom) => Pin::new(&mut custom.client).command(command).await,
+		}
+	}
+
+	pub(super) async fn close(&mut self) -> Result<(), AsyncSmtpError> {
+		match self {
+			SmtpChannel::Transport(transport, _) => transport.close().await,
+			SmtpChannel::Custom(custom) => Pin::new(&mut custom.client).close().await,
+		}
+	}
+
+	/// The server's response to the first EHLO, before STARTTLS, if we
+	/// sent it ourselves.
+	pub(super) fn first_ehlo(&self) -> Option<Response> {
+		self.handshake()
+			.iter()
+			.find(|(verb, _)| matches!(*verb, "EHLO" | "LHLO"))
+			.map(|(_, response)| response.clone())
+	}
+
+	/// The server's response to the last EHLO, after STARTTLS if the
+	/// connection was upgraded, if we sent it ourselves.
+	pub(super) fn last_ehlo(&self) -> Option<Response> {
+		self.handshake()
+			.iter()
+			.rev()
+			.find(|(verb, _)| matches!(*verb, "EHLO" | "LHLO"))
+			.map(|(_, response)| response.clone())
+	}
+
+	/// The server's responses while connecting, with the verb of their
+	/// command, if we connected ourselves. See `CustomClient::handshake`.
+	pub(super) fn handshake(&self) -> &[(&'static str, Response)] {
+		match self {
+			SmtpChannel::Transport(..) => &[],
+			SmtpChannel::Custom(custom) => &custom.handshake,
+		}
+	}
+
+	/// The TLS session, if it was inspected.
+	pub(super) fn tls_session(&self) -> Option<TlsSession> {
+		match self {
+			SmtpChannel::Transport(..) => None,
+			SmtpChannel::Custom(custom) => custom
+				.tls_inspection
+				.as_ref()?
+				.slot
+				.lock()
+				.expect("TLS session lock is not poisoned. qed.")
+				.clone(),
+		}
+	}
+
+	/// The server's DER-encoded TLS certificate, if it was inspected.
+	pub(super) fn peer_certificate(&self) -> Option<Vec<u8>> {
+		self.tls_session()?.peer_certificate
+	}
+
+	/// Where the channel is connected.
+	pub(super) fn endpoint(&self) -> SmtpEndpoint {
+		match self {
+			SmtpChannel::Transport(_, endpoint) => *endpoint,
+			SmtpChannel::Custom(custom) => custom.endpoint,
+		}
+	}
+
+	/// Durations of the connection stages, if we connected ourselves.
+	pub(super) fn timings(&self) -> SmtpTimings {
+		match self {
+			SmtpChannel::Transport(..) => SmtpTimings::default(),
+			SmtpChannel::Custom(custom) => custom.timings,
+		}
+	}
+}
+
+/// What `InspectedStream` found out about the TLS session.
+#[derive(Debug, Default, Clone)]
+pub(super) struct TlsSession {
+	/// The server's DER-encoded certificate.
+	pub(super) peer_certificate: Option<Vec<u8>>,
+	/// The negotiated protocol version, e.g. "TLSv1.3".
+	pub(super) version: Option<String>,
+	/// The negotiated cipher suite, e.g. "TLS_AES_256_GCM_SHA384".
+	pub(super) cipher: Option<String>,
+}
+
+/// Where `InspectedStream` stores the TLS session it inspected.
+type TlsSessionSlot = Arc<Mutex<Option<TlsSession>>>;
+
+/// How `InspectedStream` negotiates TLS when it inspects the session. It
+/// uses OpenSSL directly, as native-tls doesn't expose the protocol version
+/// nor the cipher suite, with the same settings as `tls_connector`.
+#[derive(Clone)]
+struct TlsInspection {
+	slot: TlsSessionSlot,
+	accept_invalid_certs: bool,
+	root_certificates: Vec<String>,
+}
+
+impl TlsInspection {
+	/// Negotiate TLS with `domain` over `tcp_stream`, and store the session
+	/// in the slot.
+	async fn connect(
+		&self,
+		domain: &str,
+		tcp_stream: TcpStream,
+	) -> io::Result<SslStream<TcpStream>> {
+		let mut builder =
+			SslConnector::builder(SslMethod::tls_client()).map_err(io::Error::other)?;
+		for pem in &self.root_certificates {
+			let certificate = X509::from_pem(pem.as_bytes()).map_err(io::Error::other)?;
+			builder
+				.cert_store_mut()
+				.add_cert(certificate)
+				.map_err(io::Error::other)?;
+		}
+		if self.accept_invalid_certs {
+			builder.set_verify(SslVerifyMode::NONE);
+		}
+		let ssl = builder
+			.build()
+			.configure()
+			.map_err(io::Error::other)?
+			.verify_hostname(!self.accept_invalid_certs)
+			.into_ssl(domain)
+			.map_err(io::Error::other)?;
+
+		let mut stream = SslStream::new(ssl, tcp_stream).map_err(io::Error::other)?;
+		Pin::new(&mut stream)
+			.connect()
+			.await
+			.map_err(io::Error::other)?;
+
+		let ssl = stream.ssl();
+		*self
+			.slot
+			.lock()
+			.expect("TLS session lock is not poisoned. qed.") = Some(TlsSession {
+			peer_certificate: ssl
+				.peer_certificate()
+				.and_then(|certificate| certificate.to_der().ok()),
+			version: Some(ssl.version_str().to_string()),
+			cipher: ssl.current_cipher().map(|cipher| cipher.name().to_string()),
+		});
+
+		Ok(stream)
+	}
+}
+
+/// The stream of an `InspectedStream`.
+enum InspectedInner {
+	/// async-smtp's stream, when the TLS session isn't inspected.
+	Network(NetworkStream),
+	/// A stream encrypted by `TlsInspection`.
+	Inspected(Box<SslStream<TcpStream>>),
+}
+
+/// async-smtp's network stream, which inspects the TLS session when it's
+/// upgraded to TLS, if it has a slot to store it. The client doesn't give
+/// its stream back, hence the shared slot. It also watches the responses
+/// for tarpits, if a threshold is set.
+pub(crate) struct InspectedStream {
+	stream: InspectedInner,
+	inspection: Option<TlsInspection>,
+	tarpit: Option<Box<DripDetector>>,
+}
+
+impl AsyncRead for InspectedStream {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let InspectedStream { stream, tarpit, .. } = &mut *self;
+		let mut read = |cx: &mut Context<'_>, buf: &mut ReadBuf<'_>| match stream {
+			InspectedInner::Network(stream) => Pin::new(stream).poll_read(cx, buf),
+			InspectedInner::Inspected(stream) => Pin::new(stream).poll_read(cx, buf),
+		};
+		match tarpit {
+			Some(tarpit) => tarpit.poll_read(cx, buf, read),
+			None => read(cx, buf),
+		}
+	}
+}
+
+impl AsyncWrite for InspectedStream {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		match &mut self.stream {
+			InspectedInner::Network(stream) => Pin::new(stream).poll_write(cx, buf),
+			InspectedInner::Inspected(stream) => Pin::new(stream).poll_write(cx, buf),
+		}
+	}
+
+	fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match &mut self.stream {
+			InspectedInner::Network(stream) => Pin::new(stream).poll_flush(cx),
+			InspectedInner::Inspected(stream) => Pin::new(stream).poll_flush(cx),
+		}
+	}
+
+	fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		match &mut self.stream {
+			InspectedInner::Network(stream) => Pin::new(stream).poll_shutdown(cx),
+			InspectedInner::Inspected(stream) => Pin::new(stream).poll_shutdown(cx),
+		}
+	}
+}
+
+#[async_trait]
+impl Connector for InspectedStream {
+	async fn connect(
+		addr: &SocketAddr,
+		timeout: Option<Duration>,
+		tls_parameters: Option<&ClientTlsParameters>,
+	) -> io::Result<Self> {
+		let stream = NetworkStream::connect(addr, timeout, tls_parameters).await?;
+
+		Ok(InspectedStream {
+			stream: InspectedInner::Network(stream),
+			inspection: None,
+			tarpit: None,
+		})
+	}
+
+	async fn connect_socks5(
+		socks5: &Socks5Config,
+		addr: &ServerAddress,
+		timeout: Option<Duration>,
+		tls_parameters: Option<&ClientTlsParameters>,
+	) -> io::Result<Self> {
+		let stream = NetworkStream::connect_socks5(socks5, addr, timeout, tls_parameters).await?;
+
+		Ok(InspectedStream {
+			stream: InspectedInner::Network(stream),
+			inspection: None,
+			tarpit: None,
+		})
+	}
+
+	async fn upgrade_tls(self, tls_parameters: &ClientTlsParameters) -> io::Result<Self> {
+		let tcp_stream = match self.stream {
+			InspectedInner::Network(NetworkStream::Tcp(tcp_stream)) => tcp_stream,
+			InspectedInner::Network(stream) => {
+				return Ok(InspectedStream {
+					stream: InspectedInner::Network(stream.upgrade_tls(tls_parameters).await?),
+					inspection: self.inspection,
+					tarpit: self.tarpit,
+				})
+			}
+			InspectedInner::Inspected(_) => {
+				return Err(io::Error::other("The connection is already encrypted"))
+			}
+		};
+
+		let stream = match &self.inspection {
+			Some(inspection) => InspectedInner::Inspected(Box::new(
+				inspection
+					.connect(&tls_parameters.domain, tcp_stream)
+					.await?,
+			)),
+			None => InspectedInner::Network(NetworkStream::Tls(
+				tls_parameters
+					.connector
+					.connect(&tls_parameters.domain, tcp_stream)
+					.await
+					.map_err(io::Error::other)?,
+			)),
+		};
+
+		Ok(InspectedStream {
+			stream,
+			inspection: self.inspection,
+			tarpit: self.tarpit,
+		})
+	}
+
+	fn is_encrypted(&self) -> bool {
+		match &self.stream {
+			InspectedInner::Network(stream) => stream.is_encrypted(),
+			InspectedInner::Inspected(_) => true,
+		}
+	}
+}
+
+/// An SMTP client bound to the input's source IP or interface, if set, and
+/// tunneled through its proxy, if set.
+pub(crate) struct CustomClient {
+	client: InnerClient<InspectedStream>,
+	/// The MX host, or one of its IP addresses.
+	address: String,
+	port: u16,
+	security: ClientSecurity,
+	hello_name: String,
+	source_ip: Option<IpAddr>,
+	source_interface: Option<String>,
+	proxy: Option<CheckEmailInputProxy>,
+	timeout: Duration,
+	connect_timeout: Option<Duration>,
+	ehlo_timeout: Option<Duration>,
+	tarpit_threshold: Option<Duration>,
+	/// Set if the TLS session, e.g. the server's certificate, should be
+	/// inspected.
+	tls_inspection: Option<TlsInspection>,
+	/// The server's responses while connecting, in order, with the verb of
+	/// their command: its greeting, with "CONNECT", and its responses to
+	/// EHLO, or LHLO, and STARTTLS. The first EHLO response advertises
+	/// STARTTLS if supported, unlike the one on the encrypted connection.
+	handshake: Vec<(&'static str, Response)>,
+	/// Set if the input's SMTP relay requires authentication.
+	auth: Option<(Mechanism, Credentials)>,
+	endpoint: SmtpEndpoint,
+	timings: SmtpTimings,
+	/// Greet the server with LHLO instead of EHLO.
+	lmtp: bool,
+}
+
+impl CustomClient {
+	pub(super) fn new(
+		address: &str,
+		port: u16,
+		security: ClientSecurity,
+		timeout: Duration,
+		input: &CheckEmailInput,
+	) -> Self {
+		CustomClient {
+			endpoint: SmtpEndpoint::new(address, port, &security),
+			client: InnerClient::new(),
+			address: address.to_string(),
+			port,
+			security,
+			hello_name: input.hello_name.clone(),
+			source_ip: input.source_ip,
+			source_interface: input.source_interface.clone(),
+			proxy: input.proxy.clone(),
+			timeout,
+			connect_timeout: input.smtp_timeouts.connect,
+			ehlo_timeout: input.smtp_timeouts.ehlo,
+			tarpit_threshold: input.tarpit_threshold,
+			tls_inspection: (input.smtp_tls_details || input.smtp_dane_validation).then(|| {
+				TlsInspection {
+					slot: TlsSessionSlot::default(),
+					accept_invalid_certs: input.smtp_accept_invalid_certs,
+					root_certificates: input.smtp_root_certificates.clone(),
+				}
+			}),
+			handshake: Vec::new(),
+			auth: input.smtp_relay.as_ref().map(|relay| {
+				(
+					relay.mechanism.to_mechanism(),
+					Credentials::new(relay.username.clone(), relay.password.clone()),
+				)
+			}),
+			timings: SmtpTimings::default(),
+			lmtp: input.lmtp_server.is_some(),
+		}
+	}
+
+	/// Connect to the server, then read its greeting and send EHLO, with
+	/// STARTTLS if the security requires it, like async-smtp's transport.
+	/// Then authenticate to the SMTP relay, if set.
+	async fn connect(&mut self) -> Result<(), AsyncSmtpError> {
+		with_stage_timeout(self.connect_timeout, self.open()).await?;
+		with_stage_timeout(self.ehlo_timeout, self.hello()).await?;
+		if let Some((mechanism, credentials)) = &self.auth {
+			Pin::new(&mut self.client)
+				.auth(*mechanism, credentials)
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// Open the connection, and read the server's greeting.
+	async fn open(&mut self) -> Result<(), AsyncSmtpError> {
+		let tcp_stream = self.connect_tcp().await?;
+		// Through a proxy, the peer is the proxy.
+		if self.proxy.is_none() {
+			self.endpoint.ip = tcp_stream.peer_addr().ok().map(|addr| addr.ip());
+		}
+		let stream = InspectedStream {
+			stream: InspectedInner::Network(NetworkStream::Tcp(tcp_stream)),
+			inspection: self.tls_inspection.clone(),
+			tarpit: self
+				.tarpit_threshold
+				.map(|threshold| Box::new(DripDetector::new(threshold))),
+		};
+		let stream = match &self.security {
+			ClientSecurity::Wrapper(tls_parameters) => {
+				let start = Instant::now();
+				let stream = stream.upgrade_tls(tls_parameters).await?;
+				self.timings.tls_handshake = Some(elapsed_millis(start));
+				stream
+			}
+			_ => stream,
+		};
+		self.endpoint.used_tls = Some(matches!(self.security, ClientSecurity::Wrapper(_)));
+		self.client.set_timeout(Some(self.timeout));
+		self.client.connect_with_stream(stream).await?;
+		let greeting = Pin::new(&mut self.client).read_response().await;
+		record_response(&mut self.handshake, "CONNECT", greeting)?;
+
+		Ok(())
+	}
+
+	/// Send EHLO, then STARTTLS and EHLO again if the security requires it.
+	async fn hello(&mut self) -> Result<(), AsyncSmtpError> {
+		let start = Instant::now();
+		let response = self.ehlo().await?;
+		self.timings.ehlo = Some(elapsed_millis(start));
+		let server_info = ServerInfo::from_response(&response)?;
+		match (
+			&self.security,
+			server_info.supports_feature(Extension::StartTls),
+		) {
+			(ClientSecurity::Required(_), false) => Err(AsyncSmtpError::from(
+				"Could not encrypt connection, aborting",
+			)),
+			(ClientSecurity::Opportunistic(tls_parameters), true)
+			| (ClientSecurity::Required(tls_parameters), true) => {
+				let start = Instant::now();
+				let starttls = Pin::new(&mut self.client).command(StarttlsCommand).await;
+				record_response(&mut self.handshake, "STARTTLS", starttls)?;
+				self.client = std::mem::take(&mut self.client)
+					.upgrade_tls_stream(tls_parameters)
+					.await?;
+				self.timings.tls_handshake = Some(elapsed_millis(start));
+				self.endpoint.used_tls = Some(true);
+				self.ehlo().await.map(|_| ())
+			}
+			_ => Ok(()),
+		}
+	}
+
+	async fn ehlo(&mut self) -> Result<Response, AsyncSmtpError> {
+		let response = Pin::new(&mut self.client)
+			.command(hello_command(&self.hello_name, self.lmtp))
+			.await;
+		let verb = if self.lmtp { "LHLO" } else { "EHLO" };
+		record_response(&mut self.handshake, verb, response)
+	}
+
+	/// Open a TCP connection to the server, through the proxy if set.
+	async fn connect_tcp(&mut self) -> Result<TcpStream, AsyncSmtpError> {
+		let proxy = match self.proxy.clone() {
+			Some(proxy) => proxy,
+			None => return self.connect_direct(&self.address.clone(), self.port).await,
+		};
+
+		// Errors up to the end of the handshake are the proxy's.
+		let mut stream = match self.connect_direct(&proxy.host, proxy.port).await {
+			Ok(stream) => stream,
+			Err(AsyncSmtpError::Io(err)) => return Err(ProxyHandshakeError::wrap(err).into()),
+			Err(err) => return Err(err),
+		};
+		future::timeout(
+			self.timeout,
+			proxy_handshake(&mut stream, &proxy, &self.address, self.port),
+		)
+		.await
+		.unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::TimedOut)))
+		.map_err(ProxyHandshakeError::wrap)?;
+
+		Ok(stream)
+	}
+
+	/// Open a TCP connection to the first reachable address of host, from
+	/// the source IP or interface. Only the addresses of the source IP's
+	/// version are tried.
+	async fn connect_direct(&mut self, host: &str, port: u16) -> Result<TcpStream, AsyncSmtpError> {
+		let source_ip = self.source_ip;
+		let start = Instant::now();
+		let addresses = lookup_host((host, port)).await?.filter(|address| {
+			source_ip.is_none_or(|source_ip| source_ip.is_ipv4() == address.is_ipv4())
+		});
+		self.timings.dns = Some(elapsed_millis(start));
+
+		let start = Instant::now();
+		let mut last_err = AsyncSmtpError::Resolution;
+		for address in addresses {
+			match future::timeout(self.timeout, self.connect_socket(address)).await {
+				Ok(Ok(stream)) => {
+					self.timings.tcp_connect = Some(elapsed_millis(start));
+					return Ok(stream);
+				}
+				Ok(Err(err)) => last_err = err.into(),
+				Err(_) => last_err = io::Error::from(io::ErrorKind::TimedOut).into(),
+			}
+		}
+
+		Err(last_err)
+	}
+
+	async fn connect_socket(&self, address: SocketAddr) -> io::Result<TcpStream> {
+		let socket = if address.is_ipv4() {
+			TcpSocket::new_v4()?
+		} else {
+			TcpSocket::new_v6()?
+		};
+		if let Some(interface) = &self.source_interface {
+			bind_interface(&socket, interface)?;
+		}
+		if let Some(source_ip) = self.source_ip {
+			socket.bind(SocketAddr::new(source_ip, 0))?;
+		}
+
+		socket.connect(address).await
+	}
+}
+
+/// Add the server's response to the command with verb, if any, to the
+/// handshake.
+fn record_response(
+	handshake: &mut Vec<(&'static str, Response)>,
+	verb: &'static str,
+	result: SmtpResult,
+) -> SmtpResult {
+	if let Ok(response)
+	| Err(AsyncSmtpError::Transient(response))
+	| Err(AsyncSmtpError::Permanent(response)) = &result
+	{
+		handshake.push((verb, response.clone()));
+	}
+
+	result
+}
+
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_interface(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+	socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_interface(_socket: &TcpSocket, _interface: &str) -> io::Result<()> {
+	Err(io::Error::new(
+		io::ErrorKind::Unsupported,
+		"Binding to a network interface is not supported on this platform",
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::tls::tests::self_signed_identity;
+	use super::*;
+	use openssl::ssl::SslAcceptor;
+	use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+	use tokio::net::TcpListener;
+
+	#[tokio::test]
+	async fn should_read_server_info_from_first_ehlo() {
+		use super::super::connectivity::SmtpServerInfo;
+		use super::super::transcript::SmtpConnection;
+		use std::str::FromStr;
+
+		let input = CheckEmailInput::new("foo@example.org".into());
+		let mut client = CustomClient::new(
+			"mx.example.org",
+			25,
+			ClientSecurity::None,
+			Duration::from_secs(5),
+			&input,
+		);
+		let response = |lines: &str| Response::from_str(lines).unwrap();
+		client.handshake = vec![
+			("CONNECT", response("220 mx.example.org ESMTP\r\n")),
+			(
+				"EHLO",
+				response("250-mx.example.org Hello\r\n250-STARTTLS\r\n250 SIZE 1000\r\n"),
+			),
+			("STARTTLS", response("220 Ready to start TLS\r\n")),
+			(
+				"EHLO",
+				response("250-mx.example.org Hello\r\n250-SIZE 1000\r\n250 SMTPUTF8\r\n"),
+			),
+		];
+		let channel = SmtpChannel::Custom(client);
+		assert_eq!(
+			channel.last_ehlo().unwrap().message,
+			vec!["mx.example.org Hello", "SIZE 1000", "SMTPUTF8"]
+		);
+
+		// The first EHLO is read without sending anything on the connection,
+		// which was never opened.
+		let mut connection = SmtpConnection::new(channel, "mx.example.org", &input);
+		let server_info = connection.read_server_info("localhost").await.unwrap();
+		assert_eq!(
+			server_info,
+			SmtpServerInfo {
+				name: "mx.example.org".into(),
+				extensions: vec!["STARTTLS".into(), "SIZE 1000".into()],
+				accepts_mail_from: None,
+			}
+		);
+	}
+
+	#[tokio::test]
+	async fn should_inspect_tls_session() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let address = listener.local_addr().unwrap();
+		tokio::spawn(async move {
+			let (certificate, key) = self_signed_identity();
+			let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+			acceptor.set_certificate(&certificate).unwrap();
+			acceptor.set_private_key(&key).unwrap();
+			let ssl = openssl::ssl::Ssl::new(acceptor.build().context()).unwrap();
+
+			let (stream, _) = listener.accept().await.unwrap();
+			let mut stream = SslStream::new(ssl, stream).unwrap();
+			Pin::new(&mut stream).accept().await.unwrap();
+			stream.write_all(b"250 OK\r\n").await.unwrap();
+		});
+
+		let inspection = TlsInspection {
+			slot: TlsSessionSlot::default(),
+			accept_invalid_certs: true,
+			root_certificates: Vec::new(),
+		};
+		let tcp_stream = TcpStream::connect(address).await.unwrap();
+		inspection
+			.connect("mx.example.org", tcp_stream)
+			.await
+			.unwrap();
+
+		let session = inspection.slot.lock().unwrap().clone().unwrap();
+		let certificate = X509::from_der(&session.peer_certificate.unwrap()).unwrap();
+		assert_eq!(
+			certificate
+				.subject_name()
+				.entries()
+				.next()
+				.unwrap()
+				.data()
+				.as_slice(),
+			b"mx.example.org"
+		);
+		assert!(session.version.unwrap().starts_with("TLSv1."));
+		assert!(session.cipher.is_some());
+	}
+
+	#[tokio::test]
+	async fn should_connect_from_source_ip() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_source_ip(Some("127.0.0.1".parse().unwrap()));
+		let mut client = CustomClient::new(
+			"127.0.0.1",
+			port,
+			ClientSecurity::None,
+			Duration::from_secs(5),
+			&input,
+		);
+
+		let stream = client.connect_tcp().await.unwrap();
+		assert_eq!(
+			stream.local_addr().unwrap().ip(),
+			"127.0.0.1".parse::<IpAddr>().unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn should_record_endpoint() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		tokio::spawn(async move {
+			let (stream, _) = listener.accept().await.unwrap();
+			let (reader, mut writer) = stream.into_split();
+			writer.write_all(b"220 localhost\r\n").await.unwrap();
+			let mut ehlo = String::new();
+			BufReader::new(reader).read_line(&mut ehlo).await.unwrap();
+			writer.write_all(b"250 localhost\r\n").await.unwrap();
+		});
+
+		let input = CheckEmailInput::new("foo@example.org".into());
+		let mut client = CustomClient::new(
+			"localhost",
+			port,
+			ClientSecurity::None,
+			Duration::from_secs(5),
+			&input,
+		);
+		assert_eq!(client.endpoint.ip, None);
+
+		client.connect().await.unwrap();
+		assert_eq!(
+			client.endpoint,
+			SmtpEndpoint {
+				ip: Some("127.0.0.1".parse().unwrap()),
+				port,
+				used_tls: Some(false),
+			}
+		);
+	}
+}
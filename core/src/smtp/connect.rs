@@ -14,24 +14,45 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use async_native_tls::TlsConnector;
+use async_native_tls::{Certificate, TlsConnector};
 use async_recursion::async_recursion;
+use async_smtp::smtp::authentication::Credentials;
+use async_smtp::smtp::error::Error as AsyncSmtpError;
 use async_smtp::{
-	smtp::{commands::*, extension::ClientId, ServerAddress, Socks5Config},
-	ClientTlsParameters, EmailAddress, SmtpClient, SmtpTransport,
+	smtp::{
+		commands::*,
+		extension::{ClientId, Extension, MailParameter, ServerInfo},
+		ServerAddress, Socks5Config,
+	},
+	ClientSecurity, ClientTlsParameters, EmailAddress, SmtpClient, SmtpTransport,
 };
-use async_std::future;
-use rand::rngs::SmallRng;
-use rand::{distributions::Alphanumeric, Rng, SeedableRng};
-use std::iter;
+use async_std::{future, task};
+use futures::future::select_ok;
+use std::net::IpAddr;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use trust_dns_proto::rr::Name;
 
+use super::catch_all::smtp_is_catch_all;
+use super::client::{CustomClient, SmtpChannel, SmtpEndpoint};
+use super::connectivity::check_connectivity;
+use super::dane::validate_dane;
 use super::parser;
-use super::{SmtpDetails, SmtpError};
-use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+use super::retry::{retry_delay, retry_host, ErrorClass};
+use super::size::probe_size;
+use super::tarpit::detect_tarpit;
+use super::timings::elapsed_millis;
+use super::transcript::SmtpConnection;
+use super::vrfy::with_vrfy_fallback;
+use super::{SmtpDetails, SmtpError, SmtpPoolKey};
+use crate::mx::{host_ips, host_to_string, ip_to_string, MxDetails};
+use crate::syntax::requires_smtputf8;
+use crate::util::{
+	constants::LOG_TARGET,
+	input_output::{CheckEmailInput, IpVersion, ProxyKind, SmtpSecurity, SmtpVerificationMode},
+	resolver::create_resolver,
+};
 
 /// Try to send an smtp command, close and return Err if fails.
 macro_rules! try_smtp (
@@ -46,38 +67,216 @@ macro_rules! try_smtp (
     })
 );
 
+/// Port of SMTP over implicit TLS (RFC 8314).
+const SMTPS_PORT: u16 = 465;
+
+/// Delay before starting the next connection attempt, when racing the IP
+/// addresses of a MX host, as recommended by RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
 /// Attempt to connect to host via SMTP, and return SMTP client on success.
-async fn connect_to_host(
+pub(super) async fn connect_to_host(
 	host: &Name,
 	port: u16,
 	input: &CheckEmailInput,
-) -> Result<SmtpTransport, SmtpError> {
-	// hostname verification fails if it ends with '.', for example, using
-	// SOCKS5 proxies we can `io: incomplete` error.
-	let host = host.to_string();
-	let host = host.trim_end_matches('.').to_string();
+) -> Result<SmtpConnection, SmtpError> {
+	let smtp_transport = open_connection(host, port, input).await?;
+
+	mail_from(smtp_transport, &host_to_string(host), port, input).await
+}
 
+/// Open an SMTP connection to host, up to the EHLO step.
+pub(super) async fn open_connection(
+	host: &Name,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpConnection, SmtpError> {
+	let (host, port) = connection_target(host, port, input);
+
+	let mut smtp_transport = if input.ip_version == IpVersion::Auto || input.proxy.is_some() {
+		connect_to_address(&host, &host, port, input).await?
+	} else {
+		race_addresses(&host, port, input).await?
+	};
+	if let Some(pool) = input.context.smtp_pool() {
+		pool.record_created();
+	}
+	inspect_server(&mut smtp_transport, &host, port, input).await;
+
+	Ok(smtp_transport)
+}
+
+/// Validate the server's TLS certificate against the TLSA records of host,
+/// if the input's `smtp_dane_validation` is set, and read the server's
+/// capabilities, if its `smtp_capabilities` is set. Must be called before
+/// `MAIL FROM`, as reading the capabilities may reset the mail transaction.
+async fn inspect_server(
+	smtp_transport: &mut SmtpConnection,
+	host: &str,
+	port: u16,
+	input: &CheckEmailInput,
+) {
+	if input.smtp_dane_validation {
+		let certificate = smtp_transport.peer_certificate();
+		let dane = validate_dane(host, port, certificate.as_deref(), input).await;
+		smtp_transport.set_dane(dane);
+	}
+	if input.smtp_capabilities {
+		// A broken connection makes `MAIL FROM` fail right after.
+		let _ = smtp_transport.read_server_info(&input.hello_name).await;
+	}
+}
+
+/// Connect to the IP addresses of host allowed by the input's `ip_version`,
+/// starting a new attempt every `CONNECTION_ATTEMPT_DELAY` until one
+/// succeeds. The pending attempts are then dropped.
+async fn race_addresses(
+	host: &str,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpConnection, SmtpError> {
+	let start = Instant::now();
+	let resolver = create_resolver(input)
+		.await
+		.map_err(|_| SmtpError::SmtpError(AsyncSmtpError::Resolution))?;
+	let ips = order_addresses(host_ips(&resolver, host).await, input.ip_version);
+	let dns = elapsed_millis(start);
+	if ips.is_empty() {
+		return Err(SmtpError::SmtpError(AsyncSmtpError::Resolution));
+	}
+
+	let attempts = ips.into_iter().enumerate().map(|(i, ip)| {
+		Box::pin(async move {
+			task::sleep(CONNECTION_ATTEMPT_DELAY * i as u32).await;
+			connect_to_address(host, &ip_to_string(ip), port, input).await
+		})
+	});
+
+	let (mut smtp_transport, _) = select_ok(attempts).await?;
+	smtp_transport.set_dns_timing(dns);
+
+	Ok(smtp_transport)
+}
+
+/// Order the IP addresses of a MX host for `race_addresses`: the preferred
+/// IP version's addresses first, alternating with the other version's,
+/// unless it's excluded.
+fn order_addresses(ips: Vec<IpAddr>, ip_version: IpVersion) -> Vec<IpAddr> {
+	let (ipv6, ipv4): (Vec<_>, Vec<_>) = ips.into_iter().partition(IpAddr::is_ipv6);
+	let (preferred, other) = match ip_version {
+		IpVersion::Ipv4Only => (ipv4, vec![]),
+		IpVersion::Ipv6Only => (ipv6, vec![]),
+		IpVersion::PreferIpv4 => (ipv4, ipv6),
+		IpVersion::Auto | IpVersion::PreferIpv6 => (ipv6, ipv4),
+	};
+
+	let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+	let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+	loop {
+		match (preferred.next(), other.next()) {
+			(None, None) => return ordered,
+			(first, second) => ordered.extend(first.into_iter().chain(second)),
+		}
+	}
+}
+
+/// Timeout of each network operation of the SMTP connection.
+pub(super) const SMTP_CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The TLS connector of the SMTP connections, with the input's root
+/// certificates, and accepting invalid certificates if the input allows it.
+fn tls_connector(input: &CheckEmailInput) -> Result<TlsConnector, SmtpError> {
+	let mut connector = TlsConnector::new()
+		.use_sni(true)
+		.danger_accept_invalid_certs(input.smtp_accept_invalid_certs)
+		.danger_accept_invalid_hostnames(input.smtp_accept_invalid_certs);
+	for pem in &input.smtp_root_certificates {
+		let certificate = Certificate::from_pem(pem.as_bytes())
+			.map_err(|err| SmtpError::SmtpError(AsyncSmtpError::Tls(err)))?;
+		connector = connector.add_root_certificate(certificate);
+	}
+
+	Ok(connector)
+}
+
+/// Open an SMTP connection to host, at `address`, which is either host
+/// itself or one of its IP addresses. The connection is bound to the
+/// input's `source_ip` and `source_interface`, unless a SOCKS5 proxy is used.
+/// SOCKS5 proxies are handled by async-smtp's transport. Otherwise, our own
+/// client is used, which enforces the connection and EHLO timeouts
+/// separately, can inspect the server's TLS certificate, and tells which IP
+/// address answered and whether the connection was encrypted.
+pub(super) async fn connect_to_address(
+	host: &str,
+	address: &str,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpConnection, SmtpError> {
 	let security = {
-		let tls_params = ClientTlsParameters::new(
-			host.clone(),
-			TlsConnector::new()
-				.use_sni(true)
-				.danger_accept_invalid_certs(true)
-				.danger_accept_invalid_hostnames(true),
-		);
+		let tls_params = ClientTlsParameters::new(host.to_string(), tls_connector(input)?);
+
+		// Port 465 is SMTPS, i.e. TLS from the start of the connection.
+		let smtp_security = match (port, input.smtp_security) {
+			(SMTPS_PORT, SmtpSecurity::Opportunistic | SmtpSecurity::Required) => {
+				SmtpSecurity::Wrapper
+			}
+			(_, smtp_security) => smtp_security,
+		};
 
-		input.smtp_security.to_client_security(tls_params)
+		smtp_security.to_client_security(tls_params)
 	};
 
+	let use_transport = input
+		.proxy
+		.as_ref()
+		.is_some_and(|proxy| proxy.kind == ProxyKind::Socks5);
+	if use_transport && input.lmtp_server.is_some() {
+		return Err(SmtpError::SmtpError(AsyncSmtpError::from(
+			"LMTP is not supported through SOCKS5 proxies",
+		)));
+	}
+	let channel = if use_transport {
+		let endpoint = SmtpEndpoint::new(address, port, &security);
+		SmtpChannel::Transport(smtp_transport(address, port, security, input), endpoint)
+	} else {
+		SmtpChannel::Custom(CustomClient::new(
+			address,
+			port,
+			security,
+			SMTP_CLIENT_TIMEOUT,
+			input,
+		))
+	};
+	let mut smtp_transport = SmtpConnection::new(channel, host, input);
+
+	try_smtp!(
+		smtp_transport.connect(address, port).await,
+		smtp_transport,
+		input.to_email,
+		address,
+		port
+	);
+
+	Ok(smtp_transport)
+}
+
+/// async-smtp's transport to `address`, through the input's SOCKS5 proxy if
+/// set, authenticating with the input's SMTP relay credentials if set.
+fn smtp_transport(
+	address: &str,
+	port: u16,
+	security: ClientSecurity,
+	input: &CheckEmailInput,
+) -> SmtpTransport {
 	let mut smtp_client = SmtpClient::with_security(
 		ServerAddress {
-			host: host.clone(),
+			host: address.to_string(),
 			port,
 		},
 		security,
 	)
 	.hello_name(ClientId::Domain(input.hello_name.clone()))
-	.timeout(Some(Duration::new(30, 0))); // Set timeout to 30s
+	.timeout(Some(SMTP_CLIENT_TIMEOUT));
 
 	if let Some(proxy) = &input.proxy {
 		let socks5_config = match (&proxy.username, &proxy.password) {
@@ -92,53 +291,220 @@ async fn connect_to_host(
 
 		smtp_client = smtp_client.use_socks5(socks5_config);
 	}
+	if let Some(relay) = &input.smtp_relay {
+		smtp_client = smtp_client
+			.credentials(Credentials::new(
+				relay.username.clone(),
+				relay.password.clone(),
+			))
+			.authentication_mechanism(vec![relay.mechanism.to_mechanism()]);
+	}
+
+	smtp_client.into_transport()
+}
+
+/// Send "MAIL FROM" on an established connection.
+pub(super) async fn mail_from(
+	mut smtp_transport: SmtpConnection,
+	host: &str,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpConnection, SmtpError> {
+	// Internationalized usernames need the SMTPUTF8 extension. Check the
+	// server supports it before sending an invalid "MAIL FROM".
+	let parameters = if requires_smtputf8(&input.to_email) {
+		let supported = match supports_smtputf8(&mut smtp_transport, input).await {
+			Ok(supported) => supported,
+			Err(err) => {
+				log::debug!(
+					target: LOG_TARGET,
+					"[email={}] Closing [host={}:{}], because of error '{:?}'.",
+					input.to_email,
+					host,
+					port,
+					err
+				);
+				let _ = smtp_transport.close().await;
+
+				return Err(SmtpError::SmtpError(err));
+			}
+		};
+		if !supported {
+			log::debug!(
+				target: LOG_TARGET,
+				"[email={}] Closing [host={}:{}], because it doesn't support SMTPUTF8.",
+				input.to_email,
+				host,
+				port
+			);
+			let _ = smtp_transport.close().await;
+
+			return Err(SmtpError::SmtpUtf8NotSupported);
+		}
 
-	let mut smtp_transport = smtp_client.into_transport();
+		vec![MailParameter::SmtpUtfEight]
+	} else {
+		vec![]
+	};
 
+	// "MAIL FROM: user@example.org"
 	try_smtp!(
-		smtp_transport.connect().await,
+		smtp_transport
+			.command(MailCommand::new(Some(from_email(input)), parameters))
+			.await,
 		smtp_transport,
 		input.to_email,
 		host,
 		port
 	);
 
-	// "MAIL FROM: user@example.org"
-	let from_email = EmailAddress::from_str(input.from_email.as_ref()).unwrap_or_else(|_| {
+	Ok(smtp_transport)
+}
+
+/// Does the SMTP server advertise the SMTPUTF8 extension, in its response
+/// to the last EHLO? See `SmtpConnection::last_ehlo`.
+async fn supports_smtputf8(
+	smtp_transport: &mut SmtpConnection,
+	input: &CheckEmailInput,
+) -> Result<bool, AsyncSmtpError> {
+	let response = smtp_transport.last_ehlo(&input.hello_name).await?;
+	let server_info = ServerInfo::from_response(&response)?;
+
+	Ok(server_info.supports_feature(Extension::SmtpUtfEight))
+}
+
+/// The input's `from_email`, or a placeholder if it's not a valid email.
+pub(super) fn from_email(input: &CheckEmailInput) -> EmailAddress {
+	EmailAddress::from_str(input.from_email.as_ref()).unwrap_or_else(|_| {
 		log::warn!(
 			"Inputted from_email \"{}\" is not a valid email, using \"user@example.org\" instead",
 			input.from_email
 		);
 		EmailAddress::from_str("user@example.org").expect("This is a valid email. qed.")
-	});
-	try_smtp!(
-		smtp_transport
-			.command(MailCommand::new(Some(from_email), vec![],))
-			.await,
-		smtp_transport,
-		input.to_email,
-		host,
-		port
-	);
+	})
+}
 
-	Ok(smtp_transport)
+/// The host and port to connect to, to verify emails on the MX `host`: the
+/// input's LMTP server or SMTP relay, if set, otherwise the MX host itself.
+fn connection_target(host: &Name, port: u16, input: &CheckEmailInput) -> (String, u16) {
+	// hostname verification fails if it ends with '.', for example, using
+	// SOCKS5 proxies we can `io: incomplete` error.
+	match (&input.lmtp_server, &input.smtp_relay) {
+		(Some(lmtp_server), _) => (lmtp_server.host.clone(), lmtp_server.port),
+		(None, Some(relay)) => (relay.host.clone(), relay.port),
+		(None, None) => (host_to_string(host), port),
+	}
+}
+
+/// Key of `host` in the SMTP connection pool.
+fn pool_key(host: &Name, port: u16, input: &CheckEmailInput) -> SmtpPoolKey {
+	let (host, port) = connection_target(host, port, input);
+	SmtpPoolKey {
+		host: host.to_lowercase(),
+		port,
+		security: input.smtp_security,
+		inspect_tls: input.smtp_tls_details || input.smtp_dane_validation,
+		accept_invalid_certs: input.smtp_accept_invalid_certs,
+		root_certificates: input.smtp_root_certificates.clone(),
+		source_ip: input.source_ip,
+		source_interface: input.source_interface.clone(),
+		proxy: input.proxy.clone(),
+		hello_name: input.hello_name.clone(),
+		lmtp: input.lmtp_server.is_some(),
+		relay: input.smtp_relay.clone(),
+	}
+}
+
+/// Get an SMTP client on host, ready to receive "RCPT TO" commands. If the
+/// connection pool is enabled, idle connections are tried first, and the
+/// ones closed in the meantime by the server are discarded.
+pub(super) async fn checkout_or_connect(
+	host: &Name,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpConnection, SmtpError> {
+	if let Some(pool) = input.context.smtp_pool() {
+		let key = pool_key(host, port, input);
+		while let Some(smtp_transport) = pool.checkout(&key) {
+			let mut smtp_transport = SmtpConnection::new(smtp_transport, &key.host, input);
+			inspect_server(&mut smtp_transport, &key.host, key.port, input).await;
+			// The connection can still verify the emails which don't need
+			// SMTPUTF8, so put it back in the pool.
+			if requires_smtputf8(&input.to_email)
+				&& matches!(
+					supports_smtputf8(&mut smtp_transport, input).await,
+					Ok(false)
+				) {
+				if let Some(rejected) = pool.checkin(key, smtp_transport.into_channel()) {
+					let _ = SmtpConnection::new(rejected, &host_to_string(host), input)
+						.close()
+						.await;
+				}
+				return Err(SmtpError::SmtpUtf8NotSupported);
+			}
+			match mail_from(smtp_transport, &key.host, key.port, input).await {
+				Ok(smtp_transport) => return Ok(smtp_transport),
+				Err(err) => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[email={}] Discarding stale pooled connection to [host={}:{}], because of error '{:?}'.",
+						input.to_email,
+						host,
+						port,
+						err
+					);
+					pool.discard();
+				}
+			}
+		}
+	}
+
+	connect_to_host(host, port, input).await
+}
+
+/// Close the SMTP connection. If the connection pool is enabled, reset the
+/// connection with RSET and put it back in the pool instead.
+pub(super) async fn release(
+	mut smtp_transport: SmtpConnection,
+	host: &Name,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<(), SmtpError> {
+	let pool = match input.context.smtp_pool() {
+		Some(pool) => pool,
+		None => return smtp_transport.close().await.map_err(SmtpError::SmtpError),
+	};
+
+	if smtp_transport.command(RsetCommand).await.is_ok() {
+		match pool.checkin(pool_key(host, port, input), smtp_transport.into_channel()) {
+			Some(rejected) => {
+				smtp_transport = SmtpConnection::new(rejected, &host_to_string(host), input)
+			}
+			None => return Ok(()),
+		}
+	}
+
+	// The verification itself succeeded, so ignore errors when closing.
+	let _ = smtp_transport.close().await;
+
+	Ok(())
 }
 
 /// Description of the deliverability information we can gather from
 /// communicating with the SMTP server.
-struct Deliverability {
+pub(super) struct Deliverability {
 	/// Is this email account's inbox full?
-	has_full_inbox: bool,
+	pub(super) has_full_inbox: bool,
 	/// Can we send an email to this address?
-	is_deliverable: bool,
+	pub(super) is_deliverable: bool,
 	/// Is the email blocked or disabled by the provider?
-	is_disabled: bool,
+	pub(super) is_disabled: bool,
 }
 
 /// Check if `to_email` exists on host SMTP server. This is the core logic of
 /// this tool.
-async fn email_deliverable(
-	smtp_transport: &mut SmtpTransport,
+pub(super) async fn email_deliverable(
+	smtp_transport: &mut SmtpConnection,
 	to_email: &EmailAddress,
 ) -> Result<Deliverability, SmtpError> {
 	// "RCPT TO: <target email>"
@@ -216,48 +582,39 @@ async fn email_deliverable(
 	}
 }
 
-/// Verify the existence of a catch-all on the domain.
-async fn smtp_is_catch_all(
-	smtp_transport: &mut SmtpTransport,
-	domain: &str,
-) -> Result<bool, SmtpError> {
-	// Create a random 15-char alphanumerical string.
-	let mut rng = SmallRng::from_entropy();
-	let random_email: String = iter::repeat(())
-		.map(|()| rng.sample(Alphanumeric))
-		.map(char::from)
-		.take(15)
-		.collect();
-	let random_email = EmailAddress::new(format!("{random_email}@{domain}"));
-
-	email_deliverable(
-		smtp_transport,
-		&random_email.expect("Email is correctly constructed. qed."),
-	)
-	.await
-	.map(|deliverability| deliverability.is_deliverable)
-}
-
 async fn create_smtp_future(
 	to_email: &EmailAddress,
 	host: &Name,
 	port: u16,
 	domain: &str,
 	input: &CheckEmailInput,
-) -> Result<(bool, Deliverability), SmtpError> {
+	known_catch_all: Option<bool>,
+) -> Result<SmtpDetails, SmtpError> {
+	if input.smtp_verification_mode != SmtpVerificationMode::Mailbox {
+		return check_connectivity(host, port, input).await;
+	}
+
 	// FIXME If the SMTP is not connectable, we should actually return an
 	// Ok(SmtpDetails { can_connect_smtp: false, ... }).
-	let mut smtp_transport = connect_to_host(host, port, input).await?;
-
-	let is_catch_all = smtp_is_catch_all(&mut smtp_transport, domain)
-		.await
-		.unwrap_or(false);
-	let deliverability = if is_catch_all {
-		Deliverability {
-			has_full_inbox: false,
-			is_deliverable: true,
-			is_disabled: false,
+	let mut smtp_transport = checkout_or_connect(host, port, input).await?;
+
+	// Skip the random email probes if we already know the answer.
+	let (is_catch_all, catch_all_probes) = match known_catch_all {
+		Some(is_catch_all) => (is_catch_all, None),
+		None => {
+			let probes = smtp_is_catch_all(&mut smtp_transport, domain, input).await;
+			(probes.is_catch_all(), Some(probes))
 		}
+	};
+	let (deliverability, verified_with_vrfy) = if is_catch_all {
+		(
+			Deliverability {
+				has_full_inbox: false,
+				is_deliverable: true,
+				is_disabled: false,
+			},
+			false,
+		)
 	} else {
 		let mut result = email_deliverable(&mut smtp_transport, to_email).await;
 
@@ -275,17 +632,75 @@ async fn create_smtp_future(
 				);
 
 				let _ = smtp_transport.close().await;
+				let transcript = smtp_transport.take_transcript();
 				smtp_transport = connect_to_host(host, port, input).await?;
+				smtp_transport.prepend_transcript(transcript);
 				result = email_deliverable(&mut smtp_transport, to_email).await;
 			}
 		}
 
-		result?
+		let (result, verified_with_vrfy) =
+			with_vrfy_fallback(&mut smtp_transport, to_email, input, result).await;
+
+		(result?, verified_with_vrfy)
 	};
 
-	smtp_transport.close().await.map_err(SmtpError::SmtpError)?;
+	let mut smtp_details = SmtpDetails {
+		can_connect_smtp: true,
+		has_full_inbox: deliverability.has_full_inbox,
+		is_catch_all,
+		is_deliverable: deliverability.is_deliverable,
+		is_disabled: deliverability.is_disabled,
+		catch_all_probes,
+		verified_with_vrfy,
+		tls: smtp_transport.tls_details(),
+		dane: smtp_transport.dane(),
+		server_info: smtp_transport.server_info(),
+		mx_ip: smtp_transport.endpoint().ip,
+		mx_port: Some(smtp_transport.endpoint().port),
+		used_tls: smtp_transport.endpoint().used_tls,
+		timings: smtp_transport.timings(),
+		..Default::default()
+	};
 
-	Ok((is_catch_all, deliverability))
+	smtp_details.size_probe = match input.smtp_size_probe {
+		Some(size) => {
+			match probe_size(
+				&mut smtp_transport,
+				&input.hello_name,
+				from_email(input),
+				to_email,
+				size,
+			)
+			.await
+			{
+				Ok(size_probe) => Some(size_probe),
+				Err(err) => {
+					// The deliverability is already known, so don't fail the
+					// verification because of the probe.
+					log::debug!(
+						target: LOG_TARGET,
+						"[email={}] SIZE probe failed on [host={}:{}], because of error '{:?}'.",
+						input.to_email,
+						host,
+						port,
+						err
+					);
+					let _ = smtp_transport.close().await;
+					smtp_details.transcript = smtp_transport.take_transcript();
+					smtp_details.annotations = smtp_transport.take_annotations();
+					return Ok(smtp_details);
+				}
+			}
+		}
+		None => None,
+	};
+
+	smtp_details.transcript = smtp_transport.take_transcript();
+	smtp_details.annotations = smtp_transport.take_annotations();
+	release(smtp_transport, host, port, input).await?;
+
+	Ok(smtp_details)
 }
 
 /// Get all email details we can from one single `EmailAddress`, without
@@ -296,76 +711,258 @@ async fn check_smtp_without_retry(
 	port: u16,
 	domain: &str,
 	input: &CheckEmailInput,
+	known_catch_all: Option<bool>,
 ) -> Result<SmtpDetails, SmtpError> {
-	let fut = create_smtp_future(to_email, host, port, domain, input);
-	let (is_catch_all, deliverability) = if let Some(smtp_timeout) = input.smtp_timeout {
-		future::timeout(smtp_timeout, fut).await??
+	let fut = create_smtp_future(to_email, host, port, domain, input, known_catch_all);
+	let result = if let Some(smtp_timeout) = input.smtp_timeout {
+		future::timeout(smtp_timeout, fut).await?
 	} else {
-		fut.await?
+		fut.await
 	};
 
-	Ok(SmtpDetails {
-		can_connect_smtp: true,
-		has_full_inbox: deliverability.has_full_inbox,
-		is_catch_all,
-		is_deliverable: deliverability.is_deliverable,
-		is_disabled: deliverability.is_disabled,
-	})
+	result.map_err(detect_tarpit)
 }
 
 /// Get all email details we can from one single `EmailAddress`.
-/// Retry the SMTP connection on error, in particular to avoid greylisting.
+/// Retry the SMTP connection on error, in particular to avoid greylisting,
+/// as decided by the retry policy.
+///
+/// If `known_catch_all` is set, the catch-all verification is skipped and
+/// its value is used instead. `attempt` counts the attempts from 1, and
+/// `greylisted` tells whether a previous attempt was greylisted. The other
+/// hosts of `mx`, the domain's MX lookup, can be retried on, see
+/// `RetryStrategy::OtherHost`.
 #[async_recursion]
+#[allow(clippy::too_many_arguments)]
 pub async fn check_smtp_with_retry(
 	to_email: &EmailAddress,
 	host: &Name,
 	port: u16,
 	domain: &str,
+	mx: &MxDetails,
 	input: &CheckEmailInput,
-	count: usize,
+	attempt: usize,
+	known_catch_all: Option<bool>,
+	greylisted: bool,
 ) -> Result<SmtpDetails, SmtpError> {
 	log::debug!(
 		target: LOG_TARGET,
 		"[email={}] Check SMTP [attempt={}] on [host={}:{}]",
 		input.to_email,
-		input.retries - count + 1,
+		attempt,
 		host,
 		port
 	);
 
-	let result = check_smtp_without_retry(to_email, host, port, domain, input).await;
+	let mut result =
+		check_smtp_without_retry(to_email, host, port, domain, input, known_catch_all).await;
+	if let Ok(smtp_details) = &mut result {
+		smtp_details.greylisted_then_accepted = greylisted && smtp_details.is_deliverable;
+		smtp_details.mx_host = Some(host_to_string(host));
+	}
 
 	log::debug!(
 		target: LOG_TARGET,
 		"[email={}] Got result for [attempt={}] on [host={}:{}], [result={:?}]",
 		input.to_email,
-		input.retries - count + 1,
+		attempt,
 		host,
 		port,
 		result
 	);
 
-	match &result {
-		// Don't retry if we used Hotmail or Yahoo API. This two options should
-		// be non-callable, as this function only deals with actual SMTP
-		// connection errors.
-		#[cfg(feature = "headless")]
-		Err(SmtpError::HotmailError(_)) => result,
-		Err(SmtpError::YahooError(_)) => result,
-		Err(SmtpError::GmailError(_)) => result,
-		// Only retry if the SMTP error was unknown.
-		Err(err) if err.get_description().is_none() => {
-			if count <= 1 {
-				result
-			} else {
-				log::debug!(
-					target: LOG_TARGET,
-					"[email={}] Potential greylisting detected, retrying.",
-					input.to_email,
-				);
-				check_smtp_with_retry(to_email, host, port, domain, input, count - 1).await
-			}
-		}
-		_ => result,
+	let (class, delay) = match &result {
+		Err(err) => match retry_delay(input, attempt, err) {
+			Some(retry) => retry,
+			None => return result,
+		},
+		Ok(_) => return result,
+	};
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Got {:?} error, retrying in {:?}.",
+		input.to_email,
+		class,
+		delay
+	);
+	if !delay.is_zero() {
+		task::sleep(delay).await;
+	}
+
+	// Greylisting servers only accept retries after a while.
+	let greylisted = greylisted || (class == ErrorClass::Greylisted && !delay.is_zero());
+	let host = &retry_host(host, mx, class, input);
+	check_smtp_with_retry(
+		to_email,
+		host,
+		port,
+		domain,
+		mx,
+		input,
+		attempt + 1,
+		known_catch_all,
+		greylisted,
+	)
+	.await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::smtp::connectivity::SmtpServerInfo;
+	use crate::smtp::test_server;
+	use crate::smtp::{SmtpPool, SmtpPoolConfig};
+	use crate::util::input_output::{SmtpAuthMechanism, SmtpRelay};
+	use crate::CheckEmailContext;
+	use async_smtp::smtp::ServerAddress;
+
+	#[test]
+	fn should_not_share_relay_connections() {
+		let pool = SmtpPool::new(SmtpPoolConfig {
+			max_idle_time: Duration::from_secs(60),
+			max_idle_per_host: 1,
+		});
+		let host = Name::from_str("mx.example.org.").unwrap();
+		let mut relay_input = CheckEmailInput::default();
+		relay_input.set_smtp_relay(Some(SmtpRelay {
+			host: "smtp.relay.example".into(),
+			port: 587,
+			username: "user".into(),
+			password: "secret".into(),
+			mechanism: SmtpAuthMechanism::Plain,
+		}));
+		let channel = SmtpChannel::Transport(
+			SmtpClient::with_security(
+				ServerAddress {
+					host: "smtp.relay.example".into(),
+					port: 587,
+				},
+				ClientSecurity::None,
+			)
+			.into_transport(),
+			SmtpEndpoint::default(),
+		);
+		assert!(pool
+			.checkin(pool_key(&host, 25, &relay_input), channel)
+			.is_none());
+
+		let input = CheckEmailInput::default();
+		assert!(pool.checkout(&pool_key(&host, 25, &input)).is_none());
+		let mut other_hello = relay_input.clone();
+		other_hello.set_hello_name("other.example".into());
+		assert!(pool.checkout(&pool_key(&host, 25, &other_hello)).is_none());
+		assert!(pool.checkout(&pool_key(&host, 25, &relay_input)).is_some());
+	}
+
+	#[tokio::test]
+	async fn should_keep_pooled_connection_without_smtputf8() {
+		let port = test_server::serve(test_server::PLAIN_EHLO).await;
+		let host = Name::from_str("127.0.0.1.").unwrap();
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input
+			.set_retries(2)
+			.set_context(CheckEmailContext::new().with_smtp_pool(SmtpPoolConfig::default()));
+		let smtp_transport = checkout_or_connect(&host, port, &input).await.unwrap();
+		release(smtp_transport, &host, port, &input).await.unwrap();
+
+		let mut utf8_input = input.clone();
+		utf8_input.to_email = "jöhn@example.org".into();
+		let to_email = crate::syntax::check_syntax(&utf8_input.to_email)
+			.address
+			.unwrap();
+		let result = check_smtp_with_retry(
+			&to_email,
+			&host,
+			port,
+			"example.org",
+			&MxDetails::default(),
+			&utf8_input,
+			1,
+			None,
+			false,
+		)
+		.await;
+		assert!(matches!(result, Err(SmtpError::SmtpUtf8NotSupported)));
+
+		// Neither retried on a new connection, nor dropped from the pool.
+		let stats = input.context.smtp_pool_stats().unwrap();
+		assert_eq!(stats.connections_created, 1);
+		assert_eq!(stats.connections_discarded, 0);
+		assert_eq!(stats.idle_connections, 1);
+	}
+
+	#[tokio::test]
+	async fn should_report_server_capabilities() {
+		let port = test_server::serve(
+			"250-localhost greets you\r\n250-PIPELINING\r\n250-SIZE 35882577\r\n250 8BITMIME\r\n",
+		)
+		.await;
+		let host = Name::from_str("127.0.0.1.").unwrap();
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_retries(1).set_smtp_capabilities(true);
+		let to_email = EmailAddress::from_str("foo@example.org").unwrap();
+
+		let smtp_details = check_smtp_with_retry(
+			&to_email,
+			&host,
+			port,
+			"example.org",
+			&MxDetails::default(),
+			&input,
+			1,
+			None,
+			false,
+		)
+		.await
+		.unwrap();
+		assert_eq!(
+			smtp_details.server_info,
+			Some(SmtpServerInfo {
+				name: "localhost".into(),
+				extensions: vec![
+					"PIPELINING".into(),
+					"SIZE 35882577".into(),
+					"8BITMIME".into()
+				],
+				accepts_mail_from: None,
+			})
+		);
+	}
+
+	#[test]
+	fn should_order_addresses_by_ip_version() {
+		let ips: Vec<IpAddr> = ["192.0.2.1", "192.0.2.2", "2001:db8::1"]
+			.iter()
+			.map(|ip| ip.parse().unwrap())
+			.collect();
+
+		assert_eq!(
+			order_addresses(ips.clone(), IpVersion::PreferIpv6),
+			vec![ips[2], ips[0], ips[1]]
+		);
+		assert_eq!(
+			order_addresses(ips.clone(), IpVersion::PreferIpv4),
+			vec![ips[0], ips[2], ips[1]]
+		);
+		assert_eq!(
+			order_addresses(ips.clone(), IpVersion::Ipv4Only),
+			vec![ips[0], ips[1]]
+		);
+		assert_eq!(
+			order_addresses(ips.clone(), IpVersion::Ipv6Only),
+			vec![ips[2]]
+		);
+	}
+
+	#[test]
+	fn should_reject_invalid_root_certificate() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		assert!(tls_connector(&input).is_ok());
+
+		input.set_smtp_root_certificates(vec!["not a certificate".into()]);
+		assert!(matches!(
+			tls_connector(&input),
+			Err(SmtpError::SmtpError(AsyncSmtpError::Tls(_)))
+		));
 	}
 }
@@ -0,0 +1,126 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Connectivity-only verification: connect to the SMTP server and read its
+//! capabilities, without ever probing a mailbox with `RCPT TO`.
+
+use async_smtp::smtp::commands::MailCommand;
+use async_smtp::smtp::response::Response;
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::Name;
+
+use super::connect::{from_email, open_connection};
+use super::{SmtpDetails, SmtpError};
+use crate::util::input_output::{CheckEmailInput, SmtpVerificationMode};
+
+/// What the SMTP server told about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SmtpServerInfo {
+	/// Name the server announced in its EHLO response.
+	pub name: String,
+	/// Extensions advertised in the EHLO response, e.g. "PIPELINING" or
+	/// "SIZE 35882577". If the connection was upgraded with STARTTLS, these
+	/// are the ones advertised on the encrypted connection.
+	pub extensions: Vec<String>,
+	/// Did the server accept our `MAIL FROM`? Only present with the
+	/// `MailFrom` mode.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub accepts_mail_from: Option<bool>,
+}
+
+/// Connect to host, send EHLO, and `MAIL FROM` if the input's mode asks for
+/// it, then close the connection.
+pub(super) async fn check_connectivity(
+	host: &Name,
+	port: u16,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, SmtpError> {
+	let mut smtp_transport = open_connection(host, port, input).await?;
+	let mut server_info = match smtp_transport.read_server_info(&input.hello_name).await {
+		Ok(server_info) => server_info,
+		Err(err) => {
+			let _ = smtp_transport.close().await;
+			return Err(SmtpError::SmtpError(err));
+		}
+	};
+
+	if input.smtp_verification_mode == SmtpVerificationMode::MailFrom {
+		let mail_from = smtp_transport
+			.command(MailCommand::new(Some(from_email(input)), vec![]))
+			.await;
+		server_info.accepts_mail_from = Some(mail_from.is_ok());
+	}
+
+	// The server answered, so ignore errors when closing.
+	let _ = smtp_transport.close().await;
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		server_info: Some(server_info),
+		tls: smtp_transport.tls_details(),
+		dane: smtp_transport.dane(),
+		mx_ip: smtp_transport.endpoint().ip,
+		mx_port: Some(smtp_transport.endpoint().port),
+		used_tls: smtp_transport.endpoint().used_tls,
+		timings: smtp_transport.timings(),
+		transcript: smtp_transport.take_transcript(),
+		annotations: smtp_transport.take_annotations(),
+		..Default::default()
+	})
+}
+
+/// Read the server's name and extensions from its EHLO response.
+pub(super) fn parse_ehlo(response: &Response) -> SmtpServerInfo {
+	let mut lines = response.message.iter();
+	let name = lines
+		.next()
+		.and_then(|line| line.split_whitespace().next())
+		.unwrap_or_default()
+		.to_string();
+
+	SmtpServerInfo {
+		name,
+		extensions: lines.map(|line| line.trim().to_string()).collect(),
+		accepts_mail_from: None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+
+	#[test]
+	fn should_parse_ehlo_response() {
+		let response = Response::from_str(
+			"250-mx.example.org Hello [192.0.2.1]\r\n250-PIPELINING\r\n250-SIZE 35882577\r\n250 8BITMIME\r\n",
+		)
+		.unwrap();
+
+		assert_eq!(
+			parse_ehlo(&response),
+			SmtpServerInfo {
+				name: "mx.example.org".into(),
+				extensions: vec![
+					"PIPELINING".into(),
+					"SIZE 35882577".into(),
+					"8BITMIME".into()
+				],
+				accepts_mail_from: None,
+			}
+		);
+	}
+}
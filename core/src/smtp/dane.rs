@@ -0,0 +1,228 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DANE (RFC 7672) validation of the certificate presented by the SMTP
+//! server, against the TLSA records of the MX host.
+
+use async_std_resolver::lookup::Lookup;
+use openssl::error::ErrorStack;
+use openssl::sha::{sha256, sha512};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::rdata::tlsa::{CertUsage, Matching, Selector, TLSA};
+use trust_dns_proto::rr::{RData, RecordType};
+use trust_dns_resolver::error::ResolveErrorKind;
+
+use crate::util::input_output::CheckEmailInput;
+use crate::util::resolver::create_resolver;
+
+/// Result of the DANE validation of the SMTP server's certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaneStatus {
+	/// The MX host has no TLSA records, i.e. doesn't use DANE.
+	NoRecords,
+	/// The TLSA records couldn't be looked up.
+	LookupFailed,
+	/// The MX host has TLSA records, but they aren't DNSSEC-validated, so
+	/// they can't be trusted and the certificate isn't checked against them.
+	Unauthenticated,
+	/// The connection isn't encrypted, so there's no certificate to check.
+	NoCertificate,
+	/// None of the TLSA records can be checked. native-tls doesn't expose
+	/// the certificate chain, so only the records matching the server's own
+	/// certificate, i.e. with the PKIX-EE or DANE-EE usage, are supported.
+	Unusable,
+	/// The certificate matches one of the usable TLSA records.
+	Valid,
+	/// The certificate matches none of the usable TLSA records.
+	Invalid,
+}
+
+/// Validate `certificate`, the DER-encoded certificate presented by host on
+/// port, against the TLSA records of host. The records are only used if
+/// they have a valid DNSSEC chain of trust, as required by RFC 7672.
+pub(super) async fn validate_dane(
+	host: &str,
+	port: u16,
+	certificate: Option<&[u8]>,
+	input: &CheckEmailInput,
+) -> DaneStatus {
+	let resolver = match create_resolver(input).await {
+		Ok(resolver) => resolver,
+		Err(_) => return DaneStatus::LookupFailed,
+	};
+	let name = format!("_{}._tcp.{}", port, host.trim_end_matches('.'));
+	match resolver.lookup(&name, RecordType::TLSA).await {
+		Ok(lookup) if tlsa_records(&lookup).is_empty() => return DaneStatus::NoRecords,
+		Ok(_) => {}
+		Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+			return DaneStatus::NoRecords
+		}
+		Err(_) => return DaneStatus::LookupFailed,
+	};
+
+	// The records exist, look them up again through the validating resolver,
+	// which fails if they aren't signed.
+	match resolver.validated_lookup(&name, RecordType::TLSA).await {
+		Some(Ok(lookup)) => match_records(&tlsa_records(&lookup), certificate),
+		_ => DaneStatus::Unauthenticated,
+	}
+}
+
+/// The TLSA records of lookup.
+fn tlsa_records(lookup: &Lookup) -> Vec<TLSA> {
+	lookup
+		.iter()
+		.filter_map(|rdata| match rdata {
+			RData::TLSA(tlsa) => Some(tlsa.clone()),
+			_ => None,
+		})
+		.collect()
+}
+
+/// Match `certificate` against the end-entity TLSA records.
+fn match_records(records: &[TLSA], certificate: Option<&[u8]>) -> DaneStatus {
+	if records.is_empty() {
+		return DaneStatus::NoRecords;
+	}
+	let certificate = match certificate.map(X509::from_der) {
+		Some(Ok(certificate)) => certificate,
+		_ => return DaneStatus::NoCertificate,
+	};
+
+	let usable = records
+		.iter()
+		.filter(|tlsa| {
+			matches!(
+				tlsa.cert_usage(),
+				CertUsage::Service | CertUsage::DomainIssued
+			)
+		})
+		.filter_map(|tlsa| matches_record(tlsa, &certificate).ok().flatten())
+		.collect::<Vec<_>>();
+
+	if usable.is_empty() {
+		DaneStatus::Unusable
+	} else if usable.contains(&true) {
+		DaneStatus::Valid
+	} else {
+		DaneStatus::Invalid
+	}
+}
+
+/// Whether certificate matches tlsa, or None if the record's selector or
+/// matching type is unknown.
+fn matches_record(tlsa: &TLSA, certificate: &X509) -> Result<Option<bool>, ErrorStack> {
+	let selected = match tlsa.selector() {
+		Selector::Full => certificate.to_der()?,
+		Selector::Spki => certificate.public_key()?.public_key_to_der()?,
+		_ => return Ok(None),
+	};
+	let data = match tlsa.matching() {
+		Matching::Raw => selected,
+		Matching::Sha256 => sha256(&selected).to_vec(),
+		Matching::Sha512 => sha512(&selected).to_vec(),
+		_ => return Ok(None),
+	};
+
+	Ok(Some(data == tlsa.cert_data()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::tls::tests::self_signed_certificate;
+	use super::*;
+	use crate::util::resolver::Resolver;
+	use crate::CheckEmailContext;
+	use async_std_resolver::ResolveError;
+	use async_trait::async_trait;
+	use std::sync::Arc;
+	use trust_dns_proto::op::Query;
+	use trust_dns_proto::rr::{Name, Record};
+
+	/// A resolver with an unsigned TLSA record for every name.
+	#[derive(Debug)]
+	struct UnsignedResolver;
+
+	#[async_trait]
+	impl Resolver for UnsignedResolver {
+		async fn lookup(
+			&self,
+			name: &str,
+			record_type: RecordType,
+		) -> Result<Lookup, ResolveError> {
+			let query = Query::query(Name::from_ascii(name)?, record_type);
+			let tlsa = TLSA::new(
+				CertUsage::DomainIssued,
+				Selector::Spki,
+				Matching::Sha256,
+				vec![0; 32],
+			);
+			let record = Record::from_rdata(query.name().clone(), 300, RData::TLSA(tlsa));
+			Ok(Lookup::new_with_max_ttl(query, Arc::from(vec![record])))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_not_trust_unvalidated_tlsa_records() {
+		let der = self_signed_certificate().to_der().unwrap();
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_context(CheckEmailContext::new().with_resolver(Arc::new(UnsignedResolver)));
+
+		assert_eq!(
+			validate_dane("mx.example.org", 25, Some(&der), &input).await,
+			DaneStatus::Unauthenticated
+		);
+	}
+
+	#[test]
+	fn should_match_certificate_against_tlsa_records() {
+		let certificate = self_signed_certificate();
+		let der = certificate.to_der().unwrap();
+		let spki = certificate
+			.public_key()
+			.unwrap()
+			.public_key_to_der()
+			.unwrap();
+		let record = |usage, data: &[u8]| {
+			TLSA::new(
+				usage,
+				Selector::Spki,
+				Matching::Sha256,
+				sha256(data).to_vec(),
+			)
+		};
+
+		assert_eq!(match_records(&[], Some(&der)), DaneStatus::NoRecords);
+		assert_eq!(
+			match_records(&[record(CertUsage::DomainIssued, &spki)], None),
+			DaneStatus::NoCertificate
+		);
+		assert_eq!(
+			match_records(&[record(CertUsage::DomainIssued, &spki)], Some(&der)),
+			DaneStatus::Valid
+		);
+		assert_eq!(
+			match_records(&[record(CertUsage::DomainIssued, b"other")], Some(&der)),
+			DaneStatus::Invalid
+		);
+		assert_eq!(
+			match_records(&[record(CertUsage::TrustAnchor, &spki)], Some(&der)),
+			DaneStatus::Unusable
+		);
+	}
+}
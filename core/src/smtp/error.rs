@@ -14,12 +14,18 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use super::challenge::Challenge;
 use super::gmail::GmailError;
+use super::gmx::GmxError;
+use super::icloud::IcloudError;
+use super::mailru::MailruError;
 #[cfg(feature = "headless")]
 use super::microsoft::hotmail::HotmailError;
 use super::microsoft::microsoft365::Microsoft365Error;
 use super::parser;
+use super::proton::ProtonError;
 use super::yahoo::YahooError;
+use super::yandex::YandexError;
 use crate::util::ser_with_display::ser_with_display;
 use async_smtp::smtp::error::Error as AsyncSmtpError;
 use async_std::future;
@@ -48,8 +54,37 @@ pub enum SmtpError {
 	HotmailError(HotmailError),
 	/// Error when verifying a Microsoft 365 email via HTTP request.
 	Microsoft365Error(Microsoft365Error),
-	/// Email is in the `skipped_domains` parameter.
+	/// Error when verifying a Proton Mail email via HTTP request.
+	ProtonError(ProtonError),
+	/// Error when verifying an iCloud email via HTTP requests.
+	IcloudError(IcloudError),
+	/// Error when verifying a Mail.ru email via HTTP request.
+	MailruError(MailruError),
+	/// Error when verifying a Yandex email via HTTP requests.
+	YandexError(YandexError),
+	/// Error when verifying a GMX or WEB.DE email via HTTP request.
+	GmxError(GmxError),
+	/// Error of a custom provider verifier, see `ProviderVerifier`.
+	ProviderError(String),
+	/// The provider answered with a CAPTCHA or bot challenge, so the email
+	/// couldn't be verified. See `ErrorClass::Challenged`.
+	ChallengeRequired(Challenge),
+	/// Email is in the `skipped_domains` parameter, or in a provider route
+	/// skipping it. The message names the pattern which matched.
 	SkippedDomain(String),
+	/// The email's username contains non-ASCII characters, but the SMTP
+	/// server doesn't support the SMTPUTF8 extension, so it can't receive
+	/// emails for this address.
+	SmtpUtf8NotSupported,
+	/// The SMTP session shared by several emails, see `check_email_batch`,
+	/// failed before this email could be verified. Contains the debug
+	/// representation of the error, which is reported on the first email
+	/// affected.
+	BatchError(String),
+	/// The SMTP server paused in the middle of a response for longer than the
+	/// input's `tarpit_threshold`, which is how tarpits slow down bulk
+	/// senders. Contains the step which was answered too slowly, e.g. "RCPT".
+	Tarpitted(String),
 }
 
 impl From<SocksError> for SmtpError {
@@ -66,7 +101,10 @@ impl From<future::TimeoutError> for SmtpError {
 
 impl From<YahooError> for SmtpError {
 	fn from(e: YahooError) -> Self {
-		SmtpError::YahooError(e)
+		match e {
+			YahooError::ChallengeRequired(challenge) => SmtpError::ChallengeRequired(challenge),
+			e => SmtpError::YahooError(e),
+		}
 	}
 }
 
@@ -79,7 +117,10 @@ impl From<GmailError> for SmtpError {
 #[cfg(feature = "headless")]
 impl From<HotmailError> for SmtpError {
 	fn from(e: HotmailError) -> Self {
-		SmtpError::HotmailError(e)
+		match e {
+			HotmailError::ChallengeRequired(challenge) => SmtpError::ChallengeRequired(challenge),
+			e => SmtpError::HotmailError(e),
+		}
 	}
 }
 
@@ -89,16 +130,53 @@ impl From<Microsoft365Error> for SmtpError {
 	}
 }
 
+impl From<ProtonError> for SmtpError {
+	fn from(e: ProtonError) -> Self {
+		SmtpError::ProtonError(e)
+	}
+}
+
+impl From<IcloudError> for SmtpError {
+	fn from(e: IcloudError) -> Self {
+		SmtpError::IcloudError(e)
+	}
+}
+
+impl From<MailruError> for SmtpError {
+	fn from(e: MailruError) -> Self {
+		SmtpError::MailruError(e)
+	}
+}
+
+impl From<YandexError> for SmtpError {
+	fn from(e: YandexError) -> Self {
+		SmtpError::YandexError(e)
+	}
+}
+
+impl From<GmxError> for SmtpError {
+	fn from(e: GmxError) -> Self {
+		SmtpError::GmxError(e)
+	}
+}
+
 impl SmtpError {
 	/// Get a human-understandable description of the error, in form of an enum
-	/// SmtpErrorDesc. This only parses the following known errors:
-	/// - IP blacklisted
-	/// - IP needs reverse DNS
+	/// SmtpErrorDesc. This only parses the error responses of the SMTP
+	/// server, see `SmtpErrorDesc` for the known categories. Blacklisting and
+	/// greylisting are checked first, since their responses often also
+	/// mention a relay or a policy.
 	pub fn get_description(&self) -> Option<SmtpErrorDesc> {
 		match self {
-			SmtpError::SmtpError(_) => {
+			SmtpError::SmtpError(AsyncSmtpError::Transient(r) | AsyncSmtpError::Permanent(r)) => {
 				if parser::is_err_ip_blacklisted(self) {
 					Some(SmtpErrorDesc::IpBlacklisted)
+				} else if parser::is_err_greylisted(self) {
+					Some(SmtpErrorDesc::Greylisted)
+				} else if let Some(desc) =
+					parser::error_category(&r.message.join("; ").to_lowercase())
+				{
+					Some(desc)
 				} else if parser::is_err_needs_rdns(self) {
 					Some(SmtpErrorDesc::NeedsRDNS)
 				} else {
@@ -108,9 +186,37 @@ impl SmtpError {
 			_ => None,
 		}
 	}
+
+	/// Did the provider throttle us, i.e. answer "429 Too Many Requests" or
+	/// with a challenge?
+	pub fn is_throttled(&self) -> bool {
+		let err = match self {
+			SmtpError::ChallengeRequired(_) => return true,
+			SmtpError::YahooError(YahooError::ReqwestError(err))
+			| SmtpError::GmailError(GmailError::ReqwestError(err))
+			| SmtpError::ProtonError(ProtonError::ReqwestError(err))
+			| SmtpError::IcloudError(IcloudError::ReqwestError(err))
+			| SmtpError::MailruError(MailruError::ReqwestError(err))
+			| SmtpError::YandexError(YandexError::ReqwestError(err))
+			| SmtpError::GmxError(GmxError::ReqwestError(err)) => err,
+			_ => return false,
+		};
+
+		err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS)
+	}
+
+	/// Did the error happen while talking to the MX host, e.g. a refused
+	/// connection, a time-out or an error response, so that another MX host
+	/// of the domain might answer instead?
+	pub fn is_host_error(&self) -> bool {
+		matches!(
+			self,
+			SmtpError::SmtpError(_) | SmtpError::TimeoutError(_) | SmtpError::Tarpitted(_)
+		)
+	}
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 /// SmtpErrorDesc describes a description of which category the error belongs
 /// to.
 pub enum SmtpErrorDesc {
@@ -118,4 +224,58 @@ pub enum SmtpErrorDesc {
 	IpBlacklisted,
 	/// The IP needs a reverse DNS entry.
 	NeedsRDNS,
+	/// The mailbox is full, or over quota.
+	MailboxFull,
+	/// The email account is disabled or suspended by the provider.
+	AccountDisabled,
+	/// The server refuses to relay to the recipient's domain.
+	RelayDenied,
+	/// The server limits the rate of our connections, recipients or
+	/// messages.
+	RateLimited,
+	/// The server temporarily rejects unknown senders, expecting them to
+	/// retry later.
+	Greylisted,
+	/// The message is rejected by the server's policy, e.g. SPF or DMARC.
+	PolicyRejection,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_smtp::smtp::response::{Category, Code, Detail, Response, Severity};
+
+	fn permanent_error(message: &str) -> SmtpError {
+		SmtpError::SmtpError(AsyncSmtpError::Permanent(Response::new(
+			Code::new(
+				Severity::PermanentNegativeCompletion,
+				Category::MailSystem,
+				Detail::Zero,
+			),
+			vec![message.to_string()],
+		)))
+	}
+
+	#[test]
+	fn should_describe_blacklisted_ip_before_categories() {
+		for message in [
+			"5.7.1 Relaying denied. IP name possibly forged [45.154.35.252]",
+			"5.7.1 <unknown[23.129.64.100]>: Client host rejected: Access denied",
+			"554 5.7.1 23.129.64.213 is listed on the DNSBL dnsbl.spfbl.net",
+		] {
+			assert_eq!(
+				permanent_error(message).get_description(),
+				Some(SmtpErrorDesc::IpBlacklisted),
+				"{message}"
+			);
+		}
+		assert_eq!(
+			permanent_error("550 5.7.1 Unable to relay").get_description(),
+			Some(SmtpErrorDesc::RelayDenied)
+		);
+		assert_eq!(
+			permanent_error("550 5.7.1 SPF check failed").get_description(),
+			Some(SmtpErrorDesc::PolicyRejection)
+		);
+	}
 }
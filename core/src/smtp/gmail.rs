@@ -16,7 +16,7 @@
 
 use super::SmtpDetails;
 use crate::{
-	smtp::http_api::create_client,
+	smtp::http_api::{create_client, error_for_throttle},
 	util::{
 		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
 	},
@@ -59,6 +59,7 @@ pub async fn check_gmail(
 		.query(&[("email", to_email)])
 		.send()
 		.await?;
+	error_for_throttle(&response)?;
 
 	let email_exists = response.headers().contains_key("Set-Cookie");
 
@@ -0,0 +1,152 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Verification of the addresses of GMX and WEB.DE, both run by United
+//! Internet, whose SMTP servers are too unreliable to tell.
+
+use super::SmtpDetails;
+use crate::{
+	smtp::http_api::{create_client, error_for_throttle},
+	util::{
+		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
+	},
+};
+use async_smtp::EmailAddress;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const GMX_AVAILABILITY_API: &str =
+	"https://onereg-email-suggest.mam.gmx.net/email-alias/availability";
+const WEBDE_AVAILABILITY_API: &str =
+	"https://onereg-email-suggest.mam.web.de/email-alias/availability";
+
+/// Domains of the addresses hosted by GMX itself.
+const GMX_DOMAINS: &[&str] = &["gmx.de", "gmx.net", "gmx.at", "gmx.ch"];
+
+/// The request of the availability API of the signup pages.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AvailabilityRequest<'a> {
+	email_address: &'a str,
+}
+
+/// The response of the availability API of the signup pages.
+#[derive(Debug, Deserialize)]
+struct AvailabilityResponse {
+	/// Can the address be signed up for?
+	available: bool,
+}
+
+/// Possible errors when checking GMX and WEB.DE email addresses.
+#[derive(Debug, Serialize)]
+pub enum GmxError {
+	/// Error when serializing or deserializing HTTP requests and responses.
+	#[serde(serialize_with = "ser_with_display")]
+	ReqwestError(ReqwestError),
+}
+
+impl fmt::Display for GmxError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{self:?}")
+	}
+}
+
+impl From<ReqwestError> for GmxError {
+	fn from(error: ReqwestError) -> Self {
+		GmxError::ReqwestError(error)
+	}
+}
+
+/// The availability API of the signup page of `domain`'s brand, or None if
+/// it's not a domain of GMX or WEB.DE.
+fn availability_api(domain: &str) -> Option<&'static str> {
+	let domain = domain.to_lowercase();
+	if domain == "web.de" {
+		Some(WEBDE_AVAILABILITY_API)
+	} else if GMX_DOMAINS.contains(&domain.as_str()) {
+		Some(GMX_AVAILABILITY_API)
+	} else {
+		None
+	}
+}
+
+/// Is host one of the MX hosts of GMX or WEB.DE, for one of their own
+/// domains, as opposed to the custom domains they host?
+pub(super) fn is_gmx_host(host_lowercase: &str, domain: &str) -> bool {
+	(host_lowercase.ends_with(".gmx.net.") || host_lowercase.ends_with(".web.de."))
+		&& availability_api(domain).is_some()
+}
+
+/// Use the availability API of the GMX or WEB.DE signup page to verify if
+/// an email address of theirs exists.
+pub async fn check_gmx(
+	to_email: &EmailAddress,
+	domain: &str,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, GmxError> {
+	let api = availability_api(domain).unwrap_or(GMX_AVAILABILITY_API);
+	let to_email = to_email.to_string();
+	let response = create_client(input, "gmx")?
+		.post(api)
+		.json(&AvailabilityRequest {
+			email_address: &to_email,
+		})
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<AvailabilityResponse>().await?;
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] gmx response: {:?}",
+		to_email,
+		response
+	);
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable: !response.available,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::smtp::routing::verification_methods;
+	use crate::smtp::{SmtpError, VerificationMethod};
+
+	#[test]
+	fn should_route_gmx_addresses() {
+		assert_eq!(availability_api("WEB.de"), Some(WEBDE_AVAILABILITY_API));
+		assert_eq!(availability_api("gmx.ch"), Some(GMX_AVAILABILITY_API));
+		assert!(is_gmx_host("mx00.gmx.net.", "gmx.de"));
+		assert!(!is_gmx_host("mx00.gmx.net.", "example.org"));
+
+		// Their MX hosts are skipped by default, unless the API is used.
+		let mut input = CheckEmailInput::default();
+		assert!(matches!(
+			verification_methods("mx-ha03.web.de.", "web.de", &input),
+			Err(SmtpError::SkippedDomain(_))
+		));
+		input.set_gmx_use_api(true);
+		assert_eq!(
+			verification_methods("mx-ha03.web.de.", "web.de", &input).unwrap(),
+			vec![VerificationMethod::GmxApi]
+		);
+	}
+}
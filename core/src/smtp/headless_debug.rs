@@ -0,0 +1,149 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Diagnostics of the headless verifications which don't find the elements
+//! they expect, usually because the provider changed its page.
+
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use fantoccini::{error::CmdError, Client};
+use serde::Serialize;
+
+use super::microsoft::hotmail::HotmailError;
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// The state of the page when a headless verification didn't find the
+/// elements it expected.
+#[derive(Debug, Serialize)]
+pub struct HeadlessDiagnostics {
+	/// The error which revealed the unexpected page.
+	pub error: String,
+	/// The URL of the page.
+	pub url: Option<String>,
+	/// The HTML of the page, unless it was written to the input's
+	/// `headless_debug_dir`.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub html: Option<String>,
+	/// The base64-encoded PNG screenshot of the page, unless it was written
+	/// to the input's `headless_debug_dir`.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub screenshot: Option<String>,
+	/// The files the HTML and the screenshot were written to.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub files: Vec<PathBuf>,
+}
+
+/// Run `page`, the interactions of the `flow` headless verification with
+/// its page. If it doesn't find an element, the page is captured in a
+/// `HotmailError::UnexpectedPage` error, or in the input's
+/// `headless_debug_dir`.
+pub(crate) async fn with_diagnostics<T>(
+	client: &Client,
+	input: &CheckEmailInput,
+	flow: &str,
+	page: impl Future<Output = Result<T, CmdError>>,
+) -> Result<T, HotmailError> {
+	let err = match page.await {
+		Ok(result) => return Ok(result),
+		Err(err) if err.is_miss() || matches!(err, CmdError::WaitTimeout) => err,
+		Err(err) => return Err(err.into()),
+	};
+
+	let mut diagnostics = HeadlessDiagnostics {
+		error: err.to_string(),
+		url: client.current_url().await.ok().map(|url| url.to_string()),
+		html: client.source().await.ok(),
+		screenshot: client
+			.screenshot()
+			.await
+			.ok()
+			.map(|png| STANDARD.encode(png)),
+		files: vec![],
+	};
+	if let Some(dir) = &input.headless_debug_dir {
+		save_diagnostics(&mut diagnostics, dir, flow);
+	}
+
+	Err(HotmailError::UnexpectedPage(Box::new(diagnostics)))
+}
+
+/// Move the HTML and the screenshot of `diagnostics` to files in `dir`,
+/// named after the flow and the time. They're kept in `diagnostics` if
+/// they can't be written.
+fn save_diagnostics(diagnostics: &mut HeadlessDiagnostics, dir: &Path, flow: &str) {
+	let millis = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_millis())
+		.unwrap_or_default();
+	let html = diagnostics
+		.html
+		.take()
+		.map(|html| ("html", html.into_bytes()));
+	let screenshot = diagnostics
+		.screenshot
+		.take()
+		.map(|screenshot| ("png", STANDARD.decode(screenshot).unwrap_or_default()));
+
+	for (extension, content) in html.into_iter().chain(screenshot) {
+		let path = dir.join(format!("{flow}-{millis}.{extension}"));
+		match fs::create_dir_all(dir).and_then(|_| fs::write(&path, &content)) {
+			Ok(()) => diagnostics.files.push(path),
+			Err(err) => {
+				log::debug!(
+					target: LOG_TARGET,
+					"Cannot write headless diagnostics to {:?}: {}",
+					path,
+					err
+				);
+				match extension {
+					"html" => diagnostics.html = Some(String::from_utf8_lossy(&content).into()),
+					_ => diagnostics.screenshot = Some(STANDARD.encode(content)),
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_save_diagnostics_to_dir() {
+		let dir = std::env::temp_dir().join(format!("headless-debug-{}", std::process::id()));
+		let mut diagnostics = HeadlessDiagnostics {
+			error: "timeout waiting on condition".into(),
+			url: None,
+			html: Some("<html></html>".into()),
+			screenshot: Some(STANDARD.encode([0x89, b'P', b'N', b'G'])),
+			files: vec![],
+		};
+
+		save_diagnostics(&mut diagnostics, &dir, "hotmail");
+
+		assert!(diagnostics.html.is_none() && diagnostics.screenshot.is_none());
+		assert_eq!(diagnostics.files.len(), 2);
+		assert_eq!(
+			fs::read_to_string(&diagnostics.files[0]).unwrap(),
+			"<html></html>"
+		);
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,391 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Pool of the WebDriver sessions of the headless verifications, shared by
+//! all the verifications of a context, so that verifying at scale doesn't
+//! overwhelm the WebDriver server with parallel sessions.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use fantoccini::Client;
+use serde::{Deserialize, Serialize};
+
+use super::microsoft::hotmail::{new_session, HotmailError};
+use super::webdriver::MANAGED_WEBDRIVER;
+use crate::util::input_output::CheckEmailInput;
+
+/// How often a queued verification checks for a free session slot.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Configuration of the headless session pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadlessPoolConfig {
+	/// Maximum number of sessions open in parallel, idle or not, per
+	/// WebDriver endpoint. Further verifications are queued until a
+	/// session is released.
+	pub max_sessions: usize,
+	/// Maximum time a session can stay idle in the pool. Older sessions
+	/// are closed instead of being reused.
+	pub max_idle_time: Duration,
+}
+
+impl Default for HeadlessPoolConfig {
+	fn default() -> Self {
+		HeadlessPoolConfig {
+			max_sessions: 4,
+			max_idle_time: Duration::from_secs(60),
+		}
+	}
+}
+
+/// Counters of the headless session pool, useful for tuning its config.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HeadlessPoolStats {
+	/// Number of new sessions opened on a WebDriver server.
+	pub sessions_created: u64,
+	/// Number of times an idle session was reused.
+	pub sessions_reused: u64,
+	/// Number of sessions closed, because they had been idle for too long,
+	/// or failed.
+	pub sessions_discarded: u64,
+	/// Number of verifications which had to wait for a free session.
+	pub verifications_queued: u64,
+	/// Number of sessions currently idle in the pool.
+	pub idle_sessions: usize,
+}
+
+/// What a verification gets from the pool.
+enum Slot {
+	/// An idle session to reuse.
+	Idle(Client),
+	/// A free slot, to open a new session in.
+	New,
+	/// No free slot, the verification has to wait.
+	Full,
+}
+
+/// The sessions of a WebDriver endpoint.
+#[derive(Default)]
+struct Sessions {
+	/// Number of sessions in use, or being opened.
+	active: usize,
+	/// The idle sessions, with the proxy their browser goes through.
+	idle: Vec<(Client, Option<String>, Instant)>,
+}
+
+/// A pool of WebDriver sessions, keyed by WebDriver endpoint.
+pub(crate) struct HeadlessPool {
+	config: HeadlessPoolConfig,
+	sessions: Mutex<HashMap<String, Sessions>>,
+	created: AtomicU64,
+	reused: AtomicU64,
+	discarded: AtomicU64,
+	queued: AtomicU64,
+}
+
+impl fmt::Debug for HeadlessPool {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("HeadlessPool")
+			.field("config", &self.config)
+			.field("stats", &self.stats())
+			.finish()
+	}
+}
+
+impl HeadlessPool {
+	pub fn new(config: HeadlessPoolConfig) -> Self {
+		HeadlessPool {
+			config,
+			sessions: Mutex::new(HashMap::new()),
+			created: AtomicU64::new(0),
+			reused: AtomicU64::new(0),
+			discarded: AtomicU64::new(0),
+			queued: AtomicU64::new(0),
+		}
+	}
+
+	/// Take the most recently used idle session of `webdriver` going
+	/// through `proxy`, or a slot to open a new one in. Sessions idle for
+	/// longer than `max_idle_time`, and idle sessions going through another
+	/// proxy when the pool is full, are returned in `expired`, to be closed
+	/// by the caller.
+	fn try_acquire(
+		&self,
+		webdriver: &str,
+		proxy: &Option<String>,
+		expired: &mut Vec<Client>,
+	) -> Slot {
+		let mut sessions = self
+			.sessions
+			.lock()
+			.expect("Pool lock is not poisoned. qed.");
+		let sessions = sessions.entry(webdriver.to_string()).or_default();
+
+		let max_idle_time = self.config.max_idle_time;
+		let (fresh, stale): (Vec<_>, Vec<_>) = sessions
+			.idle
+			.drain(..)
+			.partition(|(_, _, idle_since)| idle_since.elapsed() <= max_idle_time);
+		sessions.idle = fresh;
+		self.discarded
+			.fetch_add(stale.len() as u64, Ordering::Relaxed);
+		expired.extend(stale.into_iter().map(|(client, _, _)| client));
+
+		let max_sessions = self.config.max_sessions.max(1);
+		if let Some(index) = sessions
+			.idle
+			.iter()
+			.rposition(|(_, idle_proxy, _)| idle_proxy == proxy)
+		{
+			let (client, _, _) = sessions.idle.remove(index);
+			sessions.active += 1;
+			self.reused.fetch_add(1, Ordering::Relaxed);
+			Slot::Idle(client)
+		} else if sessions.active + sessions.idle.len() < max_sessions {
+			sessions.active += 1;
+			Slot::New
+		} else if !sessions.idle.is_empty() {
+			// Make room for a session going through this proxy.
+			let (client, _, _) = sessions.idle.remove(0);
+			expired.push(client);
+			self.discarded.fetch_add(1, Ordering::Relaxed);
+			sessions.active += 1;
+			Slot::New
+		} else {
+			Slot::Full
+		}
+	}
+
+	/// Give back the slot of a session of `webdriver`, with the session to
+	/// keep idle, if it's reusable, and the proxy it goes through.
+	fn release(&self, webdriver: &str, client: Option<(Client, Option<String>)>) {
+		let mut sessions = self
+			.sessions
+			.lock()
+			.expect("Pool lock is not poisoned. qed.");
+		let sessions = sessions.entry(webdriver.to_string()).or_default();
+		sessions.active = sessions.active.saturating_sub(1);
+		match client {
+			Some((client, proxy)) => sessions.idle.push((client, proxy, Instant::now())),
+			None => {
+				self.discarded.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+	}
+
+	/// Close all idle sessions.
+	pub async fn clear(&self) {
+		let idle = {
+			let mut sessions = self
+				.sessions
+				.lock()
+				.expect("Pool lock is not poisoned. qed.");
+			sessions
+				.values_mut()
+				.flat_map(|sessions| sessions.idle.drain(..))
+				.collect::<Vec<_>>()
+		};
+		self.discarded
+			.fetch_add(idle.len() as u64, Ordering::Relaxed);
+		for (client, _, _) in idle {
+			let _ = client.close().await;
+		}
+	}
+
+	pub fn stats(&self) -> HeadlessPoolStats {
+		HeadlessPoolStats {
+			sessions_created: self.created.load(Ordering::Relaxed),
+			sessions_reused: self.reused.load(Ordering::Relaxed),
+			sessions_discarded: self.discarded.load(Ordering::Relaxed),
+			verifications_queued: self.queued.load(Ordering::Relaxed),
+			idle_sessions: self
+				.sessions
+				.lock()
+				.expect("Pool lock is not poisoned. qed.")
+				.values()
+				.map(|sessions| sessions.idle.len())
+				.sum(),
+		}
+	}
+}
+
+/// A WebDriver session, from the context's headless pool if enabled. Call
+/// `release` once the verification succeeded. Otherwise, dropping the
+/// session discards it.
+pub(crate) struct HeadlessSession {
+	client: Option<Client>,
+	/// The pool and the WebDriver endpoint the session belongs to.
+	pool: Option<(Arc<HeadlessPool>, String)>,
+	/// The proxy the browser goes through, as "host:port".
+	proxy: Option<String>,
+}
+
+impl HeadlessSession {
+	/// Open a session on `webdriver`, or reuse an idle one of the context's
+	/// headless pool, waiting for a free slot if the pool is full.
+	/// `MANAGED_WEBDRIVER` stands for the context's managed WebDriver server.
+	pub async fn open(webdriver: &str, input: &CheckEmailInput) -> Result<Self, HotmailError> {
+		let managed;
+		let webdriver = match (webdriver, input.context.managed_webdriver()) {
+			(MANAGED_WEBDRIVER, Some(server)) => {
+				managed = server.endpoint().await?;
+				managed.as_str()
+			}
+			(MANAGED_WEBDRIVER, None) => {
+				return Err(HotmailError::WebDriverProcess(io::Error::new(
+					io::ErrorKind::NotFound,
+					"The context has no managed WebDriver server",
+				)))
+			}
+			(webdriver, _) => webdriver,
+		};
+		let proxy = input
+			.proxy
+			.as_ref()
+			.map(|proxy| format!("{}:{}", proxy.host, proxy.port));
+		let pool = match input.context.headless_pool() {
+			Some(pool) => pool,
+			None => {
+				return Ok(HeadlessSession {
+					client: Some(new_session(webdriver, input.proxy.as_ref()).await?),
+					pool: None,
+					proxy,
+				})
+			}
+		};
+
+		let mut queued = false;
+		loop {
+			let mut expired = vec![];
+			let slot = pool.try_acquire(webdriver, &proxy, &mut expired);
+			for client in expired {
+				let _ = client.close().await;
+			}
+
+			let client = match slot {
+				Slot::Idle(client) => client,
+				Slot::New => match new_session(webdriver, input.proxy.as_ref()).await {
+					Ok(client) => {
+						pool.created.fetch_add(1, Ordering::Relaxed);
+						client
+					}
+					Err(err) => {
+						pool.release(webdriver, None);
+						return Err(err);
+					}
+				},
+				Slot::Full => {
+					if !queued {
+						pool.queued.fetch_add(1, Ordering::Relaxed);
+						queued = true;
+					}
+					task::sleep(QUEUE_POLL_INTERVAL).await;
+					continue;
+				}
+			};
+
+			return Ok(HeadlessSession {
+				client: Some(client),
+				pool: Some((pool, webdriver.to_string())),
+				proxy,
+			});
+		}
+	}
+
+	/// Put the session back in the pool, without the cookies of this
+	/// verification, or close it if there's no pool.
+	pub async fn release(mut self) -> Result<(), HotmailError> {
+		let client = match self.client.take() {
+			Some(client) => client,
+			None => return Ok(()),
+		};
+
+		match self.pool.take() {
+			Some((pool, webdriver)) => {
+				let reusable = client.delete_all_cookies().await.is_ok();
+				if reusable {
+					pool.release(&webdriver, Some((client, self.proxy.take())));
+				} else {
+					pool.release(&webdriver, None);
+					let _ = client.close().await;
+				}
+				Ok(())
+			}
+			None => Ok(client.close().await?),
+		}
+	}
+}
+
+impl Deref for HeadlessSession {
+	type Target = Client;
+
+	fn deref(&self) -> &Client {
+		self.client
+			.as_ref()
+			.expect("The client is only taken on release. qed.")
+	}
+}
+
+impl Drop for HeadlessSession {
+	fn drop(&mut self) {
+		// The session failed, so its slot is freed without reusing it.
+		if let (Some(_), Some((pool, webdriver))) = (&self.client, &self.pool) {
+			pool.release(webdriver, None);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_queue_beyond_max_sessions() {
+		let pool = HeadlessPool::new(HeadlessPoolConfig {
+			max_sessions: 1,
+			..Default::default()
+		});
+		let mut expired = vec![];
+
+		assert!(matches!(
+			pool.try_acquire("http://localhost:4444", &None, &mut expired),
+			Slot::New
+		));
+		assert!(matches!(
+			pool.try_acquire("http://localhost:4444", &None, &mut expired),
+			Slot::Full
+		));
+		// Other WebDriver endpoints have their own slots.
+		assert!(matches!(
+			pool.try_acquire("http://localhost:4445", &None, &mut expired),
+			Slot::New
+		));
+
+		pool.release("http://localhost:4444", None);
+		assert!(matches!(
+			pool.try_acquire("http://localhost:4444", &None, &mut expired),
+			Slot::New
+		));
+		assert_eq!(pool.stats().sessions_discarded, 1);
+	}
+}
@@ -15,7 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
-use reqwest::Error as ReqwestError;
+use reqwest::{Error as ReqwestError, Response, StatusCode};
 
 /// Helper function to create a reqwest client, with optional proxy.
 pub fn create_client(
@@ -38,3 +38,13 @@ pub fn create_client(
 		Ok(reqwest::Client::new())
 	}
 }
+
+/// Turn a "429 Too Many Requests" response into an error, which the
+/// context's provider limits take as a sign to cool down.
+pub(crate) fn error_for_throttle(response: &Response) -> Result<(), ReqwestError> {
+	if response.status() == StatusCode::TOO_MANY_REQUESTS {
+		response.error_for_status_ref()?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,169 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::SmtpDetails;
+use crate::{
+	smtp::http_api::{create_client, error_for_throttle},
+	util::{
+		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
+	},
+};
+use async_smtp::EmailAddress;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const ACCOUNT_PAGE: &str = "https://appleid.apple.com/account";
+const VALIDATION_API: &str = "https://appleid.apple.com/account/validation/appleid";
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.1 Safari/605.1.15"; // Fake one to use in API requests
+
+/// Domains of the iCloud addresses, which are all Apple IDs.
+const ICLOUD_DOMAINS: &[&str] = &["icloud.com", "me.com", "mac.com"];
+
+/// The request of the Apple ID validation API.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ValidationRequest<'a> {
+	email_address: &'a str,
+}
+
+/// The response of the Apple ID validation API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ValidationResponse {
+	/// Is the address already an Apple ID?
+	used: bool,
+}
+
+/// Possible errors when checking iCloud email addresses.
+#[derive(Debug, Serialize)]
+pub enum IcloudError {
+	/// Cannot find the "scnt" session header in the account page's
+	/// response.
+	NoSession,
+	/// Error when serializing or deserializing HTTP requests and responses.
+	#[serde(serialize_with = "ser_with_display")]
+	ReqwestError(ReqwestError),
+}
+
+impl fmt::Display for IcloudError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{self:?}")
+	}
+}
+
+impl From<ReqwestError> for IcloudError {
+	fn from(error: ReqwestError) -> Self {
+		IcloudError::ReqwestError(error)
+	}
+}
+
+/// Is host one of iCloud's MX hosts, for one of iCloud's own domains, as
+/// opposed to the custom domains of iCloud+?
+pub(super) fn is_icloud_host(host_lowercase: &str, domain: &str) -> bool {
+	host_lowercase.ends_with(".mail.icloud.com.")
+		&& ICLOUD_DOMAINS.contains(&domain.to_lowercase().as_str())
+}
+
+/// Use the Apple ID validation API of Apple's account creation page to
+/// verify if an iCloud email address exists, as iCloud's SMTP servers
+/// accept all mailboxes.
+pub async fn check_icloud(
+	to_email: &EmailAddress,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, IcloudError> {
+	let client = create_client(input, "icloud")?;
+
+	// The account page opens the session the API requires.
+	let response = client
+		.get(ACCOUNT_PAGE)
+		.header("User-Agent", USER_AGENT)
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let scnt = response
+		.headers()
+		.get("scnt")
+		.cloned()
+		.ok_or(IcloudError::NoSession)?;
+	let cookies = response
+		.headers()
+		.get_all("Set-Cookie")
+		.iter()
+		.filter_map(|cookie| cookie.to_str().ok())
+		.filter_map(|cookie| cookie.split(';').next())
+		.collect::<Vec<_>>()
+		.join("; ");
+
+	let response = client
+		.post(VALIDATION_API)
+		.header("User-Agent", USER_AGENT)
+		.header("Origin", "https://appleid.apple.com")
+		.header("Referer", ACCOUNT_PAGE)
+		.header("X-Requested-With", "XMLHttpRequest")
+		.header("Accept", "application/json")
+		.header("scnt", scnt)
+		.header("Cookie", cookies)
+		.json(&ValidationRequest {
+			email_address: to_email.as_ref(),
+		})
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<ValidationResponse>().await?;
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] icloud response: {:?}",
+		to_email,
+		response
+	);
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable: response.used,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::smtp::routing::verification_methods;
+	use crate::smtp::{SmtpError, VerificationMethod};
+
+	#[test]
+	fn should_route_icloud_addresses() {
+		assert!(is_icloud_host("mx01.mail.icloud.com.", "Me.com"));
+		assert!(!is_icloud_host("mx01.mail.icloud.com.", "example.org"));
+
+		// iCloud's MX hosts are skipped by default, unless the API is used.
+		let mut input = CheckEmailInput::default();
+		assert!(matches!(
+			verification_methods("mx01.mail.icloud.com.", "icloud.com", &input),
+			Err(SmtpError::SkippedDomain(_))
+		));
+		input.set_icloud_use_api(true);
+		assert_eq!(
+			verification_methods("mx01.mail.icloud.com.", "icloud.com", &input).unwrap(),
+			vec![VerificationMethod::IcloudApi]
+		);
+		assert!(matches!(
+			verification_methods("mx01.mail.icloud.com.", "example.org", &input),
+			Err(SmtpError::SkippedDomain(_))
+		));
+	}
+}
@@ -0,0 +1,230 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rotation of the SMTP connections across a pool of local IP addresses,
+//! with per-IP reputation tracking: IPs which start getting blacklisted are
+//! left out of the rotation for a while.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{SmtpDetails, SmtpError, SmtpErrorDesc};
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// Configuration of the source IP pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceIpPoolConfig {
+	/// Local IP addresses to rotate the SMTP connections across.
+	pub ips: Vec<IpAddr>,
+	/// Share of the verifications of an IP which can end with a blacklist
+	/// error, before the IP is rested.
+	pub max_blocked_rate: f64,
+	/// Number of verifications of an IP before `max_blocked_rate` is
+	/// enforced.
+	pub min_verifications: u64,
+	/// How long a rested IP is left out of the rotation. Its counters are
+	/// reset when it comes back.
+	pub rest_duration: Duration,
+}
+
+impl SourceIpPoolConfig {
+	pub fn new(ips: Vec<IpAddr>) -> Self {
+		SourceIpPoolConfig {
+			ips,
+			max_blocked_rate: 0.2,
+			min_verifications: 20,
+			rest_duration: Duration::from_secs(3600),
+		}
+	}
+}
+
+/// Counters of an IP of the source IP pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SourceIpStats {
+	pub ip: IpAddr,
+	/// Number of SMTP verifications made from this IP.
+	pub verifications: u64,
+	/// Number of verifications which failed because the IP is blacklisted.
+	pub blocked: u64,
+	/// Number of verifications which were greylisted.
+	pub greylisted: u64,
+	/// Is the IP currently left out of the rotation?
+	pub resting: bool,
+}
+
+struct SourceIpState {
+	stats: SourceIpStats,
+	resting_until: Option<Instant>,
+}
+
+/// A pool of local IP addresses, used in turn as the source of the SMTP
+/// connections.
+pub(crate) struct SourceIpPool {
+	config: SourceIpPoolConfig,
+	next: AtomicUsize,
+	states: Mutex<Vec<SourceIpState>>,
+}
+
+impl SourceIpPool {
+	pub fn new(config: SourceIpPoolConfig) -> Self {
+		let states = config
+			.ips
+			.iter()
+			.map(|ip| SourceIpState {
+				stats: SourceIpStats {
+					ip: *ip,
+					verifications: 0,
+					blocked: 0,
+					greylisted: 0,
+					resting: false,
+				},
+				resting_until: None,
+			})
+			.collect();
+
+		SourceIpPool {
+			config,
+			next: AtomicUsize::new(0),
+			states: Mutex::new(states),
+		}
+	}
+
+	/// Pick the next IP of the rotation. If all the IPs are resting, the
+	/// one which comes back first is used anyway.
+	pub fn select(&self) -> Option<IpAddr> {
+		let mut states = self.states.lock().expect("Pool lock is not poisoned. qed.");
+		let now = Instant::now();
+		for state in states.iter_mut() {
+			if state.resting_until.is_some_and(|until| until <= now) {
+				*state = SourceIpState {
+					stats: SourceIpStats {
+						verifications: 0,
+						blocked: 0,
+						greylisted: 0,
+						resting: false,
+						..state.stats
+					},
+					resting_until: None,
+				};
+			}
+		}
+
+		let start = self.next.fetch_add(1, Ordering::Relaxed);
+		(0..states.len())
+			.map(|i| &states[(start + i) % states.len()])
+			.find(|state| state.resting_until.is_none())
+			.or_else(|| states.iter().min_by_key(|state| state.resting_until))
+			.map(|state| state.stats.ip)
+	}
+
+	/// Record the result of a verification made from ip, and rest the IP if
+	/// it gets blacklisted too often.
+	pub fn record(&self, ip: IpAddr, result: Result<&SmtpDetails, &SmtpError>) {
+		let description = result.err().and_then(SmtpError::get_description);
+		let is_blocked = description == Some(SmtpErrorDesc::IpBlacklisted);
+		let is_greylisted = description == Some(SmtpErrorDesc::Greylisted)
+			|| result.is_ok_and(|smtp_details| smtp_details.greylisted_then_accepted);
+
+		let mut states = self.states.lock().expect("Pool lock is not poisoned. qed.");
+		let state = match states.iter_mut().find(|state| state.stats.ip == ip) {
+			Some(state) => state,
+			None => return,
+		};
+
+		state.stats.verifications += 1;
+		state.stats.blocked += is_blocked as u64;
+		state.stats.greylisted += is_greylisted as u64;
+
+		let blocked_rate = state.stats.blocked as f64 / state.stats.verifications as f64;
+		if state.resting_until.is_none()
+			&& state.stats.verifications >= self.config.min_verifications
+			&& blocked_rate > self.config.max_blocked_rate
+		{
+			log::warn!(
+				target: LOG_TARGET,
+				"Resting source [ip={}] for {:?}, {} of its {} verifications were blocked.",
+				ip,
+				self.config.rest_duration,
+				state.stats.blocked,
+				state.stats.verifications
+			);
+			state.resting_until = Some(Instant::now() + self.config.rest_duration);
+			state.stats.resting = true;
+		}
+	}
+
+	pub fn stats(&self) -> Vec<SourceIpStats> {
+		self.states
+			.lock()
+			.expect("Pool lock is not poisoned. qed.")
+			.iter()
+			.map(|state| state.stats)
+			.collect()
+	}
+}
+
+/// If the input has no `source_ip`, and its context has a source IP pool,
+/// pick the next IP of the pool.
+pub(super) fn rotate_source_ip(input: &CheckEmailInput) -> Option<IpAddr> {
+	if input.source_ip.is_some() {
+		return None;
+	}
+
+	input.context.source_ip_pool()?.select()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_smtp::smtp::error::Error as AsyncSmtpError;
+	use async_smtp::smtp::response::Response;
+	use std::str::FromStr;
+
+	#[test]
+	fn should_rest_blocked_ips() {
+		let ips: Vec<IpAddr> = vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()];
+		let pool = SourceIpPool::new(SourceIpPoolConfig {
+			min_verifications: 2,
+			..SourceIpPoolConfig::new(ips.clone())
+		});
+		assert_eq!(pool.select(), Some(ips[0]));
+		assert_eq!(pool.select(), Some(ips[1]));
+
+		let blocked = SmtpError::SmtpError(AsyncSmtpError::Permanent(
+			Response::from_str("550 5.7.1 Client host is blacklisted\r\n").unwrap(),
+		));
+		pool.record(ips[0], Err(&blocked));
+		assert_eq!(pool.select(), Some(ips[0]));
+		pool.record(ips[0], Err(&blocked));
+
+		assert_eq!(pool.select(), Some(ips[1]));
+		assert_eq!(pool.select(), Some(ips[1]));
+		assert_eq!(
+			pool.stats()[0],
+			SourceIpStats {
+				ip: ips[0],
+				verifications: 2,
+				blocked: 2,
+				greylisted: 0,
+				resting: true,
+			}
+		);
+	}
+}
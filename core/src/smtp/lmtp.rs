@@ -0,0 +1,44 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! LMTP (RFC 2033), spoken by internal mail stores, e.g. Dovecot or
+//! Postfix's local delivery. They answer `RCPT TO` like SMTP servers, but
+//! are greeted with LHLO instead of EHLO.
+
+use async_smtp::smtp::commands::EhloCommand;
+use async_smtp::smtp::extension::ClientId;
+
+/// The greeting command, with `hello_name` as client identity: LHLO if
+/// lmtp is set, otherwise EHLO.
+pub(super) fn hello_command(hello_name: &str, lmtp: bool) -> String {
+	let client_id = ClientId::Domain(hello_name.into());
+	if lmtp {
+		format!("LHLO {client_id}\r\n")
+	} else {
+		EhloCommand::new(client_id).to_string()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_greet_with_lhlo() {
+		assert_eq!(hello_command("example.org", true), "LHLO example.org\r\n");
+		assert_eq!(hello_command("example.org", false), "EHLO example.org\r\n");
+	}
+}
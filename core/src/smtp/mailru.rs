@@ -0,0 +1,116 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::SmtpDetails;
+use crate::{
+	smtp::http_api::{create_client, error_for_throttle},
+	util::{
+		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
+	},
+};
+use async_smtp::EmailAddress;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const EXISTS_API: &str = "https://account.mail.ru/api/v1/user/exists";
+
+/// Domains of the addresses hosted by Mail.ru itself, the only ones its API
+/// knows about.
+const MAILRU_DOMAINS: &[&str] = &["mail.ru", "bk.ru", "inbox.ru", "list.ru", "internet.ru"];
+
+/// The body of the response of the user API.
+#[derive(Debug, Deserialize)]
+struct ExistsBody {
+	exists: bool,
+}
+
+/// The response of the user API.
+#[derive(Debug, Deserialize)]
+struct ExistsResponse {
+	body: ExistsBody,
+}
+
+/// Possible errors when checking Mail.ru email addresses.
+#[derive(Debug, Serialize)]
+pub enum MailruError {
+	/// Error when serializing or deserializing HTTP requests and responses.
+	#[serde(serialize_with = "ser_with_display")]
+	ReqwestError(ReqwestError),
+}
+
+impl fmt::Display for MailruError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{self:?}")
+	}
+}
+
+impl From<ReqwestError> for MailruError {
+	fn from(error: ReqwestError) -> Self {
+		MailruError::ReqwestError(error)
+	}
+}
+
+/// Is host one of Mail.ru's MX hosts, for one of the domains hosted by
+/// Mail.ru itself, as opposed to the custom domains of its business mail?
+pub(super) fn is_mailru_host(host_lowercase: &str, domain: &str) -> bool {
+	(host_lowercase == "mxs.mail.ru." || host_lowercase.ends_with(".mail.ru."))
+		&& MAILRU_DOMAINS.contains(&domain.to_lowercase().as_str())
+}
+
+/// Use the user API of Mail.ru's login page to verify if a Mail.ru email
+/// address exists, as Mail.ru's SMTP servers accept all mailboxes.
+pub async fn check_mailru(
+	to_email: &EmailAddress,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, MailruError> {
+	let response = create_client(input, "mailru")?
+		.post(EXISTS_API)
+		.form(&[("email", to_email.to_string())])
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<ExistsResponse>().await?;
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] mailru response: {:?}",
+		to_email,
+		response
+	);
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable: response.body.exists,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_exists_response() {
+		let response: ExistsResponse = serde_json::from_str(
+			r#"{"status": 200, "body": {"exists": true, "email": "foo@bk.ru"}, "htmlencoded": false}"#,
+		)
+		.unwrap();
+		assert!(response.body.exists);
+		assert!(is_mailru_host("mxs.mail.ru.", "BK.ru"));
+		assert!(!is_mailru_host("mxs.mail.ru.", "example.org"));
+	}
+}
@@ -14,19 +14,27 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{thread::sleep, time::Duration};
+use std::{io, thread::sleep, time::Duration};
 
 use async_smtp::EmailAddress;
 use async_std::prelude::FutureExt;
 use fantoccini::{
 	error::{CmdError, NewSessionError},
-	ClientBuilder, Locator,
+	Client, ClientBuilder, Locator,
 };
 use futures::TryFutureExt;
 use serde::Serialize;
-use serde_json::Map;
+use serde_json::{Map, Value};
 
-use crate::{smtp::SmtpDetails, util::ser_with_display::ser_with_display, LOG_TARGET};
+use crate::{
+	smtp::{
+		challenge::solve_challenges, headless_debug::with_diagnostics,
+		headless_pool::HeadlessSession, Challenge, HeadlessDiagnostics, SmtpDetails,
+	},
+	util::input_output::{CheckEmailInputProxy, ProxyKind},
+	util::ser_with_display::ser_with_display,
+	CheckEmailInput, LOG_TARGET,
+};
 
 #[derive(Debug, Serialize)]
 pub enum HotmailError {
@@ -34,6 +42,14 @@ pub enum HotmailError {
 	Cmd(CmdError),
 	#[serde(serialize_with = "ser_with_display")]
 	NewSession(NewSessionError),
+	/// The page didn't have the elements we expected, usually because it
+	/// changed.
+	UnexpectedPage(Box<HeadlessDiagnostics>),
+	/// Error when spawning the managed WebDriver server.
+	#[serde(serialize_with = "ser_with_display")]
+	WebDriverProcess(io::Error),
+	/// The page showed a CAPTCHA or bot challenge, which wasn't solved.
+	ChallengeRequired(Challenge),
 }
 
 impl From<CmdError> for HotmailError {
@@ -42,27 +58,55 @@ impl From<CmdError> for HotmailError {
 	}
 }
 
+impl From<io::Error> for HotmailError {
+	fn from(e: io::Error) -> Self {
+		Self::WebDriverProcess(e)
+	}
+}
+
 impl From<NewSessionError> for HotmailError {
 	fn from(e: NewSessionError) -> Self {
 		Self::NewSession(e)
 	}
 }
 
-/// Check if a Hotmail/Outlook email exists by connecting to the password
-/// recovery page https://account.live.com/password/reset using a headless
-/// browser. Make sure you have a WebDriver server running locally before
-/// running this, or this will error.
-pub async fn check_password_recovery(
-	to_email: &EmailAddress,
-	webdriver: &str,
-) -> Result<SmtpDetails, HotmailError> {
-	let to_email = to_email.to_string();
-	log::debug!(
-		target: LOG_TARGET,
-		"[email={}] Using Hotmail password recovery in headless navigator",
-		to_email,
-	);
+/// The W3C WebDriver proxy capability routing the browser's traffic through
+/// `proxy`. Browsers don't support proxy credentials this way, so the
+/// proxy has to authorize the sending IP.
+fn proxy_capability(proxy: &CheckEmailInputProxy) -> Value {
+	let address = format!("{}:{}", proxy.host, proxy.port);
+	match proxy.kind {
+		ProxyKind::Socks5 | ProxyKind::Socks4 => serde_json::json!({
+			"proxyType": "manual",
+			"socksProxy": address,
+			"socksVersion": if proxy.kind == ProxyKind::Socks5 { 5 } else { 4 },
+		}),
+		ProxyKind::HttpConnect => serde_json::json!({
+			"proxyType": "manual",
+			"httpProxy": address,
+			"sslProxy": address,
+		}),
+	}
+}
 
+/// The `--proxy-server` argument of Chrome, which ignores the W3C proxy
+/// capability in headless mode.
+fn chrome_proxy_arg(proxy: &CheckEmailInputProxy) -> String {
+	let scheme = match proxy.kind {
+		ProxyKind::Socks5 => "socks5",
+		ProxyKind::Socks4 => "socks4",
+		ProxyKind::HttpConnect => "http",
+	};
+
+	format!("--proxy-server={}://{}:{}", scheme, proxy.host, proxy.port)
+}
+
+/// Open a session on the headless browser of the WebDriver listening on
+/// `webdriver`, whose traffic goes through `proxy`, if any.
+pub(crate) async fn new_session(
+	webdriver: &str,
+	proxy: Option<&CheckEmailInputProxy>,
+) -> Result<Client, HotmailError> {
 	// Running in a Docker container, I run into the following error:
 	// Failed to move to new namespace: PID namespaces supported, Network namespace supported, but failed: errno = Operation not permitted
 	// In searching around I found a few different workarounds:
@@ -73,51 +117,93 @@ pub async fn check_password_recovery(
 	//
 	// TODO Look into security implications...
 	let mut caps = Map::new();
-	let opts = serde_json::json!({
-		"args": ["--headless", "--disable-gpu", "--no-sandbox", "--disable-dev-shm-usage"],
-	});
-	caps.insert("goog:chromeOptions".to_string(), opts);
+	let mut args = vec![
+		"--headless".to_string(),
+		"--disable-gpu".into(),
+		"--no-sandbox".into(),
+		"--disable-dev-shm-usage".into(),
+	];
+	if let Some(proxy) = proxy {
+		if proxy.username.is_some() {
+			log::debug!(
+				target: LOG_TARGET,
+				"Proxy credentials are not supported by the headless browser, ignoring them",
+			);
+		}
+		args.push(chrome_proxy_arg(proxy));
+		caps.insert("proxy".to_string(), proxy_capability(proxy));
+	}
+	caps.insert(
+		"goog:chromeOptions".to_string(),
+		serde_json::json!({ "args": args }),
+	);
 
 	// Connect to WebDriver instance that is listening on `webdriver`
-	let c = ClientBuilder::native()
+	Ok(ClientBuilder::native()
 		.capabilities(caps)
 		.connect(webdriver)
-		.await?;
+		.await?)
+}
+
+/// Check if a Hotmail/Outlook email exists by connecting to the password
+/// recovery page https://account.live.com/password/reset using a headless
+/// browser. Make sure you have a WebDriver server running locally before
+/// running this, or this will error.
+pub async fn check_password_recovery(
+	to_email: &EmailAddress,
+	webdriver: &str,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, HotmailError> {
+	let to_email = to_email.to_string();
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Using Hotmail password recovery in headless navigator",
+		to_email,
+	);
+
+	let c = HeadlessSession::open(webdriver, input).await?;
 
 	// Navigate to Microsoft password recovery page.
 	c.goto("https://account.live.com/password/reset").await?;
 
-	// Wait for network/javascript/dom to make the input-box available
-	// and click it.
-	let input = c.wait().for_element(Locator::Id("iSigninName")).await?;
-	// Sometimes I get "input ... is not reachable by keyboard". Addind this
-	// small sleep helps.
-	sleep(Duration::from_millis(200));
-	input.send_keys(to_email.as_str()).await?;
-
-	// Click on "Next"
-	c.find(Locator::Id("resetPwdHipAction"))
-		.await?
-		.click()
-		.await?;
-
-	// "Try entering your Microsoft account again. We don't recognise this one." means the account does not exist.
-	let f1 = c
-		.wait()
-		.for_element(Locator::Id("pMemberNameErr"))
-		.and_then(|_| async { Ok(false) });
-	// "We need to verify your identity" means that the account exists.
-	let f2 = c
-		.wait()
-		.for_element(Locator::Id("iSelectProofTitle"))
-		.and_then(|_| async { Ok(true) });
-	// "Enter the code generated by your authenticator app..."
-	let f3 = c
-		.wait()
-		.for_element(Locator::Id("iEnterVerification"))
-		.and_then(|_| async { Ok(true) });
-
-	let is_deliverable = f1.try_race(f2).try_race(f3).await?;
+	let is_deliverable = with_diagnostics(&c, input, "hotmail", async {
+		// Wait for network/javascript/dom to make the input-box available
+		// and click it.
+		let email_field = c.wait().for_element(Locator::Id("iSigninName")).await?;
+		// Sometimes I get "input ... is not reachable by keyboard". Addind this
+		// small sleep helps.
+		sleep(Duration::from_millis(200));
+		email_field.send_keys(to_email.as_str()).await?;
+
+		// Click on "Next"
+		c.find(Locator::Id("resetPwdHipAction"))
+			.await?
+			.click()
+			.await?;
+
+		solve_challenges(&c, input, "hotmail", || {
+			// "Try entering your Microsoft account again. We don't recognise this one." means the account does not exist.
+			let f1 = c
+				.wait()
+				.for_element(Locator::Id("pMemberNameErr"))
+				.and_then(|_| async { Ok(false) });
+			// "We need to verify your identity" means that the account exists.
+			let f2 = c
+				.wait()
+				.for_element(Locator::Id("iSelectProofTitle"))
+				.and_then(|_| async { Ok(true) });
+			// "Enter the code generated by your authenticator app..."
+			let f3 = c
+				.wait()
+				.for_element(Locator::Id("iEnterVerification"))
+				.and_then(|_| async { Ok(true) });
+
+			f1.try_race(f2).try_race(f3)
+		})
+		.await
+	})
+	.await?
+	.map_err(HotmailError::ChallengeRequired)?;
 
 	if is_deliverable {
 		log::debug!(
@@ -133,7 +219,7 @@ pub async fn check_password_recovery(
 		);
 	}
 
-	c.close().await?;
+	c.release().await?;
 
 	Ok(SmtpDetails {
 		can_connect_smtp: true,
@@ -141,16 +227,45 @@ pub async fn check_password_recovery(
 		is_catch_all: false,
 		is_deliverable,
 		is_disabled: false,
+		..Default::default()
 	})
 }
 
 #[cfg(test)]
 mod tests {
-	use super::check_password_recovery;
+	use super::{check_password_recovery, chrome_proxy_arg, proxy_capability};
+	use crate::util::input_output::{CheckEmailInputProxy, ProxyKind};
+	use crate::CheckEmailInput;
 	use async_smtp::EmailAddress;
 	use async_std::prelude::FutureExt;
 	use std::str::FromStr;
 
+	#[test]
+	fn should_route_browser_through_proxy() {
+		let proxy = CheckEmailInputProxy {
+			host: "proxy.example.org".into(),
+			port: 1080,
+			..Default::default()
+		};
+		assert_eq!(
+			chrome_proxy_arg(&proxy),
+			"--proxy-server=socks5://proxy.example.org:1080"
+		);
+		assert_eq!(
+			proxy_capability(&proxy)["socksProxy"],
+			"proxy.example.org:1080"
+		);
+
+		let proxy = CheckEmailInputProxy {
+			kind: ProxyKind::HttpConnect,
+			..proxy
+		};
+		assert_eq!(
+			proxy_capability(&proxy)["sslProxy"],
+			"proxy.example.org:1080"
+		);
+	}
+
 	// Ignoring this test as it requires a local process of WebDriver running on
 	// "http://localhost:4444". To debug the headless password recovery page,
 	// run chromedriver and remove the "#[ignore]".
@@ -159,11 +274,12 @@ mod tests {
 	#[ignore]
 	async fn test_hotmail_address() {
 		// This email does not exist.
+		let input = CheckEmailInput::default();
 		let email = EmailAddress::from_str("test42134@hotmail.com").unwrap();
 		// Run 10 headless sessions with the above fake email (not deliverable).
 		// It should not error.
 		for _ in 0..10 {
-			let res = check_password_recovery(&email, "http://localhost:4444")
+			let res = check_password_recovery(&email, "http://localhost:4444", &input)
 				.await
 				.unwrap();
 			assert!(!res.is_deliverable)
@@ -174,7 +290,7 @@ mod tests {
 		// Run 10 headless sessions with the above fake email (not deliverable).
 		// It should not error.
 		for _ in 0..10 {
-			let res = check_password_recovery(&email, "http://localhost:4444")
+			let res = check_password_recovery(&email, "http://localhost:4444", &input)
 				.await
 				.unwrap();
 			assert!(res.is_deliverable)
@@ -189,10 +305,11 @@ mod tests {
 	#[ignore]
 	async fn test_parallel() {
 		// This email does not exist.
+		let input = CheckEmailInput::default();
 		let email = EmailAddress::from_str("foo@bar.baz").unwrap();
 
-		let f1 = check_password_recovery(&email, "http://localhost:4444");
-		let f2 = check_password_recovery(&email, "http://localhost:4444");
+		let f1 = check_password_recovery(&email, "http://localhost:4444", &input);
+		let f2 = check_password_recovery(&email, "http://localhost:4444", &input);
 
 		let f = f1.try_join(f2).await;
 		assert!(f.is_ok(), "{:?}", f);
@@ -14,16 +14,37 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+#[cfg(feature = "headless")]
+use std::time::Duration;
+
 use async_smtp::EmailAddress;
+#[cfg(feature = "headless")]
+use async_std::prelude::FutureExt;
+#[cfg(feature = "headless")]
+use fantoccini::{error::CmdError, Locator};
+#[cfg(feature = "headless")]
+use futures::TryFutureExt;
 use reqwest::Error as ReqwestError;
 use serde::Serialize;
 
+#[cfg(feature = "headless")]
+use super::hotmail::HotmailError;
+#[cfg(feature = "headless")]
+use crate::smtp::{
+	challenge::solve_challenges, headless_debug::with_diagnostics, headless_pool::HeadlessSession,
+};
+
 use crate::{
 	smtp::{http_api::create_client, SmtpDetails},
 	util::ser_with_display::ser_with_display,
 	CheckEmailInput, LOG_TARGET,
 };
 
+/// How long to wait for the login page to answer, after submitting the
+/// email address.
+#[cfg(feature = "headless")]
+const LOGIN_PAGE_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[derive(Debug, Serialize)]
 pub enum Microsoft365Error {
 	#[serde(serialize_with = "ser_with_display")]
@@ -92,6 +113,78 @@ pub async fn check_microsoft365_api(
 	}
 }
 
+/// Check if a Microsoft 365 email exists by submitting it to the login page
+/// https://login.microsoftonline.com using a headless browser, which asks
+/// for the password of existing accounts. Make sure you have a WebDriver
+/// server running locally before running this, or this will error.
+///
+/// Returns None if the result is ambiguous, e.g. if the tenant redirects
+/// to its own identity provider, in which case the login page can't tell.
+#[cfg(feature = "headless")]
+pub async fn check_login_page(
+	to_email: &EmailAddress,
+	webdriver: &str,
+	input: &CheckEmailInput,
+) -> Result<Option<SmtpDetails>, HotmailError> {
+	let to_email = to_email.to_string();
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Using Microsoft 365 login page in headless navigator",
+		to_email,
+	);
+
+	let c = HeadlessSession::open(webdriver, input).await?;
+	c.goto("https://login.microsoftonline.com").await?;
+
+	with_diagnostics(&c, input, "microsoft365", async {
+		let email_field = c.wait().for_element(Locator::Id("i0116")).await?;
+		email_field.send_keys(to_email.as_str()).await?;
+
+		// Click on "Next"
+		c.find(Locator::Id("idSIButton9")).await?.click().await
+	})
+	.await?;
+
+	let outcome = solve_challenges(&c, input, "microsoft365", || {
+		// "This username may be incorrect" means the account does not exist.
+		let f1 = c
+			.wait()
+			.at_most(LOGIN_PAGE_TIMEOUT)
+			.for_element(Locator::Id("usernameError"))
+			.and_then(|_| async { Ok(false) });
+		// Asking for the password means that the account exists.
+		let f2 = c
+			.wait()
+			.at_most(LOGIN_PAGE_TIMEOUT)
+			.for_element(Locator::Id("i0118"))
+			.and_then(|_| async { Ok(true) });
+
+		f1.try_race(f2)
+	});
+
+	// Neither shows up when the tenant uses its own identity provider.
+	let is_deliverable = match outcome.await {
+		Ok(Ok(is_deliverable)) => Some(is_deliverable),
+		Ok(Err(challenge)) => return Err(HotmailError::ChallengeRequired(challenge)),
+		Err(CmdError::WaitTimeout) => None,
+		Err(err) => return Err(err.into()),
+	};
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Microsoft 365 login page result: {:?}",
+		to_email,
+		is_deliverable,
+	);
+
+	c.release().await?;
+
+	Ok(is_deliverable.map(|is_deliverable| SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable,
+		..Default::default()
+	}))
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -103,4 +196,21 @@ mod tests {
 
 		assert_eq!(expected, get_onedrive_url(email_address));
 	}
+
+	// Ignoring this test as it requires a local process of WebDriver running on
+	// "http://localhost:4444". To debug the headless login page, run
+	// chromedriver and remove the "#[ignore]".
+	#[cfg(feature = "headless")]
+	#[tokio::test]
+	#[ignore]
+	async fn test_login_page() {
+		use std::str::FromStr;
+
+		// This email does not exist.
+		let email = EmailAddress::from_str("test42134@microsoft.com").unwrap();
+		let res = check_login_page(&email, "http://localhost:4444", &CheckEmailInput::default())
+			.await
+			.unwrap();
+		assert!(!res.unwrap().is_deliverable);
+	}
 }
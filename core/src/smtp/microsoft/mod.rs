@@ -1,3 +1,90 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
 #[cfg(feature = "headless")]
 pub mod hotmail;
 pub mod microsoft365;
+
+/// The first labels of the domains of Microsoft's consumer addresses, e.g.
+/// "hotmail.co.uk" or "live.fr".
+const CONSUMER_BRANDS: &[&str] = &[
+	"hotmail",
+	"outlook",
+	"live",
+	"msn",
+	"windowslive",
+	"passport",
+];
+
+/// Whether a Microsoft address is a consumer or a business one, which are
+/// verified on different pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MicrosoftAccountKind {
+	/// Hotmail, Outlook.com, Live...
+	Consumer,
+	/// A Microsoft 365 tenant.
+	Microsoft365,
+}
+
+/// Whether `domain`, whose MX host is `host_lowercase`, hosts consumer or
+/// Microsoft 365 addresses. None if it's not hosted by Microsoft, at least
+/// not directly, e.g. behind an email security gateway.
+pub(crate) fn microsoft_account_kind(
+	host_lowercase: &str,
+	domain: &str,
+) -> Option<MicrosoftAccountKind> {
+	if !host_lowercase.ends_with(".protection.outlook.com.") {
+		return None;
+	}
+
+	// After some testing, I got:
+	// - *@outlook.com -> `outlook-com.olc.protection.outlook.com.`
+	// - *@outlook.fr -> `eur.olc.protection.outlook.com.`
+	// - *@hotmail.com -> `hotmail-com.olc.protection.outlook.com.`
+	// - *@hotmail.fr -> `eur.olc.protection.outlook.com.`
+	//
+	// So it seems that outlook/hotmail addresses end with `olc.protection.outlook.com.`
+	let brand = domain.split('.').next().unwrap_or_default().to_lowercase();
+	if host_lowercase.ends_with("olc.protection.outlook.com.")
+		|| CONSUMER_BRANDS.contains(&brand.as_str())
+	{
+		Some(MicrosoftAccountKind::Consumer)
+	} else {
+		Some(MicrosoftAccountKind::Microsoft365)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_tell_consumer_from_business_addresses() {
+		assert_eq!(
+			microsoft_account_kind("eur.olc.protection.outlook.com.", "hotmail.fr"),
+			Some(MicrosoftAccountKind::Consumer)
+		);
+		assert_eq!(
+			microsoft_account_kind("live-co-uk.mail.protection.outlook.com.", "live.co.uk"),
+			Some(MicrosoftAccountKind::Consumer)
+		);
+		assert_eq!(
+			microsoft_account_kind("acme-com.mail.protection.outlook.com.", "acme.com"),
+			Some(MicrosoftAccountKind::Microsoft365)
+		);
+		assert_eq!(microsoft_account_kind("mx1.acme.com.", "hotmail.com"), None);
+	}
+}
@@ -14,26 +14,108 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod batch;
+mod catch_all;
+mod challenge;
+mod client;
 mod connect;
+mod connectivity;
+mod dane;
 mod error;
 mod gmail;
+mod gmx;
+#[cfg(feature = "headless")]
+mod headless_debug;
+#[cfg(feature = "headless")]
+mod headless_pool;
 mod http_api;
+mod icloud;
+mod ip_pool;
+mod lmtp;
+mod mailru;
 mod microsoft;
 mod parser;
+mod pool;
+mod proton;
+mod provider_limit;
+mod proxy;
+mod proxy_pool;
+mod rate_limit;
+mod response_hook;
+mod retry;
+mod rotation;
+mod routing;
+mod size;
+mod tarpit;
+#[cfg(test)]
+pub(crate) mod test_server;
+mod timings;
+mod tls;
+mod transcript;
+mod vrfy;
+#[cfg(feature = "headless")]
+mod webdriver;
 mod yahoo;
+mod yandex;
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::default::Default;
+use std::future::Future;
+use std::net::IpAddr;
+use std::time::Instant;
 
 use async_smtp::EmailAddress;
+use async_std::future;
 use serde::{Deserialize, Serialize};
 use trust_dns_proto::rr::Name;
 
-use crate::{util::input_output::CheckEmailInput, LOG_TARGET};
+use crate::mx::{MxDetails, MxProvider};
+use crate::util::input_output::{CheckEmailInput, SmtpVerificationMode};
+use crate::{context::CatchAllKey, LOG_TARGET};
+use batch::check_smtp_batch_with_retry;
+pub use batch::SmtpBatchResults;
+pub use catch_all::{CatchAllConfidence, CatchAllProbes};
+pub use challenge::{Challenge, ChallengeKind, ChallengeSolver};
 use connect::check_smtp_with_retry;
+pub use connectivity::SmtpServerInfo;
+pub use dane::DaneStatus;
 pub use error::*;
+#[cfg(feature = "headless")]
+pub use headless_debug::HeadlessDiagnostics;
+#[cfg(feature = "headless")]
+pub(crate) use headless_pool::HeadlessPool;
+#[cfg(feature = "headless")]
+pub use headless_pool::{HeadlessPoolConfig, HeadlessPoolStats};
+use ip_pool::rotate_source_ip;
+pub(crate) use ip_pool::SourceIpPool;
+pub use ip_pool::{SourceIpPoolConfig, SourceIpStats};
+pub(crate) use pool::{SmtpPool, SmtpPoolKey};
+pub use pool::{SmtpPoolConfig, SmtpPoolStats};
+pub(crate) use provider_limit::ProviderLimiter;
+use provider_limit::{provider_key, with_provider_limit};
+pub use provider_limit::{ProviderLimitConfig, ProviderLimitStats};
+use proxy_pool::rotate_proxy;
+pub(crate) use proxy_pool::ProxyPool;
+pub use proxy_pool::{ProxyPoolConfig, ProxyStats};
+pub(crate) use rate_limit::RateLimiter;
+pub use rate_limit::{RateLimitConfig, RateLimitScope, RateLimitStats};
+pub use response_hook::SmtpResponseHook;
+pub use retry::{ErrorClass, ExponentialBackoff, FixedRetry, RetryPolicy, RetryStrategy};
+use rotation::rotate_identity;
+use routing::verification_methods;
+pub use routing::{FailedMethod, ProviderRoute, ProviderVerifier, VerificationMethod};
+pub use size::{SizeProbe, SizeProbeResult};
+pub use timings::SmtpTimings;
+pub use tls::TlsDetails;
+pub use transcript::{TranscriptDirection, TranscriptLine};
+#[cfg(feature = "headless")]
+pub(crate) use webdriver::ManagedWebDriver;
+#[cfg(feature = "headless")]
+pub use webdriver::{ManagedWebDriverConfig, MANAGED_WEBDRIVER};
 
 /// Details that we gathered from connecting to this email via SMTP
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct SmtpDetails {
 	/// Are we able to connect to the SMTP server?
 	pub can_connect_smtp: bool,
@@ -45,83 +127,544 @@ pub struct SmtpDetails {
 	pub is_deliverable: bool,
 	/// Is the email blocked or disabled by the provider?
 	pub is_disabled: bool,
+	/// Result of the SIZE probe, only present if `smtp_size_probe` is set in
+	/// the input.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub size_probe: Option<SizeProbe>,
+	/// Results of the catch-all probes, only present if they were made, i.e.
+	/// if the catch-all determination wasn't already cached.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub catch_all_probes: Option<CatchAllProbes>,
+	/// Did the server greylist us first, and accept the recipient on a later
+	/// retry? False if the retry rejected it. Only detected if
+	/// `greylisting_retry_delay` is set in the input.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub greylisted_then_accepted: bool,
+	/// The MX host which answered the SMTP verification.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mx_host: Option<String>,
+	/// The IP address of the SMTP server which answered, unknown through a
+	/// proxy.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mx_ip: Option<IpAddr>,
+	/// The port of the SMTP server which answered.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mx_port: Option<u16>,
+	/// Was the SMTP connection encrypted with TLS? Unknown with
+	/// opportunistic TLS through a SOCKS5 proxy, as async-smtp's transport
+	/// doesn't tell.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub used_tls: Option<bool>,
+	/// Transcript of the SMTP session, only present if `smtp_debug` is set
+	/// in the input.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub transcript: Option<Vec<TranscriptLine>>,
+	/// Was the deliverability answered by the VRFY command, because the
+	/// server rejected `RCPT TO`? Only tried if `smtp_vrfy_fallback` is set
+	/// in the input.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub verified_with_vrfy: bool,
+	/// The SMTP server's TLS certificate and session, only present if
+	/// `smtp_tls_details` is set in the input, and the connection was
+	/// encrypted.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tls: Option<TlsDetails>,
+	/// DANE validation of the SMTP server's TLS certificate, against the
+	/// TLSA records of the MX host. Only present if `smtp_dane_validation`
+	/// is set in the input.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dane: Option<DaneStatus>,
+	/// The SMTP server's name and capabilities, only present if
+	/// `smtp_capabilities` is set in the input, or with the
+	/// connectivity-only modes of `smtp_verification_mode`, in which case
+	/// the other fields say nothing about the mailbox.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub server_info: Option<SmtpServerInfo>,
+	/// Durations of the stages of the SMTP session, only present if
+	/// `smtp_timings` is set in the input.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timings: Option<SmtpTimings>,
+	/// Annotations returned by the context's SMTP response hook, see
+	/// `CheckEmailContext::with_smtp_response_hook`.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub annotations: BTreeMap<String, String>,
+	/// The method which produced the verdict, unless it's a regular SMTP
+	/// session tried first. See the input's `provider_routes`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub verification_method: Option<VerificationMethod>,
+	/// The methods tried before, which failed or whose result was
+	/// ambiguous, in order.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub failed_methods: Vec<FailedMethod>,
+	/// The email security gateway in front of the mailbox provider, e.g.
+	/// Proofpoint or Mimecast, if the MX hosts are one's. Gateways usually
+	/// accept any recipient, see the input's `security_gateways_are_risky`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub gateway: Option<MxProvider>,
 }
 
-/// Get all email details we can from one single `EmailAddress`, without
-/// retries.
-pub async fn check_smtp(
+/// Key under which the catch-all determination of `domain`, as answered by
+/// the `host` MX, is cached in the context.
+pub(crate) fn catch_all_key(domain: &str, host: &Name) -> CatchAllKey {
+	(domain.to_lowercase(), host.to_lowercase().to_string())
+}
+
+/// Is host verified by other means than a regular SMTP session, e.g. a
+/// provider's HTTP API, or skipped altogether?
+fn has_dedicated_check(host_lowercase: &str, domain: &str, input: &CheckEmailInput) -> bool {
+	verification_methods(host_lowercase, domain, input).map_or(true, |methods| !methods.is_empty())
+}
+
+/// Verify `to_email` with `method`. Returns None if the method's result is
+/// ambiguous, in which case the next method, or a regular SMTP session,
+/// is tried.
+#[cfg_attr(not(feature = "headless"), allow(unused_variables))]
+async fn verify_with_method(
+	method: &VerificationMethod,
 	to_email: &EmailAddress,
-	host: &Name,
-	port: u16,
+	host_lowercase: &str,
 	domain: &str,
 	input: &CheckEmailInput,
-) -> Result<SmtpDetails, SmtpError> {
-	let host_lowercase = host.to_lowercase().to_string();
+) -> Option<Result<SmtpDetails, SmtpError>> {
+	match method {
+		VerificationMethod::Smtp | VerificationMethod::Skip => None,
+		VerificationMethod::YahooApi => {
+			let result = yahoo::check_yahoo(to_email, input).await;
+			#[cfg(feature = "headless")]
+			if let (Err(err), Some(webdriver)) = (&result, &input.yahoo_use_headless) {
+				if err.is_unexpected_payload() {
+					log::debug!(
+						target: LOG_TARGET,
+						"[email={}] Unexpected Yahoo API payload, falling back to headless: {:?}",
+						to_email,
+						err,
+					);
+					return Some(
+						yahoo::check_password_recovery(to_email, webdriver, input)
+							.await
+							.map_err(|err| err.into()),
+					);
+				}
+			}
+
+			Some(result.map_err(|err| err.into()))
+		}
+		VerificationMethod::GmailApi => Some(
+			gmail::check_gmail(to_email, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::ProtonApi => Some(
+			proton::check_proton(to_email, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::IcloudApi => Some(
+			icloud::check_icloud(to_email, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::MailruApi => Some(
+			mailru::check_mailru(to_email, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::YandexApi => Some(
+			yandex::check_yandex(to_email, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::GmxApi => Some(
+			gmx::check_gmx(to_email, domain, input)
+				.await
+				.map_err(|err| err.into()),
+		),
+		VerificationMethod::Microsoft365Api => {
+			match microsoft::microsoft365::check_microsoft365_api(to_email, input).await {
+				Ok(Some(smtp_details)) => Some(Ok(smtp_details)),
+				// Continue in the event of an error/ambiguous result.
+				Err(err) => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[email={}] microsoft365 error: {:?}",
+						to_email,
+						err,
+					);
+					None
+				}
+				Ok(None) => None,
+			}
+		}
+		VerificationMethod::Custom(pattern) => {
+			let verifier = input
+				.context
+				.provider_verifiers()
+				.iter()
+				.find(|(registered, _)| registered.as_str() == pattern)
+				.map(|(_, verifier)| verifier.clone());
+			let verifier = match verifier {
+				Some(verifier) => verifier,
+				None => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[email={}] No provider verifier registered with [pattern={}]",
+						to_email,
+						pattern,
+					);
+					return None;
+				}
+			};
+
+			verifier
+				.verify(to_email, host_lowercase, input)
+				.await
+				.transpose()
+		}
+		#[cfg(feature = "headless")]
+		VerificationMethod::Headless(webdriver) => {
+			verify_with_headless(webdriver, to_email, host_lowercase, domain, input).await
+		}
+	}
+}
 
-	if input
-		.skipped_domains
-		.iter()
-		.any(|d| host_lowercase.contains(d))
-	{
-		return Err(SmtpError::SkippedDomain(format!(
-			"Reacher currently cannot verify emails from @{domain}"
-		)));
-	}
-
-	// FIXME Is this `contains` too lenient?
-	if input.yahoo_use_api && host_lowercase.contains("yahoo") {
-		return yahoo::check_yahoo(to_email, input)
-			.await
-			.map_err(|err| err.into());
-	}
-	if input.gmail_use_api && host_lowercase.ends_with(".google.com.") {
-		return gmail::check_gmail(to_email, input)
-			.await
-			.map_err(|err| err.into());
-	}
-	if input.microsoft365_use_api && host_lowercase.ends_with(".mail.protection.outlook.com.") {
-		match microsoft::microsoft365::check_microsoft365_api(to_email, input).await {
-			Ok(Some(smtp_details)) => return Ok(smtp_details),
+/// Verify `to_email` in a headless browser, on the page matching the MX
+/// host and the domain. Returns None for other hosts, and for ambiguous
+/// results.
+#[cfg(feature = "headless")]
+async fn verify_with_headless(
+	webdriver: &str,
+	to_email: &EmailAddress,
+	host_lowercase: &str,
+	domain: &str,
+	input: &CheckEmailInput,
+) -> Option<Result<SmtpDetails, SmtpError>> {
+	use microsoft::{microsoft_account_kind, MicrosoftAccountKind};
+
+	let microsoft = microsoft_account_kind(host_lowercase, domain);
+	if microsoft == Some(MicrosoftAccountKind::Consumer) {
+		return Some(
+			microsoft::hotmail::check_password_recovery(to_email, webdriver, input)
+				.await
+				.map_err(|err| err.into()),
+		);
+	}
+	// SMTP against Microsoft 365 servers is catch-all, and the OneDrive API
+	// misses many tenants.
+	if microsoft == Some(MicrosoftAccountKind::Microsoft365) {
+		return match microsoft::microsoft365::check_login_page(to_email, webdriver, input).await {
+			Ok(smtp_details) => smtp_details.map(Ok),
+			Err(microsoft::hotmail::HotmailError::ChallengeRequired(challenge)) => {
+				Some(Err(SmtpError::ChallengeRequired(challenge)))
+			}
 			// Continue in the event of an error/ambiguous result.
 			Err(err) => {
 				log::debug!(
 					target: LOG_TARGET,
-					"[email={}] microsoft365 error: {:?}",
+					"[email={}] microsoft365 headless error: {:?}",
 					to_email,
 					err,
 				);
+				None
 			}
-			_ => {}
-		}
+		};
 	}
-	#[cfg(feature = "headless")]
-	if let Some(webdriver) = &input.hotmail_use_headless {
-		// The password recovery page do not always work with Microsoft 365
-		// addresses. So we only test with @hotmail and @outlook addresses.
-		// ref: https://github.com/reacherhq/check-if-email-exists/issues/1185
-		//
-		// After some testing, I got:
-		// - *@outlook.com -> `outlook-com.olc.protection.outlook.com.`
-		// - *@outlook.fr -> `eur.olc.protection.outlook.com.`
-		// - *@hotmail.com -> `hotmail-com.olc.protection.outlook.com.`
-		// - *@hotmail.fr -> `eur.olc.protection.outlook.com.`
-		//
-		// So it seems that outlook/hotmail addresses end with `olc.protection.outlook.com.`
-		if host_lowercase.ends_with("olc.protection.outlook.com.") {
-			return microsoft::hotmail::check_password_recovery(to_email, webdriver)
+	if host_lowercase.contains("yahoo") {
+		return Some(
+			yahoo::check_password_recovery(to_email, webdriver, input)
 				.await
-				.map_err(|err| err.into());
+				.map_err(|err| err.into()),
+		);
+	}
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] No headless verification for [host={}]",
+		to_email,
+		host_lowercase,
+	);
+	None
+}
+
+/// Run `fut`, the SMTP verification on one MX host and port, within what's
+/// left of the input's `smtp_deadline`, counted from `start`.
+pub(crate) async fn with_smtp_deadline<T>(
+	input: &CheckEmailInput,
+	start: Instant,
+	fut: impl Future<Output = Result<T, SmtpError>>,
+) -> Result<T, SmtpError> {
+	match input.smtp_deadline {
+		Some(deadline) => future::timeout(deadline.saturating_sub(start.elapsed()), fut).await?,
+		None => fut.await,
+	}
+}
+
+/// What the context's pools picked for a verification, to record its
+/// result.
+#[derive(Debug, Default)]
+struct PoolPicks {
+	/// Source IP picked from the source IP pool.
+	source_ip: Option<IpAddr>,
+	/// Index of the proxy picked from the proxy pool.
+	proxy: Option<usize>,
+}
+
+impl PoolPicks {
+	fn record(&self, input: &CheckEmailInput, result: Result<&SmtpDetails, &SmtpError>) {
+		if let (Some(ip), Some(pool)) = (self.source_ip, input.context.source_ip_pool()) {
+			pool.record(ip, result);
+		}
+		if let (Some(index), Some(pool)) = (self.proxy, input.context.proxy_pool()) {
+			pool.record(index, result);
+		}
+	}
+}
+
+/// The input of an SMTP verification on `domain`, with the source IP,
+/// proxy, EHLO name and `MAIL FROM` address picked by the rotations, if any.
+async fn rotate_input<'a>(
+	domain: &str,
+	input: &'a CheckEmailInput,
+) -> (PoolPicks, Cow<'a, CheckEmailInput>) {
+	let source_ip = rotate_source_ip(input);
+	let proxy = rotate_proxy(domain, input);
+	let identity = rotate_identity(domain, input);
+	if source_ip.is_none() && proxy.is_none() && identity.is_none() {
+		return (PoolPicks::default(), Cow::Borrowed(input));
+	}
+
+	let mut rotated = input.clone();
+	if source_ip.is_some() {
+		rotated.source_ip = source_ip;
+	}
+	let proxy = proxy.map(|(index, proxy)| {
+		rotated.proxy = Some(proxy);
+		index
+	});
+	if let Some((hello_name, from_email)) = identity {
+		rotated.hello_name = hello_name;
+		rotated.from_email = from_email;
+	}
+
+	(PoolPicks { source_ip, proxy }, Cow::Owned(rotated))
+}
+
+/// Verify several emails of `domain` on host, over a single SMTP session,
+/// with one `RCPT TO` command per email. The catch-all probe is only made
+/// once. If host is verified by other means, e.g. a provider's HTTP API, the
+/// emails are verified one by one with `check_smtp` instead.
+///
+/// The `MAIL FROM` command is sent for `input.to_email`, so none of the
+/// emails should require the SMTPUTF8 extension. `mx` is the domain's MX
+/// lookup, whose other hosts can be retried on, see
+/// `RetryStrategy::OtherHost`.
+///
+/// Returns an error if the SMTP session couldn't be opened, otherwise the
+/// result of each email, in the same order.
+pub async fn check_smtp_batch(
+	to_emails: &[EmailAddress],
+	host: &Name,
+	port: u16,
+	domain: &str,
+	mx: &MxDetails,
+	input: &CheckEmailInput,
+) -> Result<SmtpBatchResults, SmtpError> {
+	// The result doesn't depend on the email, so a single check is enough.
+	if input.smtp_verification_mode != SmtpVerificationMode::Mailbox {
+		let to_email = match to_emails.first() {
+			Some(to_email) => to_email,
+			None => return Ok(vec![]),
+		};
+		let smtp_details = check_smtp(to_email, host, port, domain, mx, input).await?;
+		return Ok(to_emails.iter().map(|_| Ok(smtp_details.clone())).collect());
+	}
+
+	if has_dedicated_check(&host.to_lowercase().to_string(), domain, input) {
+		let mut results = SmtpBatchResults::with_capacity(to_emails.len());
+		for to_email in to_emails {
+			results.push(check_smtp(to_email, host, port, domain, mx, input).await);
 		}
+		return Ok(results);
+	}
+
+	let is_catch_all = input
+		.context
+		.catch_all_cache()
+		.get(&catch_all_key(domain, host));
+	if is_catch_all == Some(true) {
+		return Ok(to_emails
+			.iter()
+			.map(|_| {
+				Ok(SmtpDetails {
+					can_connect_smtp: true,
+					is_catch_all: true,
+					is_deliverable: true,
+					..Default::default()
+				})
+			})
+			.collect());
 	}
 
-	check_smtp_with_retry(to_email, host, port, domain, input, input.retries).await
+	let (picks, smtp_input) = rotate_input(domain, input).await;
+	let results =
+		check_smtp_batch_with_retry(to_emails, host, port, domain, mx, &smtp_input, is_catch_all)
+			.await;
+	match &results {
+		Ok(results) => results
+			.iter()
+			.for_each(|result| picks.record(input, result.as_ref())),
+		Err(err) => picks.record(input, Err(err)),
+	}
+
+	results
+}
+
+/// Get all email details we can from one single `EmailAddress`, without
+/// retries.
+///
+/// If the input's context already knows that the domain is a catch-all on
+/// this MX host, no SMTP connection is made. `mx` is the domain's MX lookup,
+/// whose other hosts can be retried on, see `RetryStrategy::OtherHost`.
+pub async fn check_smtp(
+	to_email: &EmailAddress,
+	host: &Name,
+	port: u16,
+	domain: &str,
+	mx: &MxDetails,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, SmtpError> {
+	let host_lowercase = host.to_lowercase().to_string();
+	let methods = verification_methods(&host_lowercase, domain, input)?;
+
+	// The providers' HTTP APIs and the catch-all cache are about mailboxes.
+	if input.smtp_verification_mode != SmtpVerificationMode::Mailbox {
+		let (picks, smtp_input) = rotate_input(domain, input).await;
+		let result = check_smtp_with_retry(
+			to_email,
+			host,
+			port,
+			domain,
+			mx,
+			&smtp_input,
+			1,
+			None,
+			false,
+		)
+		.await;
+		picks.record(input, result.as_ref());
+		return result;
+	}
+
+	let mut failed_methods = vec![];
+	let mut methods = methods.into_iter().peekable();
+	while let Some(method) = methods.next() {
+		let result = match &method {
+			VerificationMethod::Skip => break,
+			VerificationMethod::Smtp => {
+				Some(smtp_session(to_email, host, port, domain, mx, input).await)
+			}
+			_ => {
+				let provider = provider_key(&method, &host_lowercase, domain);
+				let verification =
+					verify_with_method(&method, to_email, &host_lowercase, domain, input);
+				with_provider_limit(input, &provider, verification).await
+			}
+		};
+		let reason = match result {
+			Some(Ok(smtp_details))
+				if method == VerificationMethod::Smtp && failed_methods.is_empty() =>
+			{
+				return Ok(smtp_details)
+			}
+			Some(Ok(smtp_details)) => {
+				return Ok(SmtpDetails {
+					verification_method: Some(method),
+					failed_methods,
+					..smtp_details
+				})
+			}
+			// The last method's error is the verdict, unless the chain
+			// falls back to SMTP.
+			Some(Err(err)) if methods.peek().is_none() => return Err(err),
+			Some(Err(err)) => format!("{err:?}"),
+			None => "ambiguous result".into(),
+		};
+		log::debug!(
+			target: LOG_TARGET,
+			"[email={}] Falling back from [method={}]: {}",
+			to_email,
+			method,
+			reason,
+		);
+		failed_methods.push(FailedMethod { method, reason });
+	}
+
+	let result = smtp_session(to_email, host, port, domain, mx, input).await;
+	if failed_methods.is_empty() {
+		return result;
+	}
+	result.map(|smtp_details| SmtpDetails {
+		verification_method: Some(VerificationMethod::Smtp),
+		failed_methods,
+		..smtp_details
+	})
+}
+
+/// Verify `to_email` with a regular SMTP session on host, unless the
+/// context already knows that the domain is a catch-all on this MX host.
+async fn smtp_session(
+	to_email: &EmailAddress,
+	host: &Name,
+	port: u16,
+	domain: &str,
+	mx: &MxDetails,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, SmtpError> {
+	let is_catch_all = input
+		.context
+		.catch_all_cache()
+		.get(&catch_all_key(domain, host));
+	if is_catch_all == Some(true) {
+		log::debug!(
+			target: LOG_TARGET,
+			"[email={}] Found cached catch-all for [domain={}] on [host={}]",
+			to_email,
+			domain,
+			host,
+		);
+
+		return Ok(SmtpDetails {
+			can_connect_smtp: true,
+			is_catch_all: true,
+			is_deliverable: true,
+			..Default::default()
+		});
+	}
+
+	let (picks, smtp_input) = rotate_input(domain, input).await;
+	let result = check_smtp_with_retry(
+		to_email,
+		host,
+		port,
+		domain,
+		mx,
+		&smtp_input,
+		1,
+		is_catch_all,
+		false,
+	)
+	.await;
+	picks.record(input, result.as_ref());
+	result
 }
 
 #[cfg(test)]
 mod tests {
-	use super::{check_smtp, CheckEmailInput, SmtpError};
+	use super::{catch_all_key, check_smtp, CheckEmailInput, MxDetails, SmtpError};
 	use async_smtp::EmailAddress;
-	use std::{str::FromStr, time::Duration};
+	use std::{
+		str::FromStr,
+		time::{Duration, Instant},
+	};
 	use tokio::runtime::Runtime;
 	use trust_dns_proto::rr::Name;
 
@@ -134,7 +677,14 @@ mod tests {
 		let mut input = CheckEmailInput::default();
 		input.set_smtp_timeout(Some(Duration::from_millis(1)));
 
-		let res = runtime.block_on(check_smtp(&to_email, &host, 25, "gmail.com", &input));
+		let res = runtime.block_on(check_smtp(
+			&to_email,
+			&host,
+			25,
+			"gmail.com",
+			&MxDetails::default(),
+			&input,
+		));
 		match res {
 			Err(SmtpError::TimeoutError(_)) => (),
 			_ => panic!("check_smtp did not time out"),
@@ -149,10 +699,49 @@ mod tests {
 		let host = Name::from_str("mx01.mail.icloud.com.").unwrap();
 		let input = CheckEmailInput::default();
 
-		let res = runtime.block_on(check_smtp(&to_email, &host, 25, "icloud.com", &input));
+		let res = runtime.block_on(check_smtp(
+			&to_email,
+			&host,
+			25,
+			"icloud.com",
+			&MxDetails::default(),
+			&input,
+		));
+		match res {
+			Err(SmtpError::SkippedDomain(message)) => {
+				assert!(message.contains("\".mail.icloud.com.\""))
+			}
+			r => panic!("{:?}", r),
+		}
+	}
+
+	#[test]
+	fn should_use_cached_catch_all() {
+		let runtime = Runtime::new().unwrap();
+
+		let to_email = EmailAddress::from_str("foo@catchall.invalid").unwrap();
+		let host = Name::from_str("mx.catchall.invalid.").unwrap();
+		let input = CheckEmailInput::default();
+		input.context.catch_all_cache().insert(
+			catch_all_key("catchall.invalid", &host),
+			true,
+			Instant::now() + Duration::from_secs(60),
+		);
+
+		// The host doesn't exist, so this only works if no SMTP connection
+		// is attempted.
+		let res = runtime.block_on(check_smtp(
+			&to_email,
+			&host,
+			25,
+			"catchall.invalid",
+			&MxDetails::default(),
+			&input,
+		));
 		match res {
-			Err(SmtpError::SkippedDomain(_)) => (),
+			Ok(details) => assert!(details.is_catch_all && details.is_deliverable),
 			r => panic!("{:?}", r),
 		}
+		assert_eq!(input.context.catch_all_cache_stats().hits, 1);
 	}
 }
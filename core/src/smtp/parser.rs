@@ -16,7 +16,7 @@
 
 //! Parse the SMTP responses to get information about the email address.
 
-use super::error::SmtpError;
+use super::error::{SmtpError, SmtpErrorDesc};
 use async_smtp::{smtp::error::Error as AsyncSmtpError, EmailAddress};
 
 /// is_invalid checks for SMTP responses meaning that the email is invalid,
@@ -97,25 +97,103 @@ pub fn is_invalid(e: &str, email: &EmailAddress) -> bool {
 	|| e.contains("address is not handled")
 }
 
+/// Lowercase patterns of the SMTP error responses, by category. A response
+/// belongs to the first category with a matching pattern. Responses
+/// matching `is_err_ip_blacklisted`, e.g. "relaying denied" or "access
+/// denied", are left out, since they are mostly sent to blacklisted IPs.
+const ERROR_PATTERNS: &[(SmtpErrorDesc, &[&str])] = &[
+	(
+		SmtpErrorDesc::MailboxFull,
+		&[
+			"insufficient",
+			// https://answers.microsoft.com/en-us/outlook_com/forum/all/how-do-i-interpret-the-delivery-failure-message/2f1bf9c0-8b03-4f8f-aacc-5f6ba60a73f3
+			"mailbox full",
+			"quote exceeded",
+			"over quota",
+			// 552 5.2.2 The email account that you tried to reach is over quota
+			"quota exceeded",
+			// 550 user has too many messages on the server
+			"too many messages",
+		],
+	),
+	(
+		SmtpErrorDesc::AccountDisabled,
+		&[
+			// 554 The email account that you tried to reach is disabled. Learn more at https://support.google.com/mail/?p=DisabledUser"
+			"disabled",
+			// 554 delivery error: Sorry your message to <EMAIL> cannot be delivered. This account has been disabled or discontinued
+			"discontinued",
+			// 550 5.2.1 Mailbox suspended
+			"suspended",
+			// 550 5.2.1 The email account is inactive
+			"inactive",
+		],
+	),
+	(
+		SmtpErrorDesc::RelayDenied,
+		&[
+			"relay denied",
+			"relaying not allowed",
+			"not permitted to relay",
+			// 550 5.7.1 Unable to relay
+			"unable to relay",
+		],
+	),
+	(
+		SmtpErrorDesc::RateLimited,
+		&[
+			// 421 4.7.0 Rate limit exceeded
+			"rate limit",
+			"ratelimit",
+			"rate-limit",
+			// 421 Too many concurrent SMTP connections
+			"too many connections",
+			"too many concurrent",
+			// 452 4.5.3 Too many recipients
+			"too many recipients",
+			"too many requests",
+			// 450 4.2.1 The user you are trying to contact is receiving mail at a rate that prevents additional messages from being delivered
+			"at a rate that",
+			"sending rate",
+		],
+	),
+	(
+		SmtpErrorDesc::PolicyRejection,
+		&[
+			// 550 5.7.1 Message rejected due to local policy
+			"local policy",
+			"policy violation",
+			"policy reasons",
+			// 550 5.7.23 The message was rejected because of Sender Policy Framework violation
+			"sender policy framework",
+			// 550 5.7.1 SPF check failed
+			"spf check",
+			"spf fail",
+			// 550 5.7.1 Unauthenticated email from example.org is not accepted due to domain's DMARC policy
+			"dmarc",
+			"dkim",
+			"authentication required",
+		],
+	),
+];
+
+/// Find the category of an SMTP error response, in `ERROR_PATTERNS`.
+pub fn error_category(e: &str) -> Option<SmtpErrorDesc> {
+	ERROR_PATTERNS
+		.iter()
+		.find(|(_, patterns)| patterns.iter().any(|pattern| e.contains(pattern)))
+		.map(|(desc, _)| *desc)
+}
+
 /// Check that the mailbox has a full inbox.
 pub fn is_full_inbox(e: &str) -> bool {
-	e.contains("insufficient")
-	// https://answers.microsoft.com/en-us/outlook_com/forum/all/how-do-i-interpret-the-delivery-failure-message/2f1bf9c0-8b03-4f8f-aacc-5f6ba60a73f3
-	|| e.contains("mailbox full")
-	// https://answers.microsoft.com/en-us/outlook_com/forum/all/how-do-i-interpret-the-delivery-failure-message/2f1bf9c0-8b03-4f8f-aacc-5f6ba60a73f3
-	|| e.contains("quote exceeded")
-	|| e.contains("over quota")
-	// 550 user has too many messages on the server
-	|| e.contains("too many messages")
+	error_category(e) == Some(SmtpErrorDesc::MailboxFull)
 }
 
 /// Check if the email account has been disabled or blocked by the email
 /// provider.
 pub fn is_disabled_account(e: &str) -> bool {
-	// 554 The email account that you tried to reach is disabled. Learn more at https://support.google.com/mail/?p=DisabledUser"
-	e.contains("disabled")
-	// 554 delivery error: Sorry your message to <EMAIL> cannot be delivered. This account has been disabled or discontinued
- || e.contains("discontinued")
+	error_category(e) == Some(SmtpErrorDesc::AccountDisabled)
 }
 
 /// Check if the error is an IO "incomplete" error.
@@ -217,11 +295,37 @@ pub fn is_err_needs_rdns(e: &SmtpError) -> bool {
 	|| e.contains("reverse dns entry")
 }
 
+/// Check if the server greylists us, i.e. temporarily rejects unknown
+/// senders, expecting them to retry later.
+pub fn is_err_greylisted(e: &SmtpError) -> bool {
+	let e = match e {
+		SmtpError::SmtpError(AsyncSmtpError::Transient(r)) => r.message.join("; ").to_lowercase(),
+		_ => {
+			return false;
+		}
+	};
+
+	// 451 4.7.1 Greylisting in action, please come back later
+	// 450 4.2.0 <EMAIL>: Recipient address rejected: Greylisted, see http://postgrey.schweikert.ch/help/example.org.html
+	e.contains("greylist")
+	|| e.contains("graylist")
+	// 451 Temporary local problem - please try later
+	// 450 4.7.1 Try again later
+	|| e.contains("try later")
+	|| e.contains("try again later")
+	// 451 4.7.1 Please try again later
+	|| e.contains("please retry")
+	// 451 4.7.0 Temporarily deferred, come back later
+	|| e.contains("come back later")
+	|| e.contains("temporarily deferred")
+	|| e.contains("temporarily rejected")
+}
+
 #[cfg(test)]
 mod tests {
 
-	use super::{is_err_ip_blacklisted, is_invalid};
-	use crate::SmtpError::SmtpError;
+	use super::{error_category, is_err_greylisted, is_err_ip_blacklisted, is_invalid};
+	use crate::{smtp::SmtpErrorDesc, SmtpError::SmtpError};
 	use async_smtp::{
 		smtp::error::Error,
 		smtp::response::{Category, Code, Detail, Response, Severity},
@@ -267,4 +371,39 @@ mod tests {
 
 		assert!(is_err_ip_blacklisted(&SmtpError(err)))
 	}
+
+	#[test]
+	fn test_is_err_greylisted() {
+		let err = Error::Transient(Response::new(
+			Code::new(
+				Severity::TransientNegativeCompletion,
+				Category::MailSystem,
+				Detail::One,
+			),
+			vec!["4.7.1 Greylisting in action, please come back later".to_string()],
+		));
+
+		assert!(is_err_greylisted(&SmtpError(err)))
+	}
+
+	#[test]
+	fn test_error_category() {
+		assert_eq!(
+			error_category("550 5.7.1 unable to relay"),
+			Some(SmtpErrorDesc::RelayDenied)
+		);
+		assert_eq!(
+			error_category("452 4.5.3 too many recipients"),
+			Some(SmtpErrorDesc::RateLimited)
+		);
+		assert_eq!(
+			error_category("552 5.2.2 mailbox full"),
+			Some(SmtpErrorDesc::MailboxFull)
+		);
+		assert_eq!(
+			error_category("550 5.7.1 message rejected due to local policy"),
+			Some(SmtpErrorDesc::PolicyRejection)
+		);
+		assert_eq!(error_category("550 5.1.1 user unknown"), None);
+	}
 }
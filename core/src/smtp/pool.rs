@@ -0,0 +1,256 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::client::SmtpChannel;
+use crate::util::input_output::{CheckEmailInputProxy, SmtpRelay, SmtpSecurity};
+
+/// Key of the SMTP connection pool. Connections are only reused by
+/// verifications which would open the same connection, with the same
+/// handshake.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct SmtpPoolKey {
+	/// The host connected to: the MX host, or the LMTP server or SMTP relay
+	/// if set, lowercase, without the trailing dot.
+	pub host: String,
+	pub port: u16,
+	pub security: SmtpSecurity,
+	/// Whether the server's TLS certificate is kept, for the TLS details or
+	/// the DANE validation.
+	pub inspect_tls: bool,
+	pub accept_invalid_certs: bool,
+	pub root_certificates: Vec<String>,
+	pub source_ip: Option<IpAddr>,
+	pub source_interface: Option<String>,
+	/// The proxy, with its credentials, if any.
+	pub proxy: Option<CheckEmailInputProxy>,
+	/// The name sent with EHLO, or LHLO to LMTP servers.
+	pub hello_name: String,
+	pub lmtp: bool,
+	/// The SMTP relay authenticated to, with its credentials, if any.
+	pub relay: Option<SmtpRelay>,
+}
+
+/// Configuration of the SMTP connection pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmtpPoolConfig {
+	/// Maximum time a connection can stay idle in the pool. Older
+	/// connections are discarded instead of being reused.
+	pub max_idle_time: Duration,
+	/// Maximum number of idle connections kept per MX host.
+	pub max_idle_per_host: usize,
+}
+
+impl Default for SmtpPoolConfig {
+	fn default() -> Self {
+		SmtpPoolConfig {
+			max_idle_time: Duration::from_secs(30),
+			max_idle_per_host: 2,
+		}
+	}
+}
+
+/// Counters of the SMTP connection pool, useful for tuning its config.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SmtpPoolStats {
+	/// Number of new connections opened to an SMTP server.
+	pub connections_created: u64,
+	/// Number of times an idle connection was reused.
+	pub connections_reused: u64,
+	/// Number of idle connections dropped, because they had been idle for
+	/// too long, the pool was full, or the server had closed them.
+	pub connections_discarded: u64,
+	/// Number of connections currently idle in the pool.
+	pub idle_connections: usize,
+}
+
+/// A pool of idle SMTP connections, which are already past the EHLO (and
+/// optional STARTTLS) step.
+pub(crate) struct SmtpPool {
+	config: SmtpPoolConfig,
+	idle: Mutex<HashMap<SmtpPoolKey, Vec<(SmtpChannel, Instant)>>>,
+	created: AtomicU64,
+	reused: AtomicU64,
+	discarded: AtomicU64,
+}
+
+impl fmt::Debug for SmtpPool {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("SmtpPool")
+			.field("config", &self.config)
+			.field("stats", &self.stats())
+			.finish()
+	}
+}
+
+impl SmtpPool {
+	pub fn new(config: SmtpPoolConfig) -> Self {
+		SmtpPool {
+			config,
+			idle: Mutex::new(HashMap::new()),
+			created: AtomicU64::new(0),
+			reused: AtomicU64::new(0),
+			discarded: AtomicU64::new(0),
+		}
+	}
+
+	/// Take the most recently used idle connection to `key`. Connections
+	/// idle for longer than `max_idle_time` are dropped along the way.
+	///
+	/// The returned connection might still have been closed by the server,
+	/// callers should call `discard` if it turns out to be unusable.
+	pub fn checkout(&self, key: &SmtpPoolKey) -> Option<SmtpChannel> {
+		let mut idle = self.idle.lock().expect("Pool lock is not poisoned. qed.");
+		let connections = idle.get_mut(key)?;
+
+		let before = connections.len();
+		connections.retain(|(_, idle_since)| idle_since.elapsed() <= self.config.max_idle_time);
+		self.discarded
+			.fetch_add((before - connections.len()) as u64, Ordering::Relaxed);
+
+		let transport = connections.pop().map(|(transport, _)| transport);
+		if connections.is_empty() {
+			idle.remove(key);
+		}
+		if transport.is_some() {
+			self.reused.fetch_add(1, Ordering::Relaxed);
+		}
+
+		transport
+	}
+
+	/// Put a connection back in the pool. If the pool is already full for
+	/// this host, the connection is given back, and should be closed by the
+	/// caller.
+	pub fn checkin(&self, key: SmtpPoolKey, transport: SmtpChannel) -> Option<SmtpChannel> {
+		let mut idle = self.idle.lock().expect("Pool lock is not poisoned. qed.");
+		let connections = idle.entry(key).or_default();
+
+		if connections.len() >= self.config.max_idle_per_host {
+			self.discarded.fetch_add(1, Ordering::Relaxed);
+			return Some(transport);
+		}
+
+		connections.push((transport, Instant::now()));
+		None
+	}
+
+	/// Record that a new connection was opened.
+	pub fn record_created(&self) {
+		self.created.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Record that a checked out connection was unusable, and dropped.
+	pub fn discard(&self) {
+		self.reused.fetch_sub(1, Ordering::Relaxed);
+		self.discarded.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Drop all idle connections.
+	pub fn clear(&self) {
+		let mut idle = self.idle.lock().expect("Pool lock is not poisoned. qed.");
+		let count = idle.values().map(Vec::len).sum::<usize>();
+		idle.clear();
+		self.discarded.fetch_add(count as u64, Ordering::Relaxed);
+	}
+
+	pub fn stats(&self) -> SmtpPoolStats {
+		SmtpPoolStats {
+			connections_created: self.created.load(Ordering::Relaxed),
+			connections_reused: self.reused.load(Ordering::Relaxed),
+			connections_discarded: self.discarded.load(Ordering::Relaxed),
+			idle_connections: self
+				.idle
+				.lock()
+				.expect("Pool lock is not poisoned. qed.")
+				.values()
+				.map(Vec::len)
+				.sum(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::smtp::client::SmtpEndpoint;
+	use async_smtp::{smtp::ServerAddress, ClientSecurity, SmtpClient};
+
+	fn transport() -> SmtpChannel {
+		SmtpChannel::Transport(
+			SmtpClient::with_security(
+				ServerAddress {
+					host: "localhost".into(),
+					port: 25,
+				},
+				ClientSecurity::None,
+			)
+			.into_transport(),
+			SmtpEndpoint::default(),
+		)
+	}
+
+	#[test]
+	fn should_respect_max_idle_per_host_and_time() {
+		let pool = SmtpPool::new(SmtpPoolConfig {
+			max_idle_time: Duration::from_secs(60),
+			max_idle_per_host: 1,
+		});
+		let key = SmtpPoolKey {
+			host: "mx.example.org".into(),
+			port: 25,
+			security: SmtpSecurity::Opportunistic,
+			inspect_tls: false,
+			accept_invalid_certs: true,
+			root_certificates: vec![],
+			source_ip: None,
+			source_interface: None,
+			proxy: None,
+			hello_name: "gmail.com".into(),
+			lmtp: false,
+			relay: None,
+		};
+
+		assert!(pool.checkin(key.clone(), transport()).is_none());
+		assert!(pool.checkin(key.clone(), transport()).is_some());
+		assert!(pool.checkout(&key).is_some());
+		assert!(pool.checkout(&key).is_none());
+
+		let pool = SmtpPool::new(SmtpPoolConfig {
+			max_idle_time: Duration::from_secs(0),
+			max_idle_per_host: 1,
+		});
+		assert!(pool.checkin(key.clone(), transport()).is_none());
+		std::thread::sleep(Duration::from_millis(5));
+		assert!(pool.checkout(&key).is_none());
+
+		assert_eq!(
+			pool.stats(),
+			SmtpPoolStats {
+				connections_discarded: 1,
+				..Default::default()
+			}
+		);
+	}
+}
@@ -0,0 +1,132 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::SmtpDetails;
+use crate::{
+	smtp::http_api::{create_client, error_for_throttle},
+	util::{
+		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
+	},
+};
+use async_smtp::EmailAddress;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const AVAILABILITY_API: &str = "https://account.proton.me/api/core/v4/users/available";
+const APP_VERSION: &str = "web-account@5.0.100.0";
+
+/// Domains of the addresses hosted by Proton itself, the only ones its
+/// availability API knows about.
+const PROTON_DOMAINS: &[&str] = &["proton.me", "protonmail.com", "protonmail.ch", "pm.me"];
+
+/// Response code of the availability API when the name is free.
+const CODE_AVAILABLE: i64 = 1000;
+/// Response code of the availability API when the name is already used.
+const CODE_ALREADY_USED: i64 = 12106;
+
+/// The response of the availability API.
+#[derive(Debug, Deserialize)]
+struct AvailabilityResponse {
+	#[serde(rename = "Code")]
+	code: i64,
+}
+
+/// Possible errors when checking Proton Mail email addresses.
+#[derive(Debug, Serialize)]
+pub enum ProtonError {
+	/// Error when serializing or deserializing HTTP requests and responses.
+	#[serde(serialize_with = "ser_with_display")]
+	ReqwestError(ReqwestError),
+	/// The API answered with an unknown code, e.g. when it asks for a
+	/// human verification.
+	UnexpectedCode(i64),
+}
+
+impl fmt::Display for ProtonError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{self:?}")
+	}
+}
+
+impl From<ReqwestError> for ProtonError {
+	fn from(error: ReqwestError) -> Self {
+		ProtonError::ReqwestError(error)
+	}
+}
+
+/// Is host one of Proton's MX hosts, for one of the domains hosted by
+/// Proton itself, as opposed to custom domains using Proton's MX hosts?
+pub(super) fn is_proton_host(host_lowercase: &str, domain: &str) -> bool {
+	host_lowercase.ends_with(".protonmail.ch.")
+		&& PROTON_DOMAINS.contains(&domain.to_lowercase().as_str())
+}
+
+/// Does the availability API's `code` mean that the address exists?
+fn is_used(code: i64) -> Result<bool, ProtonError> {
+	match code {
+		CODE_AVAILABLE => Ok(false),
+		CODE_ALREADY_USED => Ok(true),
+		code => Err(ProtonError::UnexpectedCode(code)),
+	}
+}
+
+/// Use the address availability API of Proton's signup page to verify if a
+/// Proton Mail email address exists, as Proton's SMTP servers answer the
+/// same for existing and non-existing mailboxes.
+pub async fn check_proton(
+	to_email: &EmailAddress,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, ProtonError> {
+	let response = create_client(input, "proton")?
+		.get(AVAILABILITY_API)
+		.query(&[("Name", to_email.as_ref()), ("ParseDomain", "1")])
+		.header("x-pm-appversion", APP_VERSION)
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<AvailabilityResponse>().await?;
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] proton response: {:?}",
+		to_email,
+		response
+	);
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable: is_used(response.code)?,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_availability_codes() {
+		assert!(!is_used(1000).unwrap());
+		assert!(is_used(12106).unwrap());
+		assert!(matches!(
+			is_used(9001),
+			Err(ProtonError::UnexpectedCode(9001))
+		));
+		assert!(is_proton_host("mail.protonmail.ch.", "PM.me"));
+		assert!(!is_proton_host("mail.protonmail.ch.", "example.org"));
+	}
+}
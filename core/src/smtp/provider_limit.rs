@@ -0,0 +1,257 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Request budgets of the providers' HTTP APIs and headless pages, shared
+//! by all the verifications of a context, with a cooldown when a provider
+//! throttles us, so that it doesn't revoke our access.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use serde::{Deserialize, Serialize};
+
+use super::rate_limit::{RateLimitConfig, RateLimiter};
+use super::{SmtpError, VerificationMethod};
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// Configuration of the provider limits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderLimitConfig {
+	/// Maximum number of requests per `period`, by provider. The providers
+	/// are named after their verification method, e.g. "yahoo_api" or
+	/// "custom:<pattern>", except the headless pages: "headless_hotmail",
+	/// "headless_microsoft365" and "headless_yahoo". Providers without a
+	/// budget aren't limited.
+	pub budgets: HashMap<String, usize>,
+	pub period: Duration,
+	/// How long to stop sending requests to a provider once it throttles
+	/// us, i.e. answers "429 Too Many Requests" or with a challenge.
+	pub cooldown: Duration,
+}
+
+impl ProviderLimitConfig {
+	/// Allow no request for `cooldown` to the providers which throttle us,
+	/// without budgets.
+	pub fn new(period: Duration, cooldown: Duration) -> Self {
+		ProviderLimitConfig {
+			budgets: HashMap::new(),
+			period,
+			cooldown,
+		}
+	}
+
+	/// Allow at most `max_requests` requests per `period` to `provider`.
+	pub fn with_budget(mut self, provider: impl Into<String>, max_requests: usize) -> Self {
+		self.budgets.insert(provider.into(), max_requests);
+		self
+	}
+}
+
+/// Counters of the provider limits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProviderLimitStats {
+	/// Number of requests which went through the provider limits.
+	pub requests: u64,
+	/// Number of requests which had to wait for a budget or a cooldown.
+	pub delayed_requests: u64,
+	/// Number of times a provider throttled us.
+	pub throttled: u64,
+}
+
+/// The request budgets and cooldowns of the providers.
+pub(crate) struct ProviderLimiter {
+	cooldown: Duration,
+	/// The providers with a budget.
+	budgeted: HashSet<String>,
+	budgets: RateLimiter,
+	/// End of the cooldown of the providers which throttled us.
+	cooldowns: Mutex<HashMap<String, Instant>>,
+	requests: AtomicU64,
+	delayed: AtomicU64,
+	throttled: AtomicU64,
+}
+
+impl fmt::Debug for ProviderLimiter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ProviderLimiter")
+			.field("cooldown", &self.cooldown)
+			.field("stats", &self.stats())
+			.finish()
+	}
+}
+
+impl ProviderLimiter {
+	pub fn new(config: ProviderLimitConfig) -> Self {
+		let mut budgets = RateLimitConfig::new(usize::MAX, config.period);
+		budgets.overrides = config.budgets;
+
+		ProviderLimiter {
+			cooldown: config.cooldown,
+			budgeted: budgets.overrides.keys().cloned().collect(),
+			budgets: RateLimiter::new(budgets),
+			cooldowns: Mutex::new(HashMap::new()),
+			requests: AtomicU64::new(0),
+			delayed: AtomicU64::new(0),
+			throttled: AtomicU64::new(0),
+		}
+	}
+
+	/// How long `provider` is still cooling down at `now`, if it is.
+	fn cooldown_left(&self, provider: &str, now: Instant) -> Option<Duration> {
+		let mut cooldowns = self
+			.cooldowns
+			.lock()
+			.expect("Provider limiter lock is not poisoned. qed.");
+		match cooldowns.get(provider) {
+			Some(end) if *end > now => Some(*end - now),
+			Some(_) => {
+				cooldowns.remove(provider);
+				None
+			}
+			None => None,
+		}
+	}
+
+	/// Wait until a request to `provider` is allowed, and count it.
+	pub async fn acquire(&self, provider: &str) {
+		self.requests.fetch_add(1, Ordering::Relaxed);
+		let mut delayed = false;
+		while let Some(wait) = self.cooldown_left(provider, Instant::now()) {
+			delayed = true;
+			task::sleep(wait).await;
+		}
+		// The providers without a budget aren't limited.
+		if self.budgeted.contains(provider) {
+			let before = self.budgets.stats().delayed_probes;
+			self.budgets.acquire(provider).await;
+			delayed |= self.budgets.stats().delayed_probes > before;
+		}
+		if delayed {
+			self.delayed.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	/// Start the cooldown of `provider`, which throttled us at `now`.
+	fn throttle(&self, provider: &str, now: Instant) {
+		self.throttled.fetch_add(1, Ordering::Relaxed);
+		self.cooldowns
+			.lock()
+			.expect("Provider limiter lock is not poisoned. qed.")
+			.insert(provider.to_string(), now + self.cooldown);
+	}
+
+	pub fn stats(&self) -> ProviderLimitStats {
+		ProviderLimitStats {
+			requests: self.requests.load(Ordering::Relaxed),
+			delayed_requests: self.delayed.load(Ordering::Relaxed),
+			throttled: self.throttled.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// The provider `method` sends its requests to, for the MX host and the
+/// domain, as named in `ProviderLimitConfig::budgets`.
+pub(super) fn provider_key(
+	method: &VerificationMethod,
+	host_lowercase: &str,
+	domain: &str,
+) -> String {
+	#[cfg(feature = "headless")]
+	if let VerificationMethod::Headless(_) = method {
+		use super::microsoft::{microsoft_account_kind, MicrosoftAccountKind};
+
+		return match microsoft_account_kind(host_lowercase, domain) {
+			Some(MicrosoftAccountKind::Consumer) => "headless_hotmail".into(),
+			Some(MicrosoftAccountKind::Microsoft365) => "headless_microsoft365".into(),
+			None if host_lowercase.contains("yahoo") => "headless_yahoo".into(),
+			None => "headless".into(),
+		};
+	}
+	#[cfg(not(feature = "headless"))]
+	let _ = (host_lowercase, domain);
+
+	method.to_string()
+}
+
+/// Run `verification`, a request to `provider`, within the context's
+/// provider limits, if enabled.
+pub(super) async fn with_provider_limit<T>(
+	input: &CheckEmailInput,
+	provider: &str,
+	verification: impl Future<Output = Option<Result<T, SmtpError>>>,
+) -> Option<Result<T, SmtpError>> {
+	let limiter = match input.context.provider_limiter() {
+		Some(limiter) => limiter,
+		None => return verification.await,
+	};
+
+	limiter.acquire(provider).await;
+	let result = verification.await;
+	if let Some(Err(err)) = &result {
+		if err.is_throttled() {
+			log::debug!(
+				target: LOG_TARGET,
+				"[provider={}] Throttled, cooling down for {:?}: {:?}",
+				provider,
+				limiter.cooldown,
+				err
+			);
+			limiter.throttle(provider, Instant::now());
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_cool_down_throttled_providers() {
+		let limiter = ProviderLimiter::new(
+			ProviderLimitConfig::new(Duration::from_secs(60), Duration::from_secs(300))
+				.with_budget("yahoo_api", 10),
+		);
+		let start = Instant::now();
+		assert_eq!(limiter.cooldown_left("yahoo_api", start), None);
+
+		limiter.throttle("yahoo_api", start);
+		assert_eq!(
+			limiter.cooldown_left("yahoo_api", start + Duration::from_secs(100)),
+			Some(Duration::from_secs(200))
+		);
+		assert_eq!(limiter.cooldown_left("gmail_api", start), None);
+		assert_eq!(
+			limiter.cooldown_left("yahoo_api", start + Duration::from_secs(300)),
+			None
+		);
+		assert_eq!(limiter.stats().throttled, 1);
+		assert_eq!(
+			provider_key(
+				&VerificationMethod::YahooApi,
+				"mta5.am0.yahoodns.net.",
+				"yahoo.com"
+			),
+			"yahoo_api"
+		);
+	}
+}
@@ -0,0 +1,233 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Handshakes of the SOCKS4 and HTTP CONNECT proxies, which async-smtp
+//! doesn't support. SOCKS5 proxies go through async-smtp's transport.
+
+use std::fmt;
+use std::io;
+use std::net::Ipv4Addr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::util::input_output::{CheckEmailInputProxy, ProxyKind};
+
+/// Maximum size of the HTTP CONNECT response headers.
+const MAX_HTTP_RESPONSE_LEN: usize = 8192;
+
+/// Failure to connect to a SOCKS4 or HTTP CONNECT proxy, or of its
+/// handshake, as opposed to errors of the SMTP server behind it. It's
+/// wrapped in an `io::Error`, of the same kind as the original error.
+#[derive(Debug)]
+pub(super) struct ProxyHandshakeError(io::Error);
+
+impl ProxyHandshakeError {
+	pub(super) fn wrap(err: io::Error) -> io::Error {
+		io::Error::new(err.kind(), ProxyHandshakeError(err))
+	}
+}
+
+impl fmt::Display for ProxyHandshakeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Proxy handshake failed: {}", self.0)
+	}
+}
+
+impl std::error::Error for ProxyHandshakeError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.0)
+	}
+}
+
+/// Ask the proxy, connected on stream, to open a tunnel to host:port.
+pub(super) async fn proxy_handshake<S>(
+	stream: &mut S,
+	proxy: &CheckEmailInputProxy,
+	host: &str,
+	port: u16,
+) -> io::Result<()>
+where
+	S: AsyncRead + AsyncWrite + Unpin,
+{
+	match proxy.kind {
+		ProxyKind::Socks4 => socks4_connect(stream, proxy, host, port).await,
+		ProxyKind::HttpConnect => http_connect(stream, proxy, host, port).await,
+		ProxyKind::Socks5 => Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			"SOCKS5 proxies are handled by the SMTP transport",
+		)),
+	}
+}
+
+/// SOCKS4 CONNECT request, or SOCKS4a if host isn't an IPv4 address, in
+/// which case the proxy resolves it. The username is sent as the user ID.
+async fn socks4_connect<S>(
+	stream: &mut S,
+	proxy: &CheckEmailInputProxy,
+	host: &str,
+	port: u16,
+) -> io::Result<()>
+where
+	S: AsyncRead + AsyncWrite + Unpin,
+{
+	let ip = host.parse::<Ipv4Addr>().ok();
+
+	let mut request = vec![4, 1];
+	request.extend_from_slice(&port.to_be_bytes());
+	// SOCKS4a: an invalid IP of the form 0.0.0.x, followed by the hostname.
+	request.extend_from_slice(&ip.unwrap_or_else(|| Ipv4Addr::new(0, 0, 0, 1)).octets());
+	request.extend_from_slice(proxy.username.as_deref().unwrap_or_default().as_bytes());
+	request.push(0);
+	if ip.is_none() {
+		request.extend_from_slice(host.as_bytes());
+		request.push(0);
+	}
+	stream.write_all(&request).await?;
+	stream.flush().await?;
+
+	let mut reply = [0; 8];
+	stream.read_exact(&mut reply).await?;
+	match reply[1] {
+		90 => Ok(()),
+		code => Err(io::Error::new(
+			io::ErrorKind::ConnectionRefused,
+			format!("SOCKS4 proxy rejected the connection with code {code}"),
+		)),
+	}
+}
+
+/// HTTP CONNECT request, with Basic authentication if the proxy has a
+/// username.
+async fn http_connect<S>(
+	stream: &mut S,
+	proxy: &CheckEmailInputProxy,
+	host: &str,
+	port: u16,
+) -> io::Result<()>
+where
+	S: AsyncRead + AsyncWrite + Unpin,
+{
+	// IPv6 addresses must be bracketed in the authority.
+	let authority = if host.contains(':') {
+		format!("[{host}]:{port}")
+	} else {
+		format!("{host}:{port}")
+	};
+	let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+	if let Some(username) = &proxy.username {
+		let credentials = format!(
+			"{}:{}",
+			username,
+			proxy.password.as_deref().unwrap_or_default()
+		);
+		request.push_str(&format!(
+			"Proxy-Authorization: Basic {}\r\n",
+			STANDARD.encode(credentials)
+		));
+	}
+	request.push_str("\r\n");
+	stream.write_all(request.as_bytes()).await?;
+	stream.flush().await?;
+
+	// Read byte by byte, so that nothing after the headers, i.e. the SMTP
+	// greeting, is consumed.
+	let mut response = Vec::new();
+	while !response.ends_with(b"\r\n\r\n") {
+		if response.len() >= MAX_HTTP_RESPONSE_LEN {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"HTTP proxy response is too long",
+			));
+		}
+		response.push(stream.read_u8().await?);
+	}
+
+	let response = String::from_utf8_lossy(&response);
+	let status_line = response.lines().next().unwrap_or_default();
+	match status_line.split_whitespace().nth(1) {
+		Some(status) if status.starts_with('2') => Ok(()),
+		_ => Err(io::Error::new(
+			io::ErrorKind::ConnectionRefused,
+			format!("HTTP proxy rejected the connection: {status_line}"),
+		)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::io::duplex;
+
+	fn proxy(kind: ProxyKind) -> CheckEmailInputProxy {
+		CheckEmailInputProxy {
+			host: "proxy.example.org".into(),
+			port: 1080,
+			username: Some("user".into()),
+			password: Some("pass".into()),
+			kind,
+		}
+	}
+
+	#[tokio::test]
+	async fn should_connect_through_socks4a_proxy() {
+		let (mut client, mut server) = duplex(1024);
+		let server = tokio::spawn(async move {
+			let mut request = vec![0; 28];
+			server.read_exact(&mut request).await.unwrap();
+			server.write_all(&[0, 90, 0, 0, 0, 0, 0, 0]).await.unwrap();
+			request
+		});
+
+		socks4_connect(&mut client, &proxy(ProxyKind::Socks4), "mx.example.org", 25)
+			.await
+			.unwrap();
+		let request = server.await.unwrap();
+		assert_eq!(&request[..8], &[4, 1, 0, 25, 0, 0, 0, 1]);
+		assert_eq!(&request[8..], b"user\0mx.example.org\0");
+	}
+
+	#[tokio::test]
+	async fn should_connect_through_http_proxy() {
+		let (mut client, mut server) = duplex(1024);
+		let server = tokio::spawn(async move {
+			let mut request = vec![0; 1024];
+			let len = server.read(&mut request).await.unwrap();
+			server
+				.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n220 mx ESMTP\r\n")
+				.await
+				.unwrap();
+			String::from_utf8(request[..len].to_vec()).unwrap()
+		});
+
+		http_connect(
+			&mut client,
+			&proxy(ProxyKind::HttpConnect),
+			"mx.example.org",
+			25,
+		)
+		.await
+		.unwrap();
+		let request = server.await.unwrap();
+		assert!(request.starts_with("CONNECT mx.example.org:25 HTTP/1.1\r\n"));
+		assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+
+		// The SMTP greeting is left on the stream.
+		let mut greeting = [0; 3];
+		client.read_exact(&mut greeting).await.unwrap();
+		assert_eq!(&greeting, b"220");
+	}
+}
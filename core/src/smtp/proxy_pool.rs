@@ -0,0 +1,385 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rotation of the SMTP connections across a pool of proxies, with
+//! per-proxy error attribution: proxies which keep failing to connect, or
+//! which fail their periodic health check, are skipped until they pass it
+//! again.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_smtp::smtp::error::Error as AsyncSmtpError;
+use async_std::future;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+use super::connect::connect_to_address;
+use super::proxy::ProxyHandshakeError;
+use super::rotation::rotation_index;
+use super::SmtpError;
+use crate::util::{
+	constants::LOG_TARGET,
+	input_output::{
+		CheckEmailInput, CheckEmailInputProxy, ProxyKind, RotationPolicy, SmtpSecurity,
+	},
+};
+
+/// Configuration of the proxy pool.
+#[derive(Debug, Clone)]
+pub struct ProxyPoolConfig {
+	/// Proxies to rotate the SMTP connections across.
+	pub proxies: Vec<CheckEmailInputProxy>,
+	/// How to pick the proxy of each verification.
+	pub rotation: RotationPolicy,
+	/// Number of consecutive connection failures after which a proxy is
+	/// considered dead, until it passes a health check.
+	pub max_consecutive_failures: u32,
+	/// Time between two health checks of the proxies.
+	pub health_check_interval: Duration,
+	/// Timeout of the health check of each proxy.
+	pub health_check_timeout: Duration,
+	/// SMTP server, as host and port, to connect to through each proxy
+	/// during the health checks, up to EHLO. If None, the health checks only
+	/// open a TCP connection to the proxy.
+	pub health_check_target: Option<(String, u16)>,
+}
+
+impl ProxyPoolConfig {
+	pub fn new(proxies: Vec<CheckEmailInputProxy>) -> Self {
+		ProxyPoolConfig {
+			proxies,
+			rotation: RotationPolicy::RoundRobin,
+			max_consecutive_failures: 3,
+			health_check_interval: Duration::from_secs(300),
+			health_check_timeout: Duration::from_secs(10),
+			health_check_target: None,
+		}
+	}
+}
+
+/// Counters of a proxy of the proxy pool.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProxyStats {
+	pub host: String,
+	pub port: u16,
+	pub kind: ProxyKind,
+	/// Number of SMTP verifications made through this proxy.
+	pub verifications: u64,
+	/// Number of verifications which failed to connect through this proxy.
+	pub failures: u64,
+	/// Is the proxy used by the rotation? Dead proxies are skipped.
+	pub healthy: bool,
+	/// The last connection or health check error of the proxy.
+	pub last_error: Option<String>,
+}
+
+struct ProxyState {
+	proxy: CheckEmailInputProxy,
+	stats: ProxyStats,
+	consecutive_failures: u32,
+}
+
+/// A pool of proxies, used in turn by the SMTP connections.
+pub(crate) struct ProxyPool {
+	config: ProxyPoolConfig,
+	next: AtomicUsize,
+	states: Mutex<Vec<ProxyState>>,
+	/// When the next health check is due.
+	next_health_check: Mutex<Instant>,
+}
+
+impl ProxyPool {
+	pub fn new(config: ProxyPoolConfig) -> Self {
+		let states = config
+			.proxies
+			.iter()
+			.map(|proxy| ProxyState {
+				proxy: proxy.clone(),
+				stats: ProxyStats {
+					host: proxy.host.clone(),
+					port: proxy.port,
+					kind: proxy.kind,
+					verifications: 0,
+					failures: 0,
+					healthy: true,
+					last_error: None,
+				},
+				consecutive_failures: 0,
+			})
+			.collect();
+
+		ProxyPool {
+			next_health_check: Mutex::new(Instant::now() + config.health_check_interval),
+			config,
+			next: AtomicUsize::new(0),
+			states: Mutex::new(states),
+		}
+	}
+
+	/// Pick the proxy of a verification on `domain`, following the
+	/// configured rotation, and skipping the dead proxies. If all proxies
+	/// are dead, the one picked by the rotation is used anyway, so that the
+	/// connection never bypasses the pool. Returns the proxy's index, to
+	/// record the verification's result.
+	pub fn select(&self, domain: &str) -> Option<(usize, CheckEmailInputProxy)> {
+		let states = self.states.lock().expect("Pool lock is not poisoned. qed.");
+		if states.is_empty() {
+			return None;
+		}
+
+		let start = rotation_index(self.config.rotation, states.len(), domain, || {
+			self.next.fetch_add(1, Ordering::Relaxed)
+		});
+		let index = (0..states.len())
+			.map(|i| (start + i) % states.len())
+			.find(|index| states[*index].stats.healthy)
+			.unwrap_or(start);
+
+		Some((index, states[index].proxy.clone()))
+	}
+
+	/// Record the result of a verification made through the proxy at index,
+	/// and mark the proxy as dead if it fails to connect too many times in a
+	/// row. Only the proxy's own errors, see `is_proxy_error`, are
+	/// attributed to it.
+	pub fn record<T>(&self, index: usize, result: Result<T, &SmtpError>) {
+		let mut states = self.states.lock().expect("Pool lock is not poisoned. qed.");
+		let state = match states.get_mut(index) {
+			Some(state) => state,
+			None => return,
+		};
+
+		state.stats.verifications += 1;
+		match result {
+			Err(err) if is_proxy_error(err) => {
+				state.stats.failures += 1;
+				state.stats.last_error = Some(format!("{err:?}"));
+				state.consecutive_failures += 1;
+				if state.stats.healthy
+					&& state.consecutive_failures >= self.config.max_consecutive_failures
+				{
+					log::warn!(
+						target: LOG_TARGET,
+						"Marking [proxy={}:{}] as dead after {} consecutive failures.",
+						state.proxy.host,
+						state.proxy.port,
+						state.consecutive_failures
+					);
+					state.stats.healthy = false;
+				}
+			}
+			_ => state.consecutive_failures = 0,
+		}
+	}
+
+	/// Run the health check of all proxies in a background task if it's
+	/// due, so that no verification waits for it. Skipped if there's no
+	/// tokio runtime to run the task.
+	pub fn health_check_if_due(self: &Arc<Self>) {
+		let runtime = match tokio::runtime::Handle::try_current() {
+			Ok(runtime) => runtime,
+			Err(_) => return,
+		};
+		{
+			let mut next_health_check = self
+				.next_health_check
+				.lock()
+				.expect("Pool lock is not poisoned. qed.");
+			if Instant::now() < *next_health_check {
+				return;
+			}
+			*next_health_check = Instant::now() + self.config.health_check_interval;
+		}
+
+		let pool = self.clone();
+		runtime.spawn(async move { pool.health_check().await });
+	}
+
+	/// Check all proxies concurrently. Dead proxies which pass the check are
+	/// used again, healthy ones which fail it are marked as dead.
+	pub async fn health_check(&self) {
+		let proxies = self
+			.states
+			.lock()
+			.expect("Pool lock is not poisoned. qed.")
+			.iter()
+			.map(|state| state.proxy.clone())
+			.collect::<Vec<_>>();
+
+		let results = join_all(proxies.iter().map(|proxy| check_proxy(proxy, &self.config))).await;
+
+		let mut states = self.states.lock().expect("Pool lock is not poisoned. qed.");
+		for (state, result) in states.iter_mut().zip(results) {
+			match result {
+				Ok(()) => {
+					state.stats.healthy = true;
+					state.consecutive_failures = 0;
+				}
+				Err(err) => {
+					log::debug!(
+						target: LOG_TARGET,
+						"[proxy={}:{}] failed its health check: {}",
+						state.proxy.host,
+						state.proxy.port,
+						err
+					);
+					state.stats.healthy = false;
+					state.stats.last_error = Some(err);
+				}
+			}
+		}
+	}
+
+	pub fn stats(&self) -> Vec<ProxyStats> {
+		self.states
+			.lock()
+			.expect("Pool lock is not poisoned. qed.")
+			.iter()
+			.map(|state| state.stats.clone())
+			.collect()
+	}
+}
+
+/// Is the error caused by the proxy, i.e. a failed SOCKS or HTTP CONNECT
+/// handshake, rather than by the SMTP server behind it? Time-outs and other
+/// IO errors may come from the SMTP server, so they don't count.
+fn is_proxy_error(err: &SmtpError) -> bool {
+	let err = match err {
+		SmtpError::SocksError(_) => return true,
+		SmtpError::SmtpError(AsyncSmtpError::Io(err)) => err,
+		_ => return false,
+	};
+
+	match err.get_ref() {
+		Some(inner) => {
+			inner.is::<ProxyHandshakeError>()
+				|| matches!(
+					inner.downcast_ref::<AsyncSmtpError>(),
+					Some(AsyncSmtpError::Socks5Error(_))
+				)
+		}
+		None => false,
+	}
+}
+
+/// Connect to the proxy, and to the configured health check target through
+/// it, if any.
+async fn check_proxy(proxy: &CheckEmailInputProxy, config: &ProxyPoolConfig) -> Result<(), String> {
+	let check = async {
+		match &config.health_check_target {
+			Some((host, port)) => {
+				let mut input = CheckEmailInput::new(String::new());
+				input
+					.set_proxy(proxy.clone())
+					.set_smtp_security(SmtpSecurity::None);
+				let mut smtp_transport = connect_to_address(host, host, *port, &input)
+					.await
+					.map_err(|err| format!("{err:?}"))?;
+				let _ = smtp_transport.close().await;
+			}
+			None => {
+				TcpStream::connect((proxy.host.as_str(), proxy.port))
+					.await
+					.map_err(|err| err.to_string())?;
+			}
+		}
+
+		Ok(())
+	};
+
+	future::timeout(config.health_check_timeout, check)
+		.await
+		.map_err(|err| err.to_string())?
+}
+
+/// If the input has no `proxy`, and its context has a proxy pool, pick the
+/// proxy of a verification on `domain`, and start the pool's health check
+/// in the background if it's due.
+pub(super) fn rotate_proxy(
+	domain: &str,
+	input: &CheckEmailInput,
+) -> Option<(usize, CheckEmailInputProxy)> {
+	if input.proxy.is_some() {
+		return None;
+	}
+
+	let pool = input.context.proxy_pool()?;
+	pool.health_check_if_due();
+	pool.select(domain)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io;
+
+	#[test]
+	fn should_skip_dead_proxies() {
+		let proxies = vec![
+			CheckEmailInputProxy {
+				host: "proxy1.example.org".into(),
+				port: 1080,
+				..Default::default()
+			},
+			CheckEmailInputProxy {
+				host: "proxy2.example.org".into(),
+				port: 1080,
+				..Default::default()
+			},
+		];
+		let pool = ProxyPool::new(ProxyPoolConfig {
+			max_consecutive_failures: 2,
+			..ProxyPoolConfig::new(proxies)
+		});
+		assert_eq!(pool.select("example.org").unwrap().0, 0);
+		assert_eq!(pool.select("example.org").unwrap().0, 1);
+
+		let refused = SmtpError::SmtpError(AsyncSmtpError::Io(ProxyHandshakeError::wrap(
+			io::Error::from(io::ErrorKind::ConnectionRefused),
+		)));
+		pool.record::<()>(0, Err(&refused));
+		pool.record::<()>(0, Err(&refused));
+		pool.record(1, Ok(()));
+
+		assert_eq!(pool.select("example.org").unwrap().0, 1);
+		assert_eq!(pool.select("example.org").unwrap().0, 1);
+		let stats = pool.stats();
+		assert_eq!((stats[0].failures, stats[0].healthy), (2, false));
+		assert_eq!((stats[1].verifications, stats[1].healthy), (1, true));
+	}
+
+	#[test]
+	fn should_only_attribute_handshake_errors_to_proxies() {
+		let io_error = |err: io::Error| SmtpError::SmtpError(AsyncSmtpError::Io(err));
+
+		assert!(is_proxy_error(&io_error(ProxyHandshakeError::wrap(
+			io::Error::from(io::ErrorKind::TimedOut)
+		))));
+		assert!(is_proxy_error(&io_error(io::Error::new(
+			io::ErrorKind::ConnectionRefused,
+			AsyncSmtpError::Socks5Error(fast_socks5::SocksError::ArgumentInputError("")),
+		))));
+		assert!(!is_proxy_error(&io_error(io::Error::from(
+			io::ErrorKind::TimedOut
+		))));
+		assert!(!is_proxy_error(&io_error(io::Error::from(
+			io::ErrorKind::ConnectionReset
+		))));
+	}
+}
@@ -0,0 +1,255 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rate limiting of the `RCPT TO` probes sent to each MX host or provider,
+//! shared by all the verifications of a context, so that concurrent
+//! verifications don't get the sending IP throttled.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_std::task;
+use serde::{Deserialize, Serialize};
+
+/// What the rate limits apply to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitScope {
+	/// Each MX host, e.g. "alt1.aspmx.l.google.com".
+	#[default]
+	MxHost,
+	/// The registrable domain of the MX host, according to the Public
+	/// Suffix List, e.g. "google.com" for all of Google's MX hosts, which
+	/// approximates the provider. Hosts under different registrable domains
+	/// of the same public suffix, e.g. "mx.example.co.uk" and
+	/// "mx.example.org.uk", don't share a limit.
+	Provider,
+}
+
+/// Configuration of the rate limiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+	/// Maximum number of `RCPT TO` probes per `period`, for each MX host or
+	/// provider. A limit of zero disables the rate limit.
+	pub max_probes: usize,
+	pub period: Duration,
+	pub scope: RateLimitScope,
+	/// Limits overriding `max_probes` for specific MX hosts or providers,
+	/// depending on `scope`, e.g. "google.com". An override of zero disables
+	/// the rate limit of that MX host or provider.
+	pub overrides: HashMap<String, usize>,
+}
+
+impl RateLimitConfig {
+	/// Allow `max_probes` probes per `period` for each MX host.
+	pub fn new(max_probes: usize, period: Duration) -> Self {
+		RateLimitConfig {
+			max_probes,
+			period,
+			scope: RateLimitScope::default(),
+			overrides: HashMap::new(),
+		}
+	}
+}
+
+/// Counters of the rate limiter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimitStats {
+	/// Number of probes which went through the rate limiter.
+	pub probes: u64,
+	/// Number of probes which had to wait for the rate limit.
+	pub delayed_probes: u64,
+}
+
+/// A sliding-window rate limiter of the `RCPT TO` probes.
+pub(crate) struct RateLimiter {
+	config: RateLimitConfig,
+	/// Times of the probes of the current window, by key.
+	probes: Mutex<HashMap<String, VecDeque<Instant>>>,
+	total: AtomicU64,
+	delayed: AtomicU64,
+}
+
+impl fmt::Debug for RateLimiter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("RateLimiter")
+			.field("config", &self.config)
+			.field("stats", &self.stats())
+			.finish()
+	}
+}
+
+impl RateLimiter {
+	pub fn new(config: RateLimitConfig) -> Self {
+		RateLimiter {
+			config,
+			probes: Mutex::new(HashMap::new()),
+			total: AtomicU64::new(0),
+			delayed: AtomicU64::new(0),
+		}
+	}
+
+	/// The key of the rate limit applying to host.
+	pub fn key(&self, host: &str) -> String {
+		let host = host.trim_end_matches('.').to_lowercase();
+		match self.config.scope {
+			RateLimitScope::MxHost => host,
+			RateLimitScope::Provider => match psl::domain_str(&host) {
+				Some(domain) => domain.to_string(),
+				None => host,
+			},
+		}
+	}
+
+	/// Wait until a probe to `key` is allowed, and count it.
+	pub async fn acquire(&self, key: &str) {
+		self.total.fetch_add(1, Ordering::Relaxed);
+		let mut delayed = false;
+		while let Some(wait) = self.try_acquire(key, Instant::now()) {
+			if !delayed {
+				self.delayed.fetch_add(1, Ordering::Relaxed);
+				delayed = true;
+			}
+			task::sleep(wait).await;
+		}
+	}
+
+	/// Count a probe to `key` at `now` if allowed, otherwise return how long
+	/// to wait until the oldest probe of the window expires.
+	fn try_acquire(&self, key: &str, now: Instant) -> Option<Duration> {
+		let max_probes = self
+			.config
+			.overrides
+			.get(key)
+			.copied()
+			.unwrap_or(self.config.max_probes);
+		if max_probes == 0 {
+			return None;
+		}
+
+		let period = self.config.period;
+		let mut probes = self
+			.probes
+			.lock()
+			.expect("Rate limiter lock is not poisoned. qed.");
+		// Drop the windows whose probes all expired, so that the hosts we
+		// stopped probing don't pile up.
+		probes.retain(|_, window| {
+			window
+				.back()
+				.is_some_and(|probe| now.duration_since(*probe) < period)
+		});
+		let window = probes.entry(key.to_string()).or_default();
+		while window
+			.front()
+			.is_some_and(|probe| now.duration_since(*probe) >= period)
+		{
+			window.pop_front();
+		}
+
+		if window.len() < max_probes {
+			window.push_back(now);
+			return None;
+		}
+
+		let oldest = *window.front().expect("Window is full, so not empty. qed.");
+		Some((oldest + period).saturating_duration_since(now))
+	}
+
+	pub fn stats(&self) -> RateLimitStats {
+		RateLimitStats {
+			probes: self.total.load(Ordering::Relaxed),
+			delayed_probes: self.delayed.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_limit_probes_per_window() {
+		let mut config = RateLimitConfig::new(2, Duration::from_secs(60));
+		config.scope = RateLimitScope::Provider;
+		config.overrides.insert("outlook.com".into(), 1);
+		let limiter = RateLimiter::new(config);
+		let google = limiter.key("alt1.aspmx.l.google.com.");
+		let outlook = limiter.key("example-org.mail.protection.outlook.com.");
+		assert_eq!(google, "google.com");
+
+		let start = Instant::now();
+		assert_eq!(limiter.try_acquire(&google, start), None);
+		assert_eq!(
+			limiter.try_acquire(&google, start + Duration::from_secs(10)),
+			None
+		);
+		assert_eq!(
+			limiter.try_acquire(&google, start + Duration::from_secs(20)),
+			Some(Duration::from_secs(40))
+		);
+		assert_eq!(
+			limiter.try_acquire(&google, start + Duration::from_secs(60)),
+			None
+		);
+
+		assert_eq!(limiter.try_acquire(&outlook, start), None);
+		assert!(limiter.try_acquire(&outlook, start).is_some());
+	}
+
+	#[test]
+	fn should_key_providers_by_registrable_domain() {
+		let mut config = RateLimitConfig::new(2, Duration::from_secs(60));
+		config.scope = RateLimitScope::Provider;
+		let limiter = RateLimiter::new(config);
+
+		assert_eq!(limiter.key("mx1.example.co.uk."), "example.co.uk");
+		assert_eq!(limiter.key("MX.Other.co.uk"), "other.co.uk");
+		assert_eq!(limiter.key("localhost"), "localhost");
+	}
+
+	#[test]
+	fn should_not_limit_zero_limits() {
+		let mut config = RateLimitConfig::new(0, Duration::from_secs(60));
+		config.overrides.insert("mx.example.org".into(), 0);
+		let limiter = RateLimiter::new(config);
+
+		let start = Instant::now();
+		for key in ["mx.example.org", "mx.example.com"] {
+			for _ in 0..3 {
+				assert_eq!(limiter.try_acquire(key, start), None);
+			}
+		}
+	}
+
+	#[test]
+	fn should_drop_expired_windows() {
+		let limiter = RateLimiter::new(RateLimitConfig::new(1, Duration::from_secs(60)));
+
+		let start = Instant::now();
+		assert_eq!(limiter.try_acquire("mx1.example.org", start), None);
+		assert_eq!(
+			limiter.try_acquire("mx2.example.org", start + Duration::from_secs(60)),
+			None
+		);
+
+		let probes = limiter.probes.lock().unwrap();
+		assert_eq!(probes.keys().collect::<Vec<_>>(), vec!["mx2.example.org"]);
+	}
+}
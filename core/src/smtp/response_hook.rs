@@ -0,0 +1,35 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Hooks into the raw responses of the SMTP servers, for provider-specific
+//! parsing, e.g. of internal queue IDs or localized rejection reasons.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// Called with each response of the SMTP servers. Set it on the context
+/// with `CheckEmailContext::with_smtp_response_hook`.
+pub trait SmtpResponseHook: Debug + Send + Sync {
+	/// Called with the response of `host` to the command with `verb`, e.g.
+	/// "RCPT", or with its greeting, with "CONNECT". The greeting and the
+	/// responses to EHLO and STARTTLS aren't passed through SOCKS5 proxies,
+	/// as async-smtp's transport handles them. `lines` are the raw response lines, with their code, e.g.
+	/// `["550-5.1.1 User unknown", "550 5.1.1 Try again later"]`.
+	///
+	/// The returned annotations are added to the `annotations` of the
+	/// `SmtpDetails`, overwriting the ones with the same key.
+	fn on_response(&self, host: &str, verb: &str, lines: &[String]) -> BTreeMap<String, String>;
+}
@@ -0,0 +1,285 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Whether and when to retry a failed SMTP verification.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use async_smtp::smtp::error::Error as AsyncSmtpError;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::Name;
+
+use super::{parser, SmtpError};
+use crate::mx::{is_antispam_mx, MxDetails};
+use crate::util::input_output::CheckEmailInput;
+
+/// Class of the error of a failed SMTP attempt, which retry policies use to
+/// decide whether to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+	/// The server greylisted us, i.e. asked to retry later.
+	Greylisted,
+	/// The connection failed, was reset or timed out.
+	Connection,
+	/// An SMTP error response whose meaning is unknown.
+	Unknown,
+	/// The provider answered with a CAPTCHA or bot challenge: it blocks us
+	/// for now, and tells nothing about the address.
+	Challenged,
+}
+
+impl ErrorClass {
+	/// Classify err, or None if retrying wouldn't change the result, e.g. if
+	/// the server rejected the recipient, or for the HTTP API errors.
+	pub fn of(err: &SmtpError) -> Option<Self> {
+		match err {
+			// The server's extensions won't change on retry, and tarpits
+			// would be as slow again.
+			SmtpError::SmtpUtf8NotSupported | SmtpError::Tarpitted(_) => None,
+			SmtpError::ChallengeRequired(_) => Some(ErrorClass::Challenged),
+			_ if parser::is_err_greylisted(err) => Some(ErrorClass::Greylisted),
+			SmtpError::SocksError(_)
+			| SmtpError::TimeoutError(_)
+			| SmtpError::SmtpError(AsyncSmtpError::Io(_))
+			| SmtpError::SmtpError(AsyncSmtpError::Resolution) => Some(ErrorClass::Connection),
+			SmtpError::SmtpError(_) if err.get_description().is_none() => Some(ErrorClass::Unknown),
+			_ => None,
+		}
+	}
+}
+
+/// Which MX host a failed SMTP verification is retried on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryStrategy {
+	/// Retry on the same MX host.
+	#[default]
+	SameHost,
+	/// Retry connection errors and unknown error responses on the next MX
+	/// host of the domain, by preference, wrapping around. Greylisted
+	/// attempts are still retried on the same host, as greylisting starts
+	/// over on another one.
+	OtherHost,
+}
+
+/// Decides whether to retry a failed SMTP verification, and after which
+/// delay. Set it on the context with `CheckEmailContext::with_retry_policy`.
+pub trait RetryPolicy: Debug + Send + Sync {
+	/// Delay before the next attempt, after `attempt` attempts, counting
+	/// from 1, the last of which failed with an error of `class`. None to
+	/// give up.
+	fn retry_delay(&self, attempt: usize, class: ErrorClass) -> Option<Duration>;
+}
+
+/// Retry after a fixed delay.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FixedRetry {
+	/// Maximum number of attempts, including the first one.
+	pub max_attempts: usize,
+	pub delay: Duration,
+	/// The error classes to retry on.
+	pub retry_on: Vec<ErrorClass>,
+}
+
+impl RetryPolicy for FixedRetry {
+	fn retry_delay(&self, attempt: usize, class: ErrorClass) -> Option<Duration> {
+		if attempt >= self.max_attempts || !self.retry_on.contains(&class) {
+			return None;
+		}
+
+		Some(self.delay)
+	}
+}
+
+/// Retry after a delay which doubles at each attempt, up to `max_delay`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ExponentialBackoff {
+	/// Maximum number of attempts, including the first one.
+	pub max_attempts: usize,
+	/// Delay before the second attempt.
+	pub initial_delay: Duration,
+	pub max_delay: Duration,
+	/// Pick each delay randomly between zero and the computed one ("full
+	/// jitter"), so that verifications failing together don't retry
+	/// together.
+	pub jitter: bool,
+	/// The error classes to retry on.
+	pub retry_on: Vec<ErrorClass>,
+}
+
+impl RetryPolicy for ExponentialBackoff {
+	fn retry_delay(&self, attempt: usize, class: ErrorClass) -> Option<Duration> {
+		if attempt >= self.max_attempts || !self.retry_on.contains(&class) {
+			return None;
+		}
+
+		let factor = 2u32.saturating_pow(attempt.saturating_sub(1) as u32);
+		let delay = self
+			.initial_delay
+			.checked_mul(factor)
+			.map_or(self.max_delay, |delay| delay.min(self.max_delay));
+		if self.jitter {
+			Some(delay.mul_f64(rand::thread_rng().gen()))
+		} else {
+			Some(delay)
+		}
+	}
+}
+
+/// Delay before retrying after `attempt` attempts, the last of which failed
+/// with err, and the error's class. Uses the context's retry policy if set,
+/// otherwise the input's `retries` and `greylisting_retry_delay`.
+pub(super) fn retry_delay(
+	input: &CheckEmailInput,
+	attempt: usize,
+	err: &SmtpError,
+) -> Option<(ErrorClass, Duration)> {
+	let class = ErrorClass::of(err)?;
+	let delay = match input.context.retry_policy() {
+		Some(policy) => policy.retry_delay(attempt, class)?,
+		None if attempt >= input.retries => return None,
+		None if class == ErrorClass::Greylisted => {
+			input.greylisting_retry_delay.unwrap_or_default()
+		}
+		None => Duration::ZERO,
+	};
+
+	Some((class, delay))
+}
+
+/// The MX host to retry on, after an error of `class` on host, as decided
+/// by the input's `retry_strategy`. Falls back to host if the domain has no
+/// other MX host in `mx`, its MX lookup.
+pub(super) fn retry_host(
+	host: &Name,
+	mx: &MxDetails,
+	class: ErrorClass,
+	input: &CheckEmailInput,
+) -> Name {
+	if input.retry_strategy == RetryStrategy::SameHost || class == ErrorClass::Greylisted {
+		return host.clone();
+	}
+
+	let hosts = match &mx.lookup {
+		Ok(lookup) => {
+			let mut records = lookup.iter().collect::<Vec<_>>();
+			records.sort_by_key(|record| record.preference());
+			records
+				.into_iter()
+				.map(|record| record.exchange().clone())
+				.collect()
+		}
+		Err(_) => vec![],
+	};
+
+	next_host(host, hosts)
+}
+
+/// The host following host in hosts, wrapping around, skipping the antispam
+/// MX hosts. The first one if host isn't in hosts.
+fn next_host(host: &Name, hosts: Vec<Name>) -> Name {
+	let hosts = hosts
+		.into_iter()
+		.filter(|candidate| !is_antispam_mx(candidate))
+		.collect::<Vec<_>>();
+	let next = match hosts.iter().position(|candidate| candidate == host) {
+		Some(index) => hosts.get((index + 1) % hosts.len()),
+		None => hosts.first(),
+	};
+
+	next.cloned().unwrap_or_else(|| host.clone())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_std_resolver::lookup::{Lookup, MxLookup};
+	use std::sync::Arc;
+	use trust_dns_proto::op::Query;
+	use trust_dns_proto::rr::{rdata::MX, RData, Record, RecordType};
+
+	#[test]
+	fn should_back_off_exponentially() {
+		let policy = ExponentialBackoff {
+			max_attempts: 4,
+			initial_delay: Duration::from_secs(1),
+			max_delay: Duration::from_secs(3),
+			jitter: false,
+			retry_on: vec![ErrorClass::Connection],
+		};
+
+		let delays = (1..=4)
+			.map(|attempt| policy.retry_delay(attempt, ErrorClass::Connection))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			delays,
+			vec![
+				Some(Duration::from_secs(1)),
+				Some(Duration::from_secs(2)),
+				Some(Duration::from_secs(3)),
+				None
+			]
+		);
+		assert_eq!(policy.retry_delay(1, ErrorClass::Greylisted), None);
+	}
+
+	#[test]
+	fn should_pick_next_host() {
+		let hosts = ["mx1.example.org.", "mx2.example.org.", "mx3.example.org."]
+			.iter()
+			.map(|host| Name::from_ascii(host).unwrap())
+			.collect::<Vec<_>>();
+
+		assert_eq!(next_host(&hosts[0], hosts.clone()), hosts[1]);
+		assert_eq!(next_host(&hosts[2], hosts.clone()), hosts[0]);
+		let other = Name::from_ascii("mx.example.com.").unwrap();
+		assert_eq!(next_host(&other, hosts.clone()), hosts[0]);
+		assert_eq!(next_host(&other, vec![]), other);
+	}
+
+	#[test]
+	fn should_retry_on_other_mx_host() {
+		let domain = Name::from_ascii("example.org.").unwrap();
+		let records = [(20, "mx2.example.org."), (10, "mx1.example.org.")]
+			.iter()
+			.map(|(preference, host)| {
+				let mx = MX::new(*preference, Name::from_ascii(host).unwrap());
+				Record::from_rdata(domain.clone(), 300, RData::MX(mx))
+			})
+			.collect::<Vec<_>>();
+		let query = Query::query(domain, RecordType::MX);
+		let mx = MxDetails::from(MxLookup::from(Lookup::new_with_max_ttl(
+			query,
+			Arc::from(records),
+		)));
+		let mx1 = Name::from_ascii("mx1.example.org.").unwrap();
+		let mx2 = Name::from_ascii("mx2.example.org.").unwrap();
+
+		let mut input = CheckEmailInput::default();
+		assert_eq!(retry_host(&mx1, &mx, ErrorClass::Connection, &input), mx1);
+		input.set_retry_strategy(RetryStrategy::OtherHost);
+		assert_eq!(retry_host(&mx1, &mx, ErrorClass::Connection, &input), mx2);
+		assert_eq!(retry_host(&mx2, &mx, ErrorClass::Connection, &input), mx1);
+		assert_eq!(retry_host(&mx1, &mx, ErrorClass::Greylisted, &input), mx1);
+		assert_eq!(
+			retry_host(&mx1, &MxDetails::default(), ErrorClass::Connection, &input),
+			mx1
+		);
+	}
+}
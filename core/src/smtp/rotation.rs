@@ -0,0 +1,98 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Rotation of the EHLO name and the `MAIL FROM` address, since large
+//! providers fingerprint and block repeated identical values.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::util::input_output::{CheckEmailInput, RotationPolicy};
+
+/// Index of the value to use for `domain`, for a list of `len` values.
+/// `next` returns the next value of the round-robin counter.
+pub(super) fn rotation_index(
+	policy: RotationPolicy,
+	len: usize,
+	domain: &str,
+	next: impl FnOnce() -> usize,
+) -> usize {
+	let index = match policy {
+		RotationPolicy::RoundRobin => next(),
+		RotationPolicy::Random => rand::random(),
+		RotationPolicy::StickyPerDomain => {
+			// `DefaultHasher::new` always uses the same keys, so a domain
+			// keeps the same values across verifications.
+			let mut hasher = DefaultHasher::new();
+			domain.to_lowercase().hash(&mut hasher);
+			hasher.finish() as usize
+		}
+	};
+
+	index % len
+}
+
+/// Pick the EHLO name and `MAIL FROM` address of a verification on
+/// `domain`, among the input's `hello_names` and `from_emails`, following
+/// its `identity_rotation`. Returns None if both lists are empty, i.e. if
+/// the input's `hello_name` and `from_email` should be used.
+pub(super) fn rotate_identity(domain: &str, input: &CheckEmailInput) -> Option<(String, String)> {
+	if input.hello_names.is_empty() && input.from_emails.is_empty() {
+		return None;
+	}
+
+	let len = input.hello_names.len().max(input.from_emails.len());
+	let index = rotation_index(input.identity_rotation, len, domain, || {
+		input.context.next_rotation()
+	});
+	let pick = |values: &[String], default: &String| {
+		if values.is_empty() {
+			default.clone()
+		} else {
+			values[index % values.len()].clone()
+		}
+	};
+
+	Some((
+		pick(&input.hello_names, &input.hello_name),
+		pick(&input.from_emails, &input.from_email),
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_rotate_identity() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		assert_eq!(rotate_identity("example.org", &input), None);
+
+		input
+			.set_hello_names(vec!["a.example.org".into(), "b.example.org".into()])
+			.set_from_emails(vec!["me@example.org".into()]);
+		let first = rotate_identity("example.org", &input).unwrap();
+		let second = rotate_identity("example.org", &input).unwrap();
+		assert_ne!(first.0, second.0);
+		assert_eq!(first.1, "me@example.org");
+
+		input.set_identity_rotation(RotationPolicy::StickyPerDomain);
+		assert_eq!(
+			rotate_identity("example.org", &input),
+			rotate_identity("EXAMPLE.org", &input)
+		);
+	}
+}
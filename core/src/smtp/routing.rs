@@ -0,0 +1,519 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Routing of the SMTP verifications to a verification method, e.g. a
+//! provider's HTTP API, according to the MX host or the email domain.
+
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+
+use async_smtp::EmailAddress;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::error::SmtpError;
+use super::gmx::is_gmx_host;
+use super::icloud::is_icloud_host;
+use super::mailru::is_mailru_host;
+use super::microsoft::{microsoft_account_kind, MicrosoftAccountKind};
+use super::proton::is_proton_host;
+use super::yandex::is_yandex_host;
+use super::SmtpDetails;
+use crate::util::domain_policy::{skipped_pattern, HostPattern};
+use crate::util::input_output::CheckEmailInput;
+
+/// A custom verification method, for providers whose SMTP servers don't
+/// tell whether mailboxes exist. Register it on the context with
+/// `CheckEmailContext::with_provider_verifier`.
+#[async_trait]
+pub trait ProviderVerifier: Debug + Send + Sync {
+	/// Verify `to_email`, whose MX host is `host`. Returns None if the
+	/// result is ambiguous, in which case the other methods, and then a
+	/// regular SMTP session, are tried.
+	async fn verify(
+		&self,
+		to_email: &EmailAddress,
+		host: &str,
+		input: &CheckEmailInput,
+	) -> Result<Option<SmtpDetails>, SmtpError>;
+}
+
+/// How an email is verified, instead of a regular SMTP session. Written as
+/// "smtp", "yahoo_api", "gmail_api", "microsoft365_api", "proton_api",
+/// "icloud_api", "mailru_api", "yandex_api", "gmx_api",
+/// "headless:<webdriver endpoint>", "custom:<pattern>" or "skip".
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum VerificationMethod {
+	/// A regular SMTP session, which ends a chain of methods.
+	Smtp,
+	/// Yahoo's signup API, falling back to the input's `yahoo_use_headless`
+	/// if the API breaks.
+	YahooApi,
+	/// Gmail's API.
+	GmailApi,
+	/// Microsoft 365's OneDrive API, falling back to SMTP if its result is
+	/// ambiguous.
+	Microsoft365Api,
+	/// Proton's address availability API.
+	ProtonApi,
+	/// Apple's Apple ID validation API.
+	IcloudApi,
+	/// Mail.ru's user API.
+	MailruApi,
+	/// Yandex's login validation API.
+	YandexApi,
+	/// The availability API of the GMX and WEB.DE signup pages.
+	GmxApi,
+	/// A headless browser, driven by the WebDriver endpoint, on the page
+	/// matching the MX host: Hotmail's password recovery page, Microsoft
+	/// 365's login page or Yahoo's password recovery page. Other hosts, and
+	/// ambiguous results, fall back to SMTP.
+	#[cfg(feature = "headless")]
+	Headless(String),
+	/// The context's provider verifier registered with `pattern`, see
+	/// `CheckEmailContext::with_provider_verifier`.
+	Custom(String),
+	/// Don't verify the email, and report it as skipped. Only meaningful
+	/// as the first method of a chain.
+	Skip,
+}
+
+impl FromStr for VerificationMethod {
+	type Err = String;
+
+	fn from_str(method: &str) -> Result<Self, Self::Err> {
+		match method {
+			"smtp" => Ok(VerificationMethod::Smtp),
+			"yahoo_api" => Ok(VerificationMethod::YahooApi),
+			"gmail_api" => Ok(VerificationMethod::GmailApi),
+			"microsoft365_api" => Ok(VerificationMethod::Microsoft365Api),
+			"proton_api" => Ok(VerificationMethod::ProtonApi),
+			"icloud_api" => Ok(VerificationMethod::IcloudApi),
+			"mailru_api" => Ok(VerificationMethod::MailruApi),
+			"yandex_api" => Ok(VerificationMethod::YandexApi),
+			"gmx_api" => Ok(VerificationMethod::GmxApi),
+			"skip" => Ok(VerificationMethod::Skip),
+			#[cfg(feature = "headless")]
+			method if method.starts_with("headless:") => Ok(VerificationMethod::Headless(
+				method["headless:".len()..].to_string(),
+			)),
+			method if method.starts_with("custom:") => Ok(VerificationMethod::Custom(
+				method["custom:".len()..].to_string(),
+			)),
+			method => Err(format!("Unknown verification method: {method}")),
+		}
+	}
+}
+
+impl TryFrom<String> for VerificationMethod {
+	type Error = String;
+
+	fn try_from(method: String) -> Result<Self, Self::Error> {
+		method.parse()
+	}
+}
+
+impl fmt::Display for VerificationMethod {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			VerificationMethod::Smtp => write!(f, "smtp"),
+			VerificationMethod::YahooApi => write!(f, "yahoo_api"),
+			VerificationMethod::GmailApi => write!(f, "gmail_api"),
+			VerificationMethod::Microsoft365Api => write!(f, "microsoft365_api"),
+			VerificationMethod::ProtonApi => write!(f, "proton_api"),
+			VerificationMethod::IcloudApi => write!(f, "icloud_api"),
+			VerificationMethod::MailruApi => write!(f, "mailru_api"),
+			VerificationMethod::YandexApi => write!(f, "yandex_api"),
+			VerificationMethod::GmxApi => write!(f, "gmx_api"),
+			#[cfg(feature = "headless")]
+			VerificationMethod::Headless(webdriver) => write!(f, "headless:{webdriver}"),
+			VerificationMethod::Custom(pattern) => write!(f, "custom:{pattern}"),
+			VerificationMethod::Skip => write!(f, "skip"),
+		}
+	}
+}
+
+impl From<VerificationMethod> for String {
+	fn from(method: VerificationMethod) -> Self {
+		method.to_string()
+	}
+}
+
+/// Route the emails whose MX host or domain matches `pattern` to a chain
+/// of `methods`, tried in order: the next one is tried if a method fails
+/// or its result is ambiguous. `smtp` is a regular SMTP session, anywhere
+/// in the chain. The last method's error is returned, and its ambiguous
+/// results fall back to SMTP. A route takes precedence over the input's
+/// `skipped_domains`. The patterns have the same syntax as them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProviderRoute {
+	pub pattern: HostPattern,
+	pub methods: Vec<VerificationMethod>,
+}
+
+impl ProviderRoute {
+	pub fn new(pattern: HostPattern, method: VerificationMethod) -> Self {
+		ProviderRoute {
+			pattern,
+			methods: vec![method],
+		}
+	}
+
+	/// Try `method` after the route's methods.
+	pub fn with_fallback(mut self, method: VerificationMethod) -> Self {
+		self.methods.push(method);
+		self
+	}
+}
+
+/// A method of the chain which didn't produce the verdict.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailedMethod {
+	pub method: VerificationMethod,
+	/// The method's error, or "ambiguous result".
+	pub reason: String,
+}
+
+/// The methods to try, in order, to verify an email of `domain` on the MX
+/// host, before a regular SMTP session. Empty if only a regular SMTP
+/// session is needed. The first of the input's `provider_routes` matching
+/// the host or the domain wins, otherwise the first of the context's
+/// provider verifiers matching them is tried, then the methods enabled by
+/// the `*_use_api` and `*_use_headless` flags.
+///
+/// Errors with `SmtpError::SkippedDomain` if the email isn't to be verified.
+/// The MX hosts of iCloud, GMX and WEB.DE, skipped by default, aren't when
+/// the input's `icloud_use_api` or `gmx_use_api` is set.
+pub(super) fn verification_methods(
+	host_lowercase: &str,
+	domain: &str,
+	input: &CheckEmailInput,
+) -> Result<Vec<VerificationMethod>, SmtpError> {
+	let matches =
+		|pattern: &HostPattern| pattern.matches(host_lowercase) || pattern.matches(domain);
+	let route = input
+		.provider_routes
+		.iter()
+		.find(|route| matches(&route.pattern));
+	if let Some(route) = route {
+		return match route.methods.as_slice() {
+			[VerificationMethod::Skip, ..] => Err(skipped_domain(domain, &route.pattern)),
+			[] | [VerificationMethod::Smtp] => Ok(vec![]),
+			methods => Ok(methods.to_vec()),
+		};
+	}
+
+	let uses_api = (input.icloud_use_api && is_icloud_host(host_lowercase, domain))
+		|| (input.gmx_use_api && is_gmx_host(host_lowercase, domain));
+	if let (Some(pattern), false) = (skipped_pattern(host_lowercase, input), uses_api) {
+		return Err(skipped_domain(domain, pattern));
+	}
+
+	let mut methods = vec![];
+	if let Some((pattern, _)) = input
+		.context
+		.provider_verifiers()
+		.iter()
+		.find(|(pattern, _)| matches(pattern))
+	{
+		methods.push(VerificationMethod::Custom(pattern.to_string()));
+	}
+	methods.extend(default_methods(host_lowercase, domain, input));
+
+	Ok(methods)
+}
+
+fn skipped_domain(domain: &str, pattern: &HostPattern) -> SmtpError {
+	SmtpError::SkippedDomain(format!(
+		"Reacher currently cannot verify emails from @{domain}, skipped by \"{pattern}\""
+	))
+}
+
+/// The methods enabled by the input's `*_use_api` and `*_use_headless`
+/// flags for the MX host.
+fn default_methods(
+	host_lowercase: &str,
+	domain: &str,
+	input: &CheckEmailInput,
+) -> Vec<VerificationMethod> {
+	let mut methods = vec![];
+	// FIXME Is this `contains` too lenient?
+	if input.yahoo_use_api && host_lowercase.contains("yahoo") {
+		methods.push(VerificationMethod::YahooApi);
+	}
+	if input.gmail_use_api && host_lowercase.ends_with(".google.com.") {
+		methods.push(VerificationMethod::GmailApi);
+	}
+	if input.proton_use_api && is_proton_host(host_lowercase, domain) {
+		methods.push(VerificationMethod::ProtonApi);
+	}
+	if input.icloud_use_api && is_icloud_host(host_lowercase, domain) {
+		methods.push(VerificationMethod::IcloudApi);
+	}
+	if input.mailru_use_api && is_mailru_host(host_lowercase, domain) {
+		methods.push(VerificationMethod::MailruApi);
+	}
+	if input.yandex_use_api && is_yandex_host(host_lowercase, domain) {
+		methods.push(VerificationMethod::YandexApi);
+	}
+	if input.gmx_use_api && is_gmx_host(host_lowercase, domain) {
+		methods.push(VerificationMethod::GmxApi);
+	}
+	let microsoft = microsoft_account_kind(host_lowercase, domain);
+	#[cfg(feature = "headless")]
+	let microsoft_auto = input.microsoft_use_headless.is_some();
+	#[cfg(not(feature = "headless"))]
+	let microsoft_auto = false;
+	let is_microsoft365 = microsoft == Some(MicrosoftAccountKind::Microsoft365);
+	if is_microsoft365 && (input.microsoft365_use_api || microsoft_auto) {
+		methods.push(VerificationMethod::Microsoft365Api);
+	}
+	#[cfg(feature = "headless")]
+	if let (true, Some(webdriver)) = (
+		is_microsoft365,
+		input
+			.microsoft365_use_headless
+			.as_ref()
+			.or(input.microsoft_use_headless.as_ref()),
+	) {
+		methods.push(VerificationMethod::Headless(webdriver.clone()));
+	}
+	// The password recovery page do not always work with Microsoft 365
+	// addresses. So we only test with @hotmail and @outlook addresses.
+	// ref: https://github.com/reacherhq/check-if-email-exists/issues/1185
+	#[cfg(feature = "headless")]
+	if let (Some(MicrosoftAccountKind::Consumer), Some(webdriver)) = (
+		microsoft,
+		input
+			.hotmail_use_headless
+			.as_ref()
+			.or(input.microsoft_use_headless.as_ref()),
+	) {
+		methods.push(VerificationMethod::Headless(webdriver.clone()));
+	}
+
+	methods
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mx::MxDetails;
+	use crate::smtp::check_smtp;
+	use crate::CheckEmailContext;
+	use std::str::FromStr;
+	use std::sync::Arc;
+	use trust_dns_proto::rr::Name;
+
+	#[test]
+	fn should_route_emails() {
+		let mut input = CheckEmailInput::default();
+		input.set_gmail_use_api(true).set_provider_routes(vec![
+			ProviderRoute::new(
+				"*.mail.protection.outlook.com".parse().unwrap(),
+				VerificationMethod::ProtonApi,
+			),
+			ProviderRoute::new("acme.com".parse().unwrap(), VerificationMethod::Skip),
+			ProviderRoute::new("gmail.com".parse().unwrap(), VerificationMethod::Smtp)
+				.with_fallback(VerificationMethod::YahooApi),
+			ProviderRoute::new("yahoo.com".parse().unwrap(), VerificationMethod::Smtp),
+			ProviderRoute::new("icloud.com".parse().unwrap(), VerificationMethod::IcloudApi),
+		]);
+
+		assert_eq!(
+			verification_methods("acme-com.mail.protection.outlook.com.", "acme.com", &input)
+				.unwrap(),
+			vec![VerificationMethod::ProtonApi]
+		);
+		assert!(matches!(
+			verification_methods("mx.acme.com.", "acme.com", &input),
+			Err(SmtpError::SkippedDomain(message)) if message.contains("\"acme.com\"")
+		));
+		assert_eq!(
+			verification_methods("gmail-smtp-in.l.google.com.", "gmail.com", &input).unwrap(),
+			vec![VerificationMethod::Smtp, VerificationMethod::YahooApi]
+		);
+		assert!(
+			verification_methods("mta5.am0.yahoodns.net.", "yahoo.com", &input)
+				.unwrap()
+				.is_empty()
+		);
+		// Routes take precedence over the skipped domains.
+		assert_eq!(
+			verification_methods("mx01.mail.icloud.com.", "icloud.com", &input).unwrap(),
+			vec![VerificationMethod::IcloudApi]
+		);
+		assert_eq!(
+			verification_methods("alt1.aspmx.l.google.com.", "example.org", &input).unwrap(),
+			vec![VerificationMethod::GmailApi]
+		);
+	}
+
+	#[cfg(feature = "headless")]
+	#[test]
+	fn should_route_microsoft_addresses() {
+		let webdriver = VerificationMethod::Headless("http://localhost:4444".into());
+		let mut input = CheckEmailInput::default();
+		input.set_microsoft_use_headless(Some("http://localhost:4444".into()));
+
+		assert_eq!(
+			verification_methods("eur.olc.protection.outlook.com.", "hotmail.fr", &input).unwrap(),
+			vec![webdriver.clone()]
+		);
+		assert_eq!(
+			verification_methods("acme-com.mail.protection.outlook.com.", "acme.com", &input)
+				.unwrap(),
+			vec![VerificationMethod::Microsoft365Api, webdriver]
+		);
+	}
+
+	/// Says that all the emails exist.
+	#[derive(Debug)]
+	struct AcceptAll;
+
+	#[async_trait]
+	impl ProviderVerifier for AcceptAll {
+		async fn verify(
+			&self,
+			_to_email: &EmailAddress,
+			_host: &str,
+			_input: &CheckEmailInput,
+		) -> Result<Option<SmtpDetails>, SmtpError> {
+			Ok(Some(SmtpDetails {
+				is_deliverable: true,
+				..Default::default()
+			}))
+		}
+	}
+
+	/// Fails to verify the emails.
+	#[derive(Debug)]
+	struct Unavailable;
+
+	#[async_trait]
+	impl ProviderVerifier for Unavailable {
+		async fn verify(
+			&self,
+			_to_email: &EmailAddress,
+			_host: &str,
+			_input: &CheckEmailInput,
+		) -> Result<Option<SmtpDetails>, SmtpError> {
+			Err(SmtpError::ProviderError("unavailable".into()))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_fall_back_along_chain() {
+		let mut input = CheckEmailInput::default();
+		input
+			.set_context(
+				CheckEmailContext::new()
+					.with_provider_verifier(
+						"unavailable.invalid".parse().unwrap(),
+						Arc::new(Unavailable),
+					)
+					.with_provider_verifier(
+						"accept-all.invalid".parse().unwrap(),
+						Arc::new(AcceptAll),
+					),
+			)
+			.set_provider_routes(vec![
+				ProviderRoute::new(
+					"mx.example.org".parse().unwrap(),
+					VerificationMethod::Custom("unavailable.invalid".into()),
+				)
+				.with_fallback(VerificationMethod::Custom("accept-all.invalid".into())),
+				ProviderRoute::new(
+					"mx.example.com".parse().unwrap(),
+					VerificationMethod::Custom("unavailable.invalid".into()),
+				),
+			]);
+		let to_email = EmailAddress::from_str("foo@example.org").unwrap();
+
+		let host = Name::from_str("mx.example.org.").unwrap();
+		let smtp_details = check_smtp(
+			&to_email,
+			&host,
+			25,
+			"example.org",
+			&MxDetails::default(),
+			&input,
+		)
+		.await
+		.unwrap();
+		assert!(smtp_details.is_deliverable);
+		assert_eq!(
+			smtp_details.verification_method,
+			Some(VerificationMethod::Custom("accept-all.invalid".into()))
+		);
+		assert_eq!(
+			smtp_details.failed_methods,
+			vec![FailedMethod {
+				method: VerificationMethod::Custom("unavailable.invalid".into()),
+				reason: "ProviderError(\"unavailable\")".into(),
+			}]
+		);
+
+		// The last method's error is the verdict.
+		let host = Name::from_str("mx.example.com.").unwrap();
+		assert!(matches!(
+			check_smtp(
+				&to_email,
+				&host,
+				25,
+				"example.org",
+				&MxDetails::default(),
+				&input
+			)
+			.await,
+			Err(SmtpError::ProviderError(_))
+		));
+	}
+
+	#[test]
+	fn should_route_to_provider_verifiers() {
+		let mut input = CheckEmailInput::default();
+		input.set_context(
+			CheckEmailContext::new()
+				.with_provider_verifier(".regional.example".parse().unwrap(), Arc::new(AcceptAll)),
+		);
+
+		assert_eq!(
+			verification_methods("mx1.regional.example.", "example.org", &input).unwrap(),
+			vec![VerificationMethod::Custom(".regional.example".into())]
+		);
+		assert!(
+			verification_methods("mx1.other.example.", "example.org", &input)
+				.unwrap()
+				.is_empty()
+		);
+	}
+
+	#[test]
+	fn should_parse_verification_methods() {
+		let route: ProviderRoute = serde_json::from_str(
+			r#"{"pattern": "re:^mx\\d\\.acme\\.com$", "methods": ["yahoo_api", "smtp"]}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			route.methods,
+			vec![VerificationMethod::YahooApi, VerificationMethod::Smtp]
+		);
+		assert!("carrier_pigeon".parse::<VerificationMethod>().is_err());
+	}
+}
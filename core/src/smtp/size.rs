@@ -0,0 +1,167 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Probe the message size limit of an SMTP server, using the SIZE extension
+//! (RFC 1870). No message is ever sent: the probe stops after `RCPT TO`.
+
+use async_smtp::smtp::commands::{MailCommand, RcptCommand, RsetCommand};
+use async_smtp::smtp::error::Error as AsyncSmtpError;
+use async_smtp::smtp::extension::MailParameter;
+use async_smtp::smtp::response::Response;
+use async_smtp::EmailAddress;
+use serde::{Deserialize, Serialize};
+
+use super::transcript::SmtpConnection;
+use super::SmtpError;
+
+/// Outcome of the SIZE probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SizeProbeResult {
+	/// The server accepted a message of the probed size.
+	Accepted,
+	/// The server rejected a message of the probed size.
+	Rejected,
+	/// The server doesn't advertise the SIZE extension, so no probe was
+	/// made.
+	NotSupported,
+}
+
+/// Details about the SIZE probe.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SizeProbe {
+	/// The message size, in bytes, sent in `MAIL FROM:<...> SIZE=`.
+	pub size: usize,
+	/// The maximum message size advertised by the server in its EHLO
+	/// response, if any. 0 means the server has no fixed limit.
+	pub max_size: Option<usize>,
+	pub result: SizeProbeResult,
+	/// The server's response, if it rejected the probe.
+	pub message: Option<String>,
+}
+
+/// Parse the SIZE extension from an EHLO response. Returns None if the
+/// extension is not advertised, and Some(None) if it's advertised without
+/// a maximum size.
+fn parse_size_extension(response: &Response) -> Option<Option<usize>> {
+	response.message.iter().find_map(|line| {
+		let mut words = line.split_whitespace();
+		match words.next() {
+			Some(keyword) if keyword.eq_ignore_ascii_case("SIZE") => {
+				Some(words.next().and_then(|size| size.parse().ok()))
+			}
+			_ => None,
+		}
+	})
+}
+
+/// Whether the SMTP error means the message is too big: 552 reply code, or
+/// 5.3.4 enhanced status code.
+fn is_too_big(err: &AsyncSmtpError) -> bool {
+	match err {
+		AsyncSmtpError::Permanent(response) => {
+			response.code.to_string() == "552"
+				|| response
+					.first_line()
+					.map(|line| line.contains("5.3.4"))
+					.unwrap_or(false)
+		}
+		_ => false,
+	}
+}
+
+/// Probe whether a message of `size` bytes from `from_email` to `to_email`
+/// would be accepted. The connection is left with an open mail transaction,
+/// which should be reset or closed by the caller.
+pub(crate) async fn probe_size(
+	smtp_transport: &mut SmtpConnection,
+	hello_name: &str,
+	from_email: EmailAddress,
+	to_email: &EmailAddress,
+	size: usize,
+) -> Result<SizeProbe, SmtpError> {
+	// Re-send EHLO to read the server's extensions, which also resets the
+	// current mail transaction.
+	let ehlo = smtp_transport
+		.hello(hello_name)
+		.await
+		.map_err(SmtpError::SmtpError)?;
+	let max_size = match parse_size_extension(&ehlo) {
+		Some(max_size) => max_size,
+		None => {
+			return Ok(SizeProbe {
+				size,
+				max_size: None,
+				result: SizeProbeResult::NotSupported,
+				message: None,
+			})
+		}
+	};
+
+	let rejected = |err: AsyncSmtpError| SizeProbe {
+		size,
+		max_size,
+		result: SizeProbeResult::Rejected,
+		message: Some(err.to_string()),
+	};
+
+	if let Err(err) = smtp_transport
+		.command(MailCommand::new(
+			Some(from_email),
+			vec![MailParameter::Size(size)],
+		))
+		.await
+	{
+		// Make sure the connection is in a clean state after the failed
+		// MAIL FROM.
+		let _ = smtp_transport.command(RsetCommand).await;
+		return Ok(rejected(err));
+	}
+
+	// Some servers only check the size against the recipient's quota.
+	match smtp_transport
+		.command(RcptCommand::new(to_email.clone(), vec![]))
+		.await
+	{
+		Err(err) if is_too_big(&err) => Ok(rejected(err)),
+		_ => Ok(SizeProbe {
+			size,
+			max_size,
+			result: SizeProbeResult::Accepted,
+			message: None,
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+
+	#[test]
+	fn should_parse_size_extension() {
+		let response = Response::from_str(
+			"250-mx.example.org\r\n250-PIPELINING\r\n250-SIZE 35882577\r\n250 8BITMIME\r\n",
+		)
+		.unwrap();
+		assert_eq!(parse_size_extension(&response), Some(Some(35882577)));
+
+		let response = Response::from_str("250-mx.example.org\r\n250 SIZE\r\n").unwrap();
+		assert_eq!(parse_size_extension(&response), Some(None));
+
+		let response = Response::from_str("250-mx.example.org\r\n250 8BITMIME\r\n").unwrap();
+		assert_eq!(parse_size_extension(&response), None);
+	}
+}
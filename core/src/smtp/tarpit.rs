@@ -0,0 +1,231 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of tarpits, i.e. SMTP servers which deliberately answer very
+//! slowly, e.g. with a banner dripped one byte per second, to waste the
+//! time of bulk senders. A server which pauses in the middle of a response
+//! for longer than the input's `tarpit_threshold` aborts the verification.
+//! The wait for the first byte of a response, like the TCP connection and
+//! the TLS handshake, is only bounded by the input's `smtp_timeouts`.
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_smtp::smtp::error::Error as AsyncSmtpError;
+use async_std::task;
+use tokio::io::ReadBuf;
+
+use super::SmtpError;
+
+/// The error of a server which paused in the middle of its response to
+/// `step`. It's raised by `DripDetector`, which doesn't know the step, until
+/// `at_step` names it, and carried by an IO error until the verification's
+/// result is built, see `detect_tarpit`.
+#[derive(Debug)]
+struct TarpitError {
+	step: Option<String>,
+}
+
+impl fmt::Display for TarpitError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.step {
+			Some(step) => write!(f, "server paused while answering {step}"),
+			None => write!(f, "server paused in the middle of a response"),
+		}
+	}
+}
+
+impl Error for TarpitError {}
+
+fn tarpit_error(step: Option<String>) -> io::Error {
+	io::Error::new(io::ErrorKind::TimedOut, TarpitError { step })
+}
+
+/// Watches the responses read from an SMTP connection, and fails the read
+/// when the server pauses for longer than the threshold between two bytes
+/// of a response, e.g. between the lines of a multiline response.
+pub(super) struct DripDetector {
+	threshold: Duration,
+	/// Set while a response is partially read. Ready once the server paused
+	/// for the threshold.
+	pause: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
+	/// Length of the current line of the response.
+	line_len: usize,
+	/// Whether the current line is followed by another line of the same
+	/// response, i.e. starts with e.g. "250-".
+	continued: bool,
+}
+
+impl DripDetector {
+	pub(super) fn new(threshold: Duration) -> Self {
+		DripDetector {
+			threshold,
+			pause: None,
+			line_len: 0,
+			continued: false,
+		}
+	}
+
+	/// Poll `read`, which reads the connection into `buf`.
+	pub(super) fn poll_read(
+		&mut self,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+		read: impl FnOnce(&mut Context<'_>, &mut ReadBuf<'_>) -> Poll<io::Result<()>>,
+	) -> Poll<io::Result<()>> {
+		let filled = buf.filled().len();
+		match read(cx, buf) {
+			Poll::Ready(Ok(())) => {
+				self.on_read(&buf.filled()[filled..]);
+				Poll::Ready(Ok(()))
+			}
+			Poll::Pending => {
+				let paused = self
+					.pause
+					.as_mut()
+					.is_some_and(|pause| pause.as_mut().poll(cx).is_ready());
+				if paused {
+					self.pause = None;
+					Poll::Ready(Err(tarpit_error(None)))
+				} else {
+					Poll::Pending
+				}
+			}
+			result => result,
+		}
+	}
+
+	/// Follow the lines of the responses in `data`, and restart the pause
+	/// if the last response is incomplete.
+	fn on_read(&mut self, data: &[u8]) {
+		let mut in_response = self.pause.is_some();
+		for &byte in data {
+			in_response = true;
+			match byte {
+				b'\n' => {
+					in_response = self.continued;
+					self.line_len = 0;
+					self.continued = false;
+				}
+				b'-' if self.line_len == 3 => {
+					self.continued = true;
+					self.line_len += 1;
+				}
+				_ => self.line_len += 1,
+			}
+		}
+
+		self.pause = if in_response {
+			Some(Box::pin(task::sleep(self.threshold)))
+		} else {
+			None
+		};
+	}
+}
+
+/// Name `step` in the tarpit error of `result`, if any.
+pub(super) fn at_step<T>(
+	step: &str,
+	result: Result<T, AsyncSmtpError>,
+) -> Result<T, AsyncSmtpError> {
+	match result {
+		Err(AsyncSmtpError::Io(err)) if tarpit_step(&err).is_some() => {
+			Err(tarpit_error(Some(step.to_string())).into())
+		}
+		result => result,
+	}
+}
+
+/// The step of the tarpit error `err`, if it is one.
+fn tarpit_step(err: &io::Error) -> Option<&str> {
+	err.get_ref()
+		.and_then(|inner| inner.downcast_ref::<TarpitError>())
+		.map(|tarpit| tarpit.step.as_deref().unwrap_or("response"))
+}
+
+/// Turn the errors of `DripDetector` into `SmtpError::Tarpitted`.
+pub(super) fn detect_tarpit(err: SmtpError) -> SmtpError {
+	if let SmtpError::SmtpError(AsyncSmtpError::Io(io_err)) = &err {
+		if let Some(step) = tarpit_step(io_err) {
+			return SmtpError::Tarpitted(step.to_string());
+		}
+	}
+
+	err
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::io::{AsyncRead, AsyncWriteExt};
+
+	/// Read one response from `server` through a detector.
+	async fn read_response(server: &'static [&'static [u8]], pause: Duration) -> io::Result<()> {
+		let (mut client, mut writer) = tokio::io::duplex(64);
+		tokio::spawn(async move {
+			for chunk in server {
+				let _ = writer.write_all(chunk).await;
+				task::sleep(pause).await;
+			}
+		});
+
+		let mut detector = DripDetector::new(Duration::from_millis(100));
+		let mut response = Vec::new();
+		while !response.ends_with(b"250 OK\r\n") {
+			let mut data = [0; 64];
+			let mut buf = ReadBuf::new(&mut data);
+			std::future::poll_fn(|cx| {
+				detector.poll_read(cx, &mut buf, |cx, buf| {
+					Pin::new(&mut client).poll_read(cx, buf)
+				})
+			})
+			.await?;
+			response.extend_from_slice(buf.filled());
+		}
+
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn should_detect_dripped_response() {
+		let result = read_response(
+			&[b"25", b"0-mx.example.org\r\n", b"250 OK\r\n"],
+			Duration::from_millis(300),
+		)
+		.await;
+
+		let err = at_step("EHLO", result.map_err(AsyncSmtpError::from));
+		let err = detect_tarpit(SmtpError::SmtpError(err.unwrap_err()));
+		assert!(matches!(err, SmtpError::Tarpitted(step) if step == "EHLO"));
+	}
+
+	#[tokio::test]
+	async fn should_not_count_wait_before_response() {
+		// A slow answer, but sent at once, is left to the stage timeouts.
+		let result = read_response(
+			&[b"", b"250-mx.example.org\r\n250 OK\r\n"],
+			Duration::from_millis(300),
+		)
+		.await;
+
+		assert!(result.is_ok());
+	}
+}
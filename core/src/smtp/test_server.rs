@@ -0,0 +1,62 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A local SMTP server for the tests of the verification sessions.
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A minimal EHLO response, without any extension.
+pub(crate) const PLAIN_EHLO: &str = "250 localhost\r\n";
+
+/// Serve SMTP on a local port until the test ends, and return the port.
+/// EHLO is answered with `ehlo`, and the other commands are accepted.
+pub(crate) async fn serve(ehlo: &'static str) -> u16 {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let port = listener.local_addr().unwrap().port();
+	tokio::spawn(async move {
+		while let Ok((stream, _)) = listener.accept().await {
+			tokio::spawn(session(stream, ehlo));
+		}
+	});
+
+	port
+}
+
+/// Answer the commands of one SMTP session.
+async fn session(stream: TcpStream, ehlo: &str) {
+	let (reader, mut writer) = stream.into_split();
+	let mut lines = BufReader::new(reader).lines();
+	if writer.write_all(b"220 localhost ESMTP\r\n").await.is_err() {
+		return;
+	}
+
+	while let Ok(Some(line)) = lines.next_line().await {
+		let verb = line.split_whitespace().next().unwrap_or_default();
+		let response = match verb.to_uppercase().as_str() {
+			"EHLO" | "LHLO" => ehlo,
+			"STARTTLS" => "454 TLS not available\r\n",
+			"QUIT" => {
+				let _ = writer.write_all(b"221 Bye\r\n").await;
+				return;
+			}
+			_ => "250 Ok\r\n",
+		};
+		if writer.write_all(response.as_bytes()).await.is_err() {
+			return;
+		}
+	}
+}
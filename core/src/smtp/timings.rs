@@ -0,0 +1,96 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Durations of the stages of an SMTP verification, to tune the timeouts
+//! and spot slow servers.
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Durations of the stages of the SMTP verification, in milliseconds. The
+/// connection stages are only timed separately by our own client, i.e.
+/// unless a SOCKS5 proxy is used.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SmtpTimings {
+	/// Resolution of the MX host's IP addresses.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub dns: Option<u64>,
+	/// TCP connection to the server, or to the proxy.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tcp_connect: Option<u64>,
+	/// TLS handshake, including STARTTLS, if the connection is encrypted.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tls_handshake: Option<u64>,
+	/// The first EHLO.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ehlo: Option<u64>,
+	/// The last `MAIL FROM`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub mail_from: Option<u64>,
+	/// The last `RCPT TO`, i.e. the email's own, unless the domain is a
+	/// catch-all.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub rcpt_to: Option<u64>,
+}
+
+impl SmtpTimings {
+	/// These timings, completed by the ones of other.
+	pub(super) fn or(self, other: SmtpTimings) -> SmtpTimings {
+		SmtpTimings {
+			dns: self.dns.or(other.dns),
+			tcp_connect: self.tcp_connect.or(other.tcp_connect),
+			tls_handshake: self.tls_handshake.or(other.tls_handshake),
+			ehlo: self.ehlo.or(other.ehlo),
+			mail_from: self.mail_from.or(other.mail_from),
+			rcpt_to: self.rcpt_to.or(other.rcpt_to),
+		}
+	}
+}
+
+/// Milliseconds elapsed since start.
+pub(super) fn elapsed_millis(start: Instant) -> u64 {
+	start.elapsed().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_complete_timings() {
+		let connection = SmtpTimings {
+			dns: Some(3),
+			ehlo: Some(20),
+			..Default::default()
+		};
+		let session = SmtpTimings {
+			dns: Some(1),
+			rcpt_to: Some(40),
+			..Default::default()
+		};
+
+		assert_eq!(
+			session.or(connection),
+			SmtpTimings {
+				dns: Some(1),
+				ehlo: Some(20),
+				rcpt_to: Some(40),
+				..Default::default()
+			}
+		);
+	}
+}
@@ -0,0 +1,148 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Details of the certificate presented by the SMTP server during the TLS
+//! negotiation, e.g. to flag servers with expired or self-signed
+//! certificates.
+
+use std::cmp::Ordering;
+
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::error::ErrorStack;
+use openssl::x509::{X509NameRef, X509VerifyResult, X509};
+use serde::{Deserialize, Serialize};
+
+/// The certificate of the SMTP server, and the negotiated TLS session.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TlsDetails {
+	/// Subject of the certificate, e.g. "CN=mx.example.org".
+	pub subject: String,
+	/// Issuer of the certificate, e.g. "C=US, O=Let's Encrypt, CN=R3".
+	pub issuer: String,
+	/// Start of the validity period, in seconds since the Unix epoch.
+	pub not_before: i64,
+	/// End of the validity period, in seconds since the Unix epoch.
+	pub not_after: i64,
+	/// Is the current time outside of the validity period?
+	pub is_expired: bool,
+	/// Is the certificate signed by its own key?
+	pub is_self_signed: bool,
+	/// The negotiated protocol version, e.g. "TLSv1.3".
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tls_version: Option<String>,
+	/// The negotiated cipher suite, in OpenSSL's naming, e.g.
+	/// "TLS_AES_256_GCM_SHA384" or "ECDHE-RSA-AES128-GCM-SHA256".
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cipher: Option<String>,
+}
+
+/// Parse the DER-encoded certificate of the server. The session fields are
+/// left empty.
+pub(super) fn parse_certificate(der: &[u8]) -> Result<TlsDetails, ErrorStack> {
+	let certificate = X509::from_der(der)?;
+	let now = Asn1Time::days_from_now(0)?;
+	let is_expired = certificate.not_before().compare(&now)? == Ordering::Greater
+		|| certificate.not_after().compare(&now)? == Ordering::Less;
+	let is_self_signed = certificate.issued(&certificate) == X509VerifyResult::OK
+		&& certificate.verify(&*certificate.public_key()?)?;
+
+	Ok(TlsDetails {
+		subject: format_name(certificate.subject_name()),
+		issuer: format_name(certificate.issuer_name()),
+		not_before: unix_time(certificate.not_before())?,
+		not_after: unix_time(certificate.not_after())?,
+		is_expired,
+		is_self_signed,
+		tls_version: None,
+		cipher: None,
+	})
+}
+
+/// Format name like OpenSSL's one-line format, e.g. "C=US, CN=R3".
+fn format_name(name: &X509NameRef) -> String {
+	name.entries()
+		.map(|entry| {
+			let key = entry.object().nid().short_name().unwrap_or("?");
+			let value = entry.data().to_string().unwrap_or_default();
+			format!("{key}={value}")
+		})
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+fn unix_time(time: &Asn1TimeRef) -> Result<i64, ErrorStack> {
+	let diff = Asn1Time::from_unix(0)?.diff(time)?;
+
+	Ok(diff.days as i64 * 86400 + diff.secs as i64)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+	use super::*;
+	use openssl::ec::{EcGroup, EcKey};
+	use openssl::hash::MessageDigest;
+	use openssl::nid::Nid;
+	use openssl::pkey::{PKey, Private};
+	use openssl::x509::X509NameBuilder;
+
+	/// A self-signed certificate for mx.example.org, which expired in 2023.
+	pub(in crate::smtp) fn self_signed_certificate() -> X509 {
+		self_signed_identity().0
+	}
+
+	/// Same as `self_signed_certificate`, with its private key.
+	pub(in crate::smtp) fn self_signed_identity() -> (X509, PKey<Private>) {
+		let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+		let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+		let mut name = X509NameBuilder::new().unwrap();
+		name.append_entry_by_text("CN", "mx.example.org").unwrap();
+		let name = name.build();
+
+		let mut builder = X509::builder().unwrap();
+		builder.set_version(2).unwrap();
+		builder.set_subject_name(&name).unwrap();
+		builder.set_issuer_name(&name).unwrap();
+		builder.set_pubkey(&key).unwrap();
+		builder
+			.set_not_before(&Asn1Time::from_unix(1_600_000_000).unwrap())
+			.unwrap();
+		builder
+			.set_not_after(&Asn1Time::from_unix(1_700_000_000).unwrap())
+			.unwrap();
+		builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+		(builder.build(), key)
+	}
+
+	#[test]
+	fn should_parse_self_signed_certificate() {
+		let der = self_signed_certificate().to_der().unwrap();
+
+		assert_eq!(
+			parse_certificate(&der).unwrap(),
+			TlsDetails {
+				subject: "CN=mx.example.org".into(),
+				issuer: "CN=mx.example.org".into(),
+				not_before: 1_600_000_000,
+				not_after: 1_700_000_000,
+				is_expired: true,
+				is_self_signed: true,
+				tls_version: None,
+				cipher: None,
+			}
+		);
+	}
+}
@@ -0,0 +1,488 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Recording of the SMTP session, for debugging the verifications whose
+//! result is unexpected. async-smtp handles the greeting and the first EHLO
+//! itself, so the transcript starts with the connection, and then has the
+//! commands we send and the server's responses.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_smtp::smtp::error::{Error as AsyncSmtpError, SmtpResult};
+use async_smtp::smtp::response::Response;
+use serde::{Deserialize, Serialize};
+
+use super::client::{with_stage_timeout, SmtpChannel, SmtpEndpoint};
+use super::connect::SMTP_CLIENT_TIMEOUT;
+use super::connectivity::{parse_ehlo, SmtpServerInfo};
+use super::dane::DaneStatus;
+use super::lmtp::hello_command;
+use super::rate_limit::RateLimiter;
+use super::response_hook::SmtpResponseHook;
+use super::tarpit::at_step;
+use super::timings::{elapsed_millis, SmtpTimings};
+use super::tls::{parse_certificate, TlsDetails};
+use crate::util::input_output::{CheckEmailInput, SmtpTimeouts};
+
+/// Origin of a line of the transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptDirection {
+	/// A command we sent.
+	Client,
+	/// A response line of the server.
+	Server,
+	/// An event of the session which isn't an SMTP line, e.g. the connection
+	/// or a network error.
+	Event,
+}
+
+/// A line of the SMTP session transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TranscriptLine {
+	/// Milliseconds since the Unix epoch.
+	pub timestamp: u64,
+	pub direction: TranscriptDirection,
+	pub line: String,
+}
+
+/// An SMTP connection, which records its transcript if the input's
+/// `smtp_debug` is set, and enforces its `smtp_timeouts` and the context's
+/// rate limit. The responses are
+/// passed to the context's SMTP response hook.
+pub(super) struct SmtpConnection {
+	channel: SmtpChannel,
+	transcript: Option<Vec<TranscriptLine>>,
+	timeouts: SmtpTimeouts,
+	tls_details: bool,
+	/// Greet the server with LHLO instead of EHLO.
+	lmtp: bool,
+	/// Durations of the commands, and of the DNS resolution if it was done
+	/// before connecting, completed by the channel's connection stages.
+	/// Only reported if the input's `smtp_timings` is set.
+	timings: Option<SmtpTimings>,
+	dane: Option<DaneStatus>,
+	server_info: Option<SmtpServerInfo>,
+	/// The server's EHLO response, once re-sent by `last_ehlo`.
+	ehlo: Option<Response>,
+	/// The context's rate limiter, and the key of the host's rate limit.
+	rate_limit: Option<(Arc<RateLimiter>, String)>,
+	/// The context's SMTP response hook, and the host.
+	response_hook: Option<(Arc<dyn SmtpResponseHook>, String)>,
+	/// The annotations returned by the response hook so far.
+	annotations: BTreeMap<String, String>,
+}
+
+impl SmtpConnection {
+	/// Wrap channel, a connection to host.
+	pub(super) fn new(channel: SmtpChannel, host: &str, input: &CheckEmailInput) -> Self {
+		SmtpConnection {
+			channel,
+			transcript: if input.smtp_debug { Some(vec![]) } else { None },
+			timeouts: input.smtp_timeouts,
+			tls_details: input.smtp_tls_details,
+			lmtp: input.lmtp_server.is_some(),
+			timings: if input.smtp_timings {
+				Some(SmtpTimings::default())
+			} else {
+				None
+			},
+			dane: None,
+			server_info: None,
+			ehlo: None,
+			rate_limit: input.context.rate_limiter().map(|limiter| {
+				let key = limiter.key(host);
+				(limiter, key)
+			}),
+			response_hook: input
+				.context
+				.smtp_response_hook()
+				.map(|hook| (hook, host.to_string())),
+			annotations: BTreeMap::new(),
+		}
+	}
+
+	/// Open the connection to `address`, which is either the host or one of
+	/// its IP addresses.
+	pub(super) async fn connect(&mut self, address: &str, port: u16) -> Result<(), AsyncSmtpError> {
+		self.record(
+			TranscriptDirection::Event,
+			format!("Connecting to {address}:{port}"),
+		);
+		// async-smtp's transport connects and sends EHLO in a single step.
+		// Our own client enforces both timeouts itself.
+		let timeout = match (&self.channel, self.timeouts.connect, self.timeouts.ehlo) {
+			(SmtpChannel::Transport(..), None, None) | (SmtpChannel::Custom(_), _, _) => None,
+			(SmtpChannel::Transport(..), connect, ehlo) => {
+				Some(connect.unwrap_or(SMTP_CLIENT_TIMEOUT) + ehlo.unwrap_or(SMTP_CLIENT_TIMEOUT))
+			}
+		};
+		let result = at_step(
+			"connection",
+			with_stage_timeout(timeout, self.channel.connect()).await,
+		);
+		match &result {
+			Ok(()) => self.record(TranscriptDirection::Event, "Connected"),
+			Err(err) => self.record(TranscriptDirection::Event, err.to_string()),
+		}
+		if let Some((hook, host)) = &self.response_hook {
+			for (verb, response) in self.channel.handshake() {
+				self.annotations
+					.extend(hook.on_response(host, verb, &response_lines(response)));
+			}
+		}
+
+		result
+	}
+
+	/// Add a line to the transcript, if it's recorded.
+	pub(super) fn record(&mut self, direction: TranscriptDirection, line: impl Into<String>) {
+		if let Some(transcript) = &mut self.transcript {
+			transcript.push(TranscriptLine {
+				timestamp: now_millis(),
+				direction,
+				line: line.into(),
+			});
+		}
+	}
+
+	/// Send `command` to the server, within its stage timeout, and record it
+	/// with the response. `RCPT TO` commands first wait for the rate limit.
+	pub(super) async fn command<C: Display>(&mut self, command: C) -> SmtpResult {
+		let line = command.to_string();
+		let verb = command_verb(&line);
+		if let (Some((limiter, key)), "RCPT") = (&self.rate_limit, verb.as_str()) {
+			limiter.acquire(key).await;
+		}
+
+		if self.transcript.is_some() {
+			self.record(TranscriptDirection::Client, line.trim_end());
+		}
+
+		let timeout = command_timeout(&self.timeouts, &verb);
+		let start = Instant::now();
+		let result = at_step(
+			&verb,
+			with_stage_timeout(timeout, self.channel.command(command)).await,
+		);
+		if let Some(timings) = &mut self.timings {
+			match verb.as_str() {
+				"MAIL" => timings.mail_from = Some(elapsed_millis(start)),
+				"RCPT" => timings.rcpt_to = Some(elapsed_millis(start)),
+				_ => {}
+			}
+		}
+		let response = match &result {
+			Ok(response)
+			| Err(AsyncSmtpError::Transient(response))
+			| Err(AsyncSmtpError::Permanent(response)) => Some(response),
+			Err(_) => None,
+		};
+		if let (Some((hook, host)), Some(response)) = (&self.response_hook, response) {
+			self.annotations
+				.extend(hook.on_response(host, &verb, &response_lines(response)));
+		}
+		if self.transcript.is_some() {
+			match (response, &result) {
+				(Some(response), _) => self.record_response(response),
+				(None, Err(err)) => self.record(TranscriptDirection::Event, err.to_string()),
+				(None, Ok(_)) => {}
+			}
+		}
+
+		result
+	}
+
+	fn record_response(&mut self, response: &Response) {
+		for line in response_lines(response) {
+			self.record(TranscriptDirection::Server, line);
+		}
+	}
+
+	/// Re-send EHLO, or LHLO to LMTP servers, e.g. to read the server's
+	/// extensions. It resets the current mail transaction.
+	pub(super) async fn hello(&mut self, hello_name: &str) -> SmtpResult {
+		self.command(hello_command(hello_name, self.lmtp)).await
+	}
+
+	/// The server's response to the last EHLO: the one our own client got
+	/// while connecting, otherwise EHLO is re-sent once per connection, as
+	/// async-smtp's transport doesn't expose it. Re-sending EHLO resets the
+	/// current mail transaction.
+	pub(super) async fn last_ehlo(&mut self, hello_name: &str) -> SmtpResult {
+		if let Some(response) = self.ehlo.clone().or_else(|| self.channel.last_ehlo()) {
+			return Ok(response);
+		}
+
+		let response = self.hello(hello_name).await?;
+		self.ehlo = Some(response.clone());
+
+		Ok(response)
+	}
+
+	/// Close the connection, with QUIT.
+	pub(super) async fn close(&mut self) -> Result<(), AsyncSmtpError> {
+		self.record(TranscriptDirection::Client, "QUIT");
+		at_step("QUIT", self.channel.close().await)
+	}
+
+	/// Take the annotations returned by the response hook so far.
+	pub(super) fn take_annotations(&mut self) -> BTreeMap<String, String> {
+		std::mem::take(&mut self.annotations)
+	}
+
+	/// Take the transcript recorded so far, leaving an empty one.
+	pub(super) fn take_transcript(&mut self) -> Option<Vec<TranscriptLine>> {
+		self.transcript.as_mut().map(std::mem::take)
+	}
+
+	/// Insert `transcript`, e.g. of a previous connection to the same host,
+	/// before the lines recorded so far.
+	pub(super) fn prepend_transcript(&mut self, transcript: Option<Vec<TranscriptLine>>) {
+		if let (Some(current), Some(mut previous)) = (&mut self.transcript, transcript) {
+			previous.append(current);
+			*current = previous;
+		}
+	}
+
+	/// The server's TLS certificate and session, if the input's
+	/// `smtp_tls_details` is set and the connection is encrypted.
+	pub(super) fn tls_details(&self) -> Option<TlsDetails> {
+		if !self.tls_details {
+			return None;
+		}
+
+		let session = self.channel.tls_session()?;
+		// Failing to parse the certificate doesn't fail the verification.
+		let mut details = parse_certificate(session.peer_certificate.as_deref()?).ok()?;
+		details.tls_version = session.version;
+		details.cipher = session.cipher;
+
+		Some(details)
+	}
+
+	/// The server's DER-encoded TLS certificate, if it was inspected.
+	pub(super) fn peer_certificate(&self) -> Option<Vec<u8>> {
+		self.channel.peer_certificate()
+	}
+
+	/// The DANE validation of the server's certificate, if the input's
+	/// `smtp_dane_validation` is set.
+	pub(super) fn dane(&self) -> Option<DaneStatus> {
+		self.dane
+	}
+
+	pub(super) fn set_dane(&mut self, dane: DaneStatus) {
+		self.dane = Some(dane);
+	}
+
+	/// Durations of the stages of the session so far, if the input's
+	/// `smtp_timings` is set.
+	pub(super) fn timings(&self) -> Option<SmtpTimings> {
+		self.timings
+			.map(|timings| timings.or(self.channel.timings()))
+	}
+
+	/// Record the duration of the DNS resolution done before connecting.
+	pub(super) fn set_dns_timing(&mut self, dns: u64) {
+		if let Some(timings) = &mut self.timings {
+			timings.dns = Some(dns);
+		}
+	}
+
+	/// Where the connection is established.
+	pub(super) fn endpoint(&self) -> SmtpEndpoint {
+		self.channel.endpoint()
+	}
+
+	/// The server's name and capabilities, if they were read.
+	pub(super) fn server_info(&self) -> Option<SmtpServerInfo> {
+		self.server_info.clone()
+	}
+
+	/// Read the server's name and capabilities, from the first EHLO if our
+	/// own client sent it, otherwise by re-sending EHLO, as async-smtp's
+	/// transport doesn't expose them. Re-sending EHLO resets the current
+	/// mail transaction, and STARTTLS isn't advertised anymore once the
+	/// connection is encrypted.
+	pub(super) async fn read_server_info(
+		&mut self,
+		hello_name: &str,
+	) -> Result<SmtpServerInfo, AsyncSmtpError> {
+		let response = match self.channel.first_ehlo() {
+			Some(response) => response,
+			None => self.hello(hello_name).await?,
+		};
+		let server_info = parse_ehlo(&response);
+		self.server_info = Some(server_info.clone());
+
+		Ok(server_info)
+	}
+
+	pub(super) fn into_channel(self) -> SmtpChannel {
+		self.channel
+	}
+}
+
+/// The lines of `response`, as sent by the server: the code, followed by
+/// '-' on all the lines but the last one.
+fn response_lines(response: &Response) -> Vec<String> {
+	if response.message.is_empty() {
+		return vec![response.code.to_string()];
+	}
+
+	let last = response.message.len() - 1;
+
+	response
+		.message
+		.iter()
+		.enumerate()
+		.map(|(i, message)| {
+			let separator = if i == last { ' ' } else { '-' };
+			format!("{}{}{}", response.code, separator, message)
+		})
+		.collect()
+}
+
+/// The verb of the command `line`, e.g. "RCPT".
+fn command_verb(line: &str) -> String {
+	line.split_whitespace()
+		.next()
+		.unwrap_or_default()
+		.to_ascii_uppercase()
+}
+
+/// The stage timeout of a command, according to its verb.
+fn command_timeout(timeouts: &SmtpTimeouts, verb: &str) -> Option<Duration> {
+	match verb {
+		"EHLO" | "HELO" | "STARTTLS" => timeouts.ehlo,
+		"MAIL" => timeouts.mail_from,
+		"RCPT" => timeouts.rcpt_to,
+		_ => None,
+	}
+}
+
+fn now_millis() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_millis() as u64)
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::smtp::client::CustomClient;
+	use crate::CheckEmailContext;
+	use async_smtp::ClientSecurity;
+	use std::str::FromStr;
+	use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+	use tokio::net::TcpListener;
+
+	/// Extracts the queue ID of the "250 Ok: queued as <id>" responses, and
+	/// records the verbs it's called with.
+	#[derive(Debug)]
+	struct QueueIdHook;
+
+	impl SmtpResponseHook for QueueIdHook {
+		fn on_response(
+			&self,
+			_host: &str,
+			verb: &str,
+			lines: &[String],
+		) -> BTreeMap<String, String> {
+			lines
+				.iter()
+				.filter_map(|line| line.split("queued as ").nth(1))
+				.map(|id| (format!("{}_queue_id", verb.to_lowercase()), id.to_string()))
+				.chain(std::iter::once((verb.to_lowercase(), lines.join("\n"))))
+				.collect()
+		}
+	}
+
+	#[tokio::test]
+	async fn should_call_response_hook() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		tokio::spawn(async move {
+			let (stream, _) = listener.accept().await.unwrap();
+			let (reader, mut writer) = stream.into_split();
+			let mut reader = BufReader::new(reader);
+			let mut line = String::new();
+			writer.write_all(b"220 localhost\r\n").await.unwrap();
+			reader.read_line(&mut line).await.unwrap();
+			writer.write_all(b"250 localhost\r\n").await.unwrap();
+			reader.read_line(&mut line).await.unwrap();
+			writer
+				.write_all(b"250 Ok: queued as 4F2A1\r\n")
+				.await
+				.unwrap();
+		});
+
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_context(CheckEmailContext::new().with_smtp_response_hook(Arc::new(QueueIdHook)));
+		let client = CustomClient::new(
+			"localhost",
+			port,
+			ClientSecurity::None,
+			Duration::from_secs(5),
+			&input,
+		);
+		let mut connection = SmtpConnection::new(SmtpChannel::Custom(client), "localhost", &input);
+		connection.connect("localhost", port).await.unwrap();
+		connection.command("RSET\r\n").await.unwrap();
+
+		let annotations = connection.take_annotations();
+		assert_eq!(annotations.get("rset_queue_id"), Some(&"4F2A1".to_string()));
+		assert_eq!(
+			annotations.get("connect"),
+			Some(&"220 localhost".to_string())
+		);
+		assert_eq!(annotations.get("ehlo"), Some(&"250 localhost".to_string()));
+	}
+
+	#[test]
+	fn should_format_response_lines() {
+		let response =
+			Response::from_str("250-mx.example.org\r\n250-SIZE 1000\r\n250 SMTPUTF8\r\n").unwrap();
+
+		assert_eq!(
+			response_lines(&response),
+			vec!["250-mx.example.org", "250-SIZE 1000", "250 SMTPUTF8"]
+		);
+	}
+
+	#[test]
+	fn should_pick_command_timeout() {
+		let timeouts = SmtpTimeouts {
+			rcpt_to: Some(Duration::from_secs(5)),
+			..Default::default()
+		};
+
+		assert_eq!(
+			command_timeout(&timeouts, &command_verb("RCPT TO:<foo@example.org>\r\n")),
+			Some(Duration::from_secs(5))
+		);
+		assert_eq!(
+			command_timeout(&timeouts, &command_verb("MAIL FROM:<>\r\n")),
+			None
+		);
+		assert_eq!(command_timeout(&timeouts, &command_verb("RSET\r\n")), None);
+	}
+}
@@ -0,0 +1,119 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Fallback to the VRFY command (RFC 5321, section 3.5), for servers which
+//! reject all `RCPT TO` probes, but still answer VRFY truthfully.
+
+use async_smtp::smtp::commands::VrfyCommand;
+use async_smtp::smtp::error::{Error as AsyncSmtpError, SmtpResult};
+use async_smtp::EmailAddress;
+
+use super::connect::Deliverability;
+use super::transcript::SmtpConnection;
+use super::SmtpError;
+use crate::util::{constants::LOG_TARGET, input_output::CheckEmailInput};
+
+/// Interpret the server's answer to VRFY: Some(true) if the mailbox
+/// exists, Some(false) if it doesn't, None if the server won't tell, e.g.
+/// with "252 Cannot VRFY user" or "502 Command not implemented".
+fn parse_vrfy_result(result: &SmtpResult) -> Option<bool> {
+	let code = match result {
+		Ok(response) => response.code.to_string(),
+		Err(AsyncSmtpError::Permanent(response)) => response.code.to_string(),
+		Err(_) => return None,
+	};
+
+	match code.as_str() {
+		"250" | "251" => Some(true),
+		"550" | "551" | "553" => Some(false),
+		_ => None,
+	}
+}
+
+/// If `result`, the outcome of `RCPT TO`, is an error response of the
+/// server, and the input's `smtp_vrfy_fallback` is set, ask the server with
+/// VRFY instead. Returns the result to use, and whether it comes from VRFY.
+pub(super) async fn with_vrfy_fallback(
+	smtp_transport: &mut SmtpConnection,
+	to_email: &EmailAddress,
+	input: &CheckEmailInput,
+	result: Result<Deliverability, SmtpError>,
+) -> (Result<Deliverability, SmtpError>, bool) {
+	let is_rejection = matches!(
+		result,
+		Err(SmtpError::SmtpError(
+			AsyncSmtpError::Transient(_) | AsyncSmtpError::Permanent(_)
+		))
+	);
+	if !input.smtp_vrfy_fallback || !is_rejection {
+		return (result, false);
+	}
+
+	let vrfy_result = smtp_transport
+		.command(VrfyCommand::new(to_email.to_string()))
+		.await;
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] RCPT TO failed, got VRFY result {:?}",
+		to_email,
+		vrfy_result
+	);
+
+	match parse_vrfy_result(&vrfy_result) {
+		Some(is_deliverable) => (
+			Ok(Deliverability {
+				has_full_inbox: false,
+				is_deliverable,
+				is_disabled: false,
+			}),
+			true,
+		),
+		None => (result, false),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use async_smtp::smtp::response::Response;
+	use std::str::FromStr;
+
+	#[test]
+	fn should_parse_vrfy_result() {
+		let response = |line: &str| Response::from_str(line).unwrap();
+
+		assert_eq!(
+			parse_vrfy_result(&Ok(response("250 John Doe <john@example.org>\r\n"))),
+			Some(true)
+		);
+		assert_eq!(
+			parse_vrfy_result(&Err(AsyncSmtpError::Permanent(response(
+				"550 5.1.1 User unknown\r\n"
+			)))),
+			Some(false)
+		);
+		assert_eq!(
+			parse_vrfy_result(&Ok(response("252 2.5.2 Cannot VRFY user\r\n"))),
+			None
+		);
+		assert_eq!(
+			parse_vrfy_result(&Err(AsyncSmtpError::Permanent(response(
+				"502 5.5.1 VRFY command is disabled\r\n"
+			)))),
+			None
+		);
+	}
+}
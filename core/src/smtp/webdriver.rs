@@ -0,0 +1,209 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! A WebDriver server, e.g. chromedriver or geckodriver, spawned and
+//! supervised by the crate, for the headless verifications whose WebDriver
+//! endpoint is `MANAGED_WEBDRIVER`.
+
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use async_std::net::TcpStream;
+use async_std::sync::Mutex;
+use async_std::task;
+
+use crate::util::constants::LOG_TARGET;
+
+/// The WebDriver endpoint which stands for the context's managed WebDriver
+/// server, see `CheckEmailContext::with_managed_webdriver`.
+pub const MANAGED_WEBDRIVER: &str = "managed";
+
+/// How often we check whether a spawned WebDriver server accepts
+/// connections.
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Configuration of the managed WebDriver server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedWebDriverConfig {
+	/// Path of the WebDriver binary. Defaults to "chromedriver", looked up
+	/// in the PATH.
+	pub binary: PathBuf,
+	/// Extra arguments of the binary. The port is passed with `--port=`.
+	pub args: Vec<String>,
+	/// Local ports the server may listen on. The first free one is used.
+	/// Defaults to 9515 to 9615.
+	pub ports: RangeInclusive<u16>,
+	/// Maximum time to wait for the server to accept connections after
+	/// spawning it. Defaults to 10s.
+	pub startup_timeout: Duration,
+}
+
+impl Default for ManagedWebDriverConfig {
+	fn default() -> Self {
+		ManagedWebDriverConfig {
+			binary: "chromedriver".into(),
+			args: vec![],
+			ports: 9515..=9615,
+			startup_timeout: Duration::from_secs(10),
+		}
+	}
+}
+
+/// A WebDriver server child process, respawned when it crashes, and killed
+/// when dropped.
+pub(crate) struct ManagedWebDriver {
+	config: ManagedWebDriverConfig,
+	/// The running process, and its port.
+	process: Mutex<Option<(Child, u16)>>,
+	restarts: AtomicU64,
+}
+
+impl fmt::Debug for ManagedWebDriver {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ManagedWebDriver")
+			.field("config", &self.config)
+			.field("restarts", &self.restarts())
+			.finish()
+	}
+}
+
+impl ManagedWebDriver {
+	pub fn new(config: ManagedWebDriverConfig) -> Self {
+		ManagedWebDriver {
+			config,
+			process: Mutex::new(None),
+			restarts: AtomicU64::new(0),
+		}
+	}
+
+	/// The endpoint of the server, spawning it first if it's not running,
+	/// e.g. because it crashed.
+	pub async fn endpoint(&self) -> io::Result<String> {
+		let mut process = self.process.lock().await;
+		if let Some((child, port)) = process.as_mut() {
+			match child.try_wait() {
+				Ok(None) => return Ok(endpoint(*port)),
+				status => {
+					log::debug!(
+						target: LOG_TARGET,
+						"WebDriver server on port {} exited with {:?}, restarting it",
+						port,
+						status
+					);
+					self.restarts.fetch_add(1, Ordering::Relaxed);
+				}
+			}
+		}
+
+		let port = free_port(&self.config.ports).ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::AddrInUse,
+				"No free port for the WebDriver server",
+			)
+		})?;
+		let mut child = Command::new(&self.config.binary)
+			.args(&self.config.args)
+			.arg(format!("--port={port}"))
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn()?;
+
+		if let Err(err) = wait_for_port(port, self.config.startup_timeout).await {
+			let _ = child.kill();
+			let _ = child.wait();
+			*process = None;
+			return Err(err);
+		}
+		*process = Some((child, port));
+
+		Ok(endpoint(port))
+	}
+
+	/// Number of times the server was respawned after exiting.
+	pub fn restarts(&self) -> u64 {
+		self.restarts.load(Ordering::Relaxed)
+	}
+}
+
+impl Drop for ManagedWebDriver {
+	fn drop(&mut self) {
+		if let Some((child, _)) = self.process.get_mut() {
+			let _ = child.kill();
+			let _ = child.wait();
+		}
+	}
+}
+
+fn endpoint(port: u16) -> String {
+	format!("http://127.0.0.1:{port}")
+}
+
+/// The first port of `ports` nothing listens on.
+fn free_port(ports: &RangeInclusive<u16>) -> Option<u16> {
+	ports
+		.clone()
+		.find(|port| TcpListener::bind((Ipv4Addr::LOCALHOST, *port)).is_ok())
+}
+
+/// Wait until something accepts connections on `port`.
+async fn wait_for_port(port: u16, timeout: Duration) -> io::Result<()> {
+	let start = Instant::now();
+	loop {
+		if TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+			.await
+			.is_ok()
+		{
+			return Ok(());
+		}
+		if start.elapsed() >= timeout {
+			return Err(io::Error::new(
+				io::ErrorKind::TimedOut,
+				"The WebDriver server didn't start in time",
+			));
+		}
+		task::sleep(STARTUP_POLL_INTERVAL).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_pick_free_port() {
+		let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+		let used = listener.local_addr().unwrap().port();
+
+		assert_ne!(free_port(&(used..=used.saturating_add(1))), Some(used));
+		assert_eq!(free_port(&(used..=used)), None);
+	}
+
+	#[tokio::test]
+	async fn should_fail_with_missing_binary() {
+		let webdriver = ManagedWebDriver::new(ManagedWebDriverConfig {
+			binary: "/nonexistent/chromedriver".into(),
+			..Default::default()
+		});
+
+		assert!(webdriver.endpoint().await.is_err());
+	}
+}
@@ -14,14 +14,26 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::SmtpDetails;
+#[cfg(feature = "headless")]
+use super::microsoft::hotmail::HotmailError;
+#[cfg(feature = "headless")]
+use super::{
+	challenge::solve_challenges, headless_debug::with_diagnostics, headless_pool::HeadlessSession,
+};
+use super::{Challenge, ChallengeKind, SmtpDetails};
 use crate::{
-	smtp::http_api::create_client,
+	smtp::http_api::{create_client, error_for_throttle},
 	util::{
 		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
 	},
 };
 use async_smtp::EmailAddress;
+#[cfg(feature = "headless")]
+use async_std::prelude::FutureExt;
+#[cfg(feature = "headless")]
+use fantoccini::{error::CmdError, Locator};
+#[cfg(feature = "headless")]
+use futures::TryFutureExt;
 use regex::Regex;
 use reqwest::Error as ReqwestError;
 use serde::{Deserialize, Serialize};
@@ -30,6 +42,8 @@ use std::fmt;
 
 const SIGNUP_PAGE: &str = "https://login.yahoo.com/account/create?specId=yidReg&lang=en-US&src=&done=https%3A%2F%2Fwww.yahoo.com&display=login";
 const SIGNUP_API: &str = "https://login.yahoo.com/account/module/create?validateField=yid";
+#[cfg(feature = "headless")]
+const RECOVERY_PAGE: &str = "https://login.yahoo.com/forgot";
 const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_11_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/54.0.2840.71 Safari/537.36"; // Fake one to use in API requests
 
 /// The form inputs to pass into the HTTP request.
@@ -87,6 +101,26 @@ pub enum YahooError {
 	/// Error when serializing or deserializing HTTP requests and responses.
 	#[serde(serialize_with = "ser_with_display")]
 	SerdeError(SerdeError),
+	/// Error when verifying via the password recovery page in a headless
+	/// browser.
+	#[cfg(feature = "headless")]
+	HeadlessError(HotmailError),
+	/// Yahoo answered with a CAPTCHA or bot challenge.
+	ChallengeRequired(Challenge),
+}
+
+impl YahooError {
+	/// Did the API answer with a payload we don't understand, e.g. because
+	/// Yahoo changed its endpoints, rather than fail to be reached?
+	pub fn is_unexpected_payload(&self) -> bool {
+		match self {
+			YahooError::NoAcrumb | YahooError::NoCookie | YahooError::SerdeError(_) => true,
+			YahooError::ReqwestError(err) => err.is_decode(),
+			#[cfg(feature = "headless")]
+			YahooError::HeadlessError(_) => false,
+			YahooError::ChallengeRequired(_) => false,
+		}
+	}
 }
 
 impl fmt::Display for YahooError {
@@ -102,6 +136,23 @@ impl From<ReqwestError> for YahooError {
 	}
 }
 
+#[cfg(feature = "headless")]
+impl From<CmdError> for YahooError {
+	fn from(error: CmdError) -> Self {
+		YahooError::HeadlessError(HotmailError::Cmd(error))
+	}
+}
+
+#[cfg(feature = "headless")]
+impl From<HotmailError> for YahooError {
+	fn from(error: HotmailError) -> Self {
+		match error {
+			HotmailError::ChallengeRequired(challenge) => YahooError::ChallengeRequired(challenge),
+			error => YahooError::HeadlessError(error),
+		}
+	}
+}
+
 impl From<SerdeError> for YahooError {
 	fn from(error: SerdeError) -> Self {
 		YahooError::SerdeError(error)
@@ -119,6 +170,17 @@ pub async fn check_yahoo(
 		.header("User-Agent", USER_AGENT)
 		.send()
 		.await?;
+	error_for_throttle(&response)?;
+
+	// Yahoo redirects suspicious clients to a challenge page.
+	if response.url().path().starts_with("/account/challenge") {
+		return Err(YahooError::ChallengeRequired(Challenge {
+			provider: "yahoo".into(),
+			kind: ChallengeKind::Other,
+			url: Some(response.url().to_string()),
+			site_key: None,
+		}));
+	}
 
 	// Get the cookies from the response.
 	let cookies = match response.headers().get("Set-Cookie") {
@@ -182,9 +244,9 @@ pub async fn check_yahoo(
 			username.into(),
 		))
 		.send()
-		.await?
-		.json::<FormResponse>()
 		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<FormResponse>().await?;
 
 	log::debug!(
 		target: LOG_TARGET,
@@ -204,3 +266,81 @@ pub async fn check_yahoo(
 		..Default::default()
 	})
 }
+
+/// Check if a Yahoo/AOL email exists by connecting to the password recovery
+/// page https://login.yahoo.com/forgot using a headless browser, like
+/// `hotmail::check_password_recovery`. Make sure you have a WebDriver server
+/// running locally before running this, or this will error.
+#[cfg(feature = "headless")]
+pub async fn check_password_recovery(
+	to_email: &EmailAddress,
+	webdriver: &str,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, YahooError> {
+	let to_email = to_email.to_string();
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Using Yahoo password recovery in headless navigator",
+		to_email,
+	);
+
+	let c = HeadlessSession::open(webdriver, input).await?;
+	c.goto(RECOVERY_PAGE).await?;
+
+	let is_deliverable = with_diagnostics(&c, input, "yahoo", async {
+		let email_field = c.wait().for_element(Locator::Id("username")).await?;
+		email_field.send_keys(to_email.as_str()).await?;
+
+		// Click on "Continue"
+		c.find(Locator::Css("button[name='verifyYid']"))
+			.await?
+			.click()
+			.await?;
+
+		solve_challenges(&c, input, "yahoo", || {
+			// "Sorry, we don't recognize this account" means the account
+			// does not exist.
+			let f1 = c
+				.wait()
+				.for_element(Locator::Css(".error-msg"))
+				.and_then(|_| async { Ok(false) });
+			// Offering to send a verification code means that the account
+			// exists.
+			let f2 = c
+				.wait()
+				.for_element(Locator::Css(".challenge-form"))
+				.and_then(|_| async { Ok(true) });
+
+			f1.try_race(f2)
+		})
+		.await
+	})
+	.await?
+	.map_err(YahooError::ChallengeRequired)?;
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] Yahoo password recovery result: {}",
+		to_email,
+		is_deliverable,
+	);
+
+	c.release().await?;
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::de::Error;
+
+	#[test]
+	fn should_detect_unexpected_payload() {
+		assert!(YahooError::NoAcrumb.is_unexpected_payload());
+		assert!(YahooError::SerdeError(SerdeError::custom("unknown field")).is_unexpected_payload());
+	}
+}
@@ -0,0 +1,206 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::SmtpDetails;
+use crate::{
+	smtp::http_api::{create_client, error_for_throttle},
+	util::{
+		constants::LOG_TARGET, input_output::CheckEmailInput, ser_with_display::ser_with_display,
+	},
+};
+use async_smtp::EmailAddress;
+use regex::Regex;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+const REGISTRATION_PAGE: &str = "https://passport.yandex.ru/registration";
+const LOGIN_VALIDATION_API: &str = "https://passport.yandex.ru/registration-validations/login";
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_11_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/54.0.2840.71 Safari/537.36"; // Fake one to use in API requests
+
+/// Domains of the addresses hosted by Yandex itself, which share the same
+/// logins.
+const YANDEX_DOMAINS: &[&str] = &[
+	"yandex.ru",
+	"yandex.com",
+	"ya.ru",
+	"yandex.by",
+	"yandex.kz",
+	"yandex.ua",
+];
+
+/// Error code of the login validation API when the login is already used.
+const LOGIN_NOT_AVAILABLE: &str = "login.not_available";
+
+/// One error of the login validation API.
+#[derive(Debug, Deserialize)]
+struct ValidationError {
+	code: String,
+}
+
+/// The response of the login validation API.
+#[derive(Debug, Deserialize)]
+struct ValidationResponse {
+	status: String,
+	#[serde(default)]
+	errors: Vec<ValidationError>,
+}
+
+/// Possible errors when checking Yandex email addresses.
+#[derive(Debug, Serialize)]
+pub enum YandexError {
+	/// Cannot find the "csrf_token" or "track_id" fields in the
+	/// registration page.
+	NoTrack,
+	/// Error when serializing or deserializing HTTP requests and responses.
+	#[serde(serialize_with = "ser_with_display")]
+	ReqwestError(ReqwestError),
+	/// The API rejected the login for another reason than it being used,
+	/// e.g. when it asks for a captcha.
+	UnexpectedResponse(String),
+}
+
+impl fmt::Display for YandexError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{self:?}")
+	}
+}
+
+impl From<ReqwestError> for YandexError {
+	fn from(error: ReqwestError) -> Self {
+		YandexError::ReqwestError(error)
+	}
+}
+
+/// Is host one of Yandex's MX hosts, for one of the domains hosted by
+/// Yandex itself, as opposed to the custom domains of Yandex 360?
+pub(super) fn is_yandex_host(host_lowercase: &str, domain: &str) -> bool {
+	(host_lowercase.ends_with(".yandex.ru.") || host_lowercase.ends_with(".yandex.net."))
+		&& YANDEX_DOMAINS.contains(&domain.to_lowercase().as_str())
+}
+
+/// Read the "csrf_token" and "track_id" of the registration form.
+fn parse_registration_page(html: &str) -> Option<(String, String)> {
+	let field = |name: &str| {
+		let re = Regex::new(&format!(r#"name="{name}"\s+value="(?P<value>[^"]*)""#))
+			.expect("Correct regex. qed.");
+		re.captures(html)
+			.map(|captures| captures["value"].to_string())
+	};
+
+	Some((field("csrf_token")?, field("track_id")?))
+}
+
+/// Does the login validation API's response mean that the login exists?
+fn is_used(response: &ValidationResponse) -> Result<bool, YandexError> {
+	if response.status == "ok" {
+		return Ok(false);
+	}
+
+	match response.errors.first() {
+		Some(error) if error.code == LOGIN_NOT_AVAILABLE => Ok(true),
+		_ => Err(YandexError::UnexpectedResponse(format!("{response:?}"))),
+	}
+}
+
+/// Use the login validation API of Yandex's registration page to verify if
+/// a Yandex email address exists, as Yandex's SMTP servers accept all
+/// mailboxes.
+pub async fn check_yandex(
+	to_email: &EmailAddress,
+	input: &CheckEmailInput,
+) -> Result<SmtpDetails, YandexError> {
+	let client = create_client(input, "yandex")?;
+
+	// The registration page opens the track the API validates logins of.
+	let response = client
+		.get(REGISTRATION_PAGE)
+		.header("User-Agent", USER_AGENT)
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let cookies = response
+		.headers()
+		.get_all("Set-Cookie")
+		.iter()
+		.filter_map(|cookie| cookie.to_str().ok())
+		.filter_map(|cookie| cookie.split(';').next())
+		.collect::<Vec<_>>()
+		.join("; ");
+	let (csrf_token, track_id) =
+		parse_registration_page(&response.text().await?).ok_or(YandexError::NoTrack)?;
+
+	let to_email = to_email.to_string();
+	let login = to_email
+		.split('@')
+		.next()
+		.expect("The email is well-formed. qed.");
+	let response = client
+		.post(LOGIN_VALIDATION_API)
+		.header("User-Agent", USER_AGENT)
+		.header("Origin", "https://passport.yandex.ru")
+		.header("Referer", REGISTRATION_PAGE)
+		.header("X-Requested-With", "XMLHttpRequest")
+		.header("Cookie", cookies)
+		.form(&[
+			("csrf_token", csrf_token.as_str()),
+			("track_id", track_id.as_str()),
+			("login", login),
+		])
+		.send()
+		.await?;
+	error_for_throttle(&response)?;
+	let response = response.json::<ValidationResponse>().await?;
+
+	log::debug!(
+		target: LOG_TARGET,
+		"[email={}] yandex response: {:?}",
+		to_email,
+		response
+	);
+
+	Ok(SmtpDetails {
+		can_connect_smtp: true,
+		is_deliverable: is_used(&response)?,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_parse_login_validation() {
+		assert_eq!(
+			parse_registration_page(
+				r#"<input type="hidden" name="csrf_token" value="abc:123"><input type="hidden" name="track_id" value="f00"/>"#
+			),
+			Some(("abc:123".into(), "f00".into()))
+		);
+		assert_eq!(parse_registration_page("<html></html>"), None);
+
+		let response: ValidationResponse = serde_json::from_str(
+			r#"{"status": "error", "errors": [{"field": "login", "code": "login.not_available"}]}"#,
+		)
+		.unwrap();
+		assert!(is_used(&response).unwrap());
+		let response: ValidationResponse = serde_json::from_str(r#"{"status": "ok"}"#).unwrap();
+		assert!(!is_used(&response).unwrap());
+		assert!(is_yandex_host("mx.yandex.net.", "ya.ru"));
+		assert!(!is_yandex_host("mx.yandex.net.", "example.org"));
+	}
+}
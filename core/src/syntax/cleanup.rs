@@ -0,0 +1,111 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Clean up the raw input before validating its syntax.
+
+use serde::{Deserialize, Serialize};
+
+/// Which transformations to apply to the raw email address before checking
+/// its syntax. The default policy leaves the address untouched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CleanupPolicy {
+	/// Remove leading and trailing whitespace.
+	pub trim_whitespace: bool,
+	/// Lowercase the domain, after "@". Domains are case-insensitive, so this
+	/// never changes the verification result.
+	pub lowercase_domain: bool,
+	/// Lowercase the username, before "@". Most providers treat usernames as
+	/// case-insensitive, but RFC 5321 doesn't require it.
+	pub lowercase_username: bool,
+}
+
+/// A transformation which was applied to the raw email address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transformation {
+	/// Leading or trailing whitespace was removed.
+	TrimmedWhitespace,
+	/// The domain was lowercased.
+	LowercasedDomain,
+	/// The username was lowercased.
+	LowercasedUsername,
+	/// The internationalized domain was converted to its ASCII (punycode)
+	/// form.
+	DomainToAscii,
+}
+
+/// Apply `policy` to `email_address`. Only the transformations which changed
+/// the address are returned.
+pub(crate) fn clean_up(
+	email_address: &str,
+	policy: CleanupPolicy,
+) -> (String, Vec<Transformation>) {
+	let mut transformations = Vec::new();
+
+	let mut email_address = email_address;
+	if policy.trim_whitespace && email_address.trim() != email_address {
+		email_address = email_address.trim();
+		transformations.push(Transformation::TrimmedWhitespace);
+	}
+
+	let (mut username, mut domain) = match email_address.rsplit_once('@') {
+		Some((username, domain)) => (username.to_string(), domain.to_string()),
+		None => return (email_address.into(), transformations),
+	};
+	if policy.lowercase_username && username.to_lowercase() != username {
+		username = username.to_lowercase();
+		transformations.push(Transformation::LowercasedUsername);
+	}
+	if policy.lowercase_domain && domain.to_lowercase() != domain {
+		domain = domain.to_lowercase();
+		transformations.push(Transformation::LowercasedDomain);
+	}
+
+	(format!("{username}@{domain}"), transformations)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_only_report_applied_transformations() {
+		let policy = CleanupPolicy {
+			trim_whitespace: true,
+			lowercase_domain: true,
+			lowercase_username: false,
+		};
+
+		assert_eq!(
+			clean_up(" John@Example.org\n", policy),
+			(
+				"John@example.org".into(),
+				vec![
+					Transformation::TrimmedWhitespace,
+					Transformation::LowercasedDomain
+				]
+			)
+		);
+		assert_eq!(
+			clean_up("john@example.org", policy),
+			("john@example.org".into(), vec![])
+		);
+		assert_eq!(
+			clean_up(" John@Example.org", CleanupPolicy::default()),
+			(" John@Example.org".into(), vec![])
+		);
+	}
+}
@@ -0,0 +1,75 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Characters commonly found around an email address in free text, which
+/// are never part of it.
+const SURROUNDING_CHARS: &[char] = &['"', '\'', '`', '(', ')', '<', '>', ',', ';', ':', '.'];
+
+/// Tolerantly extract an email address out of free text, such as
+/// `"John Doe <john@acme.com>"`, `mailto:john@acme.com` or
+/// `  (john@acme.com), `. The returned address still has to go through
+/// `check_syntax`. Returns None if the text doesn't contain an "@".
+pub fn parse_email_like(text: &str) -> Option<String> {
+	let mut email = text.trim();
+
+	// Display name format: the address is in angle brackets.
+	if let Some((_, rest)) = email.rsplit_once('<') {
+		if let Some((address, _)) = rest.split_once('>') {
+			email = address.trim();
+		}
+	}
+
+	if email
+		.get(..7)
+		.is_some_and(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+	{
+		email = &email[7..];
+		// Drop headers, e.g. `?subject=Hello`.
+		email = email.split('?').next().unwrap_or_default();
+	}
+
+	// mailto URIs may percent-encode the "@".
+	let email = email
+		.trim_matches(|c: char| c.is_whitespace() || SURROUNDING_CHARS.contains(&c))
+		.replace("%40", "@");
+
+	if email.contains('@') {
+		Some(email)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_extract_email() {
+		let expected = Some("john@acme.com".to_string());
+
+		assert_eq!(parse_email_like("\"John Doe\" <john@acme.com>"), expected);
+		assert_eq!(parse_email_like("John Doe <john@acme.com>,"), expected);
+		assert_eq!(
+			parse_email_like("mailto:john@acme.com?subject=Hi"),
+			expected
+		);
+		assert_eq!(parse_email_like("MAILTO:john%40acme.com"), expected);
+		assert_eq!(parse_email_like("  (john@acme.com); "), expected);
+		assert_eq!(parse_email_like("john@acme.com."), expected);
+		assert_eq!(parse_email_like("John Doe"), None);
+	}
+}
@@ -14,23 +14,56 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod cleanup;
+mod extract;
 mod normalize;
+mod policy;
+mod rules;
+mod subaddress;
+mod suggest;
+mod tld;
 
 use async_smtp::EmailAddress;
-use levenshtein::levenshtein;
+use cleanup::clean_up;
+pub use cleanup::{CleanupPolicy, Transformation};
+pub use extract::parse_email_like;
 use normalize::normalize_email;
+pub use normalize::{default_normalization_rules, NormalizationRule};
+use once_cell::sync::Lazy;
+pub use policy::{LocalPartCharClass, LocalPartPattern, LocalPartPolicy};
+use rules::find_violations;
+pub use rules::SyntaxViolation;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::net::IpAddr;
+use subaddress::split_subaddress;
+pub use suggest::{DomainFrequency, DomainSuggester, DomainSuggestion, SuggestionDomains};
+pub use tld::{default_tld_list, TldList, IANA_TLD_LIST_URL};
+use trust_dns_proto::rr::Name;
+
+use crate::misc::{is_disposable, ListProvider};
+use crate::util::input_output::CheckEmailInput;
+
+/// The default syntax options, used by `check_syntax`, built once.
+static DEFAULT_INPUT: Lazy<CheckEmailInput> = Lazy::new(CheckEmailInput::default);
 
 /// Syntax information after parsing an email address
 #[derive(Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct SyntaxDetails {
-	/// The email address as a async_smtp `EmailAddress`. It will be `None` if
-	/// the email address is ill-formed.
+	/// The email address as a async_smtp `EmailAddress`, with the domain in
+	/// its ASCII form. It will be `None` if the email address is ill-formed.
 	pub address: Option<EmailAddress>,
-	/// The domain name, after "@". It will be the empty string if the email
+	/// The domain name, after "@", in its ASCII form (punycode for
+	/// internationalized domains). It will be the empty string if the email
 	/// address if ill-formed.
 	pub domain: String,
+	/// The domain name in its Unicode form. Only set for internationalized
+	/// domain names.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub unicode_domain: Option<String>,
+	/// The IP address of IP literal domains, e.g. `192.168.1.1` for
+	/// `user@[192.168.1.1]`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub ip_literal: Option<IpAddr>,
 	/// Does the email have a valid syntax?
 	pub is_valid_syntax: bool,
 	/// The username, before "@". It will be the empty string if the email
@@ -39,7 +72,36 @@ pub struct SyntaxDetails {
 	/// The normalized form of `address`. It will be `None` if the email
 	/// address is ill-formed.
 	pub normalized_email: Option<String>,
+	/// The most likely correction of the email address, if its domain looks
+	/// like a misspelled popular mail domain. Only computed when the
+	/// verification fails.
 	pub suggestion: Option<String>,
+	/// All the suggested corrections, ranked by decreasing confidence.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub suggestions: Vec<DomainSuggestion>,
+	/// Does the username contain a subaddress tag, e.g. `user+tag`? The
+	/// separator depends on the provider: `+` for most of them, `-` for
+	/// Yahoo.
+	pub is_subaddressed: bool,
+	/// The address without its subaddress tag, e.g. `user@example.org` for
+	/// `user+tag@example.org`. It will be `None` if the email address is
+	/// ill-formed.
+	pub base_address: Option<String>,
+	/// The subaddress tag, e.g. `tag` for `user+tag@example.org`.
+	pub subaddress_tag: Option<String>,
+	/// Does the username contain non-ASCII characters? If so, the SMTP
+	/// server must support the SMTPUTF8 extension (RFC 6531) to receive
+	/// emails for this address.
+	pub requires_smtputf8: bool,
+	/// The syntax rules the email address violates. It's empty if the syntax
+	/// is valid.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub syntax_violations: Vec<SyntaxViolation>,
+	/// The transformations applied to the raw input to get `address`, as
+	/// configured by the input's `cleanup_policy`. Only reported for valid
+	/// email addresses.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub transformations: Vec<Transformation>,
 }
 
 impl Default for SyntaxDetails {
@@ -47,96 +109,281 @@ impl Default for SyntaxDetails {
 		SyntaxDetails {
 			address: None,
 			domain: "".into(),
+			unicode_domain: None,
+			ip_literal: None,
 			is_valid_syntax: false,
 			username: "".into(),
 			normalized_email: None,
 			suggestion: None,
+			suggestions: Vec::new(),
+			is_subaddressed: false,
+			requires_smtputf8: false,
+			base_address: None,
+			subaddress_tag: None,
+			syntax_violations: Vec::new(),
+			transformations: Vec::new(),
 		}
 	}
 }
 
+/// Parse an IP literal domain (RFC 5321 section 4.1.3), e.g. `[192.168.1.1]`
+/// or `[IPv6:2001:db8::1]`. Returns None if the domain is not an IP literal,
+/// and Some(None) if it's an invalid one.
+fn parse_ip_literal(domain: &str) -> Option<Option<IpAddr>> {
+	let literal = domain.strip_prefix('[')?.strip_suffix(']')?;
+	let ip = match literal.get(..5) {
+		Some(tag) if tag.eq_ignore_ascii_case("IPv6:") => literal[5..].parse().ok().map(IpAddr::V6),
+		_ => literal.parse().ok().map(IpAddr::V4),
+	};
+
+	Some(ip)
+}
+
+/// Convert an internationalized domain to its ASCII (punycode) form. ASCII
+/// domains are returned as is.
+fn to_ascii_domain(domain: &str) -> Option<String> {
+	if domain.is_ascii() {
+		return Some(domain.into());
+	}
+
+	Name::from_utf8(domain).ok().map(|name| name.to_ascii())
+}
+
+/// Convert an ASCII domain to its Unicode form, if it contains punycode
+/// labels.
+fn to_unicode_domain(ascii_domain: &str) -> Option<String> {
+	if !ascii_domain
+		.split('.')
+		.any(|label| label.to_lowercase().starts_with("xn--"))
+	{
+		return None;
+	}
+
+	Name::from_ascii(ascii_domain)
+		.ok()
+		.map(|name| name.to_utf8())
+}
+
+/// Build an `EmailAddress` from an already validated address.
+fn to_email_address(address: String) -> Option<EmailAddress> {
+	if let Ok(address) = EmailAddress::new(address.clone()) {
+		return Some(address);
+	}
+
+	// async-smtp's `EmailAddress::new` rejects all non-ASCII characters and
+	// whitespace, while RFC 6531 (SMTPUTF8) and quoted local parts allow
+	// them. Its `Deserialize` impl doesn't do any validation.
+	serde_json::from_value(serde_json::Value::String(address)).ok()
+}
+
+/// Syntax details of an invalid email address.
+fn invalid(syntax_violations: Vec<SyntaxViolation>) -> SyntaxDetails {
+	SyntaxDetails {
+		syntax_violations,
+		..Default::default()
+	}
+}
+
 /// From an `email_address` string, compute syntax information about it, such as
-/// username and domain.
+/// username and domain. No network call is made.
 pub fn check_syntax(email_address: &str) -> SyntaxDetails {
-	let email_address = match EmailAddress::from_str(email_address) {
-		Ok(m) => {
-			if mailchecker::is_valid(email_address) {
-				m
-			} else {
-				return SyntaxDetails {
-					address: None,
-					domain: "".into(),
-					is_valid_syntax: false,
-					username: "".into(),
-					normalized_email: None,
-					suggestion: None,
-				};
-			}
+	check_syntax_with_input(email_address, &DEFAULT_INPUT)
+}
+
+/// Run `check_syntax` on many email addresses. Like `check_syntax`, this
+/// function doesn't make any network call, so it can be used to cheaply
+/// pre-filter a list before running the full verification.
+pub fn check_syntax_batch<I, S>(email_addresses: I) -> Vec<SyntaxDetails>
+where
+	I: IntoIterator<Item = S>,
+	S: AsRef<str>,
+{
+	email_addresses
+		.into_iter()
+		.map(|email_address| check_syntax_with_input(email_address.as_ref(), &DEFAULT_INPUT))
+		.collect()
+}
+
+/// Same as `check_syntax`, with the syntax options of `input`.
+pub(crate) fn check_syntax_with_input(
+	email_address: &str,
+	input: &CheckEmailInput,
+) -> SyntaxDetails {
+	check_syntax_inner(email_address, input, true)
+}
+
+/// Same as `check_syntax_with_input`, asking `provider`, if any, whether the
+/// domain is disposable, and falling back to the context's or the bundled
+/// list if it doesn't know. Also returns the provider's answer, for the misc
+/// checks.
+pub(crate) async fn check_syntax_with_list_provider(
+	email_address: &str,
+	input: &CheckEmailInput,
+	provider: Option<&dyn ListProvider>,
+) -> (SyntaxDetails, Option<bool>) {
+	let provider = match provider {
+		Some(provider) => provider,
+		None => return (check_syntax_with_input(email_address, input), None),
+	};
+	let syntax = check_syntax_inner(email_address, input, false);
+	if !syntax.is_valid_syntax || syntax.ip_literal.is_some() {
+		return (syntax, None);
+	}
+
+	let provider_disposable = provider.is_disposable(&syntax.domain).await;
+	let is_disposable = provider_disposable.unwrap_or_else(|| {
+		is_disposable(&syntax.domain, input.context.disposable_list().as_deref())
+	});
+	if is_disposable {
+		(
+			invalid(vec![SyntaxViolation::DisposableDomain]),
+			provider_disposable,
+		)
+	} else {
+		(syntax, provider_disposable)
+	}
+}
+
+/// Compute the syntax details, flagging the disposable domains with the
+/// context's or the bundled list if `check_disposable` is set.
+fn check_syntax_inner(
+	email_address: &str,
+	input: &CheckEmailInput,
+	check_disposable: bool,
+) -> SyntaxDetails {
+	if input.reject_non_ascii && !email_address.is_ascii() {
+		return invalid(vec![SyntaxViolation::NonAsciiCharacters]);
+	}
+
+	let (email_address, mut transformations) = clean_up(email_address, input.cleanup_policy);
+	let email_address = email_address.as_str();
+
+	let (username, domain) = match email_address.rsplit_once('@') {
+		Some(parts) => parts,
+		None => return invalid(vec![SyntaxViolation::MissingAt]),
+	};
+	let ip_literal = match parse_ip_literal(domain) {
+		Some(_) if input.reject_ip_literals => {
+			return invalid(vec![SyntaxViolation::IpLiteralDomain])
 		}
-		_ => {
-			return SyntaxDetails {
-				address: None,
-				domain: "".into(),
-				is_valid_syntax: false,
-				username: "".into(),
-				normalized_email: None,
-				suggestion: None,
-			}
+		Some(Some(ip)) => Some(ip),
+		Some(None) => return invalid(vec![SyntaxViolation::InvalidDomain]),
+		None => None,
+	};
+	let ascii_domain = match to_ascii_domain(domain) {
+		Some(ascii_domain) => ascii_domain,
+		None => return invalid(vec![SyntaxViolation::InvalidDomain]),
+	};
+
+	let mut violations = find_violations(username, &ascii_domain, input.syntax_strictness);
+	violations.extend(input.local_part_policy.violations(username));
+	if violations.is_empty()
+		&& ip_literal.is_none()
+		&& check_disposable
+		&& is_disposable(&ascii_domain, input.context.disposable_list().as_deref())
+	{
+		violations.push(SyntaxViolation::DisposableDomain);
+	}
+	if input.check_tld && ip_literal.is_none() {
+		let tld_list = input
+			.tld_list
+			.as_ref()
+			.unwrap_or_else(|| default_tld_list());
+		if !tld_list.is_valid_domain(&ascii_domain) {
+			violations.push(SyntaxViolation::UnknownTld);
 		}
+	}
+	if !violations.is_empty() {
+		return invalid(violations);
+	}
+
+	let address = match to_email_address(format!("{username}@{ascii_domain}")) {
+		Some(address) => address,
+		None => return invalid(vec![SyntaxViolation::InvalidLocalPart]),
 	};
+	if ascii_domain != domain {
+		transformations.push(Transformation::DomainToAscii);
+	}
 
-	let iter: &str = email_address.as_ref();
-	let mut iter = iter.split('@');
-	let username: String = iter
-		.next()
-		.expect("We checked above that email is valid. qed.")
-		.into();
-	let domain: String = iter
-		.next()
-		.expect("We checked above that email is valid. qed.")
-		.into();
-	let normalized_email = normalize_email(&username, &domain);
+	let normalized_email = normalize_email(username, &ascii_domain, &input.normalization_rules);
+	let subaddress = split_subaddress(username, &ascii_domain);
+	let base_address = format!(
+		"{}@{}",
+		subaddress.map(|(base, _)| base).unwrap_or(username),
+		ascii_domain
+	);
 
 	SyntaxDetails {
-		address: Some(email_address),
-		domain,
+		address: Some(address),
+		unicode_domain: to_unicode_domain(&ascii_domain),
+		domain: ascii_domain,
+		ip_literal,
 		is_valid_syntax: true,
-		username,
+		username: username.into(),
 		normalized_email: Some(normalized_email),
 		suggestion: None,
+		suggestions: Vec::new(),
+		is_subaddressed: subaddress.is_some(),
+		base_address: Some(base_address),
+		subaddress_tag: subaddress.map(|(_, tag)| tag.into()),
+		requires_smtputf8: requires_smtputf8(email_address),
+		syntax_violations: Vec::new(),
+		transformations,
 	}
 }
 
-const MAIL_PROVIDERS: &[&str] = &[
-	"gmail.com",
-	"yahoo.com",
-	"outlook.com",
-	"hotmail.com",
-	"protonmail.com",
-	"icloud.com",
-	"yandex.com",
-];
-// Supplies the syntax parameter with a suggestion that matches the mail domain best by levenshtein
-// distance.
+/// Does `email_address` need the SMTPUTF8 extension to be delivered to, i.e.
+/// does its username contain non-ASCII characters? Internationalized domains
+/// don't, as they can be converted to ASCII.
+pub fn requires_smtputf8(email_address: &str) -> bool {
+	email_address
+		.rsplit_once('@')
+		.is_some_and(|(username, _)| !username.is_ascii())
+}
+
+/// Supplies the syntax parameter with suggestions of known mail domains close
+/// to the email's domain, using the built-in list of popular mail domains.
 pub fn get_similar_mail_provider(syntax: &mut SyntaxDetails) {
-	for possible_provider in MAIL_PROVIDERS {
-		let distance = levenshtein(&syntax.domain, possible_provider);
-
-		if distance < 3 {
-			// Return full address
-			syntax.suggestion = Some(format!(
-				"{}@{}",
-				syntax.username,
-				String::from(*possible_provider),
-			));
-			break;
+	suggest_similar_domains(syntax, DomainSuggester::default_suggester(), 3);
+}
+
+/// Same as `get_similar_mail_provider`, with the suggestion options of
+/// `input`.
+pub(crate) fn get_similar_mail_provider_with_input(
+	syntax: &mut SyntaxDetails,
+	input: &CheckEmailInput,
+) {
+	match &input.suggestion_domains {
+		Some(domains) => {
+			suggest_similar_domains(syntax, domains.suggester(), input.max_suggestions)
 		}
+		None => suggest_similar_domains(
+			syntax,
+			DomainSuggester::default_suggester(),
+			input.max_suggestions,
+		),
 	}
 }
 
+fn suggest_similar_domains(syntax: &mut SyntaxDetails, suggester: &DomainSuggester, max: usize) {
+	syntax.suggestions = suggester
+		.suggest(&syntax.domain, max)
+		.into_iter()
+		.map(|suggestion| DomainSuggestion {
+			address: format!("{}@{}", syntax.username, suggestion.domain),
+			..suggestion
+		})
+		.collect();
+	syntax.suggestion = syntax
+		.suggestions
+		.first()
+		.map(|suggestion| suggestion.address.clone());
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::util::input_output::SyntaxStrictness;
 
 	#[test]
 	fn should_return_invalid_for_invalid_email() {
@@ -149,6 +396,8 @@ mod tests {
 				username: "".into(),
 				normalized_email: None,
 				suggestion: None,
+				syntax_violations: vec![SyntaxViolation::MissingAt],
+				..Default::default()
 			}
 		);
 	}
@@ -164,6 +413,8 @@ mod tests {
 				username: "".into(),
 				normalized_email: None,
 				suggestion: None,
+				syntax_violations: vec![SyntaxViolation::MissingTld],
+				..Default::default()
 			}
 		);
 	}
@@ -179,6 +430,8 @@ mod tests {
 				username: "foo".into(),
 				normalized_email: Some("foo@bar.com".into()),
 				suggestion: None,
+				base_address: Some("foo@bar.com".into()),
+				..Default::default()
 			}
 		);
 	}
@@ -192,8 +445,148 @@ mod tests {
 			username: "test".into(),
 			normalized_email: Some("test@gmali.com".into()),
 			suggestion: None,
+			..Default::default()
 		};
 		get_similar_mail_provider(&mut syntax);
 		assert_eq!(syntax.suggestion, Some("test@gmail.com".to_string()))
 	}
+
+	#[test]
+	fn should_check_syntax_in_batch() {
+		let syntaxes = check_syntax_batch(vec!["foo@bar.com", "foo"]);
+
+		assert_eq!(syntaxes.len(), 2);
+		assert!(syntaxes[0].is_valid_syntax);
+		assert!(!syntaxes[1].is_valid_syntax);
+	}
+
+	#[test]
+	fn should_parse_ip_literals() {
+		let syntax = check_syntax("user@[192.168.1.1]");
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.ip_literal, Some("192.168.1.1".parse().unwrap()));
+
+		let syntax = check_syntax("user@[IPv6:2001:db8::1]");
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.ip_literal, Some("2001:db8::1".parse().unwrap()));
+
+		assert_eq!(
+			check_syntax("user@[300.1.1.1]").syntax_violations,
+			vec![SyntaxViolation::InvalidDomain]
+		);
+
+		let mut input = CheckEmailInput::default();
+		input.set_reject_ip_literals(true);
+		assert_eq!(
+			check_syntax_with_input("user@[192.168.1.1]", &input).syntax_violations,
+			vec![SyntaxViolation::IpLiteralDomain]
+		);
+	}
+
+	#[test]
+	fn should_check_tld() {
+		assert!(check_syntax("user@example.con").is_valid_syntax);
+
+		let mut input = CheckEmailInput::default();
+		input.set_check_tld(true);
+		assert_eq!(
+			check_syntax_with_input("user@example.con", &input).syntax_violations,
+			vec![SyntaxViolation::UnknownTld]
+		);
+		assert!(check_syntax_with_input("user@example.com", &input).is_valid_syntax);
+
+		input.set_tld_list(Some(TldList::parse("CON")));
+		assert!(check_syntax_with_input("user@example.con", &input).is_valid_syntax);
+	}
+
+	#[test]
+	fn should_apply_local_part_policy() {
+		let mut input = CheckEmailInput::default();
+		input.set_local_part_policy(LocalPartPolicy {
+			banned_patterns: vec!["^spam".parse().unwrap()],
+			..Default::default()
+		});
+
+		assert!(check_syntax_with_input("john@example.org", &input).is_valid_syntax);
+		assert_eq!(
+			check_syntax_with_input("spammer@example.org", &input).syntax_violations,
+			vec![SyntaxViolation::BannedPattern]
+		);
+	}
+
+	#[test]
+	fn should_report_transformations() {
+		let mut input = CheckEmailInput::default();
+		input.set_cleanup_policy(CleanupPolicy {
+			trim_whitespace: true,
+			lowercase_domain: true,
+			lowercase_username: true,
+		});
+
+		let syntax = check_syntax_with_input(" John@Müller.DE ", &input);
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.address.unwrap().to_string(), "john@xn--mller-kva.de");
+		assert_eq!(
+			syntax.transformations,
+			vec![
+				Transformation::TrimmedWhitespace,
+				Transformation::LowercasedUsername,
+				Transformation::LowercasedDomain,
+				Transformation::DomainToAscii
+			]
+		);
+		assert!(!check_syntax(" john@example.org").is_valid_syntax);
+	}
+
+	#[test]
+	fn should_detect_subaddress() {
+		let syntax = check_syntax("john.doe+news@gmail.com");
+		assert!(syntax.is_subaddressed);
+		assert_eq!(syntax.base_address, Some("john.doe@gmail.com".into()));
+		assert_eq!(syntax.subaddress_tag, Some("news".into()));
+
+		let syntax = check_syntax("john-doe@example.org");
+		assert!(!syntax.is_subaddressed);
+		assert_eq!(syntax.base_address, Some("john-doe@example.org".into()));
+		assert_eq!(syntax.subaddress_tag, None);
+	}
+
+	#[test]
+	fn should_support_idn() {
+		let syntax = check_syntax("jörg@müller.de");
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.username, "jörg");
+		assert_eq!(syntax.domain, "xn--mller-kva.de");
+		assert_eq!(syntax.unicode_domain, Some("müller.de".into()));
+		assert_eq!(syntax.address.unwrap().to_string(), "jörg@xn--mller-kva.de");
+		assert!(syntax.requires_smtputf8);
+		assert!(!check_syntax("jorg@müller.de").requires_smtputf8);
+
+		let syntax = check_syntax("用户@例子.广告");
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.domain, "xn--fsqu00a.xn--4rr70v");
+		assert_eq!(syntax.unicode_domain, Some("例子.广告".into()));
+
+		assert!(!check_syntax("jö rg@müller.de").is_valid_syntax);
+	}
+
+	#[test]
+	fn should_reject_non_ascii_if_asked() {
+		let mut input = CheckEmailInput::default();
+		input.set_reject_non_ascii(true);
+
+		assert!(!check_syntax_with_input("jörg@müller.de", &input).is_valid_syntax);
+		assert!(check_syntax_with_input("jorg@mueller.de", &input).is_valid_syntax);
+	}
+
+	#[test]
+	fn should_accept_quoted_local_part_with_rfc5322() {
+		let mut input = CheckEmailInput::default();
+		assert!(!check_syntax_with_input("\"john doe\"@example.org", &input).is_valid_syntax);
+
+		input.set_syntax_strictness(SyntaxStrictness::Rfc5322);
+		let syntax = check_syntax_with_input("\"john doe\"@example.org", &input);
+		assert!(syntax.is_valid_syntax);
+		assert_eq!(syntax.username, "\"john doe\"");
+	}
 }
@@ -1,70 +1,168 @@
-pub fn normalize_email(username: &str, domain: &str) -> String {
-	match domain {
-		"gmail.com" | "googlemail.com" => normalize_gmail(username),
-		_ => format!("{username}@{domain}"),
+use serde::{Deserialize, Serialize};
+
+/// How to canonicalize the addresses of an email provider, so that all the
+/// variants of an address which reach the same mailbox have the same
+/// normalized form.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NormalizationRule {
+	/// The lowercase domains this rule applies to.
+	pub domains: Vec<String>,
+	/// The domain to use in the normalized email, e.g. `gmail.com` for
+	/// `googlemail.com`. Defaults to the email's own domain.
+	pub canonical_domain: Option<String>,
+	/// Whether the provider treats usernames case-insensitively, in which
+	/// case they are lowercased.
+	pub lowercase_username: bool,
+	/// Whether the provider ignores dots in usernames, in which case they
+	/// are removed.
+	pub remove_dots: bool,
+	/// The character starting a subaddress tag, which is removed along with
+	/// the tag.
+	pub subaddress_separator: Option<char>,
+}
+
+impl NormalizationRule {
+	fn new(domains: &[&str]) -> Self {
+		NormalizationRule {
+			domains: domains.iter().map(|domain| domain.to_string()).collect(),
+			canonical_domain: None,
+			lowercase_username: true,
+			remove_dots: false,
+			subaddress_separator: Some('+'),
+		}
 	}
 }
 
-/// Normalize a Gmail address.
-///
-/// See Gmail username
-/// [restrictions](https://support.google.com/mail/answer/9211434?hl=en-GB).
-///
-/// - removes
-///   [sub-addresses](https://support.google.com/a/users/answer/9282734?hl=en#zippy=%2Clearn-how)
-///   (i.e. parts after a `+` character.)
-/// - removes [dots](https://support.google.com/mail/answer/7436150).
-/// - converts to lower-case.
-/// - [replaces](https://support.google.com/mail/answer/10313?hl=en-GB#zippy=%2Cgetting-messages-sent-to-an-googlemailcom-address)
-///   `googlemail.com` with `gmail.com`.
-fn normalize_gmail(username: &str) -> String {
-	let username = match username.split_once('+') {
+/// The built-in normalization rules of the main email providers. Add your
+/// own to `CheckEmailInput::normalization_rules` to support other providers.
+pub fn default_normalization_rules() -> Vec<NormalizationRule> {
+	vec![
+		// See Gmail username
+		// [restrictions](https://support.google.com/mail/answer/9211434?hl=en-GB).
+		//
+		// - removes
+		//   [sub-addresses](https://support.google.com/a/users/answer/9282734?hl=en#zippy=%2Clearn-how)
+		//   (i.e. parts after a `+` character.)
+		// - removes [dots](https://support.google.com/mail/answer/7436150).
+		// - converts to lower-case.
+		// - [replaces](https://support.google.com/mail/answer/10313?hl=en-GB#zippy=%2Cgetting-messages-sent-to-an-googlemailcom-address)
+		//   `googlemail.com` with `gmail.com`.
+		NormalizationRule {
+			canonical_domain: Some("gmail.com".into()),
+			remove_dots: true,
+			..NormalizationRule::new(&["gmail.com", "googlemail.com"])
+		},
+		NormalizationRule::new(&["outlook.com", "hotmail.com", "live.com", "msn.com"]),
+		NormalizationRule::new(&["icloud.com", "me.com", "mac.com"]),
+		NormalizationRule::new(&["protonmail.com", "proton.me", "pm.me"]),
+		NormalizationRule::new(&["fastmail.com"]),
+		// Yahoo disposable addresses look like `basename-keyword@yahoo.com`.
+		NormalizationRule {
+			subaddress_separator: Some('-'),
+			..NormalizationRule::new(&["yahoo.com", "ymail.com", "rocketmail.com"])
+		},
+	]
+}
+
+/// Normalize an email address, using the first rule matching its domain.
+/// The domain is always lowercased, while the username is left untouched if
+/// no rule matches, as usernames are case-sensitive by default.
+pub fn normalize_email(username: &str, domain: &str, rules: &[NormalizationRule]) -> String {
+	let domain = domain.to_lowercase();
+	let rule = match rules.iter().find(|rule| rule.domains.contains(&domain)) {
+		Some(rule) => rule,
+		None => return format!("{username}@{domain}"),
+	};
+
+	let username = match rule
+		.subaddress_separator
+		.and_then(|separator| username.split_once(separator))
+	{
 		Some((username, _)) => username,
 		_ => username,
-	}
-	.chars()
-	.filter_map(|c| match c.to_ascii_lowercase() {
-		'.' => None,
-		lower => Some(lower),
-	})
-	.collect::<String>();
-
-	format!("{username}@gmail.com",)
+	};
+	let username = username
+		.chars()
+		.filter(|c| !(rule.remove_dots && *c == '.'))
+		.collect::<String>();
+	let username = if rule.lowercase_username {
+		username.to_lowercase()
+	} else {
+		username
+	};
+
+	format!(
+		"{}@{}",
+		username,
+		rule.canonical_domain.as_deref().unwrap_or(&domain)
+	)
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	fn normalize(username: &str, domain: &str) -> String {
+		normalize_email(username, domain, &default_normalization_rules())
+	}
+
 	#[test]
 	fn test_gmail_removes_periods() {
-		assert_eq!(normalize_email("a.b.c", "gmail.com"), "abc@gmail.com");
+		assert_eq!(normalize("a.b.c", "gmail.com"), "abc@gmail.com");
 	}
 
 	#[test]
 	fn test_gmail_removes_subaddress() {
-		assert_eq!(normalize_email("abc+123", "gmail.com"), "abc@gmail.com");
+		assert_eq!(normalize("abc+123", "gmail.com"), "abc@gmail.com");
 	}
 
 	#[test]
 	fn test_gmail_uses_gmail_com() {
-		assert_eq!(normalize_email("abc", "googlemail.com"), "abc@gmail.com");
+		assert_eq!(normalize("abc", "googlemail.com"), "abc@gmail.com");
 	}
 
 	#[test]
 	fn test_gmail() {
-		assert_eq!(
-			normalize_email("A.B.C+123", "googlemail.com"),
-			"abc@gmail.com"
-		);
+		assert_eq!(normalize("A.B.C+123", "googlemail.com"), "abc@gmail.com");
 	}
 
 	#[test]
 	fn test_gmail_idempotent() {
-		let normalized = normalize_email("A.B.C+123", "googlemail.com");
+		let normalized = normalize("A.B.C+123", "googlemail.com");
 
 		let (username, domain) = normalized.rsplit_once('@').unwrap();
 
-		assert_eq!(normalize_email(username, domain), normalized);
+		assert_eq!(normalize(username, domain), normalized);
+	}
+
+	#[test]
+	fn test_other_providers() {
+		assert_eq!(
+			normalize("John.Doe+x", "Outlook.com"),
+			"john.doe@outlook.com"
+		);
+		assert_eq!(normalize("john-shopping", "yahoo.com"), "john@yahoo.com");
+		// Unknown providers keep the username's case.
+		assert_eq!(
+			normalize("John.Doe+x", "Example.org"),
+			"John.Doe+x@example.org"
+		);
+	}
+
+	#[test]
+	fn test_custom_rule() {
+		let mut rules = default_normalization_rules();
+		rules.push(NormalizationRule {
+			domains: vec!["example.org".into()],
+			canonical_domain: None,
+			lowercase_username: false,
+			remove_dots: false,
+			subaddress_separator: Some('_'),
+		});
+
+		assert_eq!(
+			normalize_email("John_x", "example.org", &rules),
+			"John@example.org"
+		);
 	}
 }
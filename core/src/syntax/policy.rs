@@ -0,0 +1,200 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! User-defined restrictions on the local part, on top of the RFC grammar.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::SyntaxViolation;
+
+/// A class of characters allowed in the local part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalPartCharClass {
+	/// ASCII letters, a-z and A-Z.
+	Letters,
+	/// ASCII digits, 0-9.
+	Digits,
+	/// The "." character.
+	Dot,
+	/// The "-" character.
+	Hyphen,
+	/// The "_" character.
+	Underscore,
+	/// The "+" character.
+	Plus,
+	/// All other ASCII characters allowed by the RFCs, e.g. "!", "#" or "'".
+	OtherSpecial,
+	/// Non-ASCII characters.
+	NonAscii,
+}
+
+impl LocalPartCharClass {
+	fn of(c: char) -> Self {
+		match c {
+			'a'..='z' | 'A'..='Z' => Self::Letters,
+			'0'..='9' => Self::Digits,
+			'.' => Self::Dot,
+			'-' => Self::Hyphen,
+			'_' => Self::Underscore,
+			'+' => Self::Plus,
+			c if c.is_ascii() => Self::OtherSpecial,
+			_ => Self::NonAscii,
+		}
+	}
+}
+
+/// A regular expression that the local part must not match, as in the
+/// policy's `banned_patterns`. It's compiled once, when the pattern is
+/// parsed, so invalid regular expressions are rejected then.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LocalPartPattern(Regex);
+
+impl LocalPartPattern {
+	pub fn as_str(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl FromStr for LocalPartPattern {
+	type Err = regex::Error;
+
+	fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+		Regex::new(pattern).map(LocalPartPattern)
+	}
+}
+
+impl TryFrom<String> for LocalPartPattern {
+	type Error = regex::Error;
+
+	fn try_from(pattern: String) -> Result<Self, Self::Error> {
+		pattern.parse()
+	}
+}
+
+impl From<LocalPartPattern> for String {
+	fn from(pattern: LocalPartPattern) -> Self {
+		pattern.as_str().to_string()
+	}
+}
+
+impl fmt::Display for LocalPartPattern {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl PartialEq for LocalPartPattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+impl Eq for LocalPartPattern {}
+
+/// Restrictions on the local part, before "@", of valid email addresses.
+/// The default policy doesn't add any restriction.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LocalPartPolicy {
+	/// Maximum number of characters of the local part.
+	pub max_length: Option<usize>,
+	/// The only classes of characters allowed in the local part. None allows
+	/// all characters accepted by the syntax strictness mode.
+	pub allowed_chars: Option<Vec<LocalPartCharClass>>,
+	/// Regexes that the local part must not match, e.g. `^(spam|test\d+)$`.
+	pub banned_patterns: Vec<LocalPartPattern>,
+}
+
+impl LocalPartPolicy {
+	/// Find all the restrictions of this policy violated by `local_part`.
+	pub(crate) fn violations(&self, local_part: &str) -> Vec<SyntaxViolation> {
+		let mut violations = Vec::new();
+
+		if self
+			.max_length
+			.is_some_and(|max_length| local_part.chars().count() > max_length)
+		{
+			violations.push(SyntaxViolation::LocalPartPolicyTooLong);
+		}
+		if let Some(allowed_chars) = &self.allowed_chars {
+			if !local_part
+				.chars()
+				.all(|c| allowed_chars.contains(&LocalPartCharClass::of(c)))
+			{
+				violations.push(SyntaxViolation::DisallowedCharacter);
+			}
+		}
+		if self
+			.banned_patterns
+			.iter()
+			.any(|pattern| pattern.0.is_match(local_part))
+		{
+			violations.push(SyntaxViolation::BannedPattern);
+		}
+
+		violations
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_apply_policy() {
+		let policy = LocalPartPolicy {
+			max_length: Some(10),
+			allowed_chars: Some(vec![
+				LocalPartCharClass::Letters,
+				LocalPartCharClass::Digits,
+				LocalPartCharClass::Dot,
+			]),
+			banned_patterns: vec![r"^(spam|test\d+)".parse().unwrap()],
+		};
+
+		assert!(policy.violations("john.doe").is_empty());
+		assert_eq!(
+			policy.violations("john.doe.smith"),
+			vec![SyntaxViolation::LocalPartPolicyTooLong]
+		);
+		assert_eq!(
+			policy.violations("john+doe"),
+			vec![SyntaxViolation::DisallowedCharacter]
+		);
+		assert_eq!(
+			policy.violations("test42"),
+			vec![SyntaxViolation::BannedPattern]
+		);
+		assert!(LocalPartPolicy::default()
+			.violations("any+thing")
+			.is_empty());
+	}
+
+	#[test]
+	fn should_reject_invalid_local_part_patterns() {
+		assert!("(".parse::<LocalPartPattern>().is_err());
+		assert!(serde_json::from_str::<LocalPartPolicy>(
+			r#"{"max_length": null, "allowed_chars": null, "banned_patterns": ["^spam", "("]}"#
+		)
+		.is_err());
+	}
+}
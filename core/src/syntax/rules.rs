@@ -0,0 +1,237 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The grammar rules an email address must follow, depending on the
+//! strictness mode.
+
+use serde::{Deserialize, Serialize};
+
+use crate::util::input_output::SyntaxStrictness;
+
+/// Maximum length of the local part, per RFC 5321 section 4.5.3.1.1.
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+/// Maximum length of an address, per RFC 5321 section 4.5.3.1.3 (a path is
+/// 256 octets, including the angle brackets).
+const MAX_ADDRESS_LENGTH: usize = 254;
+/// Maximum length of a domain, per RFC 1035.
+const MAX_DOMAIN_LENGTH: usize = 253;
+/// Maximum length of a domain label, per RFC 1035.
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// A syntax rule that an email address violates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyntaxViolation {
+	/// There's no "@" in the address.
+	MissingAt,
+	/// The address contains non-ASCII characters, and the input asked to
+	/// reject them.
+	NonAsciiCharacters,
+	/// The local part, before "@", is empty.
+	EmptyLocalPart,
+	/// The local part is longer than 64 octets (RFC 5321 only).
+	LocalPartTooLong,
+	/// The local part is a quoted string, e.g. `"john doe"@example.org`
+	/// (RFC 5321 only).
+	QuotedLocalPart,
+	/// The local part contains forbidden characters, or misplaced dots.
+	InvalidLocalPart,
+	/// The domain, after "@", is empty.
+	EmptyDomain,
+	/// The domain is longer than 253 octets.
+	DomainTooLong,
+	/// The domain has an empty label, a label longer than 63 octets, or
+	/// forbidden characters.
+	InvalidDomain,
+	/// The domain has no top-level domain, e.g. `user@localhost` (RFC 5321
+	/// only).
+	MissingTld,
+	/// The address is longer than 254 octets (RFC 5321 only).
+	AddressTooLong,
+	/// The domain is a known disposable email provider.
+	DisposableDomain,
+	/// The domain is an IP literal, e.g. `user@[192.168.1.1]`, and the input
+	/// asked to reject them.
+	IpLiteralDomain,
+	/// The domain's TLD is not in the IANA list. Only checked when the input
+	/// asks for it.
+	UnknownTld,
+	/// The local part is longer than the input's `local_part_policy`
+	/// allows.
+	LocalPartPolicyTooLong,
+	/// The local part contains characters not allowed by the input's
+	/// `local_part_policy`.
+	DisallowedCharacter,
+	/// The local part matches one of the banned patterns of the input's
+	/// `local_part_policy`.
+	BannedPattern,
+}
+
+/// Characters allowed in an atom (RFC 5322 section 3.2.3). RFC 6531 adds all
+/// non-ASCII characters.
+fn is_atext(c: char) -> bool {
+	c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+/// Check a dot-atom, e.g. `john.doe`.
+fn is_dot_atom(local_part: &str) -> bool {
+	local_part
+		.split('.')
+		.all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+/// Check a quoted string, e.g. `"john doe"` (RFC 5322 section 3.2.4),
+/// without folding whitespace.
+fn is_quoted_string(local_part: &str) -> bool {
+	let content = match local_part
+		.strip_prefix('"')
+		.and_then(|s| s.strip_suffix('"'))
+	{
+		Some(content) => content,
+		None => return false,
+	};
+
+	let mut chars = content.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			// quoted-pair: a backslash followed by a printable character or
+			// a space.
+			'\\' => match chars.next() {
+				Some(escaped)
+					if escaped == ' ' || escaped == '\t' || escaped.is_ascii_graphic() => {}
+				_ => return false,
+			},
+			'"' => return false,
+			c if c == ' ' || c == '\t' || c.is_ascii_graphic() || !c.is_ascii() => {
+				if c.is_control() {
+					return false;
+				}
+			}
+			_ => return false,
+		}
+	}
+
+	true
+}
+
+/// Find the violations of the local part.
+fn local_part_violations(local_part: &str, strictness: SyntaxStrictness) -> Vec<SyntaxViolation> {
+	if local_part.is_empty() {
+		return vec![SyntaxViolation::EmptyLocalPart];
+	}
+
+	let mut violations = Vec::new();
+	if strictness == SyntaxStrictness::Rfc5321 && local_part.len() > MAX_LOCAL_PART_LENGTH {
+		violations.push(SyntaxViolation::LocalPartTooLong);
+	}
+	if local_part.starts_with('"') && local_part.len() > 1 && local_part.ends_with('"') {
+		if strictness == SyntaxStrictness::Rfc5321 {
+			violations.push(SyntaxViolation::QuotedLocalPart);
+		} else if !is_quoted_string(local_part) {
+			violations.push(SyntaxViolation::InvalidLocalPart);
+		}
+	} else if !is_dot_atom(local_part) {
+		violations.push(SyntaxViolation::InvalidLocalPart);
+	}
+
+	violations
+}
+
+/// Find the violations of the domain, given in its ASCII form.
+fn domain_violations(domain: &str, strictness: SyntaxStrictness) -> Vec<SyntaxViolation> {
+	if domain.is_empty() {
+		return vec![SyntaxViolation::EmptyDomain];
+	}
+
+	let mut violations = Vec::new();
+	if domain.len() > MAX_DOMAIN_LENGTH {
+		violations.push(SyntaxViolation::DomainTooLong);
+	}
+	let is_valid_label = |label: &str| {
+		!label.is_empty()
+			&& label.len() <= MAX_LABEL_LENGTH
+			&& !label.starts_with('-')
+			&& !label.ends_with('-')
+			&& label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+	};
+	if !domain.split('.').all(is_valid_label) {
+		violations.push(SyntaxViolation::InvalidDomain);
+	}
+	if strictness == SyntaxStrictness::Rfc5321 && !domain.contains('.') {
+		violations.push(SyntaxViolation::MissingTld);
+	}
+
+	violations
+}
+
+/// Find all syntax rules violated by an email address, split in its local
+/// part and its domain in ASCII form. IP literal domains are validated
+/// separately.
+pub(crate) fn find_violations(
+	local_part: &str,
+	ascii_domain: &str,
+	strictness: SyntaxStrictness,
+) -> Vec<SyntaxViolation> {
+	let mut violations = local_part_violations(local_part, strictness);
+	if !ascii_domain.starts_with('[') {
+		violations.extend(domain_violations(ascii_domain, strictness));
+	}
+	// +1 for the "@".
+	if strictness == SyntaxStrictness::Rfc5321
+		&& local_part.len() + ascii_domain.len() + 1 > MAX_ADDRESS_LENGTH
+	{
+		violations.push(SyntaxViolation::AddressTooLong);
+	}
+
+	violations
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_accept_quoted_local_part_in_rfc5322_only() {
+		assert_eq!(
+			find_violations("\"john doe\"", "example.org", SyntaxStrictness::Rfc5321),
+			vec![SyntaxViolation::QuotedLocalPart]
+		);
+		assert_eq!(
+			find_violations("\"john doe\"", "example.org", SyntaxStrictness::Rfc5322),
+			vec![]
+		);
+		assert_eq!(
+			find_violations("\"john\"doe\"", "example.org", SyntaxStrictness::Rfc5322),
+			vec![SyntaxViolation::InvalidLocalPart]
+		);
+	}
+
+	#[test]
+	fn should_report_all_violations() {
+		assert_eq!(
+			find_violations("john..doe", "-example", SyntaxStrictness::Rfc5321),
+			vec![
+				SyntaxViolation::InvalidLocalPart,
+				SyntaxViolation::InvalidDomain,
+				SyntaxViolation::MissingTld
+			]
+		);
+		assert_eq!(
+			find_violations(&"a".repeat(65), "localhost", SyntaxStrictness::Rfc5322),
+			vec![]
+		);
+	}
+}
@@ -0,0 +1,71 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Domains of Yahoo, whose disposable addresses use a `-` separator, e.g.
+/// `basename-keyword@yahoo.com`.
+const YAHOO_DOMAINS: &[&str] = &["ymail.com", "rocketmail.com"];
+
+/// The character separating the base username from the tag, for a given
+/// domain. Defaults to `+`, used by most providers (Gmail, Outlook,
+/// Fastmail, ProtonMail...).
+fn subaddress_separator(domain: &str) -> char {
+	let domain = domain.to_lowercase();
+	if domain.starts_with("yahoo.") || YAHOO_DOMAINS.contains(&domain.as_str()) {
+		'-'
+	} else {
+		'+'
+	}
+}
+
+/// Split a username into its base username and its subaddress tag, e.g.
+/// `user+tag` into `user` and `tag`. Returns None if the username has no
+/// tag.
+pub fn split_subaddress<'a>(username: &'a str, domain: &str) -> Option<(&'a str, &'a str)> {
+	// Don't look inside quoted local parts.
+	if username.starts_with('"') {
+		return None;
+	}
+
+	match username.split_once(subaddress_separator(domain)) {
+		Some((base, tag)) if !base.is_empty() => Some((base, tag)),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_split_subaddress() {
+		assert_eq!(
+			split_subaddress("user+tag", "gmail.com"),
+			Some(("user", "tag"))
+		);
+		assert_eq!(
+			split_subaddress("user+tag+more", "example.org"),
+			Some(("user", "tag+more"))
+		);
+		assert_eq!(split_subaddress("user-tag", "gmail.com"), None);
+		assert_eq!(
+			split_subaddress("user-tag", "yahoo.co.uk"),
+			Some(("user", "tag"))
+		);
+		assert_eq!(split_subaddress("user+tag", "yahoo.com"), None);
+		assert_eq!(split_subaddress("+tag", "gmail.com"), None);
+		assert_eq!(split_subaddress("\"user+tag\"", "gmail.com"), None);
+	}
+}
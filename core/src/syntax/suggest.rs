@@ -0,0 +1,349 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Suggest corrections for misspelled email domains, e.g. `gmail.com` for
+//! `gmial.com`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+
+/// Popular mail domains, one per line, most popular first.
+const MAIL_DOMAINS: &str = include_str!("./mail_domains.txt");
+
+/// Suggester seeded with the built-in list of popular mail domains. As we
+/// only know their ranks, their frequency follows Zipf's law.
+static DEFAULT_SUGGESTER: Lazy<DomainSuggester> =
+	Lazy::new(|| {
+		DomainSuggester::new(MAIL_DOMAINS.lines().enumerate().map(|(rank, domain)| {
+			DomainFrequency {
+				domain: domain.into(),
+				frequency: 1_000_000 / (rank as u64 + 1),
+			}
+		}))
+	});
+
+/// A known mail domain, with how often it's used. Frequencies are only
+/// compared relatively to each other, so any unit works.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DomainFrequency {
+	pub domain: String,
+	pub frequency: u64,
+}
+
+/// Known mail domains, as in the input's `suggestion_domains`. Their trie
+/// is built once, on the first suggestion, and shared by the clones.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "Vec<DomainFrequency>", into = "Vec<DomainFrequency>")]
+pub struct SuggestionDomains {
+	domains: Vec<DomainFrequency>,
+	suggester: Arc<OnceCell<DomainSuggester>>,
+}
+
+impl SuggestionDomains {
+	pub fn domains(&self) -> &[DomainFrequency] {
+		&self.domains
+	}
+
+	/// The suggester of these domains, built on the first call.
+	pub(crate) fn suggester(&self) -> &DomainSuggester {
+		self.suggester
+			.get_or_init(|| DomainSuggester::new(self.domains.iter().cloned()))
+	}
+}
+
+impl From<Vec<DomainFrequency>> for SuggestionDomains {
+	fn from(domains: Vec<DomainFrequency>) -> Self {
+		SuggestionDomains {
+			domains,
+			suggester: Arc::default(),
+		}
+	}
+}
+
+impl From<SuggestionDomains> for Vec<DomainFrequency> {
+	fn from(domains: SuggestionDomains) -> Self {
+		domains.domains
+	}
+}
+
+impl PartialEq for SuggestionDomains {
+	fn eq(&self, other: &Self) -> bool {
+		self.domains == other.domains
+	}
+}
+
+impl Eq for SuggestionDomains {}
+
+/// A suggested correction of the email address.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DomainSuggestion {
+	/// The full suggested email address.
+	pub address: String,
+	/// The suggested domain.
+	pub domain: String,
+	/// Number of edits (insertion, deletion, substitution or transposition
+	/// of adjacent characters) between the email's domain and this one.
+	pub distance: usize,
+	/// How likely this suggestion is the intended address, between 0 and
+	/// 100. It's higher for closer and more popular domains.
+	pub confidence: u8,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+	children: BTreeMap<char, TrieNode>,
+	/// Set if a domain ends at this node.
+	frequency: Option<u64>,
+}
+
+/// Finds the known domains closest to a misspelled one, by walking a trie of
+/// known domains while computing the edit distance.
+#[derive(Debug, Default)]
+pub struct DomainSuggester {
+	root: TrieNode,
+	max_frequency: u64,
+}
+
+/// A domain found within the maximum distance.
+struct Candidate {
+	domain: String,
+	distance: usize,
+	frequency: u64,
+}
+
+impl DomainSuggester {
+	/// Create a suggester from a list of known domains.
+	pub fn new<I: IntoIterator<Item = DomainFrequency>>(domains: I) -> Self {
+		let mut suggester = DomainSuggester::default();
+		for DomainFrequency { domain, frequency } in domains {
+			let mut node = &mut suggester.root;
+			for c in domain.trim().to_lowercase().chars() {
+				node = node.children.entry(c).or_default();
+			}
+			node.frequency = Some(frequency);
+			suggester.max_frequency = suggester.max_frequency.max(frequency);
+		}
+
+		suggester
+	}
+
+	/// The suggester seeded with the built-in list of popular mail domains.
+	pub fn default_suggester() -> &'static DomainSuggester {
+		&DEFAULT_SUGGESTER
+	}
+
+	/// Suggest up to `max_suggestions` known domains close to `domain`,
+	/// closest first, then most popular first. Known domains are not considered
+	/// misspelled, so they have no suggestion.
+	///
+	/// At most 1 edit is allowed for domains shorter than 8 characters, and 2
+	/// for longer ones.
+	pub fn suggest(&self, domain: &str, max_suggestions: usize) -> Vec<DomainSuggestion> {
+		let word = domain.to_lowercase().chars().collect::<Vec<_>>();
+		let max_distance = if word.len() < 8 { 1 } else { 2 };
+
+		let mut candidates = Vec::new();
+		let first_row = (0..=word.len()).collect::<Vec<_>>();
+		for (c, child) in &self.root.children {
+			self.search(
+				child,
+				*c,
+				None,
+				&word,
+				&first_row,
+				None,
+				&mut c.to_string(),
+				max_distance,
+				&mut candidates,
+			);
+		}
+
+		if candidates.iter().any(|candidate| candidate.distance == 0) {
+			return Vec::new();
+		}
+
+		candidates.sort_by(|a, b| {
+			a.distance
+				.cmp(&b.distance)
+				.then(b.frequency.cmp(&a.frequency))
+				.then(a.domain.cmp(&b.domain))
+		});
+		let mut suggestions = candidates
+			.into_iter()
+			.map(|candidate| {
+				let similarity = 1.0 - candidate.distance as f64 / word.len().max(1) as f64;
+				// Use a log scale, as domain popularity is very skewed.
+				let popularity = ((1 + candidate.frequency) as f64).ln()
+					/ ((1 + self.max_frequency) as f64).ln().max(f64::EPSILON);
+				let confidence = similarity.max(0.0) * (0.5 + 0.5 * popularity);
+
+				DomainSuggestion {
+					address: String::new(),
+					domain: candidate.domain,
+					distance: candidate.distance,
+					confidence: (confidence * 100.0).round() as u8,
+				}
+			})
+			.collect::<Vec<_>>();
+		suggestions.truncate(max_suggestions);
+
+		suggestions
+	}
+
+	/// Compute the next row of the (optimal string alignment) edit distance
+	/// matrix for the trie `node` reached with character `c`, and recurse
+	/// into its children while the distance can still be within bounds.
+	#[allow(clippy::too_many_arguments)]
+	fn search(
+		&self,
+		node: &TrieNode,
+		c: char,
+		previous_c: Option<char>,
+		word: &[char],
+		previous_row: &[usize],
+		previous_previous_row: Option<&[usize]>,
+		prefix: &mut String,
+		max_distance: usize,
+		candidates: &mut Vec<Candidate>,
+	) {
+		let mut row = Vec::with_capacity(previous_row.len());
+		row.push(previous_row[0] + 1);
+		for i in 1..previous_row.len() {
+			let mut distance = (row[i - 1] + 1)
+				.min(previous_row[i] + 1)
+				.min(previous_row[i - 1] + usize::from(word[i - 1] != c));
+			if let (Some(previous_previous_row), Some(previous_c)) =
+				(previous_previous_row, previous_c)
+			{
+				if i > 1 && word[i - 1] == previous_c && word[i - 2] == c {
+					distance = distance.min(previous_previous_row[i - 2] + 1);
+				}
+			}
+			row.push(distance);
+		}
+
+		let distance = row[row.len() - 1];
+		if let Some(frequency) = node.frequency {
+			if distance <= max_distance {
+				candidates.push(Candidate {
+					domain: prefix.clone(),
+					distance,
+					frequency,
+				});
+			}
+		}
+
+		// A transposition can use the previous row, so only stop if both
+		// rows are out of bounds.
+		let min_distance = row.iter().chain(previous_row).min().copied().unwrap_or(0);
+		if min_distance > max_distance {
+			return;
+		}
+
+		for (next_c, child) in &node.children {
+			prefix.push(*next_c);
+			self.search(
+				child,
+				*next_c,
+				Some(c),
+				word,
+				&row,
+				Some(previous_row),
+				prefix,
+				max_distance,
+				candidates,
+			);
+			prefix.pop();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_suggest_popular_domains() {
+		let suggester = DomainSuggester::default_suggester();
+
+		// Transposition.
+		assert_eq!(suggester.suggest("gmial.com", 3)[0].domain, "gmail.com");
+		// Deletion.
+		assert_eq!(suggester.suggest("hotmal.com", 3)[0].domain, "hotmail.com");
+		// Substitution.
+		assert_eq!(suggester.suggest("yahoo.cmo", 3)[0].domain, "yahoo.com");
+		assert!(suggester.suggest("gmail.com", 3).is_empty());
+		assert!(suggester.suggest("my-company.org", 3).is_empty());
+	}
+
+	#[test]
+	fn should_rank_by_frequency_with_custom_domains() {
+		let suggester = DomainSuggester::new(vec![
+			DomainFrequency {
+				domain: "example.org".into(),
+				frequency: 10,
+			},
+			DomainFrequency {
+				domain: "example.net".into(),
+				frequency: 1000,
+			},
+		]);
+
+		// Both are 2 edits away.
+		let suggestions = suggester.suggest("example.ng", 3);
+		assert_eq!(suggestions.len(), 2);
+		assert_eq!(suggestions[0].domain, "example.net");
+		assert!(suggestions[0].confidence > suggestions[1].confidence);
+	}
+
+	#[test]
+	fn should_rank_by_distance_then_frequency() {
+		let suggester = DomainSuggester::new(vec![
+			DomainFrequency {
+				domain: "example.org".into(),
+				frequency: 10,
+			},
+			DomainFrequency {
+				domain: "example.net".into(),
+				frequency: 1_000_000,
+			},
+		]);
+
+		let suggestions = suggester.suggest("example.nrg", 3);
+		assert_eq!(suggestions[0].domain, "example.org");
+		assert_eq!(suggestions[0].distance, 1);
+		assert_eq!(suggestions[1].domain, "example.net");
+		assert!(suggestions[0].confidence < suggestions[1].confidence);
+	}
+
+	#[test]
+	fn should_build_suggestion_domains_once() {
+		let domains = SuggestionDomains::from(vec![DomainFrequency {
+			domain: "example.org".into(),
+			frequency: 1,
+		}]);
+		let clone = domains.clone();
+
+		assert!(std::ptr::eq(domains.suggester(), clone.suggester()));
+		assert_eq!(
+			serde_json::to_string(&clone).unwrap(),
+			r#"[{"domain":"example.org","frequency":1}]"#
+		);
+	}
+}
@@ -0,0 +1,106 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Validate top-level domains against the IANA list.
+
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+use reqwest::Error as ReqwestError;
+use serde::{Deserialize, Serialize};
+
+/// URL of the IANA list of top-level domains.
+pub const IANA_TLD_LIST_URL: &str = "https://data.iana.org/TLD/tlds-alpha-by-domain.txt";
+
+/// Bundled copy of the IANA list.
+const TLDS: &str = include_str!("./tlds-alpha-by-domain.txt");
+
+static DEFAULT_TLD_LIST: Lazy<TldList> = Lazy::new(|| TldList::parse(TLDS));
+
+/// A set of valid top-level domains, in their lowercase ASCII form.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct TldList(HashSet<String>);
+
+impl TldList {
+	/// Parse a list in the format of the IANA file: one TLD per line, lines
+	/// starting with "#" are comments.
+	pub fn parse(list: &str) -> Self {
+		TldList(
+			list.lines()
+				.map(str::trim)
+				.filter(|line| !line.is_empty() && !line.starts_with('#'))
+				.map(str::to_lowercase)
+				.collect(),
+		)
+	}
+
+	/// Download the latest list from IANA, to refresh the bundled one.
+	pub async fn fetch_iana() -> Result<Self, ReqwestError> {
+		let list = reqwest::get(IANA_TLD_LIST_URL)
+			.await?
+			.error_for_status()?
+			.text()
+			.await?;
+
+		Ok(Self::parse(&list))
+	}
+
+	/// Is `tld` in this list? The comparison is case-insensitive.
+	pub fn contains(&self, tld: &str) -> bool {
+		self.0.contains(&tld.trim_end_matches('.').to_lowercase())
+	}
+
+	/// Does the ASCII `domain` end with a TLD of this list?
+	pub fn is_valid_domain(&self, domain: &str) -> bool {
+		domain
+			.trim_end_matches('.')
+			.rsplit('.')
+			.next()
+			.map(|tld| self.contains(tld))
+			.unwrap_or(false)
+	}
+}
+
+/// The TLD list bundled with this crate.
+pub fn default_tld_list() -> &'static TldList {
+	&DEFAULT_TLD_LIST
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_validate_tld() {
+		let list = default_tld_list();
+
+		assert!(list.is_valid_domain("example.com"));
+		assert!(list.is_valid_domain("example.CO.UK."));
+		assert!(list.is_valid_domain("xn--mller-kva.xn--p1ai"));
+		assert!(!list.is_valid_domain("example.con"));
+		assert!(!list.is_valid_domain("localhost"));
+	}
+
+	#[test]
+	fn should_parse_iana_format() {
+		let list = TldList::parse("# Version 2023010100\nCOM\nORG\n");
+
+		assert!(list.contains("com"));
+		assert!(list.contains("Org"));
+		assert!(!list.contains("net"));
+	}
+}
@@ -0,0 +1,95 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt::Debug;
+use std::time::Instant;
+
+use async_std_resolver::lookup::Lookup;
+use trust_dns_proto::rr::RecordType;
+
+use super::ttl_cache::{CacheStats, TtlCache};
+
+/// Key of a DNS lookup in the cache: the lowercase name, without its final
+/// dot, and the record type.
+pub type DnsCacheKey = (String, RecordType);
+
+/// A cache of successful DNS lookups. Implement this trait to store lookups
+/// elsewhere than in memory, e.g. in a cache shared by multiple workers.
+pub trait DnsCache: Debug + Send + Sync {
+	/// Get the lookup stored at `key`, if it's still valid.
+	fn get(&self, key: &DnsCacheKey) -> Option<Lookup>;
+	/// Store `lookup` at `key`, valid until the `valid_until` instant, which
+	/// is derived from the records' TTLs.
+	fn insert(&self, key: DnsCacheKey, lookup: Lookup, valid_until: Instant);
+	/// Remove all lookups from the cache.
+	fn clear(&self);
+	/// Get the hit/miss counters of the cache.
+	fn stats(&self) -> CacheStats;
+}
+
+impl DnsCache for TtlCache<DnsCacheKey, Lookup> {
+	fn get(&self, key: &DnsCacheKey) -> Option<Lookup> {
+		TtlCache::get(self, key)
+	}
+
+	fn insert(&self, key: DnsCacheKey, lookup: Lookup, valid_until: Instant) {
+		TtlCache::insert(self, key, lookup, valid_until)
+	}
+
+	fn clear(&self) {
+		TtlCache::clear(self)
+	}
+
+	fn stats(&self) -> CacheStats {
+		TtlCache::stats(self)
+	}
+}
+
+/// Key of `name`'s `record_type` records in the cache.
+pub(crate) fn dns_cache_key(name: &str, record_type: RecordType) -> DnsCacheKey {
+	(name.trim_end_matches('.').to_lowercase(), record_type)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+	use std::sync::Arc;
+	use std::time::Duration;
+	use trust_dns_proto::op::Query;
+	use trust_dns_proto::rr::Name;
+
+	#[test]
+	fn should_share_lookups_by_normalized_name() {
+		let cache: Box<dyn DnsCache> = Box::new(TtlCache::default());
+		let query = Query::query(Name::from_str("example.org.").unwrap(), RecordType::MX);
+		let lookup = Lookup::new_with_max_ttl(query, Arc::from(vec![]));
+
+		cache.insert(
+			dns_cache_key("Example.org.", RecordType::MX),
+			lookup,
+			Instant::now() + Duration::from_secs(60),
+		);
+
+		assert!(cache
+			.get(&dns_cache_key("example.org", RecordType::MX))
+			.is_some());
+		assert!(cache
+			.get(&dns_cache_key("example.org", RecordType::A))
+			.is_none());
+		assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+	}
+}
@@ -0,0 +1,209 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::input_output::CheckEmailInput;
+
+/// Does `domain` match `pattern`? A pattern starting with "." matches the
+/// domain itself and all its subdomains, other patterns only match the exact
+/// domain. The comparison is case-insensitive.
+pub(crate) fn matches_domain(domain: &str, pattern: &str) -> bool {
+	let domain = domain.trim_end_matches('.').to_lowercase();
+	let pattern = pattern.trim_end_matches('.').to_lowercase();
+
+	match pattern.strip_prefix('.') {
+		Some(suffix) => {
+			domain == suffix
+				|| domain
+					.strip_suffix(suffix)
+					.is_some_and(|subdomain| subdomain.ends_with('.'))
+		}
+		None => domain == pattern,
+	}
+}
+
+/// A pattern of MX hosts, as in the input's `skipped_domains`. Regular
+/// expressions and globs are compiled once, when the pattern is parsed, so
+/// invalid regular expressions are rejected then.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HostPattern {
+	pattern: String,
+	/// The compiled regular expression or glob, None for domains.
+	regex: Option<Regex>,
+}
+
+impl HostPattern {
+	pub fn as_str(&self) -> &str {
+		&self.pattern
+	}
+
+	/// Does the MX `host` match the pattern?
+	pub(crate) fn matches(&self, host: &str) -> bool {
+		match &self.regex {
+			Some(regex) => regex.is_match(host.trim_end_matches('.')),
+			None => matches_domain(host, &self.pattern),
+		}
+	}
+}
+
+impl FromStr for HostPattern {
+	type Err = regex::Error;
+
+	fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+		let regex = match pattern.strip_prefix("re:") {
+			Some(regex) => Some(regex.to_string()),
+			None if pattern.contains(['*', '?']) => Some(format!(
+				"^{}$",
+				regex::escape(pattern.trim_end_matches('.'))
+					.replace(r"\*", ".*")
+					.replace(r"\?", ".")
+			)),
+			None => None,
+		};
+
+		Ok(HostPattern {
+			pattern: pattern.to_string(),
+			regex: regex
+				.map(|regex| RegexBuilder::new(&regex).case_insensitive(true).build())
+				.transpose()?,
+		})
+	}
+}
+
+impl TryFrom<String> for HostPattern {
+	type Error = regex::Error;
+
+	fn try_from(pattern: String) -> Result<Self, Self::Error> {
+		pattern.parse()
+	}
+}
+
+impl From<HostPattern> for String {
+	fn from(pattern: HostPattern) -> Self {
+		pattern.pattern
+	}
+}
+
+impl fmt::Display for HostPattern {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.pattern)
+	}
+}
+
+impl PartialEq for HostPattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.pattern == other.pattern
+	}
+}
+
+impl Eq for HostPattern {}
+
+/// The first of the input's `skipped_domains` which matches the MX `host`.
+pub(crate) fn skipped_pattern<'a>(
+	host: &str,
+	input: &'a CheckEmailInput,
+) -> Option<&'a HostPattern> {
+	input
+		.skipped_domains
+		.iter()
+		.find(|pattern| pattern.matches(host))
+}
+
+/// Is `domain` forbidden by the input's `allowed_domains` and
+/// `blocked_domains`? The blocklist takes precedence over the allowlist.
+pub(crate) fn is_domain_blocked(domain: &str, input: &CheckEmailInput) -> bool {
+	let is_blocked = input
+		.blocked_domains
+		.iter()
+		.any(|pattern| matches_domain(domain, pattern));
+	let is_allowed = input.allowed_domains.is_empty()
+		|| input
+			.allowed_domains
+			.iter()
+			.any(|pattern| matches_domain(domain, pattern));
+
+	is_blocked || !is_allowed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_match_exact_and_suffix() {
+		assert!(matches_domain("acme.com", "ACME.com"));
+		assert!(!matches_domain("mail.acme.com", "acme.com"));
+		assert!(matches_domain("acme.com", ".acme.com"));
+		assert!(matches_domain("mail.acme.com", ".acme.com"));
+		assert!(!matches_domain("notacme.com", ".acme.com"));
+	}
+
+	fn matches_host(host: &str, pattern: &str) -> bool {
+		pattern.parse::<HostPattern>().unwrap().matches(host)
+	}
+
+	#[test]
+	fn should_match_skipped_hosts() {
+		assert!(matches_host("mx1.zoho.com.", ".zoho.com."));
+		assert!(!matches_host("mx1.mycustomzoho.com.", ".zoho.com."));
+		assert!(matches_host(
+			"mxbw-bluewin-ch.hdb-cs04.ellb.ch.",
+			"*bluewin-ch.*"
+		));
+		assert!(matches_host("MX2.zoho.com.", "mx?.zoho.com"));
+		assert!(!matches_host("mx10.zoho.com.", "mx?.zoho.com"));
+		assert!(matches_host("mx10.zoho.com.", r"re:^mx\d+\.zoho\.com$"));
+
+		let input = CheckEmailInput::default();
+		assert_eq!(
+			skipped_pattern("mx01.mail.icloud.com.", &input).map(HostPattern::as_str),
+			Some(".mail.icloud.com.")
+		);
+		assert_eq!(skipped_pattern("gmail-smtp-in.l.google.com.", &input), None);
+	}
+
+	#[test]
+	fn should_reject_invalid_regexes() {
+		assert!("re:(".parse::<HostPattern>().is_err());
+		assert!(serde_json::from_str::<Vec<HostPattern>>(r#"["*.zoho.com", "re:("]"#).is_err());
+		assert_eq!(
+			serde_json::to_string(&"re:^mx\\d+$".parse::<HostPattern>().unwrap()).unwrap(),
+			r#""re:^mx\\d+$""#
+		);
+	}
+
+	#[test]
+	fn should_block_domains() {
+		let mut input = CheckEmailInput::default();
+		assert!(!is_domain_blocked("acme.com", &input));
+
+		input.set_blocked_domains(vec![".acme.com".into()]);
+		assert!(is_domain_blocked("mail.acme.com", &input));
+		assert!(!is_domain_blocked("gmail.com", &input));
+
+		input.set_allowed_domains(vec!["gmail.com".into(), "acme.com".into()]);
+		assert!(is_domain_blocked("acme.com", &input));
+		assert!(!is_domain_blocked("gmail.com", &input));
+		assert!(is_domain_blocked("yahoo.com", &input));
+	}
+}
@@ -0,0 +1,213 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! DNS lookups over HTTPS (RFC 8484) and over TLS (RFC 7858). Neither needs
+//! raw access to UDP port 53.
+
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::future;
+use async_std_resolver::lookup::Lookup;
+use async_std_resolver::ResolveError;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use trust_dns_proto::op::{Message, MessageType, OpCode, Query, ResponseCode};
+use trust_dns_proto::rr::{Name, RecordType};
+use trust_dns_resolver::error::ResolveErrorKind;
+
+/// An encrypted DNS server.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptedDns {
+	/// DNS-over-HTTPS, e.g. `https://cloudflare-dns.com/dns-query`.
+	Https { url: String },
+	/// DNS-over-TLS. `server_name` is used to verify the server's
+	/// certificate.
+	Tls {
+		socket_addr: SocketAddr,
+		server_name: String,
+	},
+}
+
+impl EncryptedDns {
+	/// Cloudflare's DNS-over-HTTPS endpoint.
+	pub fn cloudflare_https() -> Self {
+		EncryptedDns::Https {
+			url: "https://cloudflare-dns.com/dns-query".into(),
+		}
+	}
+
+	/// Google's DNS-over-HTTPS endpoint.
+	pub fn google_https() -> Self {
+		EncryptedDns::Https {
+			url: "https://dns.google/dns-query".into(),
+		}
+	}
+
+	/// Cloudflare's DNS-over-TLS server.
+	pub fn cloudflare_tls() -> Self {
+		EncryptedDns::Tls {
+			socket_addr: SocketAddr::from(([1, 1, 1, 1], 853)),
+			server_name: "cloudflare-dns.com".into(),
+		}
+	}
+
+	/// Google's DNS-over-TLS server.
+	pub fn google_tls() -> Self {
+		EncryptedDns::Tls {
+			socket_addr: SocketAddr::from(([8, 8, 8, 8], 853)),
+			server_name: "dns.google".into(),
+		}
+	}
+
+	/// Look up the `record_type` records of `name`. `timeout` applies to the
+	/// whole query, including the connection.
+	pub(crate) async fn lookup(
+		&self,
+		name: &str,
+		record_type: RecordType,
+		timeout: Duration,
+	) -> Result<Lookup, ResolveError> {
+		let mut name = Name::from_str(name)?;
+		name.set_fqdn(true);
+		let query = Query::query(name, record_type);
+		let request = build_request(query.clone()).to_vec()?;
+
+		let response = future::timeout(timeout, async {
+			match self {
+				EncryptedDns::Https { url } => send_https(url, request).await,
+				EncryptedDns::Tls {
+					socket_addr,
+					server_name,
+				} => send_tls(socket_addr, server_name, &request).await,
+			}
+		})
+		.await
+		.map_err(|_| ResolveError::from(ResolveErrorKind::Timeout))??;
+
+		to_lookup(query, Message::from_vec(&response)?)
+	}
+}
+
+/// A recursive query for `query`.
+fn build_request(query: Query) -> Message {
+	let mut message = Message::new();
+	message
+		.set_id(rand::random())
+		.set_message_type(MessageType::Query)
+		.set_op_code(OpCode::Query)
+		.set_recursion_desired(true)
+		.add_query(query);
+
+	message
+}
+
+async fn send_https(url: &str, request: Vec<u8>) -> Result<Vec<u8>, ResolveError> {
+	let response = reqwest::Client::new()
+		.post(url)
+		.header("accept", "application/dns-message")
+		.header("content-type", "application/dns-message")
+		.body(request)
+		.send()
+		.await
+		.and_then(|response| response.error_for_status())
+		.map_err(|err| ResolveError::from(format!("DNS-over-HTTPS error: {err}")))?;
+
+	response
+		.bytes()
+		.await
+		.map(|bytes| bytes.to_vec())
+		.map_err(|err| ResolveError::from(format!("DNS-over-HTTPS error: {err}")))
+}
+
+async fn send_tls(
+	socket_addr: &SocketAddr,
+	server_name: &str,
+	request: &[u8],
+) -> Result<Vec<u8>, ResolveError> {
+	let stream = TcpStream::connect(socket_addr).await?;
+	let mut stream = async_native_tls::connect(server_name, stream)
+		.await
+		.map_err(|err| ResolveError::from(format!("DNS-over-TLS error: {err}")))?;
+
+	// Messages are prefixed with their length, like DNS over TCP.
+	let length =
+		u16::try_from(request.len()).map_err(|_| ResolveError::from("DNS request is too long"))?;
+	stream.write_all(&length.to_be_bytes()).await?;
+	stream.write_all(request).await?;
+	stream.flush().await?;
+
+	let mut length = [0; 2];
+	stream.read_exact(&mut length).await?;
+	let mut response = vec![0; u16::from_be_bytes(length) as usize];
+	stream.read_exact(&mut response).await?;
+
+	Ok(response)
+}
+
+/// Convert a DNS response to a `Lookup`, with the same errors as the
+/// standard resolver when there's no record.
+fn to_lookup(query: Query, response: Message) -> Result<Lookup, ResolveError> {
+	let records = response
+		.answers()
+		.iter()
+		.filter(|record| record.record_type() == query.query_type())
+		.cloned()
+		.collect::<Vec<_>>();
+
+	if records.is_empty() {
+		return Err(ResolveErrorKind::NoRecordsFound {
+			query: Box::new(query),
+			soa: None,
+			negative_ttl: None,
+			response_code: response.response_code(),
+			trusted: response.response_code() == ResponseCode::NXDomain,
+		}
+		.into());
+	}
+
+	Ok(Lookup::new_with_max_ttl(query, Arc::from(records)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use trust_dns_proto::rr::{rdata::MX, RData, Record};
+
+	#[test]
+	fn should_convert_response_to_lookup() {
+		let name = Name::from_str("example.org.").unwrap();
+		let query = Query::query(name.clone(), RecordType::MX);
+		let mx = Name::from_str("mx.example.org.").unwrap();
+
+		let mut response = build_request(query.clone());
+		response.set_message_type(MessageType::Response);
+		response.add_answer(Record::from_rdata(name, 300, RData::MX(MX::new(10, mx))));
+		let response = Message::from_vec(&response.to_vec().unwrap()).unwrap();
+
+		let lookup = to_lookup(query.clone(), response).unwrap();
+		assert_eq!(lookup.iter().count(), 1);
+
+		let mut empty = build_request(query.clone());
+		empty.set_response_code(ResponseCode::NXDomain);
+		assert!(to_lookup(query, empty).is_err());
+	}
+}
@@ -14,37 +14,127 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(feature = "headless")]
+use std::path::PathBuf;
 use std::time::Duration;
 
+use async_smtp::smtp::authentication::Mechanism;
 use async_smtp::{ClientSecurity, ClientTlsParameters};
 use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
 
-use crate::misc::{MiscDetails, MiscError};
+use super::domain_policy::HostPattern;
+use super::encrypted_dns::EncryptedDns;
+use crate::confidence::{Confidence, ConfidenceSignal};
+use crate::context::CheckEmailContext;
+use crate::dns::DnsDetails;
+use crate::misc::{MiscDetails, MiscError, RoleAccount};
 use crate::mx::{MxDetails, MxError};
-use crate::smtp::{SmtpDetails, SmtpError, SmtpErrorDesc};
-use crate::syntax::SyntaxDetails;
+use crate::provider::ProviderCategory;
+use crate::rdap::RdapDetails;
+use crate::rules::ReachabilityRule;
+use crate::smtp::{
+	ProviderRoute, RateLimitConfig, RetryStrategy, SmtpDetails, SmtpError, SmtpErrorDesc,
+};
+use crate::syntax::{
+	default_normalization_rules, CleanupPolicy, DomainFrequency, LocalPartPolicy,
+	NormalizationRule, SuggestionDomains, SyntaxDetails, TldList,
+};
+
+/// The protocol spoken by a proxy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+	/// A SOCKS5 proxy.
+	#[default]
+	Socks5,
+	/// A SOCKS4 proxy. Hostnames are sent to the proxy using SOCKS4a.
+	Socks4,
+	/// An HTTP proxy supporting the CONNECT method.
+	HttpConnect,
+}
 
 /// Perform the email verification via a specified proxy. The usage of a proxy
 /// is optional.
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct CheckEmailInputProxy {
-	/// Use the specified SOCKS5 proxy host to perform email verification.
+	/// Use the specified proxy host to perform email verification.
 	pub host: String,
-	/// Use the specified SOCKS5 proxy port to perform email verification.
+	/// Use the specified proxy port to perform email verification.
 	pub port: u16,
-	/// Username to pass to proxy authentication.
+	/// Username to pass to proxy authentication. For SOCKS4 proxies, it's
+	/// sent as the user ID, and the password is ignored.
 	pub username: Option<String>,
 	/// Password to pass to proxy authentication.
 	pub password: Option<String>,
+	/// The proxy's protocol. Defaults to Socks5.
+	#[serde(default)]
+	pub kind: ProxyKind,
+}
+
+/// The SASL mechanism used to authenticate to a SMTP relay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpAuthMechanism {
+	/// PLAIN, sending the username and password at once.
+	#[default]
+	Plain,
+	/// LOGIN, sending the username and password in two steps.
+	Login,
+	/// XOAUTH2, with an OAuth2 access token as password.
+	Xoauth2,
+}
+
+impl SmtpAuthMechanism {
+	pub(crate) fn to_mechanism(self) -> Mechanism {
+		match self {
+			Self::Plain => Mechanism::Plain,
+			Self::Login => Mechanism::Login,
+			Self::Xoauth2 => Mechanism::Xoauth2,
+		}
+	}
+}
+
+/// An authenticated SMTP relay, e.g. a submission server, through which the
+/// `RCPT TO` probes are sent instead of the recipient's MX hosts. The relay
+/// must verify recipients itself, e.g. with callouts to the MX hosts,
+/// otherwise all of them are reported deliverable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct SmtpRelay {
+	/// The relay's host.
+	pub host: String,
+	/// The relay's port, usually 587 or 465.
+	pub port: u16,
+	/// Username to authenticate with.
+	pub username: String,
+	/// Password to authenticate with, or an OAuth2 access token for the
+	/// XOAUTH2 mechanism.
+	pub password: String,
+	/// The SASL mechanism. Defaults to Plain.
+	#[serde(default)]
+	pub mechanism: SmtpAuthMechanism,
+}
+
+/// An LMTP server, e.g. an internal Dovecot or Postfix mail store, against
+/// which the mailboxes are verified instead of the recipient's MX hosts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LmtpServer {
+	/// The server's host.
+	pub host: String,
+	/// The server's port, usually 24.
+	pub port: u16,
 }
 
 /// Define how to apply TLS to a SMTP client connection. Will be converted into
 /// async_smtp::ClientSecurity.
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum SmtpSecurity {
 	/// Insecure connection only (for testing purposes).
 	None,
 	/// Start with insecure connection and use `STARTTLS` when available.
+	#[default]
 	Opportunistic,
 	/// Start with insecure connection and require `STARTTLS`.
 	Required,
@@ -52,12 +142,6 @@ pub enum SmtpSecurity {
 	Wrapper,
 }
 
-impl Default for SmtpSecurity {
-	fn default() -> Self {
-		Self::Opportunistic
-	}
-}
-
 impl SmtpSecurity {
 	pub fn to_client_security(self, tls_params: ClientTlsParameters) -> ClientSecurity {
 		match self {
@@ -69,6 +153,100 @@ impl SmtpSecurity {
 	}
 }
 
+/// SMTP settings of a domain, overriding the input's ones when set, see
+/// `domain_overrides`.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DomainConfig {
+	/// Overrides `smtp_port`.
+	pub smtp_port: Option<u16>,
+	/// Overrides `smtp_timeout`.
+	pub smtp_timeout: Option<Duration>,
+	/// Overrides `smtp_security`.
+	pub smtp_security: Option<SmtpSecurity>,
+	/// Overrides `retries`.
+	pub retries: Option<usize>,
+	/// Overrides `proxy`.
+	pub proxy: Option<CheckEmailInputProxy>,
+}
+
+/// Timeouts of the stages of an SMTP attempt. Each one bounds a single
+/// stage, e.g. each `RCPT TO` command, and a stage without timeout is only
+/// bounded by `smtp_timeout`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SmtpTimeouts {
+	/// Opening the connection, up to the server's greeting.
+	pub connect: Option<Duration>,
+	/// Each `EHLO` command, with `STARTTLS` if used.
+	pub ehlo: Option<Duration>,
+	/// Each `MAIL FROM` command.
+	pub mail_from: Option<Duration>,
+	/// Each `RCPT TO` command.
+	pub rcpt_to: Option<Duration>,
+}
+
+/// How far the SMTP verification goes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpVerificationMode {
+	/// Probe the mailbox with `RCPT TO`.
+	#[default]
+	Mailbox,
+	/// Only connect and send EHLO, to check the server is reachable and
+	/// read its capabilities. No mailbox is probed, so the email's
+	/// reachability is unknown.
+	Connectivity,
+	/// Like `Connectivity`, and also send `MAIL FROM`, to check the server
+	/// accepts the sender.
+	MailFrom,
+}
+
+/// How to pick a value among several, e.g. the EHLO name and `MAIL FROM`
+/// address, or a proxy of the context's proxy pool.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotationPolicy {
+	/// Use each value in turn, across the verifications sharing a context.
+	#[default]
+	RoundRobin,
+	/// Pick a random value for each verification.
+	Random,
+	/// Always use the same values for a given email domain.
+	StickyPerDomain,
+}
+
+/// Which IP versions to use to connect to the MX hosts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpVersion {
+	/// Let the system resolve the MX host, and try its addresses in order.
+	#[default]
+	Auto,
+	/// Race the IPv4 and IPv6 addresses, giving IPv4 a head start.
+	PreferIpv4,
+	/// Race the IPv6 and IPv4 addresses, giving IPv6 a head start.
+	PreferIpv6,
+	/// Only connect over IPv4.
+	Ipv4Only,
+	/// Only connect over IPv6, e.g. on IPv6-only networks.
+	Ipv6Only,
+}
+
+/// Which grammar to validate the email address syntax against.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SyntaxStrictness {
+	/// Addresses deliverable over SMTP on the public internet: dot-atom local
+	/// part of at most 64 octets, domain with a TLD, and RFC 5321 length
+	/// limits.
+	#[default]
+	Rfc5321,
+	/// The looser RFC 5322 grammar, which also accepts quoted local parts,
+	/// domains without a TLD and longer addresses. Comments and folding
+	/// whitespace are not supported.
+	Rfc5322,
+}
+
 /// Builder pattern for the input argument into the main `email_exists`
 /// function.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -85,19 +263,72 @@ pub struct CheckEmailInput {
 	///
 	/// Defaults to "gmail.com" (note: "localhost" is not a FQDN).
 	pub hello_name: String,
-	/// Perform the email verification via the specified SOCK5 proxy. The usage of a
-	/// proxy is optional.
+	/// Names to rotate across in the `EHLO:` SMTP command, following
+	/// `identity_rotation`. If not empty, they replace `hello_name`.
+	///
+	/// Defaults to empty.
+	pub hello_names: Vec<String>,
+	/// Emails to rotate across in the `MAIL FROM:` SMTP command, following
+	/// `identity_rotation`. If not empty, they replace `from_email`.
+	///
+	/// Defaults to empty.
+	pub from_emails: Vec<String>,
+	/// How to pick the values of `hello_names` and `from_emails`.
+	///
+	/// Defaults to RoundRobin.
+	pub identity_rotation: RotationPolicy,
+	/// Perform the email verification via the specified SOCKS5, SOCKS4 or
+	/// HTTP CONNECT proxy. The usage of a proxy is optional.
+	/// If set, the context's proxy pool isn't used.
 	pub proxy: Option<CheckEmailInputProxy>,
 	/// SMTP port to use for email validation. Generally, ports 25, 465, 587
 	/// and 2525 are used.
 	///
 	/// Defaults to 25.
 	pub smtp_port: u16,
+	/// Ports to try, in order, after `smtp_port`, when the connection to it
+	/// is refused or times out, e.g. because outbound port 25 is filtered.
+	/// Each MX host is tried on all ports before moving on to the next one.
+	/// On port 465, implicit TLS is used instead of `STARTTLS`.
+	///
+	/// Defaults to no fallback port.
+	pub smtp_fallback_ports: Vec<u16>,
 	/// Add timeout for the SMTP verification step. Set to None if you don't
 	/// want to use a timeout.
 	///
 	/// Defaults to 12s (more than 10s, but when run twice less than 30s).
 	pub smtp_timeout: Option<Duration>,
+	/// Timeouts of the stages of each SMTP attempt, so that servers which
+	/// stall on one stage, e.g. tarpits slowly answering `RCPT TO`, are given
+	/// up on early. With a SOCKS5 proxy, the connection and the first `EHLO`
+	/// are a single step, bounded by the sum of both timeouts.
+	///
+	/// Defaults to no stage timeouts.
+	pub smtp_timeouts: SmtpTimeouts,
+	/// Time limit of the whole SMTP verification, including the retries and
+	/// the fallback ports and MX hosts, while `smtp_timeout` only bounds each
+	/// attempt.
+	///
+	/// Defaults to None, i.e. no time limit.
+	pub smtp_deadline: Option<Duration>,
+	/// Time limit of the whole verification by `check_email`: DNS lookups,
+	/// SMTP attempts with their retries, and the misc and optional checks.
+	/// When it's reached, the output only has the syntax details, and a
+	/// time-out SMTP error. `check_email_batch` ignores it, as the SMTP
+	/// sessions are shared between emails, see `smtp_deadline` instead.
+	///
+	/// Defaults to None, i.e. no time limit.
+	pub total_timeout: Option<Duration>,
+	/// Maximum pause of the SMTP server in the middle of a response, e.g.
+	/// between the bytes of its banner or the lines of its EHLO response. A
+	/// server dripping its responses slower is considered a tarpit, and the
+	/// verification is aborted with a `Tarpitted` error, without retries.
+	/// The wait for the start of a response is bounded by `smtp_timeouts`
+	/// instead. Not enforced through SOCKS5 proxies, whose connections are
+	/// handled by async-smtp's transport.
+	///
+	/// Defaults to None, i.e. no tarpit detection.
+	pub tarpit_threshold: Option<Duration>,
 	/// For Yahoo email addresses, use Yahoo's API instead of connecting
 	/// directly to their SMTP servers.
 	///
@@ -113,49 +344,496 @@ pub struct CheckEmailInput {
 	///
 	/// Defaults to false.
 	pub microsoft365_use_api: bool,
+	/// For Proton Mail email addresses, use the address availability API of
+	/// Proton's signup page instead of connecting directly to their SMTP
+	/// servers, which answer the same for all mailboxes. Addresses on
+	/// custom domains hosted by Proton are still verified via SMTP.
+	///
+	/// Defaults to false.
+	pub proton_use_api: bool,
+	/// For iCloud email addresses (@icloud.com, @me.com and @mac.com), use
+	/// the Apple ID validation API of Apple's account creation page, as
+	/// iCloud's SMTP servers accept all mailboxes. Takes precedence over
+	/// the `.mail.icloud.com.` entry of `skipped_domains`. Addresses on
+	/// custom domains hosted by iCloud are still skipped.
+	///
+	/// Defaults to false.
+	pub icloud_use_api: bool,
+	/// For Mail.ru email addresses (@mail.ru, @bk.ru, @inbox.ru, @list.ru
+	/// and @internet.ru), use the user API of Mail.ru's login page instead
+	/// of connecting directly to their SMTP servers, which accept all
+	/// mailboxes.
+	///
+	/// Defaults to false.
+	pub mailru_use_api: bool,
+	/// For Yandex email addresses (@yandex.ru, @ya.ru...), use the login
+	/// validation API of Yandex's registration page instead of connecting
+	/// directly to their SMTP servers, which accept all mailboxes.
+	///
+	/// Defaults to false.
+	pub yandex_use_api: bool,
+	/// For GMX and WEB.DE email addresses (@gmx.de, @gmx.net, @gmx.at,
+	/// @gmx.ch and @web.de), use the availability API of their signup
+	/// pages, as their SMTP servers are unreliable. Takes precedence over
+	/// the `.gmx.net.` and `.web.de.` entries of `skipped_domains`.
+	///
+	/// Defaults to false.
+	pub gmx_use_api: bool,
 	// Whether to check if a gravatar image is existing for the given email.
 	//
 	// Defaults to false.
 	pub check_gravatar: bool,
+	/// Timeout of the Gravatar request, if `check_gravatar` is set. Set to
+	/// None if you don't want to use a timeout.
+	///
+	/// Defaults to None.
+	pub gravatar_timeout: Option<Duration>,
 	/// Check if a the email address is present in HaveIBeenPwned API.
 	// If the api_key is filled, HaveIBeenPwned API is checked
 	pub haveibeenpwned_api_key: Option<String>,
+	/// Only report the breaches which HaveIBeenPwned verified to be
+	/// legitimate.
+	///
+	/// Defaults to false.
+	pub haveibeenpwned_verified_only: bool,
+	/// Domains of free email providers, used to compute the misc's
+	/// `is_free_provider` and the provider category.
+	///
+	/// Defaults to None, i.e. the bundled list of free email providers.
+	pub free_provider_domains: Option<Vec<String>>,
+	/// Role account usernames, with their severity: `NeverSend` roles are
+	/// reported as `Invalid`, `Risky` ones as `Risky`.
+	///
+	/// Defaults to None, i.e. the built-in list of role accounts.
+	pub role_accounts: Option<Vec<RoleAccount>>,
 	/// For Hotmail/Outlook email addresses, use a headless navigator
 	/// connecting to the password recovery page instead of the SMTP server.
 	/// This assumes you have a WebDriver compatible process running, then pass
 	/// its endpoint, usually http://localhost:4444. We recommend running
 	/// chromedriver (and not geckodriver) as it allows parallel requests.
+	/// Pass `MANAGED_WEBDRIVER` to use the WebDriver server spawned by the
+	/// context, see `CheckEmailContext::with_managed_webdriver`.
 	///
 	/// Defaults to None.
 	#[cfg(feature = "headless")]
 	pub hotmail_use_headless: Option<String>,
-	/// Number of retries of SMTP connections to do.
+	/// For Microsoft 365 email addresses, use a headless navigator
+	/// connecting to the login page, whose WebDriver endpoint is passed like
+	/// `hotmail_use_headless`. Tried after `microsoft365_use_api`, and
+	/// before the SMTP server if the login page's result is ambiguous.
+	///
+	/// Defaults to None.
+	#[cfg(feature = "headless")]
+	pub microsoft365_use_headless: Option<String>,
+	/// For all Microsoft email addresses, tell consumer Hotmail/Outlook
+	/// addresses from Microsoft 365 ones with their MX host and domain, and
+	/// verify them accordingly: consumer ones with a headless navigator on
+	/// the password recovery page, Microsoft 365 ones with OneDrive's API,
+	/// then a headless navigator on the login page, then SMTP. The
+	/// WebDriver endpoint is passed like `hotmail_use_headless`, which,
+	/// like `microsoft365_use_headless`, takes precedence over it.
+	///
+	/// Defaults to None.
+	#[cfg(feature = "headless")]
+	pub microsoft_use_headless: Option<String>,
+	/// For Yahoo/AOL email addresses, fall back to a headless navigator
+	/// connecting to the password recovery page, whose WebDriver endpoint is
+	/// passed like `hotmail_use_headless`, when `yahoo_use_api` gets an
+	/// unexpected payload, e.g. because Yahoo changed its API.
+	///
+	/// Defaults to None.
+	#[cfg(feature = "headless")]
+	pub yahoo_use_headless: Option<String>,
+	/// Directory where the headless verifications write the HTML and a
+	/// screenshot of the page when they don't find the elements they
+	/// expect. Otherwise, both are included in the error.
+	///
+	/// Defaults to None.
+	#[cfg(feature = "headless")]
+	pub headless_debug_dir: Option<PathBuf>,
+	/// Number of retries of SMTP connections to do. Ignored if the context
+	/// has a retry policy, see `CheckEmailContext::with_retry_policy`.
 	///
 	/// Defaults to 2 to avoid greylisting.
 	pub retries: usize,
+	/// When the SMTP server answers with a greylisting response, e.g.
+	/// `451 4.7.1 Greylisted, try again later`, wait this long before
+	/// retrying, instead of retrying immediately. Greylisters typically
+	/// accept retries after 5 to 15 minutes. The number of retries is still
+	/// `retries`. Ignored if the context has a retry policy.
+	///
+	/// Defaults to None, i.e. retry immediately.
+	pub greylisting_retry_delay: Option<Duration>,
+	/// Which MX host to retry on, e.g. another MX host of the domain after
+	/// a connection error, rather than hammering the same one.
+	///
+	/// Defaults to SameHost.
+	pub retry_strategy: RetryStrategy,
+	/// Record the SMTP session, i.e. the commands sent and the server's
+	/// responses with timestamps, and add the transcript to the SMTP
+	/// details.
+	///
+	/// Defaults to false.
+	pub smtp_debug: bool,
+	/// If the SMTP server rejects `RCPT TO`, ask it whether the mailbox
+	/// exists with the VRFY command instead. Some legacy servers answer VRFY
+	/// truthfully while rejecting all `RCPT TO` probes.
+	///
+	/// Defaults to false.
+	pub smtp_vrfy_fallback: bool,
+	/// Include the details of the SMTP server's TLS certificate, and the
+	/// negotiated protocol version and cipher suite, in the output, when the
+	/// connection is encrypted. Ignored when using a SOCKS5
+	/// proxy.
+	///
+	/// Defaults to false.
+	pub smtp_tls_details: bool,
+	/// Accept the SMTP server's TLS certificate even if it's invalid, e.g.
+	/// self-signed, expired, or issued for another host name. Many small
+	/// mail servers have broken certificates, and the verification doesn't
+	/// send any email, so this is on by default. Turn it off to require a
+	/// valid certificate, trusted by the system or `smtp_root_certificates`.
+	///
+	/// Defaults to true.
+	pub smtp_accept_invalid_certs: bool,
+	/// Additional root certificates, PEM-encoded, trusted when validating
+	/// the SMTP server's TLS certificate. Only useful if
+	/// `smtp_accept_invalid_certs` is false.
+	///
+	/// Defaults to none.
+	pub smtp_root_certificates: Vec<String>,
+	/// Validate the SMTP server's TLS certificate against the TLSA records
+	/// of the MX host (DANE), and report the result in the output. The
+	/// TLSA records are only used if they are DNSSEC-validated, which isn't
+	/// possible with `encrypted_dns` or a custom resolver. Ignored when
+	/// using a SOCKS5 proxy.
+	///
+	/// Defaults to false.
+	pub smtp_dane_validation: bool,
+	/// Include the SMTP server's name and the extensions it advertises in
+	/// its EHLO response, e.g. SIZE or STARTTLS, in the output. With a
+	/// SOCKS5 proxy, EHLO is re-sent on the possibly encrypted connection,
+	/// which doesn't advertise STARTTLS anymore.
+	///
+	/// Defaults to false.
+	pub smtp_capabilities: bool,
+	/// Include the durations of the stages of the SMTP session in the
+	/// output: DNS resolution, TCP connection, TLS handshake, EHLO,
+	/// `MAIL FROM` and `RCPT TO`.
+	///
+	/// Defaults to false.
+	pub smtp_timings: bool,
+	/// How far the SMTP verification goes. The connectivity-only modes never
+	/// send `RCPT TO`, and skip the providers' HTTP APIs, which also probe
+	/// the mailbox.
+	///
+	/// Defaults to Mailbox.
+	pub smtp_verification_mode: SmtpVerificationMode,
+	/// Send the SMTP probes through this authenticated relay, instead of
+	/// connecting to the MX hosts. The `smtp_port` is then ignored, and
+	/// `smtp_security` applies to the connection to the relay.
+	///
+	/// Defaults to None.
+	pub smtp_relay: Option<SmtpRelay>,
+	/// Verify the mailboxes on this LMTP server, greeting it with LHLO,
+	/// instead of connecting to the MX hosts, e.g. to validate addresses
+	/// against an internal directory. The `smtp_port` and `smtp_relay` are
+	/// then ignored, and the email's domain needs no public MX records. Not
+	/// supported through SOCKS5 proxies.
+	///
+	/// Defaults to None.
+	pub lmtp_server: Option<LmtpServer>,
 	/// How to apply TLS to a SMTP client connection.
 	///
 	/// Defaults to Opportunistic.
 	pub smtp_security: SmtpSecurity,
+	/// Number of random addresses to probe to detect a catch-all domain. The
+	/// domain is only considered a catch-all if all of them are accepted,
+	/// as some servers accept a first unknown recipient, but reject the
+	/// next ones. The probes stop at the first rejected one.
+	///
+	/// Defaults to 1.
+	pub catch_all_probes: usize,
+	/// Delay between two catch-all probes. It counts in `smtp_timeout`.
+	///
+	/// Defaults to None, i.e. no delay.
+	pub catch_all_probe_delay: Option<Duration>,
+	/// Which IP versions to use to connect to the MX hosts. Apart from
+	/// `Auto`, the MX host's addresses are resolved with the input's DNS
+	/// settings, and tried "happy eyeballs" style (RFC 8305): alternating
+	/// between IPv6 and IPv4, with a new attempt started every 250ms until
+	/// one connects. Ignored when using a proxy, which resolves the MX host
+	/// itself.
+	///
+	/// Defaults to Auto.
+	pub ip_version: IpVersion,
+	/// Source IP address of the SMTP connections, for servers with several
+	/// outbound IPs. Only the MX host's addresses of the same IP version are
+	/// tried. Ignored when using a SOCKS5 proxy, otherwise it's the source
+	/// of the connection to the proxy.
+	///
+	/// Defaults to None, i.e. let the system choose.
+	pub source_ip: Option<IpAddr>,
+	/// Network interface the SMTP connections are bound to, e.g. "eth1".
+	/// Only supported on Linux, where binding to an interface may require
+	/// the CAP_NET_RAW capability. Ignored when using a SOCKS5 proxy.
+	///
+	/// Defaults to None, i.e. let the system choose.
+	pub source_interface: Option<String>,
 	/// **IMPORTANT:** This is a beta feature, and might be completely removed,
 	/// or moved somewhere else, before the next release.
 	///
-	/// List of domains to skip when doing an SMTP connection, because we know
-	/// they return "unknown". For each string in this list, we check if the MX
-	/// record **contains** the string; if yes, we return an error saying the
-	/// SMTP verification is skipped.
+	/// List of MX hosts to skip when doing an SMTP connection, because we
+	/// know they return "unknown". The SMTP result is then a `SkippedDomain`
+	/// error, naming the pattern which matched the MX host. Each pattern is
+	/// either:
+	/// - a regular expression, if it starts with "re:", e.g.
+	///   "re:^mx\d+\.zoho\.com$", matched case-insensitively against the
+	///   MX host without its trailing dot,
+	/// - a glob, if it contains "*" or "?", e.g. "mx*.zoho.com", matching
+	///   the whole MX host,
+	/// - otherwise a domain, with the same rules as `allowed_domains`, e.g.
+	///   ".zoho.com" matches "zoho.com" and all its subdomains.
 	///
 	/// Related issue: https://github.com/reacherhq/check-if-email-exists/issues/937
 	///
-	/// ## Example
+	/// Defaults to a list of providers known to answer "unknown".
+	pub skipped_domains: Vec<HostPattern>,
+	/// Report the emails whose MX host is in `skipped_domains` as `Risky`
+	/// instead of `Unknown`.
+	///
+	/// Defaults to false.
+	pub skipped_domains_are_risky: bool,
+	/// Report the emails whose MX hosts are an email security gateway, e.g.
+	/// Proofpoint, Mimecast, Barracuda or Cisco IronPort, and which the
+	/// SMTP verification found deliverable, as catch-all, hence `Risky`:
+	/// gateways accept almost any recipient. The gateway is reported in the
+	/// `gateway` field of the SMTP details either way.
+	///
+	/// Defaults to false.
+	pub security_gateways_are_risky: bool,
+	/// Routes of the emails to a chain of verification methods, e.g. a
+	/// provider's HTTP API, then a headless browser, then SMTP, according
+	/// to their MX host or domain. The first matching route wins, and takes
+	/// precedence over `skipped_domains` and the `*_use_api` and
+	/// `*_use_headless` flags, which only apply to the emails matching no
+	/// route.
+	///
+	/// Defaults to no routes.
+	pub provider_routes: Vec<ProviderRoute>,
+	/// If the email's domain has no MX records, try the MX records of its
+	/// parent domains instead, e.g. use `example.com`'s MX records for
+	/// `user@team.example.com`. The domain which provided the MX records is
+	/// reported in the `mx_domain` field of the MX details.
+	///
+	/// Defaults to false.
+	pub mx_parent_domain_fallback: bool,
+	/// Try the MX hosts one after the other, by ascending priority, until
+	/// one of them answers, instead of a single one picked in the middle of
+	/// the list. The next host is tried when the connection is refused,
+	/// times out or errors.
+	///
+	/// Defaults to false.
+	pub try_all_mx_hosts: bool,
+	/// SMTP settings of specific email domains, e.g. another port or a
+	/// proxy, overriding the input's ones. Keys are matched
+	/// case-insensitively against the email's domain.
+	///
+	/// Defaults to empty.
+	pub domain_overrides: HashMap<String, DomainConfig>,
+	/// If the email's domain has no MX records, but has an A or AAAA record,
+	/// use the domain itself as MX host, as per RFC 5321. This is reported
+	/// in the `implicit_mx` field of the MX details.
+	///
+	/// Defaults to false.
+	pub implicit_mx_fallback: bool,
+	/// Validate the MX records with DNSSEC, and report the result in the
+	/// `dnssec_validated` field of the MX details. This needs an additional
+	/// DNS query per verification. It's not supported with `encrypted_dns`.
+	///
+	/// Defaults to false.
+	pub dnssec_validation: bool,
+	/// DNSBL zones, e.g. `mx::SPAMHAUS_ZEN`, to check the MX hosts' IP
+	/// addresses against. Listings are reported in the `dnsbl_listings`
+	/// field of the MX details. Empty to disable the check.
+	///
+	/// Defaults to [].
+	pub dnsbl_zones: Vec<String>,
+	/// Resolve the IP addresses of each MX host, and add them to the
+	/// `mx_records` field of the MX details. They are always resolved if
+	/// another check needs them, e.g. `dnsbl_zones` or `check_mx_rdns`.
+	///
+	/// Defaults to false.
+	pub resolve_mx_ips: bool,
+	/// Resolve the IP addresses of each MX host, and their reverse DNS (PTR)
+	/// hostnames. They are reported in the `rdns` field of the MX details.
+	///
+	/// Defaults to false.
+	pub check_mx_rdns: bool,
+	/// Detect whether the domain is parked, from its nameservers and MX
+	/// hosts, and whether it resolves all its subdomains with wildcard DNS
+	/// records. It's reported in the `parked_domain` field of the MX details.
+	///
+	/// Defaults to false.
+	pub check_parked_domain: bool,
+	/// Compute a numeric confidence score, together with the breakdown of
+	/// how it was computed, and add it to the output.
+	///
+	/// Defaults to false.
+	pub compute_confidence: bool,
+	/// Rules overriding the `is_reachable` verdict, evaluated in order once
+	/// all the checks ran: the first rule whose conditions all hold sets
+	/// the verdict, e.g. to treat the catch-all domains of a provider as
+	/// `Safe`, or the disposable emails as `Invalid`.
+	///
+	/// Defaults to no rules, i.e. the built-in verdict.
+	pub reachability_rules: Vec<ReachabilityRule>,
+	/// Weights of the confidence score's signals, overriding their default
+	/// ones, e.g. to not penalize catch-all domains.
 	///
-	/// If you want to skip Zoho emails, it's good enough to ".zoho.com." to
-	/// the list. This way it will skip all Zoho emails as their MX records
-	/// show "mx{N}.zoho.com.". Simply putting "zoho" might give false
-	/// positives if you have an email provider like "mycustomzoho.com".
+	/// Defaults to no overrides.
+	pub confidence_weights: HashMap<ConfidenceSignal, i32>,
+	/// Detect the coarse category of the email provider (consumer webmail,
+	/// business hosted, self-hosted, disposable), and add it to the output.
 	///
-	/// Defaults to: [""]
-	pub skipped_domains: Vec<String>,
+	/// Defaults to false.
+	pub check_provider_category: bool,
+	/// Fetch and parse the SPF record of the email's domain, and add it to
+	/// the `dns` details of the output.
+	///
+	/// Defaults to false.
+	pub check_spf: bool,
+	/// Fetch and parse the DMARC policy of the email's domain, and add it to
+	/// the `dns` details of the output.
+	///
+	/// Defaults to false.
+	pub check_dmarc: bool,
+	/// Fetch the MTA-STS policy and the SMTP TLS reporting record of the
+	/// email's domain, and add them to the `dns` details of the output. This
+	/// tells whether the domain requires TLS to receive mail.
+	///
+	/// Defaults to false.
+	pub check_mta_sts: bool,
+	/// Look up the registration date and registrar of the email's domain
+	/// over RDAP, and add them to the `rdap` details of the output. Freshly
+	/// registered domains are a common spam signal.
+	///
+	/// Defaults to false.
+	pub check_rdap: bool,
+	/// Flag likely spamtraps in the `misc` details, with heuristics: trap
+	/// seed usernames, the context's trap feed, see
+	/// `CheckEmailContext::with_trap_feed`, and, if `check_rdap` is set,
+	/// re-activated dormant domains and young domains accepting all
+	/// recipients.
+	///
+	/// Defaults to false.
+	pub check_spamtraps: bool,
+	/// If set, and if the SMTP server advertises the SIZE extension, probe
+	/// whether a message of this many bytes would be accepted, with
+	/// `MAIL FROM:<...> SIZE=<n>`. No message is sent.
+	///
+	/// Defaults to None.
+	pub smtp_size_probe: Option<usize>,
+	/// Nameservers, as IP and port, to use for DNS lookups. Each one is
+	/// queried over UDP, and over TCP for truncated responses.
+	///
+	/// Defaults to an empty list, i.e. the system's resolver configuration.
+	pub dns_nameservers: Vec<SocketAddr>,
+	/// Make DNS lookups over HTTPS or TLS instead, e.g. in environments
+	/// where UDP port 53 is blocked. Takes precedence over
+	/// `dns_nameservers`.
+	///
+	/// Defaults to None, i.e. plain DNS.
+	pub encrypted_dns: Option<EncryptedDns>,
+	/// Timeout of each DNS query, independently of `smtp_timeout`.
+	///
+	/// Defaults to 5s.
+	pub dns_timeout: Duration,
+	/// Number of retries of a failed DNS query, e.g. after a timeout. A
+	/// missing record is not retried.
+	///
+	/// Defaults to 2.
+	pub dns_retries: usize,
+	/// Consider email addresses with non-ASCII characters, i.e.
+	/// internationalized domains or local parts, as invalid.
+	///
+	/// Defaults to false.
+	pub reject_non_ascii: bool,
+	/// Transformations, such as trimming whitespace or lowercasing, to apply
+	/// to `to_email` before checking its syntax. The applied ones are
+	/// reported in the syntax's `transformations`, while the output's `input`
+	/// keeps the raw value.
+	///
+	/// Defaults to no transformation.
+	pub cleanup_policy: CleanupPolicy,
+	/// Consider email addresses with an IP literal domain, e.g.
+	/// `user@[192.168.1.1]`, as invalid. When they're allowed, the MX lookup
+	/// is skipped, and the SMTP connection is made directly to the IP.
+	///
+	/// Defaults to false.
+	pub reject_ip_literals: bool,
+	/// Extract the email address out of free text before verifying it, e.g.
+	/// `john@acme.com` out of `"John Doe <john@acme.com>"` or
+	/// `mailto:john@acme.com`. See `parse_email_like`. The output's `input`
+	/// field still contains the original text.
+	///
+	/// Defaults to false.
+	pub extract_email: bool,
+	/// If not empty, only verify emails on these domains. Domains starting
+	/// with "." also match all their subdomains, e.g. ".acme.com" matches
+	/// "acme.com" and "mail.acme.com".
+	///
+	/// Defaults to an empty list, i.e. all domains are allowed.
+	pub allowed_domains: Vec<String>,
+	/// Never verify emails on these domains, with the same matching rules as
+	/// `allowed_domains`. Such emails are reported as `Blocked`, and no
+	/// network call is made.
+	///
+	/// Defaults to an empty list.
+	pub blocked_domains: Vec<String>,
+	/// Validate the domain's TLD against the IANA list, so that e.g.
+	/// `user@example.con` is considered invalid without any network call.
+	///
+	/// Defaults to false.
+	pub check_tld: bool,
+	/// The list of valid TLDs used when `check_tld` is set. Use
+	/// `TldList::fetch_iana` to get an up-to-date one.
+	///
+	/// Defaults to None, i.e. the list bundled with this crate.
+	pub tld_list: Option<TldList>,
+	/// Which grammar to validate the email address syntax against.
+	///
+	/// Defaults to Rfc5321.
+	pub syntax_strictness: SyntaxStrictness,
+	/// Additional restrictions on the local part, such as a maximum length,
+	/// allowed characters or banned patterns. Violations are reported in
+	/// the syntax's `syntax_violations`.
+	///
+	/// Defaults to no restriction.
+	pub local_part_policy: LocalPartPolicy,
+	/// Provider-specific rules used to compute the syntax's
+	/// `normalized_email`. The first rule matching the email's domain is
+	/// used.
+	///
+	/// Defaults to the built-in rules for Gmail, Outlook, iCloud,
+	/// ProtonMail, Fastmail and Yahoo.
+	pub normalization_rules: Vec<NormalizationRule>,
+	/// Known mail domains, with their frequency, used to suggest corrections
+	/// of misspelled domains. A custom list is indexed once, on the first
+	/// suggestion, and the index is shared by the clones of the input.
+	///
+	/// Defaults to None, i.e. the built-in list of popular mail domains.
+	pub suggestion_domains: Option<SuggestionDomains>,
+	/// Maximum number of suggested corrections of a misspelled domain.
+	///
+	/// Defaults to 3.
+	pub max_suggestions: usize,
+	/// State shared with other verifications, such as the catch-all cache.
+	/// This field is never (de)serialized.
+	///
+	/// Defaults to a new, empty context, i.e. nothing is shared.
+	#[serde(skip)]
+	pub context: CheckEmailContext,
 }
 
 impl Default for CheckEmailInput {
@@ -164,33 +842,123 @@ impl Default for CheckEmailInput {
 			to_email: "".into(),
 			from_email: "reacher.email@gmail.com".into(), // Unused, owned by Reacher
 			hello_name: "gmail.com".into(),
+			hello_names: vec![],
+			from_emails: vec![],
+			identity_rotation: RotationPolicy::default(),
 			#[cfg(feature = "headless")]
 			hotmail_use_headless: None,
+			#[cfg(feature = "headless")]
+			microsoft365_use_headless: None,
+			#[cfg(feature = "headless")]
+			microsoft_use_headless: None,
+			#[cfg(feature = "headless")]
+			yahoo_use_headless: None,
+			#[cfg(feature = "headless")]
+			headless_debug_dir: None,
 			proxy: None,
 			smtp_port: 25,
+			smtp_fallback_ports: Vec::new(),
 			smtp_security: SmtpSecurity::default(),
+			catch_all_probes: 1,
+			catch_all_probe_delay: None,
+			ip_version: IpVersion::default(),
+			source_ip: None,
+			source_interface: None,
 			smtp_timeout: Some(Duration::from_secs(12)),
+			smtp_timeouts: SmtpTimeouts::default(),
+			smtp_deadline: None,
+			total_timeout: None,
+			tarpit_threshold: None,
 			yahoo_use_api: true,
 			gmail_use_api: false,
 			microsoft365_use_api: false,
+			proton_use_api: false,
+			icloud_use_api: false,
+			mailru_use_api: false,
+			yandex_use_api: false,
+			gmx_use_api: false,
 			check_gravatar: false,
+			gravatar_timeout: None,
 			haveibeenpwned_api_key: None,
+			haveibeenpwned_verified_only: false,
+			free_provider_domains: None,
+			role_accounts: None,
 			retries: 2,
-			skipped_domains: vec![
+			greylisting_retry_delay: None,
+			retry_strategy: RetryStrategy::default(),
+			smtp_debug: false,
+			smtp_vrfy_fallback: false,
+			smtp_tls_details: false,
+			smtp_accept_invalid_certs: true,
+			smtp_root_certificates: vec![],
+			smtp_dane_validation: false,
+			smtp_capabilities: false,
+			smtp_timings: false,
+			smtp_verification_mode: SmtpVerificationMode::default(),
+			smtp_relay: None,
+			lmtp_server: None,
+			skipped_domains: [
 				// on @bluewin.ch
 				// - mx-v02.bluewin.ch.
-				".bluewin.ch.".into(),
+				".bluewin.ch.",
 				// on @bluewin.ch
 				// - mxbw-bluewin-ch.hdb-cs04.ellb.ch.
-				"bluewin-ch.".into(),
+				"*bluewin-ch.*",
 				// on @gmx.de, @gmx.ch, @gmx.net
-				".gmx.net.".into(),
+				".gmx.net.",
 				// on @icloud.com
-				".mail.icloud.com.".into(),
+				".mail.icloud.com.",
 				// on @web.de
-				".web.de.".into(),
-				".zoho.com.".into(),
-			],
+				".web.de.",
+				".zoho.com.",
+			]
+			.iter()
+			.map(|pattern| {
+				pattern
+					.parse()
+					.expect("Default skipped domains are valid. qed.")
+			})
+			.collect(),
+			skipped_domains_are_risky: false,
+			security_gateways_are_risky: false,
+			provider_routes: vec![],
+			mx_parent_domain_fallback: false,
+			try_all_mx_hosts: false,
+			domain_overrides: HashMap::new(),
+			implicit_mx_fallback: false,
+			dnssec_validation: false,
+			dnsbl_zones: Vec::new(),
+			resolve_mx_ips: false,
+			check_mx_rdns: false,
+			check_parked_domain: false,
+			compute_confidence: false,
+			reachability_rules: vec![],
+			confidence_weights: HashMap::new(),
+			check_provider_category: false,
+			check_spf: false,
+			check_dmarc: false,
+			check_mta_sts: false,
+			check_rdap: false,
+			check_spamtraps: false,
+			smtp_size_probe: None,
+			dns_nameservers: Vec::new(),
+			encrypted_dns: None,
+			dns_timeout: Duration::from_secs(5),
+			dns_retries: 2,
+			reject_non_ascii: false,
+			cleanup_policy: CleanupPolicy::default(),
+			reject_ip_literals: false,
+			extract_email: false,
+			allowed_domains: Vec::new(),
+			blocked_domains: Vec::new(),
+			check_tld: false,
+			tld_list: None,
+			syntax_strictness: SyntaxStrictness::default(),
+			local_part_policy: LocalPartPolicy::default(),
+			normalization_rules: default_normalization_rules(),
+			suggestion_domains: None,
+			max_suggestions: 3,
+			context: CheckEmailContext::default(),
 		}
 	}
 }
@@ -204,6 +972,62 @@ impl CheckEmailInput {
 		}
 	}
 
+	/// Create a new CheckEmailInput tuned for speed, at the cost of more
+	/// `unknown` results. On top of the defaults, it sets:
+	/// - `smtp_timeout`: 5s,
+	/// - `retries`: 1, i.e. no retry on greylisting.
+	///
+	/// Use the setters to further customize it.
+	pub fn preset_fast(to_email: String) -> CheckEmailInput {
+		let mut input = CheckEmailInput::new(to_email);
+		input
+			.set_smtp_timeout(Some(Duration::from_secs(5)))
+			.set_retries(1);
+		input
+	}
+
+	/// Create a new CheckEmailInput tuned for accuracy, at the cost of
+	/// slower verifications. On top of the defaults, it sets:
+	/// - `smtp_timeout`: 30s,
+	/// - `retries`: 3, to get past greylisting,
+	/// - `microsoft365_use_api`: true,
+	/// - `mx_parent_domain_fallback`: true,
+	/// - `compute_confidence`: true.
+	///
+	/// Use the setters to further customize it.
+	pub fn preset_accurate(to_email: String) -> CheckEmailInput {
+		let mut input = CheckEmailInput::new(to_email);
+		input
+			.set_smtp_timeout(Some(Duration::from_secs(30)))
+			.set_retries(3)
+			.set_microsoft365_use_api(true)
+			.set_mx_parent_domain_fallback(true)
+			.set_compute_confidence(true);
+		input
+	}
+
+	/// Create a new CheckEmailInput which keeps a low profile on the SMTP
+	/// servers, to avoid being flagged. On top of the defaults, it sets:
+	/// - `retries`: 1, to open a single connection per verification,
+	/// - `catch_all_probes`: 1, to send a single random `RCPT TO`,
+	/// - a context rate limiting the probes to 10 per minute for each MX
+	///   host, see `RateLimitConfig`. The context has no SMTP connection
+	///   pool, so that each connection is closed with `QUIT` instead of
+	///   being kept open.
+	///
+	/// Use the setters to further customize it, in particular
+	/// `set_hello_name` and `set_from_email` with a domain you own. Give the
+	/// other inputs a clone of this input's context, so that they share the
+	/// rate limit.
+	pub fn preset_stealth(to_email: String) -> CheckEmailInput {
+		let mut input = CheckEmailInput::new(to_email);
+		input.set_retries(1).set_catch_all_probes(1).set_context(
+			CheckEmailContext::new()
+				.with_rate_limit(RateLimitConfig::new(10, Duration::from_secs(60))),
+		);
+		input
+	}
+
 	/// Set the email to use in the `MAIL FROM:` SMTP command. Defaults to
 	/// `user@example.org` if not explicitly set.
 	#[deprecated(since = "0.8.24", note = "Please use set_from_email instead")]
@@ -234,6 +1058,30 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set the names to rotate across in the `EHLO:` SMTP command. Defaults
+	/// to empty, i.e. always use `hello_name`.
+	pub fn set_hello_names(&mut self, names: Vec<String>) -> &mut CheckEmailInput {
+		self.hello_names = names;
+		self
+	}
+
+	/// Set the emails to rotate across in the `MAIL FROM:` SMTP command.
+	/// Defaults to empty, i.e. always use `from_email`.
+	pub fn set_from_emails(&mut self, emails: Vec<String>) -> &mut CheckEmailInput {
+		self.from_emails = emails;
+		self
+	}
+
+	/// Set how to pick the EHLO name and `MAIL FROM` address among
+	/// `hello_names` and `from_emails`. Defaults to RoundRobin.
+	pub fn set_identity_rotation(
+		&mut self,
+		identity_rotation: RotationPolicy,
+	) -> &mut CheckEmailInput {
+		self.identity_rotation = identity_rotation;
+		self
+	}
+
 	/// Use the specified SOCK5 proxy to perform email verification.
 	#[deprecated(since = "0.8.24", note = "Please use set_proxy instead")]
 	pub fn proxy(&mut self, proxy_host: String, proxy_port: u16) -> &mut CheckEmailInput {
@@ -245,7 +1093,7 @@ impl CheckEmailInput {
 		self
 	}
 
-	/// Use the specified SOCK5 proxy to perform email verification.
+	/// Use the specified proxy to perform email verification.
 	pub fn set_proxy(&mut self, proxy: CheckEmailInputProxy) -> &mut CheckEmailInput {
 		self.proxy = Some(proxy);
 		self
@@ -257,6 +1105,105 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set the delay before retrying after a greylisting response. Defaults
+	/// to None, i.e. retry immediately.
+	pub fn set_greylisting_retry_delay(&mut self, delay: Option<Duration>) -> &mut CheckEmailInput {
+		self.greylisting_retry_delay = delay;
+		self
+	}
+
+	/// Set which MX host to retry on. Defaults to SameHost.
+	pub fn set_retry_strategy(&mut self, retry_strategy: RetryStrategy) -> &mut CheckEmailInput {
+		self.retry_strategy = retry_strategy;
+		self
+	}
+
+	/// Record the transcript of the SMTP session in the output. Defaults to
+	/// false.
+	pub fn set_smtp_debug(&mut self, smtp_debug: bool) -> &mut CheckEmailInput {
+		self.smtp_debug = smtp_debug;
+		self
+	}
+
+	/// Fall back to the VRFY command when the SMTP server rejects `RCPT TO`.
+	/// Defaults to false.
+	pub fn set_smtp_vrfy_fallback(&mut self, smtp_vrfy_fallback: bool) -> &mut CheckEmailInput {
+		self.smtp_vrfy_fallback = smtp_vrfy_fallback;
+		self
+	}
+
+	/// Include the details of the SMTP server's TLS certificate in the
+	/// output. Defaults to false.
+	pub fn set_smtp_tls_details(&mut self, smtp_tls_details: bool) -> &mut CheckEmailInput {
+		self.smtp_tls_details = smtp_tls_details;
+		self
+	}
+
+	/// Accept invalid TLS certificates from the SMTP server, e.g.
+	/// self-signed ones. Defaults to true.
+	pub fn set_smtp_accept_invalid_certs(
+		&mut self,
+		smtp_accept_invalid_certs: bool,
+	) -> &mut CheckEmailInput {
+		self.smtp_accept_invalid_certs = smtp_accept_invalid_certs;
+		self
+	}
+
+	/// Trust additional PEM-encoded root certificates when validating the
+	/// SMTP server's TLS certificate.
+	pub fn set_smtp_root_certificates(
+		&mut self,
+		smtp_root_certificates: Vec<String>,
+	) -> &mut CheckEmailInput {
+		self.smtp_root_certificates = smtp_root_certificates;
+		self
+	}
+
+	/// Validate the SMTP server's TLS certificate against the TLSA records
+	/// of the MX host. Defaults to false.
+	pub fn set_smtp_dane_validation(&mut self, smtp_dane_validation: bool) -> &mut CheckEmailInput {
+		self.smtp_dane_validation = smtp_dane_validation;
+		self
+	}
+
+	/// Include the SMTP server's EHLO capabilities in the output. Defaults
+	/// to false.
+	pub fn set_smtp_capabilities(&mut self, smtp_capabilities: bool) -> &mut CheckEmailInput {
+		self.smtp_capabilities = smtp_capabilities;
+		self
+	}
+
+	/// Include the durations of the stages of the SMTP session in the
+	/// output. Defaults to false.
+	pub fn set_smtp_timings(&mut self, smtp_timings: bool) -> &mut CheckEmailInput {
+		self.smtp_timings = smtp_timings;
+		self
+	}
+
+	/// Set how far the SMTP verification goes, e.g. connectivity only,
+	/// without probing the mailbox. Defaults to Mailbox.
+	pub fn set_smtp_verification_mode(
+		&mut self,
+		smtp_verification_mode: SmtpVerificationMode,
+	) -> &mut CheckEmailInput {
+		self.smtp_verification_mode = smtp_verification_mode;
+		self
+	}
+
+	/// Send the SMTP probes through an authenticated relay, instead of
+	/// connecting to the MX hosts. Defaults to None.
+	pub fn set_smtp_relay(&mut self, smtp_relay: Option<SmtpRelay>) -> &mut CheckEmailInput {
+		self.smtp_relay = smtp_relay;
+		self
+	}
+
+	/// Verify the mailboxes on an LMTP server, instead of the MX hosts.
+	/// Defaults to None.
+	pub fn set_lmtp_server(&mut self, lmtp_server: Option<LmtpServer>) -> &mut CheckEmailInput {
+		self.lmtp_server = lmtp_server;
+		self
+	}
+
 	/// Add optional timeout for the SMTP verification step.
 	#[deprecated(since = "0.8.24", note = "Please use set_smtp_timeout instead")]
 	pub fn smtp_timeout(&mut self, duration: Duration) -> &mut CheckEmailInput {
@@ -270,12 +1217,54 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set the ports to try, in order, after `smtp_port` when it can't be
+	/// reached, e.g. `vec![587, 465, 2525]`.
+	pub fn set_smtp_fallback_ports(&mut self, ports: Vec<u16>) -> &mut CheckEmailInput {
+		self.smtp_fallback_ports = ports;
+		self
+	}
+
 	/// Set the SMTP client security to use for TLS.
 	pub fn set_smtp_security(&mut self, smtp_security: SmtpSecurity) -> &mut CheckEmailInput {
 		self.smtp_security = smtp_security;
 		self
 	}
 
+	/// Set the number of random addresses to probe to detect a catch-all
+	/// domain. Defaults to 1.
+	pub fn set_catch_all_probes(&mut self, catch_all_probes: usize) -> &mut CheckEmailInput {
+		self.catch_all_probes = catch_all_probes;
+		self
+	}
+
+	/// Set the delay between two catch-all probes. Defaults to no delay.
+	pub fn set_catch_all_probe_delay(&mut self, delay: Option<Duration>) -> &mut CheckEmailInput {
+		self.catch_all_probe_delay = delay;
+		self
+	}
+
+	/// Set which IP versions to use to connect to the MX hosts.
+	pub fn set_ip_version(&mut self, ip_version: IpVersion) -> &mut CheckEmailInput {
+		self.ip_version = ip_version;
+		self
+	}
+
+	/// Set the source IP address of the SMTP connections. Defaults to None.
+	pub fn set_source_ip(&mut self, source_ip: Option<IpAddr>) -> &mut CheckEmailInput {
+		self.source_ip = source_ip;
+		self
+	}
+
+	/// Set the network interface the SMTP connections are bound to. Defaults
+	/// to None.
+	pub fn set_source_interface(
+		&mut self,
+		source_interface: Option<String>,
+	) -> &mut CheckEmailInput {
+		self.source_interface = source_interface;
+		self
+	}
+
 	/// Add optional timeout for the SMTP verification step. This is the
 	/// timeout for _each_ SMTP connection attempt, not for the whole email
 	/// verification process.
@@ -284,6 +1273,34 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set the timeouts of the stages of each SMTP attempt: the connection,
+	/// `EHLO`, `MAIL FROM` and `RCPT TO`.
+	pub fn set_smtp_timeouts(&mut self, smtp_timeouts: SmtpTimeouts) -> &mut CheckEmailInput {
+		self.smtp_timeouts = smtp_timeouts;
+		self
+	}
+
+	/// Set the time limit of the whole SMTP verification, across all its
+	/// attempts. Defaults to None.
+	pub fn set_smtp_deadline(&mut self, duration: Option<Duration>) -> &mut CheckEmailInput {
+		self.smtp_deadline = duration;
+		self
+	}
+
+	/// Set the time limit of the whole verification, including DNS lookups
+	/// and the misc checks. Defaults to None.
+	pub fn set_total_timeout(&mut self, duration: Option<Duration>) -> &mut CheckEmailInput {
+		self.total_timeout = duration;
+		self
+	}
+
+	/// Set the maximum pause of the SMTP server in the middle of a response,
+	/// before being considered a tarpit. Defaults to None.
+	pub fn set_tarpit_threshold(&mut self, threshold: Option<Duration>) -> &mut CheckEmailInput {
+		self.tarpit_threshold = threshold;
+		self
+	}
+
 	/// Set whether to use Yahoo's API or connecting directly to their SMTP
 	/// servers. Defaults to true.
 	#[deprecated(since = "0.8.24", note = "Please use set_yahoo_use_api instead")]
@@ -313,6 +1330,42 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set whether to use Proton's address availability API or connecting
+	/// directly to their SMTP servers. Defaults to false.
+	pub fn set_proton_use_api(&mut self, use_api: bool) -> &mut CheckEmailInput {
+		self.proton_use_api = use_api;
+		self
+	}
+
+	/// Set whether to use Apple's Apple ID validation API for iCloud email
+	/// addresses, which are skipped otherwise. Defaults to false.
+	pub fn set_icloud_use_api(&mut self, use_api: bool) -> &mut CheckEmailInput {
+		self.icloud_use_api = use_api;
+		self
+	}
+
+	/// Set whether to use Mail.ru's user API or connecting directly to their
+	/// SMTP servers. Defaults to false.
+	pub fn set_mailru_use_api(&mut self, use_api: bool) -> &mut CheckEmailInput {
+		self.mailru_use_api = use_api;
+		self
+	}
+
+	/// Set whether to use Yandex's login validation API or connecting
+	/// directly to their SMTP servers. Defaults to false.
+	pub fn set_yandex_use_api(&mut self, use_api: bool) -> &mut CheckEmailInput {
+		self.yandex_use_api = use_api;
+		self
+	}
+
+	/// Set whether to use the availability API of the GMX and WEB.DE signup
+	/// pages for their email addresses, which are skipped otherwise.
+	/// Defaults to false.
+	pub fn set_gmx_use_api(&mut self, use_api: bool) -> &mut CheckEmailInput {
+		self.gmx_use_api = use_api;
+		self
+	}
+
 	/// Whether to check if a gravatar image is existing for the given email.
 	/// Defaults to false.
 	pub fn set_check_gravatar(&mut self, check_gravatar: bool) -> &mut CheckEmailInput {
@@ -320,6 +1373,12 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set the timeout of the Gravatar request. Defaults to None.
+	pub fn set_gravatar_timeout(&mut self, timeout: Option<Duration>) -> &mut CheckEmailInput {
+		self.gravatar_timeout = timeout;
+		self
+	}
+
 	/// Whether to haveibeenpwned' API for the given email
 	/// check only if the api_key is set
 	pub fn set_haveibeenpwned_api_key(&mut self, api_key: Option<String>) -> &mut CheckEmailInput {
@@ -327,6 +1386,36 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Only report the verified breaches of HaveIBeenPwned. Defaults to
+	/// false.
+	pub fn set_haveibeenpwned_verified_only(
+		&mut self,
+		verified_only: bool,
+	) -> &mut CheckEmailInput {
+		self.haveibeenpwned_verified_only = verified_only;
+		self
+	}
+
+	/// Set the role account usernames, with their severity. Set to None to
+	/// use the built-in list.
+	pub fn set_role_accounts(
+		&mut self,
+		role_accounts: Option<Vec<RoleAccount>>,
+	) -> &mut CheckEmailInput {
+		self.role_accounts = role_accounts;
+		self
+	}
+
+	/// Set the domains of free email providers. Set to None to use the
+	/// bundled list.
+	pub fn set_free_provider_domains(
+		&mut self,
+		free_provider_domains: Option<Vec<String>>,
+	) -> &mut CheckEmailInput {
+		self.free_provider_domains = free_provider_domains;
+		self
+	}
+
 	/// Set whether or not to use a headless navigator to navigate to Hotmail's
 	/// password recovery page to check if an email exists. If set to
 	/// `Some(<endpoint>)`, this endpoint must point to a WebDriver process,
@@ -340,20 +1429,406 @@ impl CheckEmailInput {
 		self
 	}
 
+	/// Set whether or not to use a headless navigator to navigate to
+	/// Microsoft 365's login page to check if an email exists. If set to
+	/// `Some(<endpoint>)`, this endpoint must point to a WebDriver process,
+	/// usually listening on http://localhost:4444. Defaults to None.
+	#[cfg(feature = "headless")]
+	pub fn set_microsoft365_use_headless(
+		&mut self,
+		use_headless: Option<String>,
+	) -> &mut CheckEmailInput {
+		self.microsoft365_use_headless = use_headless;
+		self
+	}
+
+	/// Set whether or not to verify all Microsoft email addresses with a
+	/// headless navigator, on the password recovery page or the login page
+	/// depending on whether they're consumer or Microsoft 365 addresses. If
+	/// set to `Some(<endpoint>)`, this endpoint must point to a WebDriver
+	/// process, usually listening on http://localhost:4444. Defaults to
+	/// None.
+	#[cfg(feature = "headless")]
+	pub fn set_microsoft_use_headless(
+		&mut self,
+		use_headless: Option<String>,
+	) -> &mut CheckEmailInput {
+		self.microsoft_use_headless = use_headless;
+		self
+	}
+
+	/// Set whether or not to fall back to a headless navigator on Yahoo's
+	/// password recovery page when Yahoo's API gets an unexpected payload.
+	/// If set to `Some(<endpoint>)`, this endpoint must point to a WebDriver
+	/// process, usually listening on http://localhost:4444. Defaults to
+	/// None.
+	#[cfg(feature = "headless")]
+	pub fn set_yahoo_use_headless(&mut self, use_headless: Option<String>) -> &mut CheckEmailInput {
+		self.yahoo_use_headless = use_headless;
+		self
+	}
+
+	/// Set the directory where the headless verifications write the page
+	/// they didn't expect. Defaults to None, i.e. the page is included in
+	/// the error.
+	#[cfg(feature = "headless")]
+	pub fn set_headless_debug_dir(&mut self, dir: Option<PathBuf>) -> &mut CheckEmailInput {
+		self.headless_debug_dir = dir;
+		self
+	}
+
 	/// **IMPORTANT:** This is a beta feature, and might be completely removed,
 	/// or moved somewhere else, before the next release.
 	///
-	/// List of domains to skip when doing an SMTP connection, because we know
-	/// they return "unknown".
-	pub fn set_skipped_domains(&mut self, domains: Vec<String>) -> &mut CheckEmailInput {
+	/// List of MX host patterns to skip when doing an SMTP connection,
+	/// because we know they return "unknown".
+	pub fn set_skipped_domains(&mut self, domains: Vec<HostPattern>) -> &mut CheckEmailInput {
 		self.skipped_domains = domains;
 		self
 	}
+
+	/// Route the emails matching a route's pattern to its chain of
+	/// verification methods. Defaults to no routes.
+	pub fn set_provider_routes(&mut self, routes: Vec<ProviderRoute>) -> &mut CheckEmailInput {
+		self.provider_routes = routes;
+		self
+	}
+
+	/// Report the emails on skipped MX hosts as `Risky` instead of
+	/// `Unknown`. Defaults to false.
+	pub fn set_skipped_domains_are_risky(
+		&mut self,
+		skipped_domains_are_risky: bool,
+	) -> &mut CheckEmailInput {
+		self.skipped_domains_are_risky = skipped_domains_are_risky;
+		self
+	}
+
+	/// Report the deliverable emails behind an email security gateway as
+	/// catch-all, hence `Risky`. Defaults to false.
+	pub fn set_security_gateways_are_risky(
+		&mut self,
+		security_gateways_are_risky: bool,
+	) -> &mut CheckEmailInput {
+		self.security_gateways_are_risky = security_gateways_are_risky;
+		self
+	}
+
+	/// Set whether to fall back to the parent domains' MX records when the
+	/// email's domain has none. Defaults to false.
+	pub fn set_mx_parent_domain_fallback(&mut self, fallback: bool) -> &mut CheckEmailInput {
+		self.mx_parent_domain_fallback = fallback;
+		self
+	}
+
+	/// Set whether to try the MX hosts one after the other, by ascending
+	/// priority, until one answers. Defaults to false.
+	pub fn set_try_all_mx_hosts(&mut self, try_all_mx_hosts: bool) -> &mut CheckEmailInput {
+		self.try_all_mx_hosts = try_all_mx_hosts;
+		self
+	}
+
+	/// Set the SMTP settings of specific email domains. Defaults to empty.
+	pub fn set_domain_overrides(
+		&mut self,
+		domain_overrides: HashMap<String, DomainConfig>,
+	) -> &mut CheckEmailInput {
+		self.domain_overrides = domain_overrides;
+		self
+	}
+
+	/// The input to verify an email of `domain` with, i.e. with the
+	/// domain's `domain_overrides` applied, if any.
+	pub(crate) fn for_domain(&self, domain: &str) -> Cow<'_, CheckEmailInput> {
+		let config = match self
+			.domain_overrides
+			.iter()
+			.find(|(key, _)| key.eq_ignore_ascii_case(domain))
+		{
+			Some((_, config)) => config,
+			None => return Cow::Borrowed(self),
+		};
+
+		let mut input = self.clone();
+		if let Some(smtp_port) = config.smtp_port {
+			input.smtp_port = smtp_port;
+		}
+		if let Some(smtp_timeout) = config.smtp_timeout {
+			input.smtp_timeout = Some(smtp_timeout);
+		}
+		if let Some(smtp_security) = config.smtp_security {
+			input.smtp_security = smtp_security;
+		}
+		if let Some(retries) = config.retries {
+			input.retries = retries;
+		}
+		if let Some(proxy) = &config.proxy {
+			input.proxy = Some(proxy.clone());
+		}
+
+		Cow::Owned(input)
+	}
+
+	/// Set whether to use the domain itself as MX host when it has no MX
+	/// records, but an A or AAAA record. Defaults to false.
+	pub fn set_implicit_mx_fallback(&mut self, fallback: bool) -> &mut CheckEmailInput {
+		self.implicit_mx_fallback = fallback;
+		self
+	}
+
+	/// Set whether to validate the MX records with DNSSEC. Defaults to
+	/// false.
+	pub fn set_dnssec_validation(&mut self, validation: bool) -> &mut CheckEmailInput {
+		self.dnssec_validation = validation;
+		self
+	}
+
+	/// Set the DNSBL zones to check the MX hosts' IP addresses against.
+	/// Defaults to [].
+	pub fn set_dnsbl_zones(&mut self, zones: Vec<String>) -> &mut CheckEmailInput {
+		self.dnsbl_zones = zones;
+		self
+	}
+
+	/// Set whether to resolve the IP addresses of each MX host. Defaults to
+	/// false.
+	pub fn set_resolve_mx_ips(&mut self, resolve_mx_ips: bool) -> &mut CheckEmailInput {
+		self.resolve_mx_ips = resolve_mx_ips;
+		self
+	}
+
+	/// Set whether to resolve the reverse DNS of the MX hosts' IP addresses.
+	/// Defaults to false.
+	pub fn set_check_mx_rdns(&mut self, check_mx_rdns: bool) -> &mut CheckEmailInput {
+		self.check_mx_rdns = check_mx_rdns;
+		self
+	}
+
+	/// Set whether to detect parked domains and wildcard DNS records.
+	/// Defaults to false.
+	pub fn set_check_parked_domain(&mut self, check_parked_domain: bool) -> &mut CheckEmailInput {
+		self.check_parked_domain = check_parked_domain;
+		self
+	}
+
+	/// Set whether to compute a confidence score, with its breakdown.
+	/// Defaults to false.
+	pub fn set_compute_confidence(&mut self, compute_confidence: bool) -> &mut CheckEmailInput {
+		self.compute_confidence = compute_confidence;
+		self
+	}
+
+	/// Set the rules overriding the `is_reachable` verdict, the first
+	/// matching one winning. Defaults to no rules.
+	pub fn set_reachability_rules(&mut self, rules: Vec<ReachabilityRule>) -> &mut CheckEmailInput {
+		self.reachability_rules = rules;
+		self
+	}
+
+	/// Override the weights of the confidence score's signals. Defaults to
+	/// no overrides.
+	pub fn set_confidence_weights(
+		&mut self,
+		weights: HashMap<ConfidenceSignal, i32>,
+	) -> &mut CheckEmailInput {
+		self.confidence_weights = weights;
+		self
+	}
+
+	/// Set whether to detect the category of the email provider.
+	/// Defaults to false.
+	pub fn set_check_provider_category(
+		&mut self,
+		check_provider_category: bool,
+	) -> &mut CheckEmailInput {
+		self.check_provider_category = check_provider_category;
+		self
+	}
+
+	/// Set whether to fetch and parse the domain's SPF record. Defaults to
+	/// false.
+	pub fn set_check_spf(&mut self, check_spf: bool) -> &mut CheckEmailInput {
+		self.check_spf = check_spf;
+		self
+	}
+
+	/// Set whether to fetch and parse the domain's DMARC policy. Defaults to
+	/// false.
+	pub fn set_check_dmarc(&mut self, check_dmarc: bool) -> &mut CheckEmailInput {
+		self.check_dmarc = check_dmarc;
+		self
+	}
+
+	/// Set whether to fetch the domain's MTA-STS policy and TLS-RPT record.
+	/// Defaults to false.
+	pub fn set_check_mta_sts(&mut self, check_mta_sts: bool) -> &mut CheckEmailInput {
+		self.check_mta_sts = check_mta_sts;
+		self
+	}
+
+	/// Set whether to look up the domain's registration details over RDAP.
+	/// Defaults to false.
+	pub fn set_check_rdap(&mut self, check_rdap: bool) -> &mut CheckEmailInput {
+		self.check_rdap = check_rdap;
+		self
+	}
+
+	/// Flag likely spamtraps in the `misc` details. Defaults to false.
+	pub fn set_check_spamtraps(&mut self, check_spamtraps: bool) -> &mut CheckEmailInput {
+		self.check_spamtraps = check_spamtraps;
+		self
+	}
+
+	/// Set the message size, in bytes, to probe with the SIZE extension. Set
+	/// to None to disable the probe. Defaults to None.
+	pub fn set_smtp_size_probe(&mut self, size: Option<usize>) -> &mut CheckEmailInput {
+		self.smtp_size_probe = size;
+		self
+	}
+
+	/// Set the nameservers to use for DNS lookups. An empty list uses the
+	/// system's resolver configuration.
+	pub fn set_dns_nameservers(
+		&mut self,
+		dns_nameservers: Vec<SocketAddr>,
+	) -> &mut CheckEmailInput {
+		self.dns_nameservers = dns_nameservers;
+		self
+	}
+
+	/// Set the DNS-over-HTTPS or DNS-over-TLS server to use for DNS
+	/// lookups. Set to None to use plain DNS.
+	pub fn set_encrypted_dns(
+		&mut self,
+		encrypted_dns: Option<EncryptedDns>,
+	) -> &mut CheckEmailInput {
+		self.encrypted_dns = encrypted_dns;
+		self
+	}
+
+	/// Set the timeout of each DNS query. Defaults to 5s.
+	pub fn set_dns_timeout(&mut self, dns_timeout: Duration) -> &mut CheckEmailInput {
+		self.dns_timeout = dns_timeout;
+		self
+	}
+
+	/// Set the number of retries of a failed DNS query. Defaults to 2.
+	pub fn set_dns_retries(&mut self, dns_retries: usize) -> &mut CheckEmailInput {
+		self.dns_retries = dns_retries;
+		self
+	}
+
+	/// Set whether to consider email addresses with non-ASCII characters as
+	/// invalid. Defaults to false.
+	pub fn set_reject_non_ascii(&mut self, reject_non_ascii: bool) -> &mut CheckEmailInput {
+		self.reject_non_ascii = reject_non_ascii;
+		self
+	}
+
+	/// Set the transformations to apply to `to_email` before checking its
+	/// syntax.
+	pub fn set_cleanup_policy(&mut self, cleanup_policy: CleanupPolicy) -> &mut CheckEmailInput {
+		self.cleanup_policy = cleanup_policy;
+		self
+	}
+
+	/// Set whether to consider email addresses with an IP literal domain as
+	/// invalid. Defaults to false.
+	pub fn set_reject_ip_literals(&mut self, reject_ip_literals: bool) -> &mut CheckEmailInput {
+		self.reject_ip_literals = reject_ip_literals;
+		self
+	}
+
+	/// Set whether to extract the email address out of free text before
+	/// verifying it. Defaults to false.
+	pub fn set_extract_email(&mut self, extract_email: bool) -> &mut CheckEmailInput {
+		self.extract_email = extract_email;
+		self
+	}
+
+	/// Set the domains to restrict verifications to. An empty list allows
+	/// all domains.
+	pub fn set_allowed_domains(&mut self, allowed_domains: Vec<String>) -> &mut CheckEmailInput {
+		self.allowed_domains = allowed_domains;
+		self
+	}
+
+	/// Set the domains to never verify.
+	pub fn set_blocked_domains(&mut self, blocked_domains: Vec<String>) -> &mut CheckEmailInput {
+		self.blocked_domains = blocked_domains;
+		self
+	}
+
+	/// Set whether to validate the domain's TLD against the IANA list.
+	/// Defaults to false.
+	pub fn set_check_tld(&mut self, check_tld: bool) -> &mut CheckEmailInput {
+		self.check_tld = check_tld;
+		self
+	}
+
+	/// Set the list of valid TLDs. Set to None to use the bundled IANA list.
+	pub fn set_tld_list(&mut self, tld_list: Option<TldList>) -> &mut CheckEmailInput {
+		self.tld_list = tld_list;
+		self
+	}
+
+	/// Set which grammar to validate the email address syntax against.
+	/// Defaults to Rfc5321.
+	pub fn set_syntax_strictness(
+		&mut self,
+		syntax_strictness: SyntaxStrictness,
+	) -> &mut CheckEmailInput {
+		self.syntax_strictness = syntax_strictness;
+		self
+	}
+
+	/// Set the additional restrictions on the local part.
+	pub fn set_local_part_policy(
+		&mut self,
+		local_part_policy: LocalPartPolicy,
+	) -> &mut CheckEmailInput {
+		self.local_part_policy = local_part_policy;
+		self
+	}
+
+	/// Set the provider-specific normalization rules. Defaults to the
+	/// built-in rules, see `default_normalization_rules`.
+	pub fn set_normalization_rules(
+		&mut self,
+		normalization_rules: Vec<NormalizationRule>,
+	) -> &mut CheckEmailInput {
+		self.normalization_rules = normalization_rules;
+		self
+	}
+
+	/// Set the known mail domains used to suggest corrections of misspelled
+	/// domains. Set to None to use the built-in list.
+	pub fn set_suggestion_domains(
+		&mut self,
+		suggestion_domains: Option<Vec<DomainFrequency>>,
+	) -> &mut CheckEmailInput {
+		self.suggestion_domains = suggestion_domains.map(SuggestionDomains::from);
+		self
+	}
+
+	/// Set the maximum number of suggested corrections of a misspelled
+	/// domain. Defaults to 3.
+	pub fn set_max_suggestions(&mut self, max_suggestions: usize) -> &mut CheckEmailInput {
+		self.max_suggestions = max_suggestions;
+		self
+	}
+
+	/// Share the given context (caches...) with this verification. Pass
+	/// clones of the same context to all inputs which should share state.
+	pub fn set_context(&mut self, context: CheckEmailContext) -> &mut CheckEmailInput {
+		self.context = context;
+		self
+	}
 }
 
 /// An enum to describe how confident we are that the recipient address is
 /// real.
-#[derive(Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Reachable {
 	/// The email is safe to send.
@@ -363,14 +1838,17 @@ pub enum Reachable {
 	/// when one of the following happens:
 	/// - catch-all email,
 	/// - disposable email,
-	/// - role-based address,
+	/// - role-based address with a `Risky` severity,
 	/// - full inbox.
 	Risky,
-	/// Emails that don't exist or are syntactically incorrect. Do not send to
-	/// these emails.
+	/// Emails that don't exist, are syntactically incorrect, or are role
+	/// accounts with a `NeverSend` severity. Do not send to these emails.
 	Invalid,
 	/// We're unable to get a valid response from the recipient's email server.
 	Unknown,
+	/// The email's domain is forbidden by the input's `allowed_domains` or
+	/// `blocked_domains`, so it wasn't verified.
+	Blocked,
 }
 
 /// The result of the [check_email](check_email) function.
@@ -387,6 +1865,18 @@ pub struct CheckEmailOutput {
 	pub smtp: Result<SmtpDetails, SmtpError>,
 	/// Details about the email address.
 	pub syntax: SyntaxDetails,
+	/// Confidence score on how reachable the email is, only computed if
+	/// `compute_confidence` is set in the input.
+	pub confidence: Option<Confidence>,
+	/// Category of the email provider, only computed if
+	/// `check_provider_category` is set in the input.
+	pub provider_category: Option<ProviderCategory>,
+	/// Details about the domain's DNS records, only fetched if one of the
+	/// DNS checks, e.g. `check_spf` or `check_dmarc`, is set in the input.
+	pub dns: Option<DnsDetails>,
+	/// Registration details of the email's domain, only fetched if
+	/// `check_rdap` is set in the input.
+	pub rdap: Option<RdapDetails>,
 }
 
 impl Default for CheckEmailOutput {
@@ -398,6 +1888,10 @@ impl Default for CheckEmailOutput {
 			mx: Ok(MxDetails::default()),
 			smtp: Ok(SmtpDetails::default()),
 			syntax: SyntaxDetails::default(),
+			confidence: None,
+			provider_category: None,
+			dns: None,
+			rdap: None,
 		}
 	}
 }
@@ -416,6 +1910,10 @@ impl Serialize for CheckEmailOutput {
 			// the `get_description` on SmtpError.
 			#[serde(skip_serializing_if = "Option::is_none")]
 			description: Option<SmtpErrorDesc>,
+			// Set on transient MX errors, to hint that the verification can
+			// be retried later.
+			#[serde(skip_serializing_if = "Option::is_none")]
+			retryable: Option<bool>,
 		}
 
 		let mut map = serializer.serialize_map(Some(1))?;
@@ -428,6 +1926,7 @@ impl Serialize for CheckEmailOutput {
 				&MyError {
 					error,
 					description: None,
+					retryable: None,
 				},
 			)?,
 		}
@@ -438,6 +1937,7 @@ impl Serialize for CheckEmailOutput {
 				&MyError {
 					error,
 					description: None,
+					retryable: Some(true).filter(|_| error.is_retryable()),
 				},
 			)?,
 		}
@@ -448,18 +1948,69 @@ impl Serialize for CheckEmailOutput {
 				&MyError {
 					error,
 					description: error.get_description(),
+					retryable: None,
 				},
 			)?,
 		}
 		map.serialize_entry("syntax", &self.syntax)?;
+		if let Some(confidence) = &self.confidence {
+			map.serialize_entry("confidence", confidence)?;
+		}
+		if let Some(provider_category) = &self.provider_category {
+			map.serialize_entry("provider_category", provider_category)?;
+		}
+		if let Some(dns) = &self.dns {
+			map.serialize_entry("dns", dns)?;
+		}
+		if let Some(rdap) = &self.rdap {
+			map.serialize_entry("rdap", rdap)?;
+		}
 		map.end()
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use super::CheckEmailOutput;
+	use super::{CheckEmailInput, CheckEmailOutput, DomainConfig, SmtpAuthMechanism, SmtpRelay};
 	use async_smtp::smtp::response::{Category, Code, Detail, Response, Severity};
+	use std::time::Duration;
+
+	#[test]
+	fn should_tune_fast_and_accurate_presets() {
+		let fast = CheckEmailInput::preset_fast("foo@example.org".into());
+		assert_eq!(fast.smtp_timeout, Some(Duration::from_secs(5)));
+		assert_eq!(fast.retries, 1);
+
+		let accurate = CheckEmailInput::preset_accurate("foo@example.org".into());
+		assert_eq!(accurate.smtp_timeout, Some(Duration::from_secs(30)));
+		assert_eq!(accurate.retries, 3);
+		assert!(accurate.microsoft365_use_api);
+		assert!(accurate.mx_parent_domain_fallback);
+		assert!(accurate.compute_confidence);
+	}
+
+	#[tokio::test]
+	async fn should_rate_limit_stealth_preset() {
+		let stealth = CheckEmailInput::preset_stealth("foo@example.org".into());
+		assert_eq!(stealth.retries, 1);
+		assert_eq!(stealth.catch_all_probes, 1);
+		assert!(stealth.context.smtp_pool_stats().is_none());
+
+		// 10 probes per minute go through, the 11th waits.
+		let limiter = stealth.context.rate_limiter().unwrap();
+		for _ in 0..10 {
+			limiter.acquire("mx.example.org").await;
+		}
+		let eleventh = async_std::future::timeout(
+			Duration::from_millis(50),
+			limiter.acquire("mx.example.org"),
+		);
+		assert!(eleventh.await.is_err());
+		assert_eq!(
+			stealth.context.rate_limit_stats().unwrap().delayed_probes,
+			1
+		);
+	}
 
 	#[test]
 	fn should_serialize_correctly() {
@@ -482,26 +2033,67 @@ mod tests {
 				mx: Ok(super::MxDetails::default()),
 				syntax: super::SyntaxDetails::default(),
 				smtp: Err(super::SmtpError::SmtpError(r.into())),
+				confidence: None,
+				provider_category: None,
+				dns: None,
+				rdap: None,
 			}
 		}
 
 		let res = dummy_response_with_message("blacklist");
 		let actual = serde_json::to_string(&res).unwrap();
 		// Make sure the `description` is present with IpBlacklisted.
-		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: blacklist"},"description":"IpBlacklisted"},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null}}"#;
+		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"is_free_provider":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: blacklist"},"description":"IpBlacklisted"},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null,"is_subaddressed":false,"base_address":null,"subaddress_tag":null,"requires_smtputf8":false}}"#;
 		assert_eq!(expected, actual);
 
 		let res =
 			dummy_response_with_message("Client host rejected: cannot find your reverse hostname");
 		let actual = serde_json::to_string(&res).unwrap();
 		// Make sure the `description` is present with NeedsRDNs.
-		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: Client host rejected: cannot find your reverse hostname"},"description":"NeedsRDNS"},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null}}"#;
+		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"is_free_provider":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: Client host rejected: cannot find your reverse hostname"},"description":"NeedsRDNS"},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null,"is_subaddressed":false,"base_address":null,"subaddress_tag":null,"requires_smtputf8":false}}"#;
 		assert_eq!(expected, actual);
 
 		let res = dummy_response_with_message("foobar");
 		let actual = serde_json::to_string(&res).unwrap();
 		// Make sure the `description` is NOT present.
-		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: foobar"}},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null}}"#;
+		let expected = r#"{"input":"foo","is_reachable":"unknown","misc":{"is_disposable":false,"is_role_account":false,"is_free_provider":false,"gravatar_url":null,"haveibeenpwned":null},"mx":{"accepts_mail":false,"records":[]},"smtp":{"error":{"type":"SmtpError","message":"transient: foobar"}},"syntax":{"address":null,"domain":"","is_valid_syntax":false,"username":"","normalized_email":null,"suggestion":null,"is_subaddressed":false,"base_address":null,"subaddress_tag":null,"requires_smtputf8":false}}"#;
 		assert_eq!(expected, actual);
 	}
+
+	#[test]
+	fn should_apply_domain_overrides() {
+		let mut input = CheckEmailInput::default();
+		input.set_domain_overrides(
+			vec![(
+				"Example.org".to_string(),
+				DomainConfig {
+					smtp_port: Some(587),
+					retries: Some(5),
+					..Default::default()
+				},
+			)]
+			.into_iter()
+			.collect(),
+		);
+
+		let overridden = input.for_domain("example.org");
+		assert_eq!(overridden.smtp_port, 587);
+		assert_eq!(overridden.retries, 5);
+		assert_eq!(overridden.smtp_timeout, input.smtp_timeout);
+		assert_eq!(input.for_domain("example.com").smtp_port, 25);
+	}
+
+	#[test]
+	fn should_default_smtp_relay_mechanism_to_plain() {
+		let relay: SmtpRelay = serde_json::from_str(
+			r#"{"host":"smtp.example.com","port":587,"username":"foo","password":"bar"}"#,
+		)
+		.unwrap();
+
+		assert_eq!(relay.mechanism, SmtpAuthMechanism::Plain);
+		assert_eq!(
+			relay.mechanism.to_mechanism(),
+			async_smtp::smtp::authentication::Mechanism::Plain
+		);
+	}
 }
@@ -0,0 +1,141 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! ASN and geolocation data of IP addresses, from a pluggable source.
+
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// The network an IP address belongs to.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IpInfo {
+	/// The autonomous system number, e.g. 15169 for Google.
+	pub asn: Option<u32>,
+	/// The organization owning the autonomous system.
+	pub network_owner: Option<String>,
+	/// The ISO 3166-1 alpha-2 code of the country where the network is
+	/// registered, e.g. "US".
+	pub country: Option<String>,
+}
+
+/// A source of ASN and geolocation data. Implement this trait to use another
+/// database, e.g. a MaxMind GeoLite2 reader.
+pub trait IpInfoSource: Debug + Send + Sync {
+	/// Get the network `ip` belongs to, or None if it's unknown.
+	fn lookup(&self, ip: IpAddr) -> Option<IpInfo>;
+}
+
+/// An in-memory table of IP ranges, in the tab-separated format of the free
+/// iptoasn.com database: `range_start range_end asn country description`.
+#[derive(Debug, Default, Clone)]
+pub struct Ip2AsnTable {
+	/// Ranges sorted by their first address.
+	ranges: Vec<(IpAddr, IpAddr, IpInfo)>,
+}
+
+impl Ip2AsnTable {
+	/// Parse the tab-separated database. Malformed lines, and ranges which
+	/// are not routed (ASN 0), are skipped.
+	pub fn parse(tsv: &str) -> Self {
+		let mut ranges = tsv
+			.lines()
+			.filter_map(|line| {
+				let mut fields = line.split('\t');
+				let start = fields.next()?.parse::<IpAddr>().ok()?;
+				let end = fields.next()?.parse::<IpAddr>().ok()?;
+				let asn = fields.next()?.parse::<u32>().ok().filter(|asn| *asn != 0)?;
+				let country = fields
+					.next()
+					.filter(|country| !country.is_empty() && *country != "None")
+					.map(String::from);
+				let network_owner = fields
+					.next()
+					.filter(|owner| !owner.is_empty())
+					.map(String::from);
+
+				Some((
+					start,
+					end,
+					IpInfo {
+						asn: Some(asn),
+						network_owner,
+						country,
+					},
+				))
+			})
+			.collect::<Vec<_>>();
+		ranges.sort_by_key(|(start, _, _)| *start);
+
+		Ip2AsnTable { ranges }
+	}
+
+	/// Read and parse the tab-separated database at `path`.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Ok(Self::parse(&fs::read_to_string(path)?))
+	}
+}
+
+impl IpInfoSource for Ip2AsnTable {
+	fn lookup(&self, ip: IpAddr) -> Option<IpInfo> {
+		// The last range starting at or before `ip`.
+		let index = self.ranges.partition_point(|(start, _, _)| *start <= ip);
+		let (_, end, info) = self.ranges.get(index.checked_sub(1)?)?;
+
+		if ip <= *end {
+			Some(info.clone())
+		} else {
+			None
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn should_look_up_ip_ranges() {
+		let table = Ip2AsnTable::parse(
+			"1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n\
+			 1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n\
+			 8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n\
+			 2001:4860::\t2001:4860:ffff:ffff:ffff:ffff:ffff:ffff\t15169\tUS\tGOOGLE\n\
+			 malformed line\n",
+		);
+
+		let google = IpInfo {
+			asn: Some(15169),
+			network_owner: Some("GOOGLE".into()),
+			country: Some("US".into()),
+		};
+		assert_eq!(
+			table.lookup("8.8.8.8".parse().unwrap()),
+			Some(google.clone())
+		);
+		assert_eq!(
+			table.lookup("2001:4860:4860::8888".parse().unwrap()),
+			Some(google)
+		);
+		assert_eq!(table.lookup("1.0.2.1".parse().unwrap()), None);
+		assert_eq!(table.lookup("9.9.9.9".parse().unwrap()), None);
+		assert_eq!(table.lookup("0.0.0.1".parse().unwrap()), None);
+	}
+}
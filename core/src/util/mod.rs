@@ -15,5 +15,11 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod constants;
+pub mod dns_cache;
+pub(crate) mod domain_policy;
+pub mod encrypted_dns;
 pub mod input_output;
+pub mod ip_info;
+pub(crate) mod resolver;
 pub mod ser_with_display;
+pub mod ttl_cache;
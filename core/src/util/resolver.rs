@@ -0,0 +1,407 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std_resolver::config::{
+	NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts,
+};
+use async_std_resolver::lookup::{Lookup, MxLookup};
+use async_std_resolver::proto::xfer::DnsRequestOptions;
+use async_std_resolver::{resolver, AsyncStdResolver, ResolveError};
+use async_trait::async_trait;
+use trust_dns_proto::rr::{RData, RecordType};
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::system_conf::read_system_conf;
+
+use super::dns_cache::{dns_cache_key, DnsCache};
+use super::encrypted_dns::EncryptedDns;
+use super::input_output::CheckEmailInput;
+
+/// A DNS resolver. Implement this trait to replace the built-in resolvers,
+/// e.g. with a mock in tests, and set it on the context with
+/// `CheckEmailContext::with_resolver`.
+#[async_trait]
+pub trait Resolver: Debug + Send + Sync {
+	/// Look up the `record_type` records of `name`. If there are none, the
+	/// error should be `ResolveErrorKind::NoRecordsFound`, with the
+	/// NXDOMAIN response code if `name` doesn't exist.
+	async fn lookup(&self, name: &str, record_type: RecordType) -> Result<Lookup, ResolveError>;
+}
+
+/// How DNS queries are sent.
+enum DnsTransport {
+	/// Plain DNS, over UDP and TCP.
+	Plain(Box<AsyncStdResolver>),
+	/// DNS-over-HTTPS or DNS-over-TLS.
+	Encrypted(EncryptedDns),
+	/// A user-provided resolver.
+	Custom(Arc<dyn Resolver>),
+}
+
+/// The DNS resolver used for a verification.
+pub(crate) struct DnsResolver {
+	transport: DnsTransport,
+	/// Lookups shared with other verifications, if the context has a DNS
+	/// cache.
+	cache: Option<Arc<dyn DnsCache>>,
+	/// A resolver with the same configuration as the plain transport, which
+	/// validates DNSSEC. Only set if the input's `dnssec_validation` or
+	/// `smtp_dane_validation` is set.
+	validating: Option<Box<AsyncStdResolver>>,
+	/// Timeout of each query, for the encrypted transport. The plain
+	/// resolvers have it in their options.
+	timeout: Duration,
+	/// Number of retries of failed queries, for the encrypted transport.
+	retries: usize,
+}
+
+impl DnsResolver {
+	/// Look up the `record_type` records of `name`, from the cache if
+	/// possible.
+	pub(crate) async fn lookup(
+		&self,
+		name: &str,
+		record_type: RecordType,
+	) -> Result<Lookup, ResolveError> {
+		let key = dns_cache_key(name, record_type);
+		if let Some(lookup) = self.cache.as_ref().and_then(|cache| cache.get(&key)) {
+			return Ok(lookup);
+		}
+
+		let lookup = match &self.transport {
+			DnsTransport::Plain(resolver) => {
+				resolver
+					.lookup(name, record_type, DnsRequestOptions::default())
+					.await
+			}
+			DnsTransport::Custom(resolver) => resolver.lookup(name, record_type).await,
+			DnsTransport::Encrypted(encrypted_dns) => {
+				self.encrypted_lookup(encrypted_dns, name, record_type)
+					.await
+			}
+		}?;
+
+		if let Some(cache) = &self.cache {
+			cache.insert(key, lookup.clone(), lookup.valid_until());
+		}
+
+		Ok(lookup)
+	}
+
+	/// Look up `name` on the encrypted DNS server, retrying on errors other
+	/// than a missing record.
+	async fn encrypted_lookup(
+		&self,
+		encrypted_dns: &EncryptedDns,
+		name: &str,
+		record_type: RecordType,
+	) -> Result<Lookup, ResolveError> {
+		let mut retries = self.retries;
+		loop {
+			match encrypted_dns.lookup(name, record_type, self.timeout).await {
+				Err(err)
+					if retries > 0
+						&& !matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) =>
+				{
+					retries -= 1;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	/// Look up the MX records of `name`.
+	pub(crate) async fn mx_lookup(&self, name: &str) -> Result<MxLookup, ResolveError> {
+		self.lookup(name, RecordType::MX).await.map(MxLookup::from)
+	}
+
+	/// Look up the TXT records of `name`. The character strings of each
+	/// record are concatenated, as SPF and DMARC expect.
+	pub(crate) async fn txt_lookup(&self, name: &str) -> Result<Vec<String>, ResolveError> {
+		let lookup = self.lookup(name, RecordType::TXT).await?;
+
+		Ok(lookup
+			.iter()
+			.filter_map(|rdata| match rdata {
+				RData::TXT(txt) => Some(
+					txt.txt_data()
+						.iter()
+						.map(|data| String::from_utf8_lossy(data))
+						.collect(),
+				),
+				_ => None,
+			})
+			.collect())
+	}
+
+	/// Whether the MX records of `name` are signed, with a valid DNSSEC
+	/// chain of trust. The cache is bypassed, since it doesn't keep track of
+	/// validation.
+	///
+	/// Returns None if DNSSEC validation is disabled, or if DNS queries are
+	/// encrypted, since the resolver then can't validate the answers.
+	pub(crate) async fn validate_mx(&self, name: &str) -> Option<bool> {
+		let validating = self.validating.as_ref()?;
+
+		Some(validating.mx_lookup(name).await.is_ok())
+	}
+
+	/// Look up the `record_type` records of `name`, only succeeding if the
+	/// answer has a valid DNSSEC chain of trust. The cache is bypassed.
+	///
+	/// Returns None if the resolver can't validate DNSSEC, see
+	/// `validate_mx`.
+	pub(crate) async fn validated_lookup(
+		&self,
+		name: &str,
+		record_type: RecordType,
+	) -> Option<Result<Lookup, ResolveError>> {
+		let validating = self.validating.as_ref()?;
+
+		Some(
+			validating
+				.lookup(name, record_type, DnsRequestOptions::default())
+				.await,
+		)
+	}
+}
+
+/// Query each nameserver over UDP, falling back to TCP for truncated
+/// responses, like the system configuration does.
+fn nameserver_group(nameservers: &[SocketAddr]) -> NameServerConfigGroup {
+	let mut group = NameServerConfigGroup::with_capacity(nameservers.len() * 2);
+
+	for socket_addr in nameservers {
+		for protocol in [Protocol::Udp, Protocol::Tcp] {
+			group.push(NameServerConfig {
+				socket_addr: *socket_addr,
+				protocol,
+				tls_dns_name: None,
+				trust_nx_responses: true,
+				bind_addr: None,
+			});
+		}
+	}
+
+	group
+}
+
+/// The configuration of the plain resolvers: the input's `dns_nameservers`,
+/// or the system configuration if there are none, with the input's
+/// `dns_timeout` and `dns_retries`.
+fn plain_config(input: &CheckEmailInput) -> Result<(ResolverConfig, ResolverOpts), ResolveError> {
+	let (config, mut opts) = if input.dns_nameservers.is_empty() {
+		read_system_conf()?
+	} else {
+		(
+			ResolverConfig::from_parts(None, vec![], nameserver_group(&input.dns_nameservers)),
+			ResolverOpts::default(),
+		)
+	};
+	opts.timeout = input.dns_timeout;
+	opts.attempts = input.dns_retries;
+
+	Ok((config, opts))
+}
+
+/// The options of the resolver validating DNSSEC, if the input's
+/// `dnssec_validation` or `smtp_dane_validation` is set: `opts`, with
+/// validation enabled.
+fn validating_opts(input: &CheckEmailInput, mut opts: ResolverOpts) -> Option<ResolverOpts> {
+	if !input.dnssec_validation && !input.smtp_dane_validation {
+		return None;
+	}
+
+	opts.validate = true;
+	Some(opts)
+}
+
+/// Create the DNS resolver used for the verification: the context's custom
+/// resolver if set, otherwise the input's `encrypted_dns` server if set, otherwise its `dns_nameservers`, or the
+/// system configuration if there are none. The input's `dns_timeout` and
+/// `dns_retries` override the system configuration.
+pub(crate) async fn create_resolver(input: &CheckEmailInput) -> Result<DnsResolver, ResolveError> {
+	if let Some(resolver) = input.context.resolver() {
+		return Ok(DnsResolver {
+			transport: DnsTransport::Custom(resolver),
+			cache: input.context.dns_cache(),
+			validating: None,
+			timeout: input.dns_timeout,
+			retries: input.dns_retries,
+		});
+	}
+
+	if let Some(encrypted_dns) = &input.encrypted_dns {
+		return Ok(DnsResolver {
+			transport: DnsTransport::Encrypted(encrypted_dns.clone()),
+			cache: input.context.dns_cache(),
+			validating: None,
+			timeout: input.dns_timeout,
+			retries: input.dns_retries,
+		});
+	}
+
+	let (config, opts) = plain_config(input)?;
+	let validating = match validating_opts(input, opts) {
+		Some(opts) => Some(Box::new(resolver(config.clone(), opts).await?)),
+		None => None,
+	};
+
+	Ok(DnsResolver {
+		transport: DnsTransport::Plain(Box::new(resolver(config, opts).await?)),
+		cache: input.context.dns_cache(),
+		validating,
+		timeout: input.dns_timeout,
+		retries: input.dns_retries,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mx::check_mx;
+	use crate::syntax::check_syntax;
+	use crate::CheckEmailContext;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use tokio::net::TcpListener;
+	use trust_dns_proto::op::{Query, ResponseCode};
+	use trust_dns_proto::rr::{rdata::MX, Name, Record};
+
+	/// A resolver which only knows the MX records of example.org.
+	#[derive(Debug)]
+	struct MockResolver;
+
+	#[async_trait]
+	impl Resolver for MockResolver {
+		async fn lookup(
+			&self,
+			name: &str,
+			record_type: RecordType,
+		) -> Result<Lookup, ResolveError> {
+			let query = Query::query(Name::from_ascii(name)?, record_type);
+			if name.trim_end_matches('.') != "example.org" || record_type != RecordType::MX {
+				return Err(ResolveErrorKind::NoRecordsFound {
+					query: Box::new(query),
+					soa: None,
+					negative_ttl: None,
+					response_code: ResponseCode::NXDomain,
+					trusted: true,
+				}
+				.into());
+			}
+
+			let mx = MX::new(10, Name::from_ascii("mx.example.org.")?);
+			let record = Record::from_rdata(query.name().clone(), 300, RData::MX(mx));
+			Ok(Lookup::new_with_max_ttl(query, Arc::from(vec![record])))
+		}
+	}
+
+	#[tokio::test]
+	async fn should_use_custom_resolver() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_context(CheckEmailContext::new().with_resolver(Arc::new(MockResolver)));
+
+		let mx = check_mx(&check_syntax("foo@example.org"), &input)
+			.await
+			.unwrap();
+		let hosts = mx
+			.lookup
+			.unwrap()
+			.iter()
+			.map(|mx| mx.exchange().to_string())
+			.collect::<Vec<_>>();
+		assert_eq!(hosts, vec!["mx.example.org."]);
+	}
+
+	#[test]
+	fn should_query_nameservers_over_udp_and_tcp() {
+		let group = nameserver_group(&["10.0.0.53:5353".parse().unwrap()]);
+
+		assert_eq!(group.len(), 2);
+		assert!(group.iter().all(|config| config.socket_addr.port() == 5353));
+		assert_eq!(group[0].protocol, Protocol::Udp);
+		assert_eq!(group[1].protocol, Protocol::Tcp);
+	}
+
+	#[test]
+	fn should_set_dns_timeout_and_retries() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input
+			.set_dns_nameservers(vec!["10.0.0.53:53".parse().unwrap()])
+			.set_dns_timeout(Duration::from_millis(1500))
+			.set_dns_retries(4);
+
+		let (_, opts) = plain_config(&input).unwrap();
+		assert_eq!(opts.timeout, Duration::from_millis(1500));
+		assert_eq!(opts.attempts, 4);
+	}
+
+	#[test]
+	fn should_only_validate_dnssec_if_enabled() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		assert!(validating_opts(&input, ResolverOpts::default()).is_none());
+
+		input.set_dnssec_validation(true);
+		let opts = validating_opts(&input, ResolverOpts::default()).unwrap();
+		assert!(opts.validate);
+	}
+
+	#[tokio::test]
+	async fn should_not_report_dnssec_if_disabled() {
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input.set_context(CheckEmailContext::new().with_resolver(Arc::new(MockResolver)));
+
+		let mx = check_mx(&check_syntax("foo@example.org"), &input)
+			.await
+			.unwrap();
+		assert!(mx.lookup.is_ok());
+		assert_eq!(mx.dnssec_validated, None);
+	}
+
+	#[tokio::test]
+	async fn should_retry_encrypted_dns_after_timeout() {
+		// A DNS-over-TLS server which accepts connections, but never answers.
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let socket_addr = listener.local_addr().unwrap();
+		let connections = Arc::new(AtomicUsize::new(0));
+		let accepted = connections.clone();
+		tokio::spawn(async move {
+			let mut streams = vec![];
+			while let Ok((stream, _)) = listener.accept().await {
+				accepted.fetch_add(1, Ordering::SeqCst);
+				streams.push(stream);
+			}
+		});
+
+		let mut input = CheckEmailInput::new("foo@example.org".into());
+		input
+			.set_encrypted_dns(Some(EncryptedDns::Tls {
+				socket_addr,
+				server_name: "localhost".into(),
+			}))
+			.set_dns_timeout(Duration::from_millis(50))
+			.set_dns_retries(2);
+		let resolver = create_resolver(&input).await.unwrap();
+
+		let err = resolver.mx_lookup("example.org").await.unwrap_err();
+		assert!(matches!(err.kind(), ResolveErrorKind::Timeout));
+		assert_eq!(connections.load(Ordering::SeqCst), 3);
+	}
+}
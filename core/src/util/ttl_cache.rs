@@ -0,0 +1,169 @@
+// check-if-email-exists
+// Copyright (C) 2018-2022 Reacher
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Hit and miss counters of a cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheStats {
+	/// Number of lookups which found a valid entry.
+	pub hits: u64,
+	/// Number of lookups which found no entry, or an expired one.
+	pub misses: u64,
+}
+
+/// Default maximum number of entries of a cache.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// A thread-safe in-memory cache, where each entry is valid until a given
+/// instant (usually derived from a DNS record's TTL). It holds at most
+/// `capacity` entries: once full, inserting evicts the expired entries, or
+/// the one expiring first if none is.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+	entries: Mutex<HashMap<K, (V, Instant)>>,
+	capacity: usize,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+impl<K, V> Default for TtlCache<K, V> {
+	fn default() -> Self {
+		TtlCache::with_capacity(DEFAULT_CACHE_CAPACITY)
+	}
+}
+
+impl<K, V> TtlCache<K, V> {
+	/// Create a cache holding at most `capacity` entries.
+	pub fn with_capacity(capacity: usize) -> Self {
+		TtlCache {
+			entries: Mutex::new(HashMap::new()),
+			capacity: capacity.max(1),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+		}
+	}
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+	/// Get the value stored at `key`, if it's still valid. Expired entries
+	/// are evicted.
+	pub fn get(&self, key: &K) -> Option<V> {
+		let mut entries = self
+			.entries
+			.lock()
+			.expect("Cache lock is not poisoned. qed.");
+		let value = match entries.get(key) {
+			Some((value, valid_until)) if *valid_until > Instant::now() => Some(value.clone()),
+			Some(_) => {
+				entries.remove(key);
+				None
+			}
+			None => None,
+		};
+
+		if value.is_some() {
+			self.hits.fetch_add(1, Ordering::Relaxed);
+		} else {
+			self.misses.fetch_add(1, Ordering::Relaxed);
+		}
+
+		value
+	}
+
+	/// Store `value` at `key`, valid until the `valid_until` instant,
+	/// evicting entries if the cache is full.
+	pub fn insert(&self, key: K, value: V, valid_until: Instant) {
+		let mut entries = self
+			.entries
+			.lock()
+			.expect("Cache lock is not poisoned. qed.");
+		if entries.len() >= self.capacity && !entries.contains_key(&key) {
+			let now = Instant::now();
+			entries.retain(|_, (_, valid_until)| *valid_until > now);
+		}
+		if entries.len() >= self.capacity && !entries.contains_key(&key) {
+			let first_expiring = entries
+				.iter()
+				.min_by_key(|(_, (_, valid_until))| *valid_until)
+				.map(|(key, _)| key.clone());
+			if let Some(first_expiring) = first_expiring {
+				entries.remove(&first_expiring);
+			}
+		}
+
+		entries.insert(key, (value, valid_until));
+	}
+
+	/// Remove all entries from the cache. Stats are kept.
+	pub fn clear(&self) {
+		self.entries
+			.lock()
+			.expect("Cache lock is not poisoned. qed.")
+			.clear();
+	}
+
+	/// Get the hit/miss counters of this cache.
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn should_expire_entries() {
+		let cache = TtlCache::default();
+		cache.insert("a", true, Instant::now() + Duration::from_secs(60));
+		cache.insert("b", true, Instant::now() - Duration::from_secs(1));
+
+		assert_eq!(cache.get(&"a"), Some(true));
+		assert_eq!(cache.get(&"b"), None);
+		assert_eq!(cache.get(&"c"), None);
+		assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 2 });
+	}
+
+	#[test]
+	fn should_bound_entries() {
+		let now = Instant::now();
+		let cache = TtlCache::with_capacity(2);
+		cache.insert("expired", true, now - Duration::from_secs(1));
+		cache.insert("a", true, now + Duration::from_secs(60));
+		// The expired entry is swept.
+		cache.insert("b", true, now + Duration::from_secs(30));
+		assert_eq!(cache.get(&"a"), Some(true));
+		assert_eq!(cache.get(&"b"), Some(true));
+
+		// The entry expiring first is evicted.
+		cache.insert("c", true, now + Duration::from_secs(90));
+		assert_eq!(cache.get(&"b"), None);
+		assert_eq!(cache.get(&"a"), Some(true));
+		assert_eq!(cache.get(&"c"), Some(true));
+		assert_eq!(cache.entries.lock().unwrap().len(), 2);
+	}
+}